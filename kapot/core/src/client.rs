@@ -25,8 +25,9 @@ use std::{
     task::{Context, Poll},
 };
 
+use crate::config::ShuffleCompression;
 use crate::error::{KapotError, Result};
-use crate::serde::scheduler::{Action, PartitionId};
+use crate::serde::scheduler::{Action, ByteRange, PartitionId};
 
 use arrow_flight;
 use arrow_flight::utils::flight_data_to_arrow_batch;
@@ -42,8 +43,9 @@ use datafusion::error::DataFusionError;
 
 use crate::serde::protobuf;
 use crate::utils::create_grpc_client_connection;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
 use datafusion::physical_plan::{RecordBatchStream, SendableRecordBatchStream};
-use futures::{Stream, StreamExt};
+use futures::{Stream, StreamExt, TryStreamExt};
 use log::{debug, warn};
 use prost::Message;
 use tonic::{Code, Streaming};
@@ -79,11 +81,15 @@ impl KapotClient {
     }
 
     /// Fetch a partition from an executor
+    #[allow(clippy::too_many_arguments)]
     pub async fn fetch_partition(
         &mut self,
         executor_id: &str,
         partition_id: &PartitionId,
         path: &str,
+        range: Option<ByteRange>,
+        compression: ShuffleCompression,
+        checksum: Option<u32>,
         host: &str,
         port: u16,
     ) -> Result<SendableRecordBatchStream> {
@@ -94,8 +100,50 @@ impl KapotClient {
             path: path.to_owned(),
             host: host.to_owned(),
             port,
+            range,
+            compression,
+            checksum,
+            streaming: false,
+            map_partition_id: 0,
         };
-        self.execute_action(&action)
+        self.execute_fetch(executor_id, partition_id, &action).await
+    }
+
+    /// Fetch a partition streamed from the memory of the map task `map_partition_id`
+    /// while it is still running on the executor
+    pub async fn fetch_partition_stream(
+        &mut self,
+        executor_id: &str,
+        partition_id: &PartitionId,
+        map_partition_id: usize,
+        compression: ShuffleCompression,
+        host: &str,
+        port: u16,
+    ) -> Result<SendableRecordBatchStream> {
+        let action = Action::FetchPartition {
+            job_id: partition_id.job_id.clone(),
+            stage_id: partition_id.stage_id,
+            partition_id: partition_id.partition_id,
+            path: String::new(),
+            host: host.to_owned(),
+            port,
+            range: None,
+            compression,
+            checksum: None,
+            streaming: true,
+            map_partition_id,
+        };
+        self.execute_fetch(executor_id, partition_id, &action).await
+    }
+
+    async fn execute_fetch(
+        &mut self,
+        executor_id: &str,
+        partition_id: &PartitionId,
+        action: &Action,
+    ) -> Result<SendableRecordBatchStream> {
+        let stream = self
+            .execute_action(action)
             .await
             .map_err(|error| match error {
                 // map grpc connection error to partition fetch error.
@@ -106,7 +154,25 @@ impl KapotClient {
                     msg,
                 ),
                 other => other,
-            })
+            })?;
+
+        // an error halfway through the transfer, e.g. on a batch failing to be decoded,
+        // fails the fetch too
+        let executor_id = executor_id.to_owned();
+        let (stage_id, map_partition_id) =
+            (partition_id.stage_id, partition_id.partition_id);
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            stream.schema(),
+            stream.map_err(move |e| {
+                let error = KapotError::FetchFailed(
+                    executor_id.clone(),
+                    stage_id,
+                    map_partition_id,
+                    e.to_string(),
+                );
+                ArrowError::ExternalError(Box::new(error)).into()
+            }),
+        )))
     }
 
     /// Execute an action and retrieve the results
@@ -46,6 +46,93 @@ pub const KAPOT_PLUGIN_DIR: &str = "kapot.plugin_dir";
 /// max message size for gRPC clients
 pub const KAPOT_GRPC_CLIENT_MAX_MESSAGE_SIZE: &str =
     "kapot.grpc_client_max_message_size";
+/// Indicate whether a map task writes all of its shuffle output partitions into a single
+/// data file, instead of one file per output partition
+pub const KAPOT_SHUFFLE_SORT_BASED_ENABLED: &str = "kapot.shuffle.sort_based.enabled";
+/// Indicate whether a global sort is range partitioned across executors instead of being
+/// merged into a single partition
+pub const KAPOT_SHUFFLE_RANGE_PARTITIONING_ENABLED: &str =
+    "kapot.shuffle.range_partitioning.enabled";
+/// Number of rows sampled from each input partition to compute the range partitioning bounds
+pub const KAPOT_SHUFFLE_RANGE_PARTITIONING_SAMPLE_SIZE: &str =
+    "kapot.shuffle.range_partitioning.sample_size";
+/// Maximum number of bytes of shuffle output a map task buffers in memory before spilling
+pub const KAPOT_SHUFFLE_WRITER_BUFFER_SIZE: &str = "kapot.shuffle.writer.buffer_size";
+/// Compression codec of shuffle files and of shuffle partitions transferred over Flight
+pub const KAPOT_SHUFFLE_COMPRESSION: &str = "kapot.shuffle.compression";
+/// Object store url the shuffle output of map tasks is written to, so that it outlives the
+/// executor which produced it. The executor work directory is used when empty.
+pub const KAPOT_SHUFFLE_REMOTE_STORAGE_URL: &str = "kapot.shuffle.remote_storage.url";
+/// Maximum number of remote shuffle partitions a task fetches concurrently
+pub const KAPOT_SHUFFLE_READER_MAX_REQUESTS: &str = "kapot.shuffle.reader.max_requests";
+/// Maximum number of bytes of remote shuffle partitions a task has fetched but not yet
+/// read, as estimated from the partition statistics. There is no limit when set to 0.
+pub const KAPOT_SHUFFLE_READER_MAX_BYTES_IN_FLIGHT: &str =
+    "kapot.shuffle.reader.max_bytes_in_flight";
+/// Indicate whether adjacent small shuffle partitions are coalesced into a single task when
+/// the stage reading them is resolved
+pub const KAPOT_ADAPTIVE_COALESCE_PARTITIONS_ENABLED: &str =
+    "kapot.adaptive.coalesce_partitions.enabled";
+/// Size in bytes of the shuffle partitions read by a task the adaptive execution aims for
+pub const KAPOT_ADAPTIVE_ADVISORY_PARTITION_SIZE: &str =
+    "kapot.adaptive.advisory_partition_size";
+/// Indicate whether skewed partitions of a join are split into several tasks when the
+/// stage of the join is resolved
+pub const KAPOT_ADAPTIVE_SKEW_JOIN_ENABLED: &str = "kapot.adaptive.skew_join.enabled";
+/// A partition of a join input is skewed when it is larger than this factor times the
+/// median size of the partitions of the input
+pub const KAPOT_ADAPTIVE_SKEW_JOIN_SKEWED_PARTITION_FACTOR: &str =
+    "kapot.adaptive.skew_join.skewed_partition_factor";
+/// Minimum size in bytes of a skewed partition of a join input
+pub const KAPOT_ADAPTIVE_SKEW_JOIN_SKEWED_PARTITION_THRESHOLD: &str =
+    "kapot.adaptive.skew_join.skewed_partition_threshold";
+/// Indicate whether a partitioned hash join is switched to a broadcast join when the
+/// stage of the join is resolved and its build side turns out to be small
+pub const KAPOT_ADAPTIVE_BROADCAST_JOIN_ENABLED: &str =
+    "kapot.adaptive.broadcast_join.enabled";
+/// Indicate whether a copy of the tasks running much longer than the other tasks of their
+/// stage is launched on another executor
+pub const KAPOT_SPECULATION_ENABLED: &str = "kapot.speculation.enabled";
+/// A task is speculated when it runs longer than this multiplier times the median
+/// duration of the successful tasks of its stage
+pub const KAPOT_SPECULATION_MULTIPLIER: &str = "kapot.speculation.multiplier";
+/// Fraction of the tasks of a stage which must be successful before the stage is
+/// speculated
+pub const KAPOT_SPECULATION_QUANTILE: &str = "kapot.speculation.quantile";
+/// Minimum duration in milliseconds of a task before it is speculated
+pub const KAPOT_SPECULATION_MIN_TASK_RUNTIME_MS: &str =
+    "kapot.speculation.min_task_runtime_ms";
+/// Number of times a task is attempted before its stage is failed
+pub const KAPOT_TASK_MAX_FAILURES: &str = "kapot.task.max_failures";
+/// Number of times a stage is attempted after shuffle fetch failures before it is failed
+pub const KAPOT_STAGE_MAX_FAILURES: &str = "kapot.stage.max_failures";
+/// Delay in milliseconds before a failed task is retried, doubled with every further
+/// failure of the task
+pub const KAPOT_TASK_RETRY_BACKOFF_MS: &str = "kapot.task.retry_backoff_ms";
+/// Upper bound in milliseconds of the delay before a failed task is retried
+pub const KAPOT_TASK_RETRY_MAX_BACKOFF_MS: &str = "kapot.task.retry_max_backoff_ms";
+/// Comma separated list of patterns. A task failure whose error message contains one of
+/// them fails the stage of the task instead of being retried.
+pub const KAPOT_TASK_NON_RETRYABLE_ERRORS: &str = "kapot.task.non_retryable_errors";
+/// Name of the scheduling pool of the job. The executor slots are shared between the
+/// pools with running jobs in proportion to the weights the scheduler sets to them
+pub const KAPOT_JOB_POOL: &str = "kapot.job.pool";
+/// Priority of the job within its scheduling pool, the higher the sooner its tasks are
+/// scheduled. Jobs with the same priority are scheduled in submission order
+pub const KAPOT_JOB_PRIORITY: &str = "kapot.job.priority";
+/// Tenant the scheduler quotas of the job are accounted to, the session of the job when
+/// empty
+pub const KAPOT_JOB_TENANT: &str = "kapot.job.tenant";
+/// Time in seconds after its submission a job is cancelled and failed with a timeout
+/// error, 0 for no timeout
+pub const KAPOT_JOB_TIMEOUT: &str = "kapot.job.timeout";
+/// Absolute deadline in milliseconds since the Unix epoch of the jobs submitted by the
+/// client, after which they are cancelled and failed with a timeout error, 0 for no
+/// deadline
+pub const KAPOT_JOB_DEADLINE: &str = "kapot.job.deadline";
+/// Indicate whether all the stages of the job are launched at once, once enough slots are
+/// available for all of their tasks, with the shuffle output streamed between them
+pub const KAPOT_JOB_GANG_SCHEDULING_ENABLED: &str = "kapot.job.gang_scheduling.enabled";
 
 pub type ParseResult<T> = result::Result<T, String>;
 
@@ -102,7 +189,7 @@ impl KapotConfigBuilder {
 }
 
 /// kapot configuration
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct KapotConfig {
     /// Settings stored in map for easy serde
     settings: HashMap<String, String>,
@@ -136,6 +223,51 @@ impl KapotConfig {
                 )));
             }
         }
+        if let Some(v) = settings.get(KAPOT_SHUFFLE_COMPRESSION) {
+            v.parse::<ShuffleCompression>().map_err(|e| KapotError::General(format!("Failed to parse user-supplied value '{KAPOT_SHUFFLE_COMPRESSION}' for configuration setting '{v}': {e}")))?;
+        }
+        if let Some(v) = settings.get(KAPOT_SHUFFLE_REMOTE_STORAGE_URL) {
+            if !v.is_empty() {
+                url::Url::parse(v).map_err(|e| KapotError::General(format!("Failed to parse user-supplied value '{KAPOT_SHUFFLE_REMOTE_STORAGE_URL}' for configuration setting '{v}': {e}")))?;
+            }
+        }
+        if settings.get(KAPOT_SHUFFLE_READER_MAX_REQUESTS).is_some_and(|v| v == "0") {
+            return Err(KapotError::General(format!(
+                "Configuration setting '{KAPOT_SHUFFLE_READER_MAX_REQUESTS}' must be greater than 0"
+            )));
+        }
+
+        if settings.get(KAPOT_ADAPTIVE_ADVISORY_PARTITION_SIZE).is_some_and(|v| v == "0") {
+            return Err(KapotError::General(format!(
+                "Configuration setting '{KAPOT_ADAPTIVE_ADVISORY_PARTITION_SIZE}' must be greater than 0"
+            )));
+        }
+
+        if settings.get(KAPOT_ADAPTIVE_SKEW_JOIN_SKEWED_PARTITION_FACTOR).is_some_and(|v| v == "0") {
+            return Err(KapotError::General(format!(
+                "Configuration setting '{KAPOT_ADAPTIVE_SKEW_JOIN_SKEWED_PARTITION_FACTOR}' must be greater than 0"
+            )));
+        }
+
+        if settings.get(KAPOT_SPECULATION_MULTIPLIER).is_some_and(|v| v.parse::<f64>().is_ok_and(|v| v < 1.0)) {
+            return Err(KapotError::General(format!(
+                "Configuration setting '{KAPOT_SPECULATION_MULTIPLIER}' must be at least 1"
+            )));
+        }
+
+        if settings.get(KAPOT_SPECULATION_QUANTILE).is_some_and(|v| v.parse::<f64>().is_ok_and(|v| !(0.0..=1.0).contains(&v))) {
+            return Err(KapotError::General(format!(
+                "Configuration setting '{KAPOT_SPECULATION_QUANTILE}' must be between 0 and 1"
+            )));
+        }
+
+        for key in [KAPOT_TASK_MAX_FAILURES, KAPOT_STAGE_MAX_FAILURES] {
+            if settings.get(key).is_some_and(|v| v == "0") {
+                return Err(KapotError::General(format!(
+                    "Configuration setting '{key}' must be greater than 0"
+                )));
+            }
+        }
 
         Ok(Self { settings })
     }
@@ -157,6 +289,11 @@ impl KapotConfig {
                     .parse::<usize>()
                     .map_err(|e| format!("{e:?}"))?;
             }
+            DataType::Float64 => {
+                val.to_string()
+                    .parse::<f64>()
+                    .map_err(|e| format!("{e:?}"))?;
+            }
             DataType::Boolean => {
                 val.to_string()
                     .parse::<bool>()
@@ -201,7 +338,7 @@ impl KapotConfig {
                              "Sets whether enable information_schema".to_string(),
                              DataType::Boolean, Some("false".to_string())),
             ConfigEntry::new(KAPOT_HASH_JOIN_SINGLE_PARTITION_THRESHOLD.to_string(),
-                "Sets threshold in bytes for collecting the smaller side of the hash join in memory".to_string(),
+                "Sets threshold in bytes for collecting the smaller side of the hash join in memory and broadcasting it to all tasks of the join".to_string(),
                 DataType::UInt64, Some((1024 * 1024).to_string())),
             ConfigEntry::new(KAPOT_COLLECT_STATISTICS.to_string(),
                 "Configuration for collecting statistics during scan".to_string(),
@@ -214,6 +351,93 @@ impl KapotConfig {
                              "Configuration for max message size in gRPC clients".to_string(),
                              DataType::UInt64,
                              Some((128 * 1024 * 1024).to_string())),
+            ConfigEntry::new(KAPOT_SHUFFLE_SORT_BASED_ENABLED.to_string(),
+                             "Sets whether to write the shuffle output of a map task into a single data file holding all its output partitions one after the other".to_string(),
+                             DataType::Boolean, Some("false".to_string())),
+            ConfigEntry::new(KAPOT_SHUFFLE_RANGE_PARTITIONING_ENABLED.to_string(),
+                             "Sets whether to sort in parallel by range partitioning the input of a global sort".to_string(),
+                             DataType::Boolean, Some("false".to_string())),
+            ConfigEntry::new(KAPOT_SHUFFLE_RANGE_PARTITIONING_SAMPLE_SIZE.to_string(),
+                             "Sets the number of rows sampled from each input partition to compute the range bounds".to_string(),
+                             DataType::UInt64, Some("1000".to_string())),
+            ConfigEntry::new(KAPOT_SHUFFLE_WRITER_BUFFER_SIZE.to_string(),
+                             "Sets the memory budget in bytes of a shuffle map task for buffering its output partitions".to_string(),
+                             DataType::UInt64, Some((64 * 1024 * 1024).to_string())),
+            ConfigEntry::new(KAPOT_SHUFFLE_COMPRESSION.to_string(),
+                             "Sets the compression codec of shuffle files and Flight transfers, one of none, lz4 or zstd".to_string(),
+                             DataType::Utf8, Some("lz4".to_string())),
+            ConfigEntry::new(KAPOT_SHUFFLE_REMOTE_STORAGE_URL.to_string(),
+                             "Sets the object store url the shuffle output is written to instead of the executor work directory".to_string(),
+                             DataType::Utf8, Some("".to_string())),
+            ConfigEntry::new(KAPOT_SHUFFLE_READER_MAX_REQUESTS.to_string(),
+                             "Sets the maximum number of remote shuffle partitions a task fetches concurrently".to_string(),
+                             DataType::UInt64, Some("50".to_string())),
+            ConfigEntry::new(KAPOT_SHUFFLE_READER_MAX_BYTES_IN_FLIGHT.to_string(),
+                             "Sets the maximum number of bytes of remote shuffle partitions a task fetches ahead of reading them, 0 for no limit".to_string(),
+                             DataType::UInt64, Some((256 * 1024 * 1024).to_string())),
+            ConfigEntry::new(KAPOT_ADAPTIVE_COALESCE_PARTITIONS_ENABLED.to_string(),
+                             "When set to true, adjacent small shuffle partitions are coalesced into a single task up to the advisory partition size".to_string(),
+                             DataType::Boolean, Some("false".to_string())),
+            ConfigEntry::new(KAPOT_ADAPTIVE_ADVISORY_PARTITION_SIZE.to_string(),
+                             "Sets the size in bytes of the shuffle partitions read by a task the adaptive execution aims for".to_string(),
+                             DataType::UInt64, Some((64 * 1024 * 1024).to_string())),
+            ConfigEntry::new(KAPOT_ADAPTIVE_SKEW_JOIN_ENABLED.to_string(),
+                             "When set to true, skewed partitions of a join are split into several tasks".to_string(),
+                             DataType::Boolean, Some("false".to_string())),
+            ConfigEntry::new(KAPOT_ADAPTIVE_SKEW_JOIN_SKEWED_PARTITION_FACTOR.to_string(),
+                             "Sets the factor of the median partition size above which a partition of a join input is skewed".to_string(),
+                             DataType::UInt64, Some("5".to_string())),
+            ConfigEntry::new(KAPOT_ADAPTIVE_SKEW_JOIN_SKEWED_PARTITION_THRESHOLD.to_string(),
+                             "Sets the minimum size in bytes of a skewed partition of a join input".to_string(),
+                             DataType::UInt64, Some((256 * 1024 * 1024).to_string())),
+            ConfigEntry::new(KAPOT_ADAPTIVE_BROADCAST_JOIN_ENABLED.to_string(),
+                             "When set to true, a partitioned hash join whose build side is smaller than the hash join single partition threshold is switched to a broadcast join".to_string(),
+                             DataType::Boolean, Some("false".to_string())),
+            ConfigEntry::new(KAPOT_SPECULATION_ENABLED.to_string(),
+                             "When set to true, a copy of the tasks running much longer than the other tasks of their stage is launched on another executor, and the first attempt to finish is kept".to_string(),
+                             DataType::Boolean, Some("false".to_string())),
+            ConfigEntry::new(KAPOT_SPECULATION_MULTIPLIER.to_string(),
+                             "Sets how many times longer than the median duration of the successful tasks of its stage a task must run to be speculated".to_string(),
+                             DataType::Float64, Some("1.5".to_string())),
+            ConfigEntry::new(KAPOT_SPECULATION_QUANTILE.to_string(),
+                             "Sets the fraction of the tasks of a stage which must be successful before the stage is speculated".to_string(),
+                             DataType::Float64, Some("0.75".to_string())),
+            ConfigEntry::new(KAPOT_SPECULATION_MIN_TASK_RUNTIME_MS.to_string(),
+                             "Sets the minimum duration in milliseconds of a task before it is speculated".to_string(),
+                             DataType::UInt64, Some("1000".to_string())),
+            ConfigEntry::new(KAPOT_TASK_MAX_FAILURES.to_string(),
+                             "Sets the number of times a task is attempted before its stage is failed".to_string(),
+                             DataType::UInt64, Some("4".to_string())),
+            ConfigEntry::new(KAPOT_STAGE_MAX_FAILURES.to_string(),
+                             "Sets the number of times a stage is attempted after shuffle fetch failures before it is failed".to_string(),
+                             DataType::UInt64, Some("4".to_string())),
+            ConfigEntry::new(KAPOT_TASK_RETRY_BACKOFF_MS.to_string(),
+                             "Sets the delay in milliseconds before a failed task is retried, doubled with every further failure of the task".to_string(),
+                             DataType::UInt64, Some("0".to_string())),
+            ConfigEntry::new(KAPOT_TASK_RETRY_MAX_BACKOFF_MS.to_string(),
+                             "Sets the upper bound in milliseconds of the delay before a failed task is retried".to_string(),
+                             DataType::UInt64, Some("30000".to_string())),
+            ConfigEntry::new(KAPOT_TASK_NON_RETRYABLE_ERRORS.to_string(),
+                             "Sets a comma separated list of patterns, a task failure whose error message contains one of them fails the stage instead of being retried".to_string(),
+                             DataType::Utf8, Some("".to_string())),
+            ConfigEntry::new(KAPOT_JOB_POOL.to_string(),
+                             "Sets the name of the scheduling pool of the job, the executor slots are shared between the pools with running jobs in proportion to the weights the scheduler sets to them".to_string(),
+                             DataType::Utf8, Some("default".to_string())),
+            ConfigEntry::new(KAPOT_JOB_PRIORITY.to_string(),
+                             "Sets the priority of the job within its scheduling pool, jobs with a higher priority are scheduled first and jobs with the same priority in submission order".to_string(),
+                             DataType::UInt64, Some("0".to_string())),
+            ConfigEntry::new(KAPOT_JOB_TENANT.to_string(),
+                             "Sets the tenant the scheduler quotas of the job are accounted to, the session of the job when empty".to_string(),
+                             DataType::Utf8, Some("".to_string())),
+            ConfigEntry::new(KAPOT_JOB_TIMEOUT.to_string(),
+                             "Sets the time in seconds after its submission a job is cancelled and failed with a timeout error, 0 for no timeout".to_string(),
+                             DataType::UInt64, Some("0".to_string())),
+            ConfigEntry::new(KAPOT_JOB_DEADLINE.to_string(),
+                             "Sets the absolute deadline in milliseconds since the Unix epoch after which the jobs submitted by the client are cancelled and failed with a timeout error, 0 for no deadline".to_string(),
+                             DataType::UInt64, Some("0".to_string())),
+            ConfigEntry::new(KAPOT_JOB_GANG_SCHEDULING_ENABLED.to_string(),
+                             "When set to true, all the stages of the job are launched at once when enough slots are available for all of their tasks, and the shuffle output is streamed from the tasks writing it to the tasks reading it, only supported by the push-staged task scheduling policy".to_string(),
+                             DataType::Boolean, Some("false".to_string())),
         ];
         entries
             .iter()
@@ -269,6 +493,134 @@ impl KapotConfig {
         self.get_bool_setting(KAPOT_WITH_INFORMATION_SCHEMA)
     }
 
+    pub fn shuffle_sort_based_enabled(&self) -> bool {
+        self.get_bool_setting(KAPOT_SHUFFLE_SORT_BASED_ENABLED)
+    }
+
+    pub fn shuffle_range_partitioning_enabled(&self) -> bool {
+        self.get_bool_setting(KAPOT_SHUFFLE_RANGE_PARTITIONING_ENABLED)
+    }
+
+    pub fn shuffle_range_partitioning_sample_size(&self) -> usize {
+        self.get_usize_setting(KAPOT_SHUFFLE_RANGE_PARTITIONING_SAMPLE_SIZE)
+    }
+
+    pub fn shuffle_writer_buffer_size(&self) -> usize {
+        self.get_usize_setting(KAPOT_SHUFFLE_WRITER_BUFFER_SIZE)
+    }
+
+    pub fn shuffle_compression(&self) -> ShuffleCompression {
+        // infallible because we validate all configs in the constructor
+        self.get_string_setting(KAPOT_SHUFFLE_COMPRESSION)
+            .parse()
+            .unwrap()
+    }
+
+    pub fn shuffle_remote_storage_url(&self) -> Option<String> {
+        Some(self.get_string_setting(KAPOT_SHUFFLE_REMOTE_STORAGE_URL))
+            .filter(|url| !url.is_empty())
+    }
+
+    pub fn shuffle_reader_max_requests(&self) -> usize {
+        self.get_usize_setting(KAPOT_SHUFFLE_READER_MAX_REQUESTS)
+    }
+
+    pub fn shuffle_reader_max_bytes_in_flight(&self) -> usize {
+        self.get_usize_setting(KAPOT_SHUFFLE_READER_MAX_BYTES_IN_FLIGHT)
+    }
+
+    pub fn adaptive_coalesce_partitions_enabled(&self) -> bool {
+        self.get_bool_setting(KAPOT_ADAPTIVE_COALESCE_PARTITIONS_ENABLED)
+    }
+
+    pub fn adaptive_advisory_partition_size(&self) -> usize {
+        self.get_usize_setting(KAPOT_ADAPTIVE_ADVISORY_PARTITION_SIZE)
+    }
+
+    pub fn adaptive_skew_join_enabled(&self) -> bool {
+        self.get_bool_setting(KAPOT_ADAPTIVE_SKEW_JOIN_ENABLED)
+    }
+
+    pub fn adaptive_skew_join_skewed_partition_factor(&self) -> usize {
+        self.get_usize_setting(KAPOT_ADAPTIVE_SKEW_JOIN_SKEWED_PARTITION_FACTOR)
+    }
+
+    pub fn adaptive_skew_join_skewed_partition_threshold(&self) -> usize {
+        self.get_usize_setting(KAPOT_ADAPTIVE_SKEW_JOIN_SKEWED_PARTITION_THRESHOLD)
+    }
+
+    pub fn adaptive_broadcast_join_enabled(&self) -> bool {
+        self.get_bool_setting(KAPOT_ADAPTIVE_BROADCAST_JOIN_ENABLED)
+    }
+
+    pub fn speculation_enabled(&self) -> bool {
+        self.get_bool_setting(KAPOT_SPECULATION_ENABLED)
+    }
+
+    pub fn speculation_multiplier(&self) -> f64 {
+        self.get_f64_setting(KAPOT_SPECULATION_MULTIPLIER)
+    }
+
+    pub fn speculation_quantile(&self) -> f64 {
+        self.get_f64_setting(KAPOT_SPECULATION_QUANTILE)
+    }
+
+    pub fn speculation_min_task_runtime_ms(&self) -> u64 {
+        self.get_usize_setting(KAPOT_SPECULATION_MIN_TASK_RUNTIME_MS) as u64
+    }
+
+    pub fn task_max_failures(&self) -> usize {
+        self.get_usize_setting(KAPOT_TASK_MAX_FAILURES)
+    }
+
+    pub fn stage_max_failures(&self) -> usize {
+        self.get_usize_setting(KAPOT_STAGE_MAX_FAILURES)
+    }
+
+    pub fn task_retry_backoff_ms(&self) -> u64 {
+        self.get_usize_setting(KAPOT_TASK_RETRY_BACKOFF_MS) as u64
+    }
+
+    pub fn task_retry_max_backoff_ms(&self) -> u64 {
+        self.get_usize_setting(KAPOT_TASK_RETRY_MAX_BACKOFF_MS) as u64
+    }
+
+    /// The patterns of the error messages of task failures which are not retried
+    pub fn task_non_retryable_errors(&self) -> Vec<String> {
+        self.get_string_setting(KAPOT_TASK_NON_RETRYABLE_ERRORS)
+            .split(',')
+            .map(|pattern| pattern.trim())
+            .filter(|pattern| !pattern.is_empty())
+            .map(|pattern| pattern.to_string())
+            .collect()
+    }
+
+    pub fn job_pool(&self) -> String {
+        self.get_string_setting(KAPOT_JOB_POOL)
+    }
+
+    pub fn job_priority(&self) -> u64 {
+        self.get_usize_setting(KAPOT_JOB_PRIORITY) as u64
+    }
+
+    /// The tenant of the job, if it is not the session of the job
+    pub fn job_tenant(&self) -> Option<String> {
+        let tenant = self.get_string_setting(KAPOT_JOB_TENANT);
+        (!tenant.is_empty()).then_some(tenant)
+    }
+
+    pub fn job_timeout_seconds(&self) -> u64 {
+        self.get_usize_setting(KAPOT_JOB_TIMEOUT) as u64
+    }
+
+    pub fn job_deadline_ms(&self) -> u64 {
+        self.get_usize_setting(KAPOT_JOB_DEADLINE) as u64
+    }
+
+    pub fn gang_scheduling_enabled(&self) -> bool {
+        self.get_bool_setting(KAPOT_JOB_GANG_SCHEDULING_ENABLED)
+    }
+
     fn get_usize_setting(&self, key: &str) -> usize {
         if let Some(v) = self.settings.get(key) {
             // infallible because we validate all configs in the constructor
@@ -292,6 +644,18 @@ impl KapotConfig {
             v.parse::<bool>().unwrap()
         }
     }
+
+    fn get_f64_setting(&self, key: &str) -> f64 {
+        if let Some(v) = self.settings.get(key) {
+            // infallible because we validate all configs in the constructor
+            v.parse::<f64>().unwrap()
+        } else {
+            let entries = Self::valid_entries();
+            // infallible because we validate all configs in the constructor
+            let v = entries.get(key).unwrap().default_value.as_ref().unwrap();
+            v.parse::<f64>().unwrap()
+        }
+    }
     fn get_string_setting(&self, key: &str) -> String {
         if let Some(v) = self.settings.get(key) {
             // infallible because we validate all configs in the constructor
@@ -327,6 +691,23 @@ impl parse_arg::ParseArgFromStr for TaskSchedulingPolicy {
     }
 }
 
+/// Compression codec of shuffle files and Flight transfers of shuffle partitions
+#[derive(Clone, ValueEnum, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShuffleCompression {
+    None,
+    #[default]
+    Lz4,
+    Zstd,
+}
+
+impl std::str::FromStr for ShuffleCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        ValueEnum::from_str(s, true)
+    }
+}
+
 // an enum used to configure the log rolling policy
 // needs to be visible to code generated by configure_me
 #[derive(Clone, ValueEnum, Copy, Debug, serde::Deserialize)]
@@ -394,10 +775,77 @@ mod tests {
                 KAPOT_GRPC_CLIENT_MAX_MESSAGE_SIZE,
                 (8 * 1024 * 1024).to_string().as_str(),
             )
+            .set(KAPOT_SHUFFLE_SORT_BASED_ENABLED, "true")
+            .set(KAPOT_SHUFFLE_RANGE_PARTITIONING_ENABLED, "true")
+            .set(KAPOT_SHUFFLE_RANGE_PARTITIONING_SAMPLE_SIZE, "500")
+            .set(KAPOT_SHUFFLE_WRITER_BUFFER_SIZE, "1048576")
+            .set(KAPOT_SHUFFLE_COMPRESSION, "zstd")
+            .set(KAPOT_SHUFFLE_REMOTE_STORAGE_URL, "s3://bucket/shuffle")
+            .set(KAPOT_SHUFFLE_READER_MAX_REQUESTS, "8")
+            .set(KAPOT_SHUFFLE_READER_MAX_BYTES_IN_FLIGHT, "0")
+            .set(KAPOT_ADAPTIVE_COALESCE_PARTITIONS_ENABLED, "true")
+            .set(KAPOT_ADAPTIVE_ADVISORY_PARTITION_SIZE, "1048576")
+            .set(KAPOT_ADAPTIVE_SKEW_JOIN_ENABLED, "true")
+            .set(KAPOT_ADAPTIVE_SKEW_JOIN_SKEWED_PARTITION_FACTOR, "10")
+            .set(KAPOT_ADAPTIVE_SKEW_JOIN_SKEWED_PARTITION_THRESHOLD, "4194304")
+            .set(KAPOT_ADAPTIVE_BROADCAST_JOIN_ENABLED, "true")
+            .set(KAPOT_SPECULATION_ENABLED, "true")
+            .set(KAPOT_SPECULATION_MULTIPLIER, "2.5")
+            .set(KAPOT_SPECULATION_QUANTILE, "0.5")
+            .set(KAPOT_SPECULATION_MIN_TASK_RUNTIME_MS, "100")
+            .set(KAPOT_TASK_MAX_FAILURES, "1")
+            .set(KAPOT_STAGE_MAX_FAILURES, "2")
+            .set(KAPOT_TASK_RETRY_BACKOFF_MS, "500")
+            .set(KAPOT_TASK_RETRY_MAX_BACKOFF_MS, "4000")
+            .set(
+                KAPOT_TASK_NON_RETRYABLE_ERRORS,
+                "Cast error, ,Permission denied",
+            )
+            .set(KAPOT_JOB_POOL, "dashboards")
+            .set(KAPOT_JOB_PRIORITY, "10")
+            .set(KAPOT_JOB_TENANT, "analytics")
+            .set(KAPOT_JOB_TIMEOUT, "3600")
+            .set(KAPOT_JOB_DEADLINE, "1700000000000")
+            .set(KAPOT_JOB_GANG_SCHEDULING_ENABLED, "true")
             .build()?;
         assert_eq!(123, config.default_shuffle_partitions());
         assert!(config.default_with_information_schema());
         assert_eq!(8388608, config.default_grpc_client_max_message_size());
+        assert!(config.shuffle_sort_based_enabled());
+        assert!(config.shuffle_range_partitioning_enabled());
+        assert_eq!(500, config.shuffle_range_partitioning_sample_size());
+        assert_eq!(1048576, config.shuffle_writer_buffer_size());
+        assert_eq!(ShuffleCompression::Zstd, config.shuffle_compression());
+        assert_eq!(
+            Some("s3://bucket/shuffle".to_string()),
+            config.shuffle_remote_storage_url()
+        );
+        assert_eq!(8, config.shuffle_reader_max_requests());
+        assert_eq!(0, config.shuffle_reader_max_bytes_in_flight());
+        assert!(config.adaptive_coalesce_partitions_enabled());
+        assert_eq!(1048576, config.adaptive_advisory_partition_size());
+        assert!(config.adaptive_skew_join_enabled());
+        assert_eq!(10, config.adaptive_skew_join_skewed_partition_factor());
+        assert_eq!(4194304, config.adaptive_skew_join_skewed_partition_threshold());
+        assert!(config.adaptive_broadcast_join_enabled());
+        assert!(config.speculation_enabled());
+        assert_eq!(2.5, config.speculation_multiplier());
+        assert_eq!(0.5, config.speculation_quantile());
+        assert_eq!(100, config.speculation_min_task_runtime_ms());
+        assert_eq!(1, config.task_max_failures());
+        assert_eq!(2, config.stage_max_failures());
+        assert_eq!(500, config.task_retry_backoff_ms());
+        assert_eq!(4000, config.task_retry_max_backoff_ms());
+        assert_eq!(
+            vec!["Cast error".to_string(), "Permission denied".to_string()],
+            config.task_non_retryable_errors()
+        );
+        assert_eq!("dashboards", config.job_pool());
+        assert_eq!(10, config.job_priority());
+        assert_eq!(Some("analytics".to_string()), config.job_tenant());
+        assert_eq!(3600, config.job_timeout_seconds());
+        assert_eq!(1700000000000, config.job_deadline_ms());
+        assert!(config.gang_scheduling_enabled());
         Ok(())
     }
 
@@ -415,6 +863,51 @@ mod tests {
             .build();
         assert!(config.is_err());
         assert_eq!("General(\"Failed to parse user-supplied value 'kapot.with_information_schema' for configuration setting '123': ParseBoolError\")", format!("{:?}", config.unwrap_err()));
+
+        let config = KapotConfig::builder()
+            .set(KAPOT_SHUFFLE_COMPRESSION, "gzip")
+            .build();
+        assert!(config.is_err());
+
+        let config = KapotConfig::builder()
+            .set(KAPOT_SHUFFLE_REMOTE_STORAGE_URL, "not a url")
+            .build();
+        assert!(config.is_err());
+
+        let config = KapotConfig::builder()
+            .set(KAPOT_SHUFFLE_READER_MAX_REQUESTS, "0")
+            .build();
+        assert!(config.is_err());
+
+        let config = KapotConfig::builder()
+            .set(KAPOT_ADAPTIVE_ADVISORY_PARTITION_SIZE, "0")
+            .build();
+        assert!(config.is_err());
+
+        let config = KapotConfig::builder()
+            .set(KAPOT_ADAPTIVE_SKEW_JOIN_SKEWED_PARTITION_FACTOR, "0")
+            .build();
+        assert!(config.is_err());
+
+        let config = KapotConfig::builder()
+            .set(KAPOT_SPECULATION_MULTIPLIER, "0.5")
+            .build();
+        assert!(config.is_err());
+
+        let config = KapotConfig::builder()
+            .set(KAPOT_SPECULATION_QUANTILE, "1.5")
+            .build();
+        assert!(config.is_err());
+
+        let config = KapotConfig::builder()
+            .set(KAPOT_TASK_MAX_FAILURES, "0")
+            .build();
+        assert!(config.is_err());
+
+        let config = KapotConfig::builder()
+            .set(KAPOT_STAGE_MAX_FAILURES, "0")
+            .build();
+        assert!(config.is_err());
         Ok(())
     }
 }
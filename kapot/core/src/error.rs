@@ -55,6 +55,8 @@ pub enum KapotError {
     // (executor_id, map_stage_id, map_partition_id, message)
     FetchFailed(String, usize, usize, String),
     Cancelled,
+    /// A job is rejected because its tenant exceeds one of the scheduler quotas
+    QuotaExceeded(String),
 }
 
 #[allow(clippy::from_over_into)]
@@ -221,6 +223,7 @@ impl Display for KapotError {
                 )
             }
             KapotError::Cancelled => write!(f, "Task cancelled"),
+            KapotError::QuotaExceeded(desc) => write!(f, "Quota exceeded: {desc}"),
         }
     }
 }
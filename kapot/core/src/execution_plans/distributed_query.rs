@@ -16,7 +16,7 @@
 // under the License.
 
 use crate::client::KapotClient;
-use crate::config::KapotConfig;
+use crate::config::{KapotConfig, ShuffleCompression};
 use crate::serde::protobuf::execute_query_params::OptionalSessionId;
 use crate::serde::protobuf::{
     execute_query_params::Query, execute_query_result, job_status,
@@ -226,6 +226,7 @@ impl<T: 'static + AsLogicalPlan> ExecutionPlan for DistributedQueryExec<T> {
             optional_session_id: Some(OptionalSessionId::SessionId(
                 self.session_id.clone(),
             )),
+            deadline: self.config.job_deadline_ms(),
         };
 
         let stream = futures::stream::once(
@@ -234,6 +235,7 @@ impl<T: 'static + AsLogicalPlan> ExecutionPlan for DistributedQueryExec<T> {
                 self.session_id.clone(),
                 query,
                 self.config.default_grpc_client_max_message_size(),
+                self.config.shuffle_compression(),
             )
             .map_err(|e| ArrowError::ExternalError(Box::new(e))),
         )
@@ -256,6 +258,7 @@ async fn execute_query(
     session_id: String,
     query: ExecuteQueryParams,
     max_message_size: usize,
+    compression: ShuffleCompression,
 ) -> Result<impl Stream<Item = Result<RecordBatch>> + Send> {
     info!("Connecting to kapot scheduler at {}", scheduler_url);
     // TODO reuse the scheduler to avoid connecting to the kapot scheduler again and again
@@ -329,8 +332,8 @@ async fn execute_query(
                 break Err(DataFusionError::Execution(msg));
             }
             Some(job_status::Status::Successful(successful)) => {
-                let streams = successful.partition_location.into_iter().map(|p| {
-                    let f = fetch_partition(p)
+                let streams = successful.partition_location.into_iter().map(move |p| {
+                    let f = fetch_partition(p, compression)
                         .map_err(|e| ArrowError::ExternalError(Box::new(e)));
 
                     futures::stream::once(f).try_flatten()
@@ -344,6 +347,7 @@ async fn execute_query(
 
 async fn fetch_partition(
     location: PartitionLocation,
+    compression: ShuffleCompression,
 ) -> Result<SendableRecordBatchStream> {
     let metadata = location.executor_meta.ok_or_else(|| {
         DataFusionError::Internal("Received empty executor metadata".to_owned())
@@ -361,6 +365,9 @@ async fn fetch_partition(
             &metadata.id,
             &partition_id.into(),
             &location.path,
+            location.range.map(|r| r.into()),
+            compression,
+            location.checksum,
             host,
             port,
         )
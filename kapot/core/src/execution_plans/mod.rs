@@ -19,11 +19,20 @@
 //! several kapot executors.
 
 mod distributed_query;
+mod range_partitioner;
+mod range_sample;
+mod shuffle_buffer;
 mod shuffle_reader;
+mod shuffle_stream;
 mod shuffle_writer;
 mod unresolved_shuffle;
 
 pub use distributed_query::DistributedQueryExec;
+pub use range_partitioner::{sort_key_schema, RangePartitioner, RangePartitioning};
+pub use range_sample::RangeSampleExec;
 pub use shuffle_reader::ShuffleReaderExec;
-pub use shuffle_writer::ShuffleWriterExec;
+pub use shuffle_stream::{ShuffleStreamKey, ShuffleStreams};
+pub use shuffle_writer::{
+    remove_remote_shuffle_data, ShuffleWriterExec, ShuffleWriterTaskId,
+};
 pub use unresolved_shuffle::UnresolvedShuffleExec;
@@ -0,0 +1,313 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Range partitioning of shuffle output. The bounds of the output partitions are computed
+//! at runtime from a sample of the shuffle input, so that a global sort can be executed in
+//! parallel with every output partition holding a contiguous range of the sort keys.
+
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, UInt32Array};
+use datafusion::arrow::compute::take_record_batch;
+use datafusion::arrow::datatypes::{Field, Schema, SchemaRef};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::arrow::row::{OwnedRow, RowConverter, SortField};
+use datafusion::error::Result;
+use datafusion::physical_expr::LexOrdering;
+use datafusion::physical_plan::metrics;
+use datafusion::physical_plan::ExecutionPlan;
+
+/// Range partitioning of the shuffle output on a list of sort expressions.
+///
+/// The `samples` plan produces the sort keys sampled from the shuffle input, see
+/// [`RangeSampleExec`](super::RangeSampleExec). Every map task reads all of the samples, so
+/// all map tasks compute the same partition bounds.
+#[derive(Debug, Clone)]
+pub struct RangePartitioning {
+    sort_exprs: LexOrdering,
+    partition_count: usize,
+    samples: Arc<dyn ExecutionPlan>,
+}
+
+impl RangePartitioning {
+    /// Create a new range partitioning
+    pub fn new(
+        sort_exprs: LexOrdering,
+        partition_count: usize,
+        samples: Arc<dyn ExecutionPlan>,
+    ) -> Self {
+        Self {
+            sort_exprs,
+            partition_count,
+            samples,
+        }
+    }
+
+    /// Sort expressions the output is partitioned on
+    pub fn sort_exprs(&self) -> &LexOrdering {
+        &self.sort_exprs
+    }
+
+    /// Number of output partitions
+    pub fn partition_count(&self) -> usize {
+        self.partition_count
+    }
+
+    /// Plan producing the sampled sort keys used to compute the partition bounds
+    pub fn samples(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.samples
+    }
+
+    /// Return a copy of this partitioning reading its samples from another plan
+    pub fn with_samples(&self, samples: Arc<dyn ExecutionPlan>) -> Self {
+        Self {
+            sort_exprs: self.sort_exprs.clone(),
+            partition_count: self.partition_count,
+            samples,
+        }
+    }
+}
+
+/// Schema of the sort keys sampled for the given sort expressions
+pub fn sort_key_schema(
+    sort_exprs: &LexOrdering,
+    input_schema: &Schema,
+) -> Result<SchemaRef> {
+    let fields = sort_exprs
+        .iter()
+        .enumerate()
+        .map(|(i, sort_expr)| {
+            Ok(Field::new(
+                format!("key_{i}"),
+                sort_expr.expr.data_type(input_schema)?,
+                true,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+/// Create a row converter which encodes the sort keys in their sort order
+pub(crate) fn sort_key_converter(
+    sort_exprs: &LexOrdering,
+    input_schema: &Schema,
+) -> Result<RowConverter> {
+    let sort_fields = sort_exprs
+        .iter()
+        .map(|sort_expr| {
+            Ok(SortField::new_with_options(
+                sort_expr.expr.data_type(input_schema)?,
+                sort_expr.options,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(RowConverter::new(sort_fields)?)
+}
+
+/// Evaluate the sort expressions against a batch
+pub(crate) fn evaluate_sort_keys(
+    sort_exprs: &LexOrdering,
+    batch: &RecordBatch,
+) -> Result<Vec<ArrayRef>> {
+    sort_exprs
+        .iter()
+        .map(|sort_expr| sort_expr.expr.evaluate(batch)?.into_array(batch.num_rows()))
+        .collect()
+}
+
+/// Splits record batches into output partitions according to the bounds computed from the
+/// sampled sort keys.
+pub struct RangePartitioner {
+    sort_exprs: LexOrdering,
+    converter: RowConverter,
+    /// Lower bounds of the output partitions `1..partition_count`, in ascending order
+    bounds: Vec<OwnedRow>,
+    timer: metrics::Time,
+}
+
+impl RangePartitioner {
+    /// Create a partitioner whose bounds split the `samples` into `partition_count` ranges
+    /// of roughly the same size
+    pub fn try_new(
+        sort_exprs: LexOrdering,
+        partition_count: usize,
+        input_schema: &Schema,
+        samples: &[RecordBatch],
+        timer: metrics::Time,
+    ) -> Result<Self> {
+        let converter = sort_key_converter(&sort_exprs, input_schema)?;
+
+        let mut sampled_rows = vec![];
+        for batch in samples {
+            let rows = converter.convert_columns(batch.columns())?;
+            sampled_rows.extend(rows.iter().map(|row| row.owned()));
+        }
+        sampled_rows.sort();
+
+        let mut bounds = vec![];
+        if !sampled_rows.is_empty() {
+            for i in 1..partition_count {
+                bounds
+                    .push(sampled_rows[i * sampled_rows.len() / partition_count].clone());
+            }
+        }
+
+        Ok(Self {
+            sort_exprs,
+            converter,
+            bounds,
+            timer,
+        })
+    }
+
+    /// Partition the given batch, calling `f` with every non-empty output batch
+    pub fn partition<F>(&mut self, batch: RecordBatch, mut f: F) -> Result<()>
+    where
+        F: FnMut(usize, RecordBatch) -> Result<()>,
+    {
+        let timer = self.timer.timer();
+        let num_partitions = self.bounds.len() + 1;
+        let keys = evaluate_sort_keys(&self.sort_exprs, &batch)?;
+        let rows = self.converter.convert_columns(&keys)?;
+
+        let mut indices: Vec<Vec<u32>> = vec![vec![]; num_partitions];
+        for (i, row) in rows.iter().enumerate() {
+            let partition = self.bounds.partition_point(|bound| bound.row() <= row);
+            indices[partition].push(i as u32);
+        }
+
+        let mut output_batches = vec![];
+        for (partition, indices) in indices.into_iter().enumerate() {
+            if !indices.is_empty() {
+                let indices = UInt32Array::from(indices);
+                output_batches.push((partition, take_record_batch(&batch, &indices)?));
+            }
+        }
+        timer.done();
+
+        for (partition, output_batch) in output_batches {
+            f(partition, output_batch)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::{Array, Int32Array};
+    use datafusion::arrow::compute::SortOptions;
+    use datafusion::arrow::datatypes::DataType;
+    use datafusion::physical_plan::expressions::{col, PhysicalSortExpr};
+
+    fn int_batch(values: Vec<i32>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, true)]));
+        RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from(values))]).unwrap()
+    }
+
+    fn partition_values(
+        partitioner: &mut RangePartitioner,
+        batch: RecordBatch,
+    ) -> Vec<Vec<i32>> {
+        let mut partitions = vec![vec![]; partitioner.bounds.len() + 1];
+        partitioner
+            .partition(batch, |partition, output_batch| {
+                let values = output_batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap();
+                partitions[partition].extend(values.iter().map(|v| v.unwrap()));
+                Ok(())
+            })
+            .unwrap();
+        partitions
+    }
+
+    #[test]
+    fn test_range_partitioner() -> Result<()> {
+        let batch = int_batch(vec![7, 3, 9, 1, 5]);
+        let schema = batch.schema();
+        let sort_exprs = LexOrdering::new(vec![PhysicalSortExpr {
+            expr: col("a", &schema)?,
+            options: SortOptions::default(),
+        }]);
+
+        let samples = vec![int_batch((0..100).collect())];
+        let mut partitioner = RangePartitioner::try_new(
+            sort_exprs,
+            4,
+            &schema,
+            &samples,
+            metrics::Time::new(),
+        )?;
+        assert_eq!(3, partitioner.bounds.len());
+
+        let partitions =
+            partition_values(&mut partitioner, int_batch((0..100).collect()));
+        assert_eq!(4, partitions.len());
+        for (i, values) in partitions.iter().enumerate() {
+            assert_eq!(25, values.len());
+            let expected = (i as i32 * 25..(i as i32 + 1) * 25).collect::<Vec<_>>();
+            assert_eq!(&expected, values);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_range_partitioner_descending() -> Result<()> {
+        let batch = int_batch(vec![1, 2, 3, 4]);
+        let schema = batch.schema();
+        let sort_exprs = LexOrdering::new(vec![PhysicalSortExpr {
+            expr: col("a", &schema)?,
+            options: SortOptions {
+                descending: true,
+                nulls_first: true,
+            },
+        }]);
+
+        let samples = vec![int_batch(vec![1, 2, 3, 4])];
+        let mut partitioner = RangePartitioner::try_new(
+            sort_exprs,
+            2,
+            &schema,
+            &samples,
+            metrics::Time::new(),
+        )?;
+
+        let partitions = partition_values(&mut partitioner, batch);
+        assert_eq!(vec![vec![3, 4], vec![1, 2]], partitions);
+        Ok(())
+    }
+
+    #[test]
+    fn test_range_partitioner_without_samples() -> Result<()> {
+        let batch = int_batch(vec![1, 2, 3]);
+        let schema = batch.schema();
+        let sort_exprs = LexOrdering::new(vec![PhysicalSortExpr {
+            expr: col("a", &schema)?,
+            options: SortOptions::default(),
+        }]);
+
+        let mut partitioner =
+            RangePartitioner::try_new(sort_exprs, 4, &schema, &[], metrics::Time::new())?;
+
+        let partitions = partition_values(&mut partitioner, batch);
+        assert_eq!(vec![vec![1, 2, 3]], partitions);
+        Ok(())
+    }
+}
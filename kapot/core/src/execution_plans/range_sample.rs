@@ -0,0 +1,255 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::arrow::row::OwnedRow;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::TaskContext;
+use datafusion::physical_expr::{EquivalenceProperties, LexOrdering};
+use datafusion::physical_plan::execution_plan::{Boundedness, EmissionType};
+use datafusion::physical_plan::metrics::{
+    ExecutionPlanMetricsSet, MetricBuilder, MetricsSet,
+};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::{
+    DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, PlanProperties,
+    SendableRecordBatchStream, Statistics,
+};
+use futures::StreamExt;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::execution_plans::range_partitioner::{
+    evaluate_sort_keys, sort_key_converter, sort_key_schema,
+};
+
+/// RangeSampleExec draws a uniform random sample of the sort keys of each of its input
+/// partitions. The samples are used by the map tasks of a range partitioned shuffle to
+/// compute the bounds of the output partitions.
+#[derive(Debug, Clone)]
+pub struct RangeSampleExec {
+    input: Arc<dyn ExecutionPlan>,
+    sort_exprs: LexOrdering,
+    /// Maximum number of rows sampled from each input partition
+    sample_size: usize,
+    schema: SchemaRef,
+    metrics: ExecutionPlanMetricsSet,
+    properties: PlanProperties,
+}
+
+impl RangeSampleExec {
+    /// Create a new RangeSampleExec
+    pub fn try_new(
+        input: Arc<dyn ExecutionPlan>,
+        sort_exprs: LexOrdering,
+        sample_size: usize,
+    ) -> Result<Self> {
+        let schema = sort_key_schema(&sort_exprs, &input.schema())?;
+        let properties = PlanProperties::new(
+            EquivalenceProperties::new(schema.clone()),
+            Partitioning::UnknownPartitioning(
+                input.properties().output_partitioning().partition_count(),
+            ),
+            EmissionType::Final,
+            Boundedness::Bounded,
+        );
+        Ok(Self {
+            input,
+            sort_exprs,
+            sample_size,
+            schema,
+            metrics: ExecutionPlanMetricsSet::new(),
+            properties,
+        })
+    }
+
+    /// Sort expressions whose values are sampled
+    pub fn sort_exprs(&self) -> &LexOrdering {
+        &self.sort_exprs
+    }
+
+    /// Maximum number of rows sampled from each input partition
+    pub fn sample_size(&self) -> usize {
+        self.sample_size
+    }
+}
+
+impl DisplayAs for RangeSampleExec {
+    fn fmt_as(
+        &self,
+        t: DisplayFormatType,
+        f: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        match t {
+            DisplayFormatType::Default | DisplayFormatType::Verbose => {
+                write!(
+                    f,
+                    "RangeSampleExec: expr=[{}], sample_size={}",
+                    self.sort_exprs, self.sample_size
+                )
+            }
+        }
+    }
+}
+
+impl ExecutionPlan for RangeSampleExec {
+    fn name(&self) -> &str {
+        "RangeSampleExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        &self.properties
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(RangeSampleExec::try_new(
+            children[0].clone(),
+            self.sort_exprs.clone(),
+            self.sample_size,
+        )?))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let mut stream = self.input.execute(partition, context)?;
+        let converter = sort_key_converter(&self.sort_exprs, &self.input.schema())?;
+        let sort_exprs = self.sort_exprs.clone();
+        let sample_size = self.sample_size;
+        let schema = self.schema.clone();
+        let output_rows = MetricBuilder::new(&self.metrics).output_rows(partition);
+
+        let fut = async move {
+            // reservoir sampling of the encoded sort keys
+            let mut reservoir: Vec<OwnedRow> = Vec::with_capacity(sample_size);
+            let mut num_rows = 0;
+            let mut rng = StdRng::from_entropy();
+            while let Some(batch) = stream.next().await {
+                let batch = batch?;
+                let keys = evaluate_sort_keys(&sort_exprs, &batch)?;
+                let rows = converter.convert_columns(&keys)?;
+                for row in rows.iter() {
+                    num_rows += 1;
+                    if reservoir.len() < sample_size {
+                        reservoir.push(row.owned());
+                    } else {
+                        let i = rng.gen_range(0..num_rows);
+                        if i < sample_size {
+                            reservoir[i] = row.owned();
+                        }
+                    }
+                }
+            }
+
+            let columns =
+                converter.convert_rows(reservoir.iter().map(|row| row.row()))?;
+            output_rows.add(reservoir.len());
+            Ok::<_, DataFusionError>(RecordBatch::try_new(schema, columns)?)
+        };
+
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            self.schema.clone(),
+            futures::stream::once(fut),
+        )))
+    }
+
+    fn metrics(&self) -> Option<MetricsSet> {
+        Some(self.metrics.clone_inner())
+    }
+
+    fn statistics(&self) -> Result<Statistics> {
+        Ok(Statistics::new_unknown(&self.schema()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::Int32Array;
+    use datafusion::arrow::compute::SortOptions;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::physical_plan::common;
+    use datafusion::physical_plan::expressions::{col, PhysicalSortExpr};
+    use datafusion::physical_plan::memory::MemoryExec;
+    use datafusion::prelude::SessionContext;
+
+    #[tokio::test]
+    async fn test_range_sample() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, false),
+            Field::new("b", DataType::Int32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from((0..100).collect::<Vec<_>>())),
+                Arc::new(Int32Array::from((100..200).collect::<Vec<_>>())),
+            ],
+        )?;
+        let input = Arc::new(MemoryExec::try_new(
+            &[vec![batch.clone()], vec![batch.slice(0, 5)]],
+            schema.clone(),
+            None,
+        )?);
+        let sort_exprs = LexOrdering::new(vec![PhysicalSortExpr {
+            expr: col("b", &schema)?,
+            options: SortOptions::default(),
+        }]);
+
+        let sample = RangeSampleExec::try_new(input, sort_exprs, 10)?;
+        assert_eq!(
+            2,
+            sample.properties().output_partitioning().partition_count()
+        );
+        assert_eq!(1, sample.schema().fields().len());
+
+        let task_ctx = SessionContext::new().task_ctx();
+        let batches = common::collect(sample.execute(0, task_ctx.clone())?).await?;
+        assert_eq!(10, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        let values = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        assert!(values.iter().all(|v| (100..200).contains(&v.unwrap())));
+
+        let batches = common::collect(sample.execute(1, task_ctx)?).await?;
+        assert_eq!(5, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        Ok(())
+    }
+}
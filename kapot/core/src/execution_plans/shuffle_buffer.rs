@@ -0,0 +1,222 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Memory-bounded buffering of the output partitions of a shuffle map task.
+
+use std::mem;
+
+use datafusion::arrow::compute::concat_batches;
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::error::Result;
+use datafusion::execution::memory_pool::{MemoryConsumer, MemoryReservation};
+use datafusion::execution::TaskContext;
+
+/// Buffers the output partitions of a shuffle map task and coalesces the small batches
+/// produced by the partitioner into batches of at least `batch_size` rows.
+///
+/// The buffered batches are accounted against the memory pool of the task and limited to
+/// `memory_limit` bytes. When a batch doesn't fit, the writer has to spill the buffered
+/// partitions.
+pub(crate) struct ShuffleBuffer {
+    schema: SchemaRef,
+    partitions: Vec<PartitionBuffer>,
+    batch_size: usize,
+    memory_limit: usize,
+    reservation: MemoryReservation,
+}
+
+#[derive(Default)]
+struct PartitionBuffer {
+    /// Batches with at least `batch_size` rows
+    coalesced: Vec<RecordBatch>,
+    coalesced_size: usize,
+    /// Batches still to be coalesced
+    pending: Vec<RecordBatch>,
+    pending_rows: usize,
+    pending_size: usize,
+}
+
+impl ShuffleBuffer {
+    pub(crate) fn new(
+        schema: SchemaRef,
+        partition_count: usize,
+        batch_size: usize,
+        memory_limit: usize,
+        input_partition: usize,
+        context: &TaskContext,
+    ) -> Self {
+        let reservation =
+            MemoryConsumer::new(format!("ShuffleWriterExec[{input_partition}]"))
+                .with_can_spill(true)
+                .register(context.memory_pool());
+        Self {
+            schema,
+            partitions: (0..partition_count).map(|_| Default::default()).collect(),
+            batch_size: batch_size.max(1),
+            memory_limit,
+            reservation,
+        }
+    }
+
+    /// Number of bytes currently buffered
+    pub(crate) fn mem_size(&self) -> usize {
+        self.reservation.size()
+    }
+
+    /// Buffer a batch of an output partition. The batch is handed back if it exceeds the
+    /// memory budget or the memory pool can't provide the memory for it.
+    pub(crate) fn try_push(
+        &mut self,
+        partition: usize,
+        batch: RecordBatch,
+    ) -> Result<Option<RecordBatch>> {
+        let size = batch.get_array_memory_size();
+        if self.reservation.size() + size > self.memory_limit
+            || self.reservation.try_grow(size).is_err()
+        {
+            return Ok(Some(batch));
+        }
+
+        let buffer = &mut self.partitions[partition];
+        buffer.pending_size += size;
+        buffer.pending_rows += batch.num_rows();
+        buffer.pending.push(batch);
+        if buffer.pending_rows >= self.batch_size {
+            let batch = concat_batches(&self.schema, &mem::take(&mut buffer.pending))?;
+            let coalesced_size = batch.get_array_memory_size();
+            self.reservation.shrink(buffer.pending_size);
+            self.reservation.grow(coalesced_size);
+            buffer.coalesced.push(batch);
+            buffer.coalesced_size += coalesced_size;
+            buffer.pending_rows = 0;
+            buffer.pending_size = 0;
+        }
+        Ok(None)
+    }
+
+    /// Take the batches of a partition which are coalesced to at least `batch_size` rows
+    pub(crate) fn take_coalesced(&mut self, partition: usize) -> Vec<RecordBatch> {
+        let buffer = &mut self.partitions[partition];
+        self.reservation.shrink(buffer.coalesced_size);
+        buffer.coalesced_size = 0;
+        mem::take(&mut buffer.coalesced)
+    }
+
+    /// Take all buffered batches of every output partition and release their memory
+    pub(crate) fn drain(&mut self) -> Result<Vec<Vec<RecordBatch>>> {
+        let partitions = self
+            .partitions
+            .iter_mut()
+            .map(|buffer| {
+                let mut batches = mem::take(&mut buffer.coalesced);
+                if !buffer.pending.is_empty() {
+                    batches.push(concat_batches(
+                        &self.schema,
+                        &mem::take(&mut buffer.pending),
+                    )?);
+                }
+                buffer.coalesced_size = 0;
+                buffer.pending_rows = 0;
+                buffer.pending_size = 0;
+                Ok(batches)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.reservation.free();
+        Ok(partitions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::UInt32Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::execution::memory_pool::{GreedyMemoryPool, MemoryPool};
+    use datafusion::execution::runtime_env::RuntimeEnvBuilder;
+    use datafusion::prelude::SessionContext;
+    use std::sync::Arc;
+
+    fn batch(schema: &SchemaRef, num_rows: u32) -> RecordBatch {
+        RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(UInt32Array::from(
+                (0..num_rows).collect::<Vec<_>>(),
+            ))],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_coalesce() -> Result<()> {
+        let schema =
+            Arc::new(Schema::new(vec![Field::new("a", DataType::UInt32, false)]));
+        let task_ctx = SessionContext::new().task_ctx();
+        let mut buffer =
+            ShuffleBuffer::new(schema.clone(), 2, 10, usize::MAX, 0, &task_ctx);
+
+        for _ in 0..3 {
+            assert!(buffer.try_push(0, batch(&schema, 4))?.is_none());
+        }
+        assert!(buffer.try_push(1, batch(&schema, 4))?.is_none());
+        assert!(buffer.mem_size() > 0);
+
+        let coalesced = buffer.take_coalesced(0);
+        assert_eq!(1, coalesced.len());
+        assert_eq!(12, coalesced[0].num_rows());
+        assert!(buffer.take_coalesced(1).is_empty());
+
+        let partitions = buffer.drain()?;
+        assert!(partitions[0].is_empty());
+        assert_eq!(1, partitions[1].len());
+        assert_eq!(4, partitions[1][0].num_rows());
+        assert_eq!(0, buffer.mem_size());
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_limit() -> Result<()> {
+        let schema =
+            Arc::new(Schema::new(vec![Field::new("a", DataType::UInt32, false)]));
+        let batch_size = batch(&schema, 100).get_array_memory_size();
+
+        // limited by the budget of the writer
+        let task_ctx = SessionContext::new().task_ctx();
+        let mut buffer =
+            ShuffleBuffer::new(schema.clone(), 1, 1000, batch_size, 0, &task_ctx);
+        assert!(buffer.try_push(0, batch(&schema, 100))?.is_none());
+        assert!(buffer.try_push(0, batch(&schema, 100))?.is_some());
+        buffer.drain()?;
+        assert!(buffer.try_push(0, batch(&schema, 100))?.is_none());
+
+        // limited by the memory pool
+        let pool: Arc<dyn MemoryPool> = Arc::new(GreedyMemoryPool::new(batch_size));
+        let runtime = RuntimeEnvBuilder::new()
+            .with_memory_pool(pool.clone())
+            .build_arc()?;
+        let task_ctx =
+            SessionContext::new_with_config_rt(Default::default(), runtime).task_ctx();
+        let mut buffer =
+            ShuffleBuffer::new(schema.clone(), 1, 1000, usize::MAX, 0, &task_ctx);
+        assert!(buffer.try_push(0, batch(&schema, 100))?.is_none());
+        assert_eq!(batch_size, pool.reserved());
+        assert!(buffer.try_push(0, batch(&schema, 100))?.is_some());
+        buffer.drain()?;
+        assert_eq!(0, pool.reserved());
+        Ok(())
+    }
+}
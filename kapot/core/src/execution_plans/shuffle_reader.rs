@@ -16,34 +16,39 @@
 // under the License.
 
 use async_trait::async_trait;
-use datafusion::arrow::ipc::reader::StreamReader;
 use datafusion::common::stats::Precision;
 use datafusion::physical_expr::EquivalenceProperties;
 use datafusion::physical_plan::execution_plan::{Boundedness, EmissionType};
 use std::any::Any;
 use std::collections::HashMap;
 use std::fmt::Debug;
-use std::fs::File;
-use std::io::BufReader;
+use std::io::Read;
 use std::pin::Pin;
 use std::result;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use crate::client::KapotClient;
-use crate::serde::scheduler::{PartitionLocation, PartitionStats};
+use crate::config::{KapotConfig, ShuffleCompression};
+use crate::serde::scheduler::{ByteRange, PartitionLocation, PartitionStats};
+use crate::utils::ShuffleFileReader;
 
 use datafusion::arrow::datatypes::SchemaRef;
 use datafusion::arrow::error::ArrowError;
 use datafusion::arrow::record_batch::RecordBatch;
 use datafusion::common::runtime::SpawnedTask;
 
-use datafusion::error::Result;
-use datafusion::physical_plan::metrics::{ExecutionPlanMetricsSet, MetricsSet};
+use bytes::{Buf, Bytes};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::runtime_env::RuntimeEnv;
+use datafusion::physical_plan::metrics::{
+    self, ExecutionPlanMetricsSet, MetricBuilder, MetricsSet,
+};
 use datafusion::physical_plan::{
     ColumnStatistics, DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning,
     PlanProperties, RecordBatchStream, SendableRecordBatchStream, Statistics,
 };
+use futures::stream::BoxStream;
 use futures::{Stream, StreamExt, TryStreamExt};
 
 use crate::error::KapotError;
@@ -51,10 +56,13 @@ use datafusion::execution::context::TaskContext;
 use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
 use itertools::Itertools;
 use log::{error, info};
+use object_store::{GetOptions, GetRange};
 use rand::prelude::SliceRandom;
 use rand::thread_rng;
-use tokio::sync::{mpsc, Semaphore};
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
 use tokio_stream::wrappers::ReceiverStream;
+use url::Url;
 
 /// ShuffleReaderExec reads partitions that have already been materialized by a ShuffleWriterExec
 /// being executed by an executor
@@ -65,6 +73,16 @@ pub struct ShuffleReaderExec {
     pub(crate) schema: SchemaRef,
     /// Each partition of a shuffle can read data from multiple locations
     pub partition: Vec<Vec<PartitionLocation>>,
+    /// Number of partitions of the shuffle, which differs from the number of partitions
+    /// of the reader when adjacent shuffle partitions are coalesced into one, or when a
+    /// skewed shuffle partition is split into several
+    shuffle_partition_count: usize,
+    /// Compression codec used to transfer remote partitions
+    compression: ShuffleCompression,
+    /// Maximum number of remote partitions fetched concurrently
+    max_requests: usize,
+    /// Maximum number of bytes of remote partitions fetched but not yet read
+    max_bytes_in_flight: usize,
     /// Execution metrics
     metrics: ExecutionPlanMetricsSet,
     properties: PlanProperties,
@@ -85,14 +103,75 @@ impl ShuffleReaderExec {
             EmissionType::Both,
             Boundedness::Bounded,
         );
+        let config = KapotConfig::default();
         Ok(Self {
             stage_id,
             schema,
+            shuffle_partition_count: partition.len(),
             partition,
+            compression: ShuffleCompression::default(),
+            max_requests: config.shuffle_reader_max_requests(),
+            max_bytes_in_flight: config.shuffle_reader_max_bytes_in_flight(),
             metrics: ExecutionPlanMetricsSet::new(),
             properties,
         })
     }
+
+    /// Set the compression codec used to transfer remote partitions, see
+    /// [`KAPOT_SHUFFLE_COMPRESSION`]
+    ///
+    /// [`KAPOT_SHUFFLE_COMPRESSION`]: crate::config::KAPOT_SHUFFLE_COMPRESSION
+    pub fn with_compression(mut self, compression: ShuffleCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Compression codec used to transfer remote partitions
+    pub fn compression(&self) -> ShuffleCompression {
+        self.compression
+    }
+
+    /// Set the maximum number of remote partitions fetched concurrently and of their
+    /// bytes fetched ahead of being read, see [`KAPOT_SHUFFLE_READER_MAX_REQUESTS`] and
+    /// [`KAPOT_SHUFFLE_READER_MAX_BYTES_IN_FLIGHT`]
+    ///
+    /// [`KAPOT_SHUFFLE_READER_MAX_REQUESTS`]: crate::config::KAPOT_SHUFFLE_READER_MAX_REQUESTS
+    /// [`KAPOT_SHUFFLE_READER_MAX_BYTES_IN_FLIGHT`]: crate::config::KAPOT_SHUFFLE_READER_MAX_BYTES_IN_FLIGHT
+    pub fn with_fetch_limits(
+        mut self,
+        max_requests: usize,
+        max_bytes_in_flight: usize,
+    ) -> Self {
+        self.max_requests = max_requests;
+        self.max_bytes_in_flight = max_bytes_in_flight;
+        self
+    }
+
+    /// Set the number of partitions of the shuffle, when the partitions of the reader are
+    /// not the partitions of the shuffle
+    pub fn with_shuffle_partition_count(
+        mut self,
+        shuffle_partition_count: usize,
+    ) -> Self {
+        self.shuffle_partition_count = shuffle_partition_count;
+        self
+    }
+
+    /// Number of partitions of the shuffle read from
+    pub fn shuffle_partition_count(&self) -> usize {
+        self.shuffle_partition_count
+    }
+
+    /// Maximum number of remote partitions fetched concurrently
+    pub fn max_requests(&self) -> usize {
+        self.max_requests
+    }
+
+    /// Maximum number of bytes of remote partitions fetched but not yet read, 0 when
+    /// there is no limit
+    pub fn max_bytes_in_flight(&self) -> usize {
+        self.max_bytes_in_flight
+    }
 }
 
 impl DisplayAs for ShuffleReaderExec {
@@ -103,7 +182,11 @@ impl DisplayAs for ShuffleReaderExec {
     ) -> std::fmt::Result {
         match t {
             DisplayFormatType::Default | DisplayFormatType::Verbose => {
-                write!(f, "ShuffleReaderExec: partitions={}", self.partition.len())
+                write!(f, "ShuffleReaderExec: partitions={}", self.partition.len())?;
+                if self.shuffle_partition_count != self.partition.len() {
+                    write!(f, ", shuffle_partitions={}", self.shuffle_partition_count)?;
+                }
+                Ok(())
             }
         }
     }
@@ -133,11 +216,15 @@ impl ExecutionPlan for ShuffleReaderExec {
         self: Arc<Self>,
         _children: Vec<Arc<dyn ExecutionPlan>>,
     ) -> Result<Arc<dyn ExecutionPlan>> {
-        Ok(Arc::new(ShuffleReaderExec::try_new(
-            self.stage_id,
-            self.partition.clone(),
-            self.schema.clone(),
-        )?))
+        Ok(Arc::new(
+            ShuffleReaderExec::try_new(
+                self.stage_id,
+                self.partition.clone(),
+                self.schema.clone(),
+            )?
+            .with_compression(self.compression)
+            .with_fetch_limits(self.max_requests, self.max_bytes_in_flight),
+        ))
     }
 
     fn execute(
@@ -148,8 +235,6 @@ impl ExecutionPlan for ShuffleReaderExec {
         let task_id = context.task_id().unwrap_or_else(|| partition.to_string());
         info!("ShuffleReaderExec::execute({})", task_id);
 
-        // TODO make the maximum size configurable, or make it depends on global memory control
-        let max_request_num = 50usize;
         let mut partition_locations = HashMap::new();
         for p in &self.partition[partition] {
             partition_locations
@@ -167,8 +252,14 @@ impl ExecutionPlan for ShuffleReaderExec {
         // Shuffle partitions for evenly send fetching partition requests to avoid hot executors within multiple tasks
         partition_locations.shuffle(&mut thread_rng());
 
-        let response_receiver =
-            send_fetch_partitions(partition_locations, max_request_num);
+        let response_receiver = send_fetch_partitions(
+            partition_locations,
+            self.max_requests,
+            self.max_bytes_in_flight,
+            self.compression,
+            context.runtime_env(),
+            ShuffleReadMetrics::new(partition, &self.metrics),
+        );
 
         let result = RecordBatchStreamAdapter::new(
             Arc::new(self.schema.as_ref().clone()),
@@ -216,33 +307,120 @@ fn stats_for_partitions(
     }
 }
 
-struct LocalShuffleStream {
-    reader: StreamReader<BufReader<File>>,
+/// Stream of the batches of a shuffle partition, decoded by a blocking task as the IPC
+/// reader is synchronous
+struct ShuffleFileStream {
+    schema: SchemaRef,
+    batches: BoxStream<'static, Result<RecordBatch>>,
+}
+
+impl ShuffleFileStream {
+    pub fn new<R: Read + Send + 'static>(reader: ShuffleFileReader<R>) -> Self {
+        let schema = reader.schema();
+        let (tx, rx) = mpsc::channel(2);
+        let task = tokio::task::spawn_blocking(move || {
+            for batch in reader {
+                if tx.blocking_send(batch.map_err(Into::into)).is_err() {
+                    return;
+                }
+            }
+        });
+        // the partition is truncated if the task decoding it stopped early, e.g. on a
+        // panic of the IPC decoder on corrupted data
+        let task_error = futures::stream::once(task).filter_map(|result| {
+            let error = result.err().map(|e| {
+                Err(DataFusionError::Execution(format!(
+                    "Failed to read shuffle partition: {e}"
+                )))
+            });
+            futures::future::ready(error)
+        });
+        Self {
+            schema,
+            batches: ReceiverStream::new(rx).chain(task_error).boxed(),
+        }
+    }
+}
+
+impl Stream for ShuffleFileStream {
+    type Item = Result<RecordBatch>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.batches.poll_next_unpin(cx)
+    }
+}
+
+impl RecordBatchStream for ShuffleFileStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ShuffleReadMetrics {
+    /// Time spent waiting for the fetch limits before fetching remote partitions
+    fetch_wait_time: metrics::Time,
+    partition: usize,
+    metrics: ExecutionPlanMetricsSet,
 }
 
-impl LocalShuffleStream {
-    pub fn new(reader: StreamReader<BufReader<File>>) -> Self {
-        LocalShuffleStream { reader }
+impl ShuffleReadMetrics {
+    fn new(partition: usize, metrics: &ExecutionPlanMetricsSet) -> Self {
+        let fetch_wait_time =
+            MetricBuilder::new(metrics).subset_time("fetch_wait_time", partition);
+
+        Self {
+            fetch_wait_time,
+            partition,
+            metrics: metrics.clone(),
+        }
+    }
+
+    /// Bytes of the partitions fetched from a remote executor
+    fn bytes_fetched(&self, executor_id: &str) -> metrics::Count {
+        MetricBuilder::new(&self.metrics)
+            .with_new_label("executor_id", executor_id.to_owned())
+            .counter("bytes_fetched", self.partition)
     }
 }
 
-impl Stream for LocalShuffleStream {
+/// Stream of a fetched remote partition, holding its share of the bytes in flight until
+/// it is read to the end or dropped
+struct InFlightStream {
+    inner: SendableRecordBatchStream,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl InFlightStream {
+    fn new(
+        inner: SendableRecordBatchStream,
+        permit: Option<OwnedSemaphorePermit>,
+    ) -> Self {
+        Self { inner, permit }
+    }
+}
+
+impl Stream for InFlightStream {
     type Item = Result<RecordBatch>;
 
     fn poll_next(
         mut self: Pin<&mut Self>,
-        _: &mut Context<'_>,
+        cx: &mut Context<'_>,
     ) -> Poll<Option<Self::Item>> {
-        if let Some(batch) = self.reader.next() {
-            return Poll::Ready(Some(batch.map_err(|e| e.into())));
+        let poll = self.inner.poll_next_unpin(cx);
+        if let Poll::Ready(None) = poll {
+            self.permit.take();
         }
-        Poll::Ready(None)
+        poll
     }
 }
 
-impl RecordBatchStream for LocalShuffleStream {
+impl RecordBatchStream for InFlightStream {
     fn schema(&self) -> SchemaRef {
-        self.reader.schema()
+        self.inner.schema()
     }
 }
 
@@ -286,9 +464,20 @@ impl Stream for AbortableReceiverStream {
 fn send_fetch_partitions(
     partition_locations: Vec<PartitionLocation>,
     max_request_num: usize,
+    max_bytes_in_flight: usize,
+    compression: ShuffleCompression,
+    runtime: Arc<RuntimeEnv>,
+    metrics: ShuffleReadMetrics,
 ) -> AbortableReceiverStream {
     let (response_sender, response_receiver) = mpsc::channel(max_request_num);
     let semaphore = Arc::new(Semaphore::new(max_request_num));
+    // the bytes of a fetched partition are released once its stream is read, so that
+    // partitions are only fetched ahead of the consumer up to the limit
+    let bytes_semaphore = (max_bytes_in_flight > 0).then(|| {
+        Arc::new(Semaphore::new(
+            max_bytes_in_flight.min(Semaphore::MAX_PERMITS),
+        ))
+    });
     let mut spawned_tasks: Vec<SpawnedTask<()>> = vec![];
     let (local_locations, remote_locations): (Vec<_>, Vec<_>) = partition_locations
         .into_iter()
@@ -311,13 +500,48 @@ fn send_fetch_partitions(
         }
     }));
 
+    // one counter per executor rather than per fetched partition
+    let mut bytes_fetched_by_executor: HashMap<String, metrics::Count> = HashMap::new();
+    for p in &remote_locations {
+        bytes_fetched_by_executor
+            .entry(p.executor_meta.id.clone())
+            .or_insert_with(|| metrics.bytes_fetched(&p.executor_meta.id));
+    }
+
     for p in remote_locations.into_iter() {
         let semaphore = semaphore.clone();
+        let bytes_semaphore = bytes_semaphore.clone();
         let response_sender = response_sender.clone();
+        let fetch_wait_time = metrics.fetch_wait_time.clone();
+        let bytes_fetched = bytes_fetched_by_executor[&p.executor_meta.id].clone();
+        let reader = if p.remote_storage {
+            PartitionReaderEnum::ObjectStoreRemote(runtime.clone())
+        } else {
+            PartitionReaderEnum::FlightRemote(compression)
+        };
         spawned_tasks.push(SpawnedTask::spawn(async move {
+            let num_bytes = p.partition_stats.num_bytes.unwrap_or(0) as usize;
+            let timer = fetch_wait_time.timer();
+            // Block if exceeds max bytes in flight, a partition larger than the limit is
+            // fetched once all the others have been read.
+            let bytes_permit = match bytes_semaphore {
+                Some(bytes_semaphore) => {
+                    let permits = num_bytes
+                        .min(max_bytes_in_flight)
+                        .min(Semaphore::MAX_PERMITS)
+                        .min(u32::MAX as usize) as u32;
+                    Some(bytes_semaphore.acquire_many_owned(permits).await.unwrap())
+                }
+                None => None,
+            };
             // Block if exceeds max request number.
             let permit = semaphore.acquire_owned().await.unwrap();
-            let r = PartitionReaderEnum::FlightRemote.fetch_partition(&p).await;
+            timer.done();
+            let r = reader.fetch_partition(&p).await.map(|stream| {
+                bytes_fetched.add(num_bytes);
+                Box::pin(InFlightStream::new(stream, bytes_permit))
+                    as SendableRecordBatchStream
+            });
             // Block if the channel buffer is full.
             if let Err(e) = response_sender.send(r).await {
                 error!("Fail to send response event to the channel due to {}", e);
@@ -331,7 +555,9 @@ fn send_fetch_partitions(
 }
 
 fn check_is_local_location(location: &PartitionLocation) -> bool {
-    std::path::Path::new(location.path.as_str()).exists()
+    !location.remote_storage
+        && !location.streaming
+        && std::path::Path::new(location.path.as_str()).exists()
 }
 
 /// Partition reader Trait, different partition reader can have
@@ -347,9 +573,8 @@ trait PartitionReader: Send + Sync + Clone {
 #[derive(Clone)]
 enum PartitionReaderEnum {
     Local,
-    FlightRemote,
-    #[allow(dead_code)]
-    ObjectStoreRemote,
+    FlightRemote(ShuffleCompression),
+    ObjectStoreRemote(Arc<RuntimeEnv>),
 }
 
 #[async_trait]
@@ -360,10 +585,12 @@ impl PartitionReader for PartitionReaderEnum {
         location: &PartitionLocation,
     ) -> result::Result<SendableRecordBatchStream, KapotError> {
         match self {
-            PartitionReaderEnum::FlightRemote => fetch_partition_remote(location).await,
+            PartitionReaderEnum::FlightRemote(compression) => {
+                fetch_partition_remote(location, *compression).await
+            }
             PartitionReaderEnum::Local => fetch_partition_local(location).await,
-            PartitionReaderEnum::ObjectStoreRemote => {
-                fetch_partition_object_store(location).await
+            PartitionReaderEnum::ObjectStoreRemote(runtime) => {
+                fetch_partition_object_store(location, runtime).await
             }
         }
     }
@@ -371,6 +598,7 @@ impl PartitionReader for PartitionReaderEnum {
 
 async fn fetch_partition_remote(
     location: &PartitionLocation,
+    compression: ShuffleCompression,
 ) -> result::Result<SendableRecordBatchStream, KapotError> {
     let metadata = &location.executor_meta;
     let partition_id = &location.partition_id;
@@ -392,19 +620,48 @@ async fn fetch_partition_remote(
                 other => other,
             })?;
 
+    if location.streaming {
+        return kapot_client
+            .fetch_partition_stream(
+                &metadata.id,
+                partition_id,
+                location.map_partition_id,
+                compression,
+                host,
+                port,
+            )
+            .await;
+    }
     kapot_client
-        .fetch_partition(&metadata.id, partition_id, &location.path, host, port)
+        .fetch_partition(
+            &metadata.id,
+            partition_id,
+            &location.path,
+            location.range,
+            compression,
+            location.checksum,
+            host,
+            port,
+        )
         .await
 }
 
 async fn fetch_partition_local(
     location: &PartitionLocation,
 ) -> result::Result<SendableRecordBatchStream, KapotError> {
-    let path = &location.path;
+    let path = location.path.clone();
+    let (range, checksum) = (location.range, location.checksum);
     let metadata = &location.executor_meta;
     let partition_id = &location.partition_id;
 
-    let reader = fetch_partition_local_inner(path).map_err(|e| {
+    // the schema of the partition is decoded when it is opened
+    let reader = tokio::task::spawn_blocking(move || {
+        fetch_partition_local_inner(&path, range, checksum)
+    })
+    .await
+    .map_err(KapotError::from)
+    .and_then(|reader| reader)
+    .map_err(|e| {
         // return kapotError::FetchFailed may let scheduler retry this task.
         KapotError::FetchFailed(
             metadata.id.clone(),
@@ -413,38 +670,147 @@ async fn fetch_partition_local(
             e.to_string(),
         )
     })?;
-    Ok(Box::pin(LocalShuffleStream::new(reader)))
+    // a partition failing to be decoded fails the fetch too
+    let (executor_id, stage_id, map_partition_id) = (
+        metadata.id.clone(),
+        partition_id.stage_id,
+        partition_id.partition_id,
+    );
+    let stream = ShuffleFileStream::new(reader);
+    Ok(Box::pin(RecordBatchStreamAdapter::new(
+        stream.schema(),
+        stream.map_err(move |e| {
+            let error = KapotError::FetchFailed(
+                executor_id.clone(),
+                stage_id,
+                map_partition_id,
+                e.to_string(),
+            );
+            ArrowError::ExternalError(Box::new(error)).into()
+        }),
+    )))
 }
 
 fn fetch_partition_local_inner(
     path: &str,
-) -> result::Result<StreamReader<BufReader<File>>, KapotError> {
-    
-    let file = File::open(path).map_err(|e| {
-        KapotError::General(format!("Failed to open partition file at {path}: {e:?}"))
-    })?;
+    range: Option<ByteRange>,
+    checksum: Option<u32>,
+) -> result::Result<ShuffleFileReader, KapotError> {
+    // the partition is verified while it is decoded, see ShuffleFileReader
+    ShuffleFileReader::try_new(path, range, checksum)
+}
 
-    let buff_reader = std::io::BufReader::new(file);
+async fn fetch_partition_object_store(
+    location: &PartitionLocation,
+    runtime: &RuntimeEnv,
+) -> result::Result<SendableRecordBatchStream, KapotError> {
+    let metadata = &location.executor_meta;
+    let partition_id = &location.partition_id;
 
-    let reader = StreamReader::try_new(buff_reader, None).map_err(|e| {
-        KapotError::General(format!("Failed to new arrow FileReader at {path}: {e:?}"))
+    let stream = fetch_partition_object_store_inner(location, runtime)
+        .await
+        .map_err(|e| {
+            // return kapotError::FetchFailed may let scheduler retry this task.
+            KapotError::FetchFailed(
+                metadata.id.clone(),
+                partition_id.stage_id,
+                partition_id.partition_id,
+                e.to_string(),
+            )
+        })?;
+    // a partition failing to be decoded or verified fails the fetch too
+    let (executor_id, stage_id, map_partition_id) = (
+        metadata.id.clone(),
+        partition_id.stage_id,
+        partition_id.partition_id,
+    );
+    Ok(Box::pin(RecordBatchStreamAdapter::new(
+        stream.schema(),
+        stream.map_err(move |e| {
+            let error = KapotError::FetchFailed(
+                executor_id.clone(),
+                stage_id,
+                map_partition_id,
+                e.to_string(),
+            );
+            ArrowError::ExternalError(Box::new(error)).into()
+        }),
+    )))
+}
+
+async fn fetch_partition_object_store_inner(
+    location: &PartitionLocation,
+    runtime: &RuntimeEnv,
+) -> Result<SendableRecordBatchStream> {
+    let path = location.path.clone();
+    let url = Url::parse(&path).map_err(|e| {
+        DataFusionError::Execution(format!("Invalid shuffle partition url {path}: {e}"))
     })?;
+    let store = runtime.object_store_registry.get_store(&url)?;
+    let location_path = object_store::path::Path::from_url_path(url.path())?;
+
+    let options = GetOptions {
+        range: location.range.map(|range| {
+            let start = range.offset as usize;
+            GetRange::Bounded(start..start + range.length as usize)
+        }),
+        ..Default::default()
+    };
+    let chunks = store.get_opts(&location_path, options).await?.into_stream();
+
+    // the partition is decoded while it is fetched, and verified against its checksum as
+    // it is read, see ShuffleFileReader
+    let checksum = location.checksum;
+    let chunks = ObjectChunkReader::new(chunks, Handle::current());
+    let reader = tokio::task::spawn_blocking(move || {
+        ShuffleFileReader::try_new_from_reader(chunks, &path, checksum)
+    })
+    .await
+    .map_err(|e| DataFusionError::External(Box::new(e)))?
+    .map_err(|e| DataFusionError::External(Box::new(e)))?;
+
+    Ok(Box::pin(ShuffleFileStream::new(reader)))
+}
 
-    Ok(reader)
+/// Blocking reader of the chunks of an object fetched from an object store
+struct ObjectChunkReader {
+    chunks: BoxStream<'static, object_store::Result<Bytes>>,
+    chunk: Bytes,
+    handle: Handle,
 }
 
-async fn fetch_partition_object_store(
-    _location: &PartitionLocation,
-) -> result::Result<SendableRecordBatchStream, KapotError> {
-    Err(KapotError::NotImplemented(
-        "Should not use ObjectStorePartitionReader".to_string(),
-    ))
+impl ObjectChunkReader {
+    fn new(
+        chunks: BoxStream<'static, object_store::Result<Bytes>>,
+        handle: Handle,
+    ) -> Self {
+        Self {
+            chunks,
+            chunk: Bytes::new(),
+            handle,
+        }
+    }
+}
+
+impl Read for ObjectChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.handle.block_on(self.chunks.next()) {
+                Some(chunk) => self.chunk = chunk.map_err(std::io::Error::other)?,
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk[..n]);
+        self.chunk.advance(n);
+        Ok(n)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::execution_plans::ShuffleWriterExec;
+    use crate::execution_plans::{remove_remote_shuffle_data, ShuffleWriterExec};
     use crate::serde::scheduler::{ExecutorMetadata, ExecutorSpecification, PartitionId};
     use crate::utils;
     use datafusion::arrow::array::{Int32Array, StringArray, UInt32Array};
@@ -456,6 +822,7 @@ mod tests {
     use datafusion::physical_plan::common;
     use datafusion::physical_plan::memory::MemoryExec;
     use datafusion::prelude::SessionContext;
+    use std::fs::File;
     use tempfile::{tempdir, TempDir};
 
     #[tokio::test]
@@ -553,6 +920,10 @@ mod tests {
                 },
                 partition_stats: Default::default(),
                 path: "test_path".to_string(),
+                range: None,
+                remote_storage: false,
+                checksum: None,
+                streaming: false,
             })
         }
 
@@ -598,7 +969,9 @@ mod tests {
             work_dir.into_path().to_str().unwrap().to_owned(),
             Some(Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 1)),
         )
-        .unwrap();
+        .unwrap()
+        // write the input batches as they are instead of coalescing them
+        .with_buffer_size(0);
 
         let mut stream = input.execute(0, task_ctx).unwrap();
 
@@ -614,10 +987,12 @@ mod tests {
 
         // from to input partitions test the first one with two batches
         let file_path = path.value(0);
-        let reader = fetch_partition_local_inner(file_path).unwrap();
+        let checksum = crc32fast::hash(&std::fs::read(file_path).unwrap());
+        let reader =
+            fetch_partition_local_inner(file_path, None, Some(checksum)).unwrap();
 
         let mut stream: Pin<Box<dyn RecordBatchStream + Send>> =
-            async { Box::pin(LocalShuffleStream::new(reader)) }.await;
+            async { Box::pin(ShuffleFileStream::new(reader)) }.await;
 
         let result = utils::collect_stream(&mut stream)
             .await
@@ -630,6 +1005,333 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_read_local_sort_based_shuffle() -> Result<()> {
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+        let work_dir = TempDir::new()?;
+        let input = ShuffleWriterExec::try_new(
+            "local_file".to_owned(),
+            1,
+            create_test_data_plan()?,
+            work_dir.path().to_str().unwrap().to_owned(),
+            Some(Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 2)),
+        )?
+        .with_sort_based_shuffle(true);
+
+        let mut locations = vec![vec![]; 2];
+        for input_partition in 0..2 {
+            let shuffle_partitions = input
+                .execute_shuffle_write(input_partition, task_ctx.clone())
+                .await?;
+            for p in shuffle_partitions {
+                let mut location =
+                    get_test_partition_locations(1, p.path.clone(), p.checksum).remove(0);
+                location.map_partition_id = input_partition;
+                location.partition_id.partition_id = p.partition_id as usize;
+                location.range = p.range.map(|r| r.into());
+                locations[p.partition_id as usize].push(location);
+            }
+        }
+
+        let shuffle_reader =
+            ShuffleReaderExec::try_new(1, locations, create_test_batch().schema())?;
+        let mut num_rows = 0;
+        for partition in 0..2 {
+            let mut stream = shuffle_reader.execute(partition, task_ctx.clone())?;
+            let batches = utils::collect_stream(&mut stream)
+                .await
+                .map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
+            num_rows += batches.iter().map(|b| b.num_rows()).sum::<usize>();
+        }
+        // every row of the two input partitions is read back exactly once
+        assert_eq!(4 * create_test_batch().num_rows(), num_rows);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_corrupted_shuffle() -> Result<()> {
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+        let work_dir = TempDir::new()?;
+        let input = ShuffleWriterExec::try_new(
+            "local_file".to_owned(),
+            1,
+            create_test_data_plan()?,
+            work_dir.path().to_str().unwrap().to_owned(),
+            Some(Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 2)),
+        )?
+        .with_sort_based_shuffle(true);
+
+        let mut locations = vec![vec![]; 2];
+        let mut num_rows = [0; 2];
+        for p in input.execute_shuffle_write(0, task_ctx.clone()).await? {
+            num_rows[p.partition_id as usize] = p.num_rows as usize;
+            let mut location =
+                get_test_partition_locations(1, p.path.clone(), p.checksum).remove(0);
+            location.partition_id.partition_id = p.partition_id as usize;
+            location.range = p.range.map(|r| r.into());
+            locations[p.partition_id as usize].push(location);
+        }
+
+        // flip the last byte of the body of the last batch of the second output
+        // partition, before the 8 bytes of the end of stream marker, so that the
+        // partition decodes and only its checksum tells it is corrupted
+        let location = &locations[1][0];
+        let range = location.range.unwrap();
+        let mut data = std::fs::read(&location.path)?;
+        let pos = (range.offset + range.length - 9) as usize;
+        data[pos] = !data[pos];
+        std::fs::write(&location.path, data)?;
+
+        let shuffle_reader =
+            ShuffleReaderExec::try_new(1, locations, create_test_batch().schema())?;
+
+        let mut stream = shuffle_reader.execute(0, task_ctx.clone())?;
+        assert!(utils::collect_stream(&mut stream).await.is_ok());
+
+        // the mismatch is raised before the last batch of the partition is read
+        let mut stream = shuffle_reader.execute(1, task_ctx)?;
+        let mut rows_read = 0;
+        let error = loop {
+            match stream.next().await {
+                Some(Ok(batch)) => rows_read += batch.num_rows(),
+                Some(Err(e)) => break KapotError::from(e),
+                None => panic!("Expected the corrupted partition to fail"),
+            }
+        };
+        assert!(rows_read < num_rows[1]);
+        match error {
+            KapotError::FetchFailed(_, stage_id, partition_id, message) => {
+                assert_eq!(1, stage_id);
+                assert_eq!(1, partition_id);
+                assert!(message.contains("Checksum mismatch"), "{message}");
+            }
+            other => panic!("Expected FetchFailed, got {other:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_remote_storage_shuffle() -> Result<()> {
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+        let remote_dir = TempDir::new()?;
+        let remote_url = format!("file://{}", remote_dir.path().to_str().unwrap());
+
+        for sort_based_shuffle in [false, true] {
+            let work_dir = TempDir::new()?;
+            let input = ShuffleWriterExec::try_new(
+                format!("remote_storage_{sort_based_shuffle}"),
+                1,
+                create_test_data_plan()?,
+                work_dir.path().to_str().unwrap().to_owned(),
+                Some(Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 2)),
+            )?
+            .with_sort_based_shuffle(sort_based_shuffle)
+            .with_remote_storage(Some(remote_url.clone()));
+
+            let mut locations = vec![vec![]; 2];
+            for input_partition in 0..2 {
+                let shuffle_partitions = input
+                    .execute_shuffle_write(input_partition, task_ctx.clone())
+                    .await?;
+                for p in shuffle_partitions {
+                    assert!(p.remote_storage);
+                    assert!(p.path.starts_with(&remote_url));
+                    let mut location =
+                        get_test_partition_locations(1, p.path.clone(), p.checksum)
+                            .remove(0);
+                    location.map_partition_id = input_partition;
+                    location.partition_id.partition_id = p.partition_id as usize;
+                    location.range = p.range.map(|r| r.into());
+                    location.remote_storage = p.remote_storage;
+                    locations[p.partition_id as usize].push(location);
+                }
+            }
+
+            // the data files were moved to the remote storage
+            let data_files = walkdir(work_dir.path())
+                .into_iter()
+                .filter(|path| path.extension().is_some_and(|ext| ext == "arrow"))
+                .count();
+            assert_eq!(0, data_files);
+
+            let shuffle_reader =
+                ShuffleReaderExec::try_new(1, locations, create_test_batch().schema())?;
+            let mut num_rows = 0;
+            for partition in 0..2 {
+                let mut stream = shuffle_reader.execute(partition, task_ctx.clone())?;
+                let batches = utils::collect_stream(&mut stream)
+                    .await
+                    .map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
+                num_rows += batches.iter().map(|b| b.num_rows()).sum::<usize>();
+            }
+            assert_eq!(4 * create_test_batch().num_rows(), num_rows);
+
+            // the uploaded files are removed along with the job data
+            remove_remote_shuffle_data(
+                &format!("remote_storage_{sort_based_shuffle}"),
+                &remote_url,
+                task_ctx.runtime_env().object_store_registry.as_ref(),
+            )
+            .await?;
+            let remote_files = walkdir(remote_dir.path())
+                .into_iter()
+                .filter(|path| path.extension().is_some_and(|ext| ext == "arrow"))
+                .count();
+            assert_eq!(0, remote_files);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_corrupted_remote_storage_shuffle() -> Result<()> {
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+        let work_dir = TempDir::new()?;
+        let remote_dir = TempDir::new()?;
+        let input = ShuffleWriterExec::try_new(
+            "remote_corrupted".to_owned(),
+            1,
+            create_test_data_plan()?,
+            work_dir.path().to_str().unwrap().to_owned(),
+            Some(Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 2)),
+        )?
+        .with_sort_based_shuffle(true)
+        .with_remote_storage(Some(format!(
+            "file://{}",
+            remote_dir.path().to_str().unwrap()
+        )));
+
+        let mut locations = vec![vec![]; 2];
+        for p in input.execute_shuffle_write(0, task_ctx.clone()).await? {
+            let mut location =
+                get_test_partition_locations(1, p.path.clone(), p.checksum).remove(0);
+            location.partition_id.partition_id = p.partition_id as usize;
+            location.range = p.range.map(|r| r.into());
+            location.remote_storage = p.remote_storage;
+            locations[p.partition_id as usize].push(location);
+        }
+
+        // flip a byte in the middle of the second output partition
+        let location = &locations[1][0];
+        let range = location.range.unwrap();
+        let file = Url::parse(&location.path).unwrap().path().to_owned();
+        let mut data = std::fs::read(&file)?;
+        let pos = (range.offset + range.length / 2) as usize;
+        data[pos] = !data[pos];
+        std::fs::write(&file, data)?;
+
+        let shuffle_reader =
+            ShuffleReaderExec::try_new(1, locations, create_test_batch().schema())?;
+
+        let mut stream = shuffle_reader.execute(0, task_ctx.clone())?;
+        assert!(utils::collect_stream(&mut stream).await.is_ok());
+
+        let mut stream = shuffle_reader.execute(1, task_ctx)?;
+        let error = utils::collect_stream(&mut stream).await.unwrap_err();
+        assert!(
+            matches!(error, KapotError::FetchFailed(_, 1, 1, _)),
+            "Expected FetchFailed, got {error:?}"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_shuffle_with_fetch_limits() -> Result<()> {
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+        let work_dir = TempDir::new()?;
+        let remote_dir = TempDir::new()?;
+        let input = ShuffleWriterExec::try_new(
+            "remote_file".to_owned(),
+            1,
+            create_test_data_plan()?,
+            work_dir.path().to_str().unwrap().to_owned(),
+            Some(Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 2)),
+        )?
+        .with_remote_storage(Some(format!(
+            "file://{}",
+            remote_dir.path().to_str().unwrap()
+        )));
+
+        // the two map tasks ran on different executors, each serving two partitions
+        let mut locations = vec![];
+        let mut total_bytes = 0;
+        for input_partition in 0..2 {
+            for p in input
+                .execute_shuffle_write(input_partition, task_ctx.clone())
+                .await?
+            {
+                let mut location =
+                    get_test_partition_locations(1, p.path.clone(), p.checksum).remove(0);
+                location.executor_meta.id = format!("exec{input_partition}");
+                location.map_partition_id = input_partition;
+                location.partition_stats = PartitionStats::new(
+                    Some(p.num_rows),
+                    Some(p.num_batches),
+                    Some(p.num_bytes),
+                );
+                location.remote_storage = p.remote_storage;
+                total_bytes += p.num_bytes as usize;
+                locations.push(location);
+            }
+        }
+
+        // a single byte in flight only lets the partitions be fetched one at a time
+        let shuffle_reader =
+            ShuffleReaderExec::try_new(1, vec![locations], create_test_batch().schema())?
+                .with_fetch_limits(1, 1);
+        let mut stream = shuffle_reader.execute(0, task_ctx)?;
+        let batches = utils::collect_stream(&mut stream)
+            .await
+            .map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
+        let num_rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        assert_eq!(4 * create_test_batch().num_rows(), num_rows);
+
+        let metrics = shuffle_reader.metrics().unwrap();
+        assert!(metrics.sum_by_name("fetch_wait_time").is_some());
+        assert_eq!(
+            Some(total_bytes),
+            metrics.sum_by_name("bytes_fetched").map(|v| v.as_usize())
+        );
+        // a single counter per executor, whatever the number of partitions it served
+        assert_eq!(
+            2,
+            metrics
+                .iter()
+                .filter(|m| m.value().name() == "bytes_fetched")
+                .count()
+        );
+        for executor_id in ["exec0", "exec1"] {
+            let bytes_fetched = metrics.sum(|m| {
+                m.value().name() == "bytes_fetched"
+                    && m.labels().iter().any(|l| l.value() == executor_id)
+            });
+            assert!(bytes_fetched.unwrap().as_usize() > 0);
+        }
+
+        Ok(())
+    }
+
+    fn walkdir(dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+        let mut paths = vec![];
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                paths.extend(walkdir(&path));
+            } else {
+                paths.push(path);
+            }
+        }
+        paths
+    }
+
     async fn test_send_fetch_partitions(max_request_num: usize, partition_num: usize) {
         let schema = get_test_partition_schema();
         let data_array = Int32Array::from(vec![1]);
@@ -639,17 +1341,26 @@ mod tests {
         let tmp_dir = tempdir().unwrap();
         let file_path = tmp_dir.path().join("shuffle_data");
         let file = File::create(&file_path).unwrap();
-        let mut writer = StreamWriter::try_new(file, &schema).unwrap();
+        let mut writer =
+            StreamWriter::try_new(utils::ChecksumWriter::new(file), &schema).unwrap();
         writer.write(&batch).unwrap();
         writer.finish().unwrap();
+        let checksum = writer.into_inner().unwrap().checksum();
 
         let partition_locations = get_test_partition_locations(
             partition_num,
             file_path.to_str().unwrap().to_string(),
+            Some(checksum),
         );
 
-        let response_receiver =
-            send_fetch_partitions(partition_locations, max_request_num);
+        let response_receiver = send_fetch_partitions(
+            partition_locations,
+            max_request_num,
+            0,
+            ShuffleCompression::default(),
+            SessionContext::new().runtime_env(),
+            ShuffleReadMetrics::new(0, &ExecutionPlanMetricsSet::new()),
+        );
 
         let stream = RecordBatchStreamAdapter::new(
             Arc::new(schema),
@@ -660,7 +1371,11 @@ mod tests {
         assert_eq!(partition_num, result.len());
     }
 
-    fn get_test_partition_locations(n: usize, path: String) -> Vec<PartitionLocation> {
+    fn get_test_partition_locations(
+        n: usize,
+        path: String,
+        checksum: Option<u32>,
+    ) -> Vec<PartitionLocation> {
         (0..n)
             .map(|partition_id| PartitionLocation {
                 map_partition_id: 0,
@@ -678,6 +1393,10 @@ mod tests {
                 },
                 partition_stats: Default::default(),
                 path: path.clone(),
+                range: None,
+                remote_storage: false,
+                checksum,
+                streaming: false,
             })
             .collect()
     }
@@ -0,0 +1,609 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! In-memory shuffle streams of gang scheduled jobs, whose consumer tasks read the
+//! output partitions of the map tasks while they are still running.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::ipc::reader::StreamReader;
+use datafusion::arrow::ipc::writer::StreamWriter;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::disk_manager::RefCountedTempFile;
+use datafusion::execution::memory_pool::{MemoryConsumer, MemoryReservation};
+use datafusion::execution::runtime_env::RuntimeEnv;
+use datafusion::execution::TaskContext;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::SendableRecordBatchStream;
+use log::debug;
+use tokio::sync::Notify;
+
+use crate::serde::scheduler::PartitionStats;
+
+/// How long a consumer task waits for the map task to register the stream it reads
+const DEFAULT_REGISTRATION_TIMEOUT: Duration = Duration::from_secs(60);
+/// Number of removed jobs remembered, so that late reads of their streams fail at once
+const MAX_REMOVED_JOBS: usize = 1024;
+
+/// Identifies the output partition `output_partition` of the map task `map_partition`
+/// of the stage `stage_id` of job `job_id`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ShuffleStreamKey {
+    pub job_id: String,
+    pub stage_id: usize,
+    pub map_partition: usize,
+    pub output_partition: usize,
+}
+
+/// Registry of the shuffle streams written by the map tasks running on this executor.
+///
+/// Every stream is read by a single consumer task. The written batches are buffered in
+/// memory reserved from the memory pool of the map task, and spilled to disk when the
+/// reservation fails, until the consumer reads them. A stream can't be read a second
+/// time, so a retried consumer task fails, which fails the gang scheduled job.
+pub struct ShuffleStreams {
+    inner: Mutex<Streams>,
+    /// Notified whenever a map task registers a stream
+    registered: Notify,
+    registration_timeout: Duration,
+}
+
+#[derive(Default)]
+struct Streams {
+    streams: HashMap<ShuffleStreamKey, Arc<StreamEntry>>,
+    /// Most recently removed jobs, the oldest first
+    removed_jobs: VecDeque<String>,
+}
+
+struct StreamEntry {
+    state: Mutex<StreamState>,
+    notify: Notify,
+}
+
+struct StreamState {
+    schema: SchemaRef,
+    /// Written batches not read yet, in the order they were written
+    buffered: VecDeque<Buffered>,
+    /// Memory of the batches buffered in memory
+    reservation: MemoryReservation,
+    /// Set once the consumer task started reading the stream
+    read: bool,
+    /// Set once the consumer task stopped reading the stream or the job was removed.
+    /// Batches written afterwards are discarded.
+    closed: bool,
+    finished: Option<std::result::Result<(), String>>,
+}
+
+enum Buffered {
+    /// A batch in memory, together with its reserved size
+    Batch(RecordBatch, usize),
+    /// Batches spilled to disk as a complete Arrow IPC stream
+    Spill(RefCountedTempFile),
+}
+
+impl StreamEntry {
+    fn update(&self, f: impl FnOnce(&mut StreamState)) {
+        f(&mut self.state.lock().unwrap());
+        self.notify.notify_waiters();
+    }
+
+    /// Release the buffered batches, failing the stream unless it finished
+    fn close(&self, error: impl FnOnce() -> String) {
+        self.update(|state| {
+            state.closed = true;
+            state.buffered.clear();
+            state.reservation.free();
+            state.finished.get_or_insert_with(|| Err(error()));
+        });
+    }
+}
+
+static SHUFFLE_STREAMS: OnceLock<ShuffleStreams> = OnceLock::new();
+
+impl ShuffleStreams {
+    pub fn new(registration_timeout: Duration) -> Self {
+        Self {
+            inner: Mutex::default(),
+            registered: Notify::new(),
+            registration_timeout,
+        }
+    }
+
+    /// The shuffle streams of this process
+    pub fn global() -> &'static ShuffleStreams {
+        SHUFFLE_STREAMS.get_or_init(|| ShuffleStreams::new(DEFAULT_REGISTRATION_TIMEOUT))
+    }
+
+    /// Register the stream of an output partition of a map task. The batches buffered
+    /// in memory are limited to `memory_limit` bytes and reserved from the memory pool
+    /// of the task.
+    pub(crate) fn register_writer(
+        &self,
+        key: ShuffleStreamKey,
+        schema: SchemaRef,
+        memory_limit: usize,
+        context: &TaskContext,
+    ) -> Result<ShuffleStreamWriter> {
+        let reservation = MemoryConsumer::new(format!(
+            "ShuffleStreamWriter[{}/{}/{}]",
+            key.stage_id, key.map_partition, key.output_partition
+        ))
+        .with_can_spill(true)
+        .register(context.memory_pool());
+        let entry = Arc::new(StreamEntry {
+            state: Mutex::new(StreamState {
+                schema,
+                buffered: VecDeque::new(),
+                reservation,
+                read: false,
+                closed: false,
+                finished: None,
+            }),
+            notify: Notify::new(),
+        });
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.removed_jobs.contains(&key.job_id) {
+                return Err(DataFusionError::Execution(format!(
+                    "Job {} was removed",
+                    key.job_id
+                )));
+            }
+            if inner.streams.contains_key(&key) {
+                return Err(DataFusionError::Execution(format!(
+                    "Shuffle stream {key:?} is already being written"
+                )));
+            }
+            inner.streams.insert(key, entry.clone());
+        }
+        self.registered.notify_waiters();
+        Ok(ShuffleStreamWriter {
+            entry,
+            memory_limit,
+            runtime: context.runtime_env(),
+            spill: None,
+            num_batches: 0,
+            num_rows: 0,
+            num_bytes: 0,
+            finished: false,
+        })
+    }
+
+    /// Wait until the map task registered the stream, failing if its job was removed
+    async fn wait_for_stream(&self, key: &ShuffleStreamKey) -> Result<Arc<StreamEntry>> {
+        loop {
+            // register for notifications before checking the streams so that no
+            // registration is missed
+            let notified = self.registered.notified();
+            {
+                let inner = self.inner.lock().unwrap();
+                if let Some(entry) = inner.streams.get(key) {
+                    return Ok(entry.clone());
+                }
+                if inner.removed_jobs.contains(&key.job_id) {
+                    return Err(DataFusionError::Execution(format!(
+                        "Job {} was removed",
+                        key.job_id
+                    )));
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// Read a shuffle stream. The returned stream waits for the map task to register
+    /// its writer and then yields the batches as they are written. Fails if no map task
+    /// registers the stream within the registration timeout, or if the stream was
+    /// already read.
+    pub async fn read(&self, key: ShuffleStreamKey) -> Result<SendableRecordBatchStream> {
+        let entry =
+            tokio::time::timeout(self.registration_timeout, self.wait_for_stream(&key))
+                .await
+                .map_err(|_| {
+                    DataFusionError::Execution(format!(
+                        "No map task registered shuffle stream {key:?} within {:?}",
+                        self.registration_timeout
+                    ))
+                })??;
+
+        let schema = {
+            let mut state = entry.state.lock().unwrap();
+            if state.read {
+                return Err(DataFusionError::Execution(format!(
+                    "Shuffle stream {key:?} was already read by another consumer task"
+                )));
+            }
+            if let Some(Err(e)) = &state.finished {
+                return Err(DataFusionError::Execution(e.clone()));
+            }
+            state.read = true;
+            state.schema.clone()
+        };
+
+        let consumer = StreamConsumer {
+            entry,
+            spill: None,
+            done: false,
+        };
+        let batches = futures::stream::unfold(consumer, |mut consumer| async move {
+            let next = consumer.next().await?;
+            Some((next, consumer))
+        });
+        Ok(Box::pin(RecordBatchStreamAdapter::new(schema, batches)))
+    }
+
+    /// Remove the shuffle streams of a job, failing the readers still waiting on them
+    pub fn remove_job(&self, job_id: &str) {
+        let mut removed = vec![];
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.streams.retain(|key, entry| {
+                if key.job_id == job_id {
+                    removed.push(entry.clone());
+                    false
+                } else {
+                    true
+                }
+            });
+            if !inner.removed_jobs.iter().any(|removed| removed == job_id) {
+                if inner.removed_jobs.len() >= MAX_REMOVED_JOBS {
+                    inner.removed_jobs.pop_front();
+                }
+                inner.removed_jobs.push_back(job_id.to_owned());
+            }
+        }
+        self.registered.notify_waiters();
+        for entry in removed {
+            entry.close(|| format!("Job {job_id} was removed"));
+        }
+    }
+}
+
+/// The single consumer of a shuffle stream. The stream is closed once the consumer is
+/// dropped, releasing the batches written after it stopped reading.
+struct StreamConsumer {
+    entry: Arc<StreamEntry>,
+    /// Spill file currently being read
+    spill: Option<(RefCountedTempFile, StreamReader<BufReader<File>>)>,
+    done: bool,
+}
+
+enum Next {
+    Batch(RecordBatch),
+    Spill(RefCountedTempFile),
+    Finished(std::result::Result<(), String>),
+    Wait,
+}
+
+impl StreamConsumer {
+    async fn next(&mut self) -> Option<Result<RecordBatch>> {
+        if self.done {
+            return None;
+        }
+        let result = self.try_next().await;
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+
+    async fn try_next(&mut self) -> Option<Result<RecordBatch>> {
+        loop {
+            if let Some((_, reader)) = &mut self.spill {
+                match reader.next() {
+                    Some(batch) => return Some(batch.map_err(Into::into)),
+                    None => self.spill = None,
+                }
+            }
+
+            let notified = self.entry.notify.notified();
+            let next = {
+                let mut state = self.entry.state.lock().unwrap();
+                match state.buffered.pop_front() {
+                    Some(Buffered::Batch(batch, size)) => {
+                        state.reservation.shrink(size);
+                        Next::Batch(batch)
+                    }
+                    Some(Buffered::Spill(file)) => Next::Spill(file),
+                    None => match &state.finished {
+                        Some(result) => Next::Finished(result.clone()),
+                        None => Next::Wait,
+                    },
+                }
+            };
+            match next {
+                Next::Batch(batch) => return Some(Ok(batch)),
+                Next::Spill(file) => match File::open(file.path()) {
+                    Ok(f) => match StreamReader::try_new(BufReader::new(f), None) {
+                        Ok(reader) => self.spill = Some((file, reader)),
+                        Err(e) => return Some(Err(e.into())),
+                    },
+                    Err(e) => return Some(Err(e.into())),
+                },
+                Next::Finished(Ok(())) => return None,
+                Next::Finished(Err(e)) => {
+                    return Some(Err(DataFusionError::Execution(e)))
+                }
+                Next::Wait => notified.await,
+            }
+        }
+    }
+}
+
+impl Drop for StreamConsumer {
+    fn drop(&mut self) {
+        self.entry
+            .close(|| "The consumer task stopped reading the stream".to_owned());
+    }
+}
+
+/// Writes one output partition of a map task into its shuffle stream
+pub(crate) struct ShuffleStreamWriter {
+    entry: Arc<StreamEntry>,
+    memory_limit: usize,
+    runtime: Arc<RuntimeEnv>,
+    /// Spill file the batches are written to while they don't fit in memory
+    spill: Option<(RefCountedTempFile, StreamWriter<BufWriter<File>>)>,
+    num_batches: u64,
+    num_rows: u64,
+    num_bytes: u64,
+    finished: bool,
+}
+
+impl ShuffleStreamWriter {
+    /// Buffer a batch in memory, or spill it when the memory budget is exceeded or the
+    /// memory pool can't provide the memory for it
+    pub(crate) fn write(&mut self, batch: RecordBatch) -> Result<()> {
+        self.num_batches += 1;
+        self.num_rows += batch.num_rows() as u64;
+        let size = batch.get_array_memory_size();
+        self.num_bytes += size as u64;
+
+        let mut state = self.entry.state.lock().unwrap();
+        if state.closed {
+            return Ok(());
+        }
+        if state.reservation.size() + size <= self.memory_limit
+            && state.reservation.try_grow(size).is_ok()
+        {
+            // the batches spilled so far become readable before the ones that follow
+            if let Some(spill) = self.spill.take() {
+                state
+                    .buffered
+                    .push_back(Buffered::Spill(finish_spill(spill)?));
+            }
+            state.buffered.push_back(Buffered::Batch(batch, size));
+            drop(state);
+            self.entry.notify.notify_waiters();
+            return Ok(());
+        }
+        drop(state);
+
+        match &mut self.spill {
+            Some((_, writer)) => writer.write(&batch)?,
+            None => {
+                let file = self
+                    .runtime
+                    .disk_manager
+                    .create_tmp_file("ShuffleStreamWriter spill")?;
+                debug!("Spilling shuffle stream to {:?}", file.path());
+                let mut writer = StreamWriter::try_new(
+                    BufWriter::new(File::create(file.path())?),
+                    batch.schema().as_ref(),
+                )?;
+                writer.write(&batch)?;
+                self.spill = Some((file, writer));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> Result<PartitionStats> {
+        if let Some(spill) = self.spill.take() {
+            let file = finish_spill(spill)?;
+            self.entry.update(|state| {
+                if !state.closed {
+                    state.buffered.push_back(Buffered::Spill(file));
+                }
+            });
+        }
+        self.finished = true;
+        self.entry.update(|state| {
+            state.finished.get_or_insert(Ok(()));
+        });
+        Ok(PartitionStats::new(
+            Some(self.num_rows),
+            Some(self.num_batches),
+            Some(self.num_bytes),
+        ))
+    }
+}
+
+fn finish_spill(
+    (file, mut writer): (RefCountedTempFile, StreamWriter<BufWriter<File>>),
+) -> Result<RefCountedTempFile> {
+    writer.finish()?;
+    writer.into_inner()?.flush()?;
+    Ok(file)
+}
+
+impl Drop for ShuffleStreamWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.entry.update(|state| {
+                state
+                    .finished
+                    .get_or_insert(Err("Map task did not finish".to_owned()));
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::UInt32Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use futures::TryStreamExt;
+
+    fn key(job_id: &str) -> ShuffleStreamKey {
+        ShuffleStreamKey {
+            job_id: job_id.to_owned(),
+            stage_id: 1,
+            map_partition: 0,
+            output_partition: 0,
+        }
+    }
+
+    fn batch() -> Result<RecordBatch> {
+        let schema =
+            Arc::new(Schema::new(vec![Field::new("a", DataType::UInt32, false)]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![Arc::new(UInt32Array::from(vec![1, 2, 3]))],
+        )?)
+    }
+
+    #[tokio::test]
+    async fn test_read_while_writing() -> Result<()> {
+        let streams = Arc::new(ShuffleStreams::new(DEFAULT_REGISTRATION_TIMEOUT));
+        let context = TaskContext::default();
+        let batch = batch()?;
+
+        // the reader starts before the map task registered its writer
+        let reader = tokio::spawn({
+            let streams = streams.clone();
+            async move { streams.read(key("job-a")).await }
+        });
+        let mut writer = streams.register_writer(
+            key("job-a"),
+            batch.schema(),
+            usize::MAX,
+            &context,
+        )?;
+        assert!(streams
+            .register_writer(key("job-a"), batch.schema(), usize::MAX, &context)
+            .is_err());
+        writer.write(batch.clone())?;
+        writer.write(batch)?;
+        let stats = writer.finish()?;
+        assert_eq!(Some(6), stats.num_rows);
+        assert_eq!(Some(2), stats.num_batches);
+
+        let batches: Vec<_> = reader.await.unwrap()?.try_collect().await?;
+        assert_eq!(2, batches.len());
+        // a retried consumer can't read the stream again
+        assert!(streams.read(key("job-a")).await.is_err());
+        streams.remove_job("job-a");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_spill() -> Result<()> {
+        let streams = ShuffleStreams::new(DEFAULT_REGISTRATION_TIMEOUT);
+        let context = TaskContext::default();
+        let batch = batch()?;
+        let memory_limit = batch.get_array_memory_size();
+
+        let mut writer = streams.register_writer(
+            key("job-a"),
+            batch.schema(),
+            memory_limit,
+            &context,
+        )?;
+        let stream = streams.read(key("job-a")).await?;
+        // the first batch is buffered in memory and the following ones are spilled
+        // until the consumer reads it
+        for _ in 0..3 {
+            writer.write(batch.clone())?;
+        }
+        assert_eq!(memory_limit, context.memory_pool().reserved());
+        writer.finish()?;
+
+        let batches: Vec<_> = stream.try_collect().await?;
+        assert_eq!(3, batches.len());
+        assert_eq!(0, context.memory_pool().reserved());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unfinished_writer() -> Result<()> {
+        let streams = Arc::new(ShuffleStreams::new(DEFAULT_REGISTRATION_TIMEOUT));
+        let context = TaskContext::default();
+        let batch = batch()?;
+        let writer = streams.register_writer(
+            key("job-b"),
+            batch.schema(),
+            usize::MAX,
+            &context,
+        )?;
+        let stream = streams.read(key("job-b")).await?;
+        drop(writer);
+        let result: Result<Vec<_>> = stream.try_collect().await;
+        assert!(result.is_err());
+
+        let reader = tokio::spawn({
+            let streams = streams.clone();
+            async move { streams.read(key("job-c")).await }
+        });
+        tokio::task::yield_now().await;
+        streams.remove_job("job-c");
+        assert!(reader.await.unwrap().is_err());
+        // the streams of a removed job can neither be written nor read
+        assert!(streams
+            .register_writer(key("job-c"), batch.schema(), usize::MAX, &context)
+            .is_err());
+        assert!(streams.read(key("job-c")).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unknown_stream() -> Result<()> {
+        let streams = ShuffleStreams::new(Duration::from_millis(10));
+        assert!(streams.read(key("job-d")).await.is_err());
+        // reading doesn't create the stream
+        assert!(streams.inner.lock().unwrap().streams.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_consumer_stopped() -> Result<()> {
+        let streams = ShuffleStreams::new(DEFAULT_REGISTRATION_TIMEOUT);
+        let context = TaskContext::default();
+        let batch = batch()?;
+        let mut writer = streams.register_writer(
+            key("job-e"),
+            batch.schema(),
+            usize::MAX,
+            &context,
+        )?;
+        writer.write(batch.clone())?;
+        drop(streams.read(key("job-e")).await?);
+        // the buffered batches are released and the following ones discarded
+        assert_eq!(0, context.memory_pool().reserved());
+        writer.write(batch)?;
+        assert_eq!(0, context.memory_pool().reserved());
+        writer.finish()?;
+        Ok(())
+    }
+}
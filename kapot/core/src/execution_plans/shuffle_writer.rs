@@ -20,25 +20,31 @@
 //! partition is re-partitioned and streamed to disk in Arrow IPC format. Future stages of the query
 //! will use the ShuffleReaderExec to read these results.
 
-use datafusion::arrow::ipc::writer::IpcWriteOptions;
-use datafusion::arrow::ipc::CompressionType;
-
+use datafusion::arrow::ipc::reader::StreamReader;
 use datafusion::arrow::ipc::writer::StreamWriter;
+use datafusion::execution::disk_manager::RefCountedTempFile;
 use datafusion::physical_expr::EquivalenceProperties;
 use datafusion::physical_plan::execution_plan::{Boundedness, EmissionType};
 use std::any::Any;
+use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
 use std::future::Future;
+use std::io::{BufWriter, Seek, Write};
 use std::iter::Iterator;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
 
-use crate::utils;
+use crate::config::{KapotConfig, ShuffleCompression};
+use crate::execution_plans::shuffle_buffer::ShuffleBuffer;
+use crate::execution_plans::{
+    RangePartitioner, RangePartitioning, ShuffleStreamKey, ShuffleStreams,
+};
+use crate::utils::{self, ChecksumWriter};
 
-use crate::serde::protobuf::ShuffleWritePartition;
-use crate::serde::scheduler::PartitionStats;
+use crate::serde::protobuf::{self, ShuffleWritePartition};
+use crate::serde::scheduler::{ByteRange, PartitionStats};
 use datafusion::arrow::array::{
     ArrayBuilder, ArrayRef, StringBuilder, StructBuilder, UInt32Builder, UInt64Builder,
 };
@@ -46,6 +52,7 @@ use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
 
 use datafusion::arrow::record_batch::RecordBatch;
 use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::common;
 use datafusion::physical_plan::memory::MemoryStream;
 use datafusion::physical_plan::metrics::{
     self, ExecutionPlanMetricsSet, MetricBuilder, MetricsSet,
@@ -59,9 +66,24 @@ use futures::{StreamExt, TryFutureExt, TryStreamExt};
 
 use datafusion::arrow::error::ArrowError;
 use datafusion::execution::context::TaskContext;
+use datafusion::execution::object_store::ObjectStoreRegistry;
 use datafusion::physical_plan::repartition::BatchPartitioner;
 use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
 use log::{debug, info};
+use object_store::WriteMultipart;
+use tokio::io::AsyncReadExt;
+use url::Url;
+
+/// Size of the reads from a shuffle file uploaded to the remote shuffle storage
+const UPLOAD_BUFFER_SIZE: usize = 1024 * 1024;
+/// Maximum number of parts of a shuffle file uploaded concurrently
+const MAX_CONCURRENT_UPLOAD_PARTS: usize = 8;
+
+/// Id of the task a [`ShuffleWriterExec`] runs as, added by the executor as an extension of
+/// the session config of the task. The shuffle files uploaded to the remote storage are
+/// keyed by it, so that concurrent attempts of a map task upload distinct objects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShuffleWriterTaskId(pub usize);
 
 /// ShuffleWriterExec represents a section of a query plan that has consistent partitioning and
 /// can be executed as one unit with each partition being executed in parallel. The output of each
@@ -80,6 +102,21 @@ pub struct ShuffleWriterExec {
     /// Optional shuffle output partitioning.
     /// If it's none, it means there's no need to do repartitioning.
     shuffle_output_partitioning: Option<Partitioning>,
+    /// Whether to write all output partitions of a map task into a single data file
+    /// rather than one file per output partition
+    sort_based_shuffle: bool,
+    /// Range partitioning of the shuffle output. If set, the output partitioning is
+    /// unknown to DataFusion and the bounds are computed from the sampled input.
+    range_partitioning: Option<RangePartitioning>,
+    /// Memory budget in bytes for buffering the output partitions of a map task
+    buffer_size: usize,
+    /// Compression codec of the shuffle files
+    compression: ShuffleCompression,
+    /// Object store url the shuffle files are uploaded to once written to the work_dir
+    remote_storage_url: Option<String>,
+    /// Whether the output partitions are streamed from memory to the consumer tasks of a
+    /// gang scheduled job instead of being written to shuffle files
+    streaming: bool,
     /// Execution metrics
     metrics: ExecutionPlanMetricsSet,
     properties: PlanProperties,
@@ -88,7 +125,7 @@ pub struct ShuffleWriterExec {
 pub struct WriteTracker {
     pub num_batches: usize,
     pub num_rows: usize,
-    pub writer: StreamWriter<File>,
+    pub writer: StreamWriter<ChecksumWriter<File>>,
     pub path: PathBuf,
 }
 
@@ -99,6 +136,14 @@ struct ShuffleWriteMetrics {
     repart_time: metrics::Time,
     input_rows: metrics::Count,
     output_rows: metrics::Count,
+    spill_count: metrics::Count,
+    spilled_bytes: metrics::Count,
+    /// In-memory size of the batches written to shuffle files
+    uncompressed_bytes: metrics::Count,
+    /// Size of the shuffle files written
+    compressed_bytes: metrics::Count,
+    /// Time spent uploading shuffle files to the remote shuffle storage
+    upload_time: metrics::Time,
 }
 
 impl ShuffleWriteMetrics {
@@ -111,11 +156,27 @@ impl ShuffleWriteMetrics {
 
         let output_rows = MetricBuilder::new(metrics).output_rows(partition);
 
+        let spill_count = MetricBuilder::new(metrics).spill_count(partition);
+        let spilled_bytes = MetricBuilder::new(metrics).spilled_bytes(partition);
+
+        let uncompressed_bytes =
+            MetricBuilder::new(metrics).counter("uncompressed_bytes", partition);
+        let compressed_bytes =
+            MetricBuilder::new(metrics).counter("compressed_bytes", partition);
+
+        let upload_time =
+            MetricBuilder::new(metrics).subset_time("upload_time", partition);
+
         Self {
             write_time,
             repart_time,
             input_rows,
             output_rows,
+            spill_count,
+            spilled_bytes,
+            uncompressed_bytes,
+            compressed_bytes,
+            upload_time,
         }
     }
 }
@@ -146,11 +207,88 @@ impl ShuffleWriterExec {
             plan,
             work_dir,
             shuffle_output_partitioning,
+            sort_based_shuffle: false,
+            range_partitioning: None,
+            buffer_size: KapotConfig::default().shuffle_writer_buffer_size(),
+            compression: ShuffleCompression::default(),
+            remote_storage_url: None,
+            streaming: false,
             metrics: ExecutionPlanMetricsSet::new(),
             properties,
         })
     }
 
+    /// Enable or disable the sort-based shuffle layout, see [`KAPOT_SHUFFLE_SORT_BASED_ENABLED`]
+    ///
+    /// [`KAPOT_SHUFFLE_SORT_BASED_ENABLED`]: crate::config::KAPOT_SHUFFLE_SORT_BASED_ENABLED
+    pub fn with_sort_based_shuffle(mut self, sort_based_shuffle: bool) -> Self {
+        self.sort_based_shuffle = sort_based_shuffle;
+        self
+    }
+
+    /// Set the memory budget for buffering the output partitions of a map task, see
+    /// [`KAPOT_SHUFFLE_WRITER_BUFFER_SIZE`]
+    ///
+    /// [`KAPOT_SHUFFLE_WRITER_BUFFER_SIZE`]: crate::config::KAPOT_SHUFFLE_WRITER_BUFFER_SIZE
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Set the compression codec of the shuffle files, see [`KAPOT_SHUFFLE_COMPRESSION`]
+    ///
+    /// [`KAPOT_SHUFFLE_COMPRESSION`]: crate::config::KAPOT_SHUFFLE_COMPRESSION
+    pub fn with_compression(mut self, compression: ShuffleCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Upload the shuffle files to an object store instead of keeping them in the work_dir,
+    /// see [`KAPOT_SHUFFLE_REMOTE_STORAGE_URL`]
+    ///
+    /// [`KAPOT_SHUFFLE_REMOTE_STORAGE_URL`]: crate::config::KAPOT_SHUFFLE_REMOTE_STORAGE_URL
+    pub fn with_remote_storage(mut self, remote_storage_url: Option<String>) -> Self {
+        self.remote_storage_url = remote_storage_url;
+        self
+    }
+
+    /// Stream the output partitions from memory to the consumer tasks, which run at the
+    /// same time as the map tasks in a gang scheduled job
+    pub fn with_streaming(mut self, streaming: bool) -> Self {
+        self.streaming = streaming;
+        self
+    }
+
+    /// Range partition the shuffle output on the sort expressions of `range_partitioning`
+    pub fn with_range_partitioning(
+        mut self,
+        range_partitioning: RangePartitioning,
+    ) -> Self {
+        let partitioning =
+            Partitioning::UnknownPartitioning(range_partitioning.partition_count());
+        self.properties = self.properties.with_partitioning(partitioning.clone());
+        self.shuffle_output_partitioning = Some(partitioning);
+        self.range_partitioning = Some(range_partitioning);
+        self
+    }
+
+    /// Returns a copy of this writer keeping the partitioning of its input instead of
+    /// repartitioning it, so that each map task writes its output as a single partition
+    pub fn without_output_partitioning(&self) -> Result<Self> {
+        Ok(ShuffleWriterExec::try_new(
+            self.job_id.clone(),
+            self.stage_id,
+            self.plan.clone(),
+            self.work_dir.clone(),
+            None,
+        )?
+        .with_sort_based_shuffle(self.sort_based_shuffle)
+        .with_buffer_size(self.buffer_size)
+        .with_compression(self.compression)
+        .with_remote_storage(self.remote_storage_url.clone())
+        .with_streaming(self.streaming))
+    }
+
     /// Get the Job ID for this query stage
     pub fn job_id(&self) -> &str {
         &self.job_id
@@ -174,6 +312,36 @@ impl ShuffleWriterExec {
         self.shuffle_output_partitioning.as_ref()
     }
 
+    /// Whether the output of each map task is written into a single data file
+    pub fn sort_based_shuffle(&self) -> bool {
+        self.sort_based_shuffle
+    }
+
+    /// Memory budget in bytes for buffering the output partitions of a map task
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// Compression codec of the shuffle files
+    pub fn compression(&self) -> ShuffleCompression {
+        self.compression
+    }
+
+    /// Object store url the shuffle files are uploaded to, if any
+    pub fn remote_storage_url(&self) -> Option<&str> {
+        self.remote_storage_url.as_deref()
+    }
+
+    /// Get the range partitioning of the shuffle output, if any
+    pub fn range_partitioning(&self) -> Option<&RangePartitioning> {
+        self.range_partitioning.as_ref()
+    }
+
+    /// Whether the output partitions are streamed from memory to the consumer tasks
+    pub fn streaming(&self) -> bool {
+        self.streaming
+    }
+
     pub fn execute_shuffle_write(
         &self,
         input_partition: usize,
@@ -185,13 +353,110 @@ impl ShuffleWriterExec {
 
         let write_metrics = ShuffleWriteMetrics::new(input_partition, &self.metrics);
         let output_partitioning = self.shuffle_output_partitioning.clone();
+        let sort_based_shuffle = self.sort_based_shuffle;
+        let range_partitioning = self.range_partitioning.clone();
+        let buffer_size = self.buffer_size;
+        let compression = self.compression;
+        let remote_storage_url = self.remote_storage_url.clone();
+        let streaming = self.streaming;
+        let job_id = self.job_id.clone();
+        let stage_id = self.stage_id;
+        let work_dir = PathBuf::from(&self.work_dir);
         let plan = self.plan.clone();
 
         async move {
             let now = Instant::now();
-            let mut stream = plan.execute(input_partition, context)?;
+            let mut stream = plan.execute(input_partition, context.clone())?;
+
+            let part_locs = match output_partitioning {
+                output_partitioning if streaming => {
+                    let output_partitions = match &output_partitioning {
+                        Some(partitioning) => {
+                            (0..partitioning.partition_count()).collect()
+                        }
+                        None => vec![input_partition],
+                    };
+                    // the memory budget of the map task is shared by its streams
+                    let stream_memory_limit =
+                        buffer_size / output_partitions.len().max(1);
+                    let mut writers = output_partitions
+                        .iter()
+                        .map(|output_partition| {
+                            let key = ShuffleStreamKey {
+                                job_id: job_id.clone(),
+                                stage_id,
+                                map_partition: input_partition,
+                                output_partition: *output_partition,
+                            };
+                            ShuffleStreams::global().register_writer(
+                                key,
+                                stream.schema(),
+                                stream_memory_limit,
+                                &context,
+                            )
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+
+                    match output_partitioning {
+                        Some(partitioning) => {
+                            let mut partitioner = ShufflePartitioner::try_new(
+                                partitioning,
+                                range_partitioning,
+                                plan.schema().as_ref(),
+                                context.clone(),
+                                &write_metrics,
+                            )
+                            .await?;
+                            while let Some(result) = stream.next().await {
+                                let input_batch = result?;
+                                write_metrics.input_rows.add(input_batch.num_rows());
+                                partitioner.partition(
+                                    input_batch,
+                                    |output_partition, output_batch| {
+                                        writers[output_partition].write(output_batch)
+                                    },
+                                )?;
+                            }
+                        }
+                        None => {
+                            while let Some(result) = stream.next().await {
+                                let input_batch = result?;
+                                write_metrics.input_rows.add(input_batch.num_rows());
+                                writers[0].write(input_batch)?;
+                            }
+                        }
+                    }
+
+                    let part_locs = output_partitions
+                        .into_iter()
+                        .zip(writers)
+                        .map(|(output_partition, writer)| {
+                            let stats = writer.finish()?;
+                            let num_rows = stats.num_rows.unwrap_or(0);
+                            let num_bytes = stats.num_bytes.unwrap_or(0);
+                            write_metrics.output_rows.add(num_rows as usize);
+                            write_metrics.uncompressed_bytes.add(num_bytes as usize);
+                            Ok(ShuffleWritePartition {
+                                partition_id: output_partition as u64,
+                                path: String::new(),
+                                num_batches: stats.num_batches.unwrap_or(0),
+                                num_rows,
+                                num_bytes,
+                                range: None,
+                                remote_storage: false,
+                                checksum: None,
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    info!(
+                        "Executed partition {} in {} seconds, streaming its output",
+                        input_partition,
+                        now.elapsed().as_secs(),
+                    );
+                    // streamed partitions are never uploaded to the remote storage
+                    return Ok(part_locs);
+                }
 
-            match output_partitioning {
                 None => {
                     let timer = write_metrics.write_time.timer();
                     path.push(format!("{input_partition}"));
@@ -201,10 +466,11 @@ impl ShuffleWriterExec {
                     debug!("Writing results to {}", path);
 
                     // stream results to disk
-                    let stats = utils::write_stream_to_disk(
+                    let (stats, checksum) = utils::write_stream_to_disk(
                         &mut stream,
                         path,
                         &write_metrics.write_time,
+                        compression,
                     )
                     .await
                     .map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
@@ -215,6 +481,12 @@ impl ShuffleWriterExec {
                     write_metrics
                         .output_rows
                         .add(stats.num_rows.unwrap_or(0) as usize);
+                    write_metrics
+                        .uncompressed_bytes
+                        .add(stats.num_bytes.unwrap_or(0) as usize);
+                    write_metrics
+                        .compressed_bytes
+                        .add(fs::metadata(path)?.len() as usize);
                     timer.done();
 
                     info!(
@@ -230,21 +502,122 @@ impl ShuffleWriterExec {
                         num_batches: stats.num_batches.unwrap_or(0),
                         num_rows: stats.num_rows.unwrap_or(0),
                         num_bytes: stats.num_bytes.unwrap_or(0),
+                        range: None,
+                        remote_storage: false,
+                        checksum: Some(checksum),
                     }])
                 }
 
-                Some(Partitioning::Hash(exprs, num_output_partitions)) => {
-                    // we won't necessary produce output for every possible partition, so we
-                    // create writers on demand
-                    let mut writers: Vec<Option<WriteTracker>> = vec![];
-                    for _ in 0..num_output_partitions {
-                        writers.push(None);
+                Some(partitioning) if sort_based_shuffle => {
+                    // buffer the output partitions of this map task so that they can be
+                    // written one after another into a single data file
+                    let partition_count = partitioning.partition_count();
+                    let schema = stream.schema();
+                    let mut buffer = ShuffleBuffer::new(
+                        schema.clone(),
+                        partition_count,
+                        context.session_config().batch_size(),
+                        buffer_size,
+                        input_partition,
+                        &context,
+                    );
+                    let mut spills = vec![];
+
+                    let mut partitioner = ShufflePartitioner::try_new(
+                        partitioning,
+                        range_partitioning,
+                        plan.schema().as_ref(),
+                        context.clone(),
+                        &write_metrics,
+                    )
+                    .await?;
+
+                    while let Some(result) = stream.next().await {
+                        let input_batch = result?;
+
+                        write_metrics.input_rows.add(input_batch.num_rows());
+
+                        partitioner.partition(
+                            input_batch,
+                            |output_partition, output_batch| {
+                                if let Some(output_batch) =
+                                    buffer.try_push(output_partition, output_batch)?
+                                {
+                                    // out of memory, spill the buffered partitions
+                                    // together with the batch that didn't fit
+                                    let timer = write_metrics.write_time.timer();
+                                    let spilled_bytes = buffer.mem_size()
+                                        + output_batch.get_array_memory_size();
+                                    let mut partitions = buffer.drain()?;
+                                    partitions[output_partition].push(output_batch);
+                                    spills.push(SpillFile::try_new(
+                                        partitions,
+                                        &schema,
+                                        compression,
+                                        &context,
+                                    )?);
+                                    write_metrics.spill_count.add(1);
+                                    write_metrics.spilled_bytes.add(spilled_bytes);
+                                    timer.done();
+                                }
+                                Ok(())
+                            },
+                        )?;
                     }
 
-                    let mut partitioner = BatchPartitioner::try_new(
-                        Partitioning::Hash(exprs, num_output_partitions),
-                        write_metrics.repart_time.clone(),
+                    let timer = write_metrics.write_time.timer();
+                    std::fs::create_dir_all(&path)?;
+                    let data_path = path.join(format!("data-{input_partition}.arrow"));
+
+                    let part_locs = write_sort_based_shuffle(
+                        &data_path,
+                        schema,
+                        &spills,
+                        buffer.drain()?,
+                        compression,
+                        &write_metrics,
                     )?;
+                    timer.done();
+
+                    info!(
+                        "Executed partition {} in {} seconds. Wrote {} shuffle partitions to {:?} after {} spills",
+                        input_partition,
+                        now.elapsed().as_secs(),
+                        part_locs.len(),
+                        data_path,
+                        spills.len()
+                    );
+
+                    Ok(part_locs)
+                }
+
+                Some(partitioning) => {
+                    // output batches are buffered per output partition, so that they are
+                    // written as batches of the configured batch size
+                    let mut writers = PartitionWriters::new(
+                        path,
+                        input_partition,
+                        stream.schema(),
+                        partitioning.partition_count(),
+                        compression,
+                    );
+                    let mut buffer = ShuffleBuffer::new(
+                        stream.schema(),
+                        partitioning.partition_count(),
+                        context.session_config().batch_size(),
+                        buffer_size,
+                        input_partition,
+                        &context,
+                    );
+
+                    let mut partitioner = ShufflePartitioner::try_new(
+                        partitioning,
+                        range_partitioning,
+                        plan.schema().as_ref(),
+                        context.clone(),
+                        &write_metrics,
+                    )
+                    .await?;
 
                     while let Some(result) = stream.next().await {
                         let input_batch = result?;
@@ -254,88 +627,304 @@ impl ShuffleWriterExec {
                         partitioner.partition(
                             input_batch,
                             |output_partition, output_batch| {
-                                // partition func in datafusion make sure not write empty output_batch.
-                                let timer = write_metrics.write_time.timer();
-                                match &mut writers[output_partition] {
-                                    Some(w) => {
-                                        w.num_batches += 1;
-                                        w.num_rows += output_batch.num_rows();
-                                        w.writer.write(&output_batch)?;
+                                match buffer.try_push(output_partition, output_batch)? {
+                                    Some(output_batch) => {
+                                        // out of memory, spill the buffered partitions
+                                        // to their files
+                                        let spilled_bytes = buffer.mem_size()
+                                            + output_batch.get_array_memory_size();
+                                        for (partition, batches) in
+                                            buffer.drain()?.into_iter().enumerate()
+                                        {
+                                            for batch in &batches {
+                                                writers.write(
+                                                    partition,
+                                                    batch,
+                                                    &write_metrics,
+                                                )?;
+                                            }
+                                        }
+                                        writers.write(
+                                            output_partition,
+                                            &output_batch,
+                                            &write_metrics,
+                                        )?;
+                                        write_metrics.spill_count.add(1);
+                                        write_metrics.spilled_bytes.add(spilled_bytes);
                                     }
                                     None => {
-                                        let mut path = path.clone();
-                                        path.push(format!("{output_partition}"));
-                                        std::fs::create_dir_all(&path)?;
-
-                                        path.push(format!(
-                                            "data-{input_partition}.arrow"
-                                        ));
-                                        debug!("Writing results to {:?}", path);
-
-                                        let options = IpcWriteOptions::default()
-                                            .try_with_compression(Some(
-                                                CompressionType::LZ4_FRAME,
-                                            ))?;
-
-                                        let file = File::create(path.clone())?;
-                                        let mut writer =
-                                            StreamWriter::try_new_with_options(
-                                                file,
-                                                stream.schema().as_ref(),
-                                                options,
+                                        for batch in
+                                            buffer.take_coalesced(output_partition)
+                                        {
+                                            writers.write(
+                                                output_partition,
+                                                &batch,
+                                                &write_metrics,
                                             )?;
-
-                                        writer.write(&output_batch)?;
-                                        writers[output_partition] = Some(WriteTracker {
-                                            num_batches: 1,
-                                            num_rows: output_batch.num_rows(),
-                                            writer,
-                                            path,
-                                        });
+                                        }
                                     }
                                 }
-                                write_metrics.output_rows.add(output_batch.num_rows());
-                                timer.done();
                                 Ok(())
                             },
                         )?;
                     }
 
-                    let mut part_locs = vec![];
-
-                    for (i, w) in writers.iter_mut().enumerate() {
-                        match w {
-                            Some(w) => {
-                                let num_bytes = fs::metadata(&w.path)?.len();
-                                w.writer.finish()?;
-                                debug!(
-                                    "Finished writing shuffle partition {} at {:?}. Batches: {}. Rows: {}. Bytes: {}.",
-                                    i,
-                                    w.path,
-                                    w.num_batches,
-                                    w.num_rows,
-                                    num_bytes
-                                );
-
-                                part_locs.push(ShuffleWritePartition {
-                                    partition_id: i as u64,
-                                    path: w.path.to_string_lossy().to_string(),
-                                    num_batches: w.num_batches as u64,
-                                    num_rows: w.num_rows as u64,
-                                    num_bytes,
-                                });
-                            }
-                            None => {}
+                    for (partition, batches) in buffer.drain()?.into_iter().enumerate() {
+                        for batch in &batches {
+                            writers.write(partition, batch, &write_metrics)?;
                         }
                     }
+                    writers.finish(&write_metrics)
+                }
+            }?;
+
+            match remote_storage_url {
+                Some(url) => {
+                    let timer = write_metrics.upload_time.timer();
+                    let part_locs =
+                        upload_to_remote_storage(part_locs, &work_dir, &url, &context)
+                            .await?;
+                    timer.done();
                     Ok(part_locs)
                 }
+                None => Ok(part_locs),
+            }
+        }
+    }
+}
+
+/// Writes every output partition of a map task into its own Arrow IPC file. The files are
+/// created on demand, since not every output partition necessarily receives data.
+struct PartitionWriters {
+    path: PathBuf,
+    input_partition: usize,
+    schema: SchemaRef,
+    writers: Vec<Option<WriteTracker>>,
+    compression: ShuffleCompression,
+}
+
+impl PartitionWriters {
+    fn new(
+        path: PathBuf,
+        input_partition: usize,
+        schema: SchemaRef,
+        partition_count: usize,
+        compression: ShuffleCompression,
+    ) -> Self {
+        Self {
+            path,
+            input_partition,
+            schema,
+            writers: (0..partition_count).map(|_| None).collect(),
+            compression,
+        }
+    }
+
+    fn write(
+        &mut self,
+        output_partition: usize,
+        output_batch: &RecordBatch,
+        write_metrics: &ShuffleWriteMetrics,
+    ) -> Result<()> {
+        let timer = write_metrics.write_time.timer();
+        match &mut self.writers[output_partition] {
+            Some(w) => {
+                w.num_batches += 1;
+                w.num_rows += output_batch.num_rows();
+                w.writer.write(output_batch)?;
+            }
+            None => {
+                let mut path = self.path.clone();
+                path.push(format!("{output_partition}"));
+                std::fs::create_dir_all(&path)?;
+
+                path.push(format!("data-{}.arrow", self.input_partition));
+                debug!("Writing results to {:?}", path);
+
+                let file = File::create(path.clone())?;
+                let mut writer = StreamWriter::try_new_with_options(
+                    ChecksumWriter::new(file),
+                    self.schema.as_ref(),
+                    utils::ipc_write_options(self.compression)?,
+                )?;
 
-                _ => Err(DataFusionError::Execution(
-                    "Invalid shuffle partitioning scheme".to_owned(),
-                )),
+                writer.write(output_batch)?;
+                self.writers[output_partition] = Some(WriteTracker {
+                    num_batches: 1,
+                    num_rows: output_batch.num_rows(),
+                    writer,
+                    path,
+                });
             }
         }
+        write_metrics.output_rows.add(output_batch.num_rows());
+        write_metrics
+            .uncompressed_bytes
+            .add(output_batch.get_array_memory_size());
+        timer.done();
+        Ok(())
+    }
+
+    fn finish(
+        self,
+        write_metrics: &ShuffleWriteMetrics,
+    ) -> Result<Vec<ShuffleWritePartition>> {
+        let mut part_locs = vec![];
+
+        for (i, w) in self.writers.into_iter().enumerate() {
+            if let Some(mut w) = w {
+                w.writer.finish()?;
+                let checksum = w.writer.into_inner()?.checksum();
+                let num_bytes = fs::metadata(&w.path)?.len();
+                write_metrics.compressed_bytes.add(num_bytes as usize);
+                debug!(
+                    "Finished writing shuffle partition {} at {:?}. Batches: {}. Rows: {}. Bytes: {}.",
+                    i,
+                    w.path,
+                    w.num_batches,
+                    w.num_rows,
+                    num_bytes
+                );
+
+                part_locs.push(ShuffleWritePartition {
+                    partition_id: i as u64,
+                    path: w.path.to_string_lossy().to_string(),
+                    num_batches: w.num_batches as u64,
+                    num_rows: w.num_rows as u64,
+                    num_bytes,
+                    range: None,
+                    remote_storage: false,
+                    checksum: Some(checksum),
+                });
+            }
+        }
+        Ok(part_locs)
+    }
+}
+
+/// Output partitions of a map task spilled to a temporary file, every output partition as
+/// a complete Arrow IPC stream at its own byte range
+struct SpillFile {
+    file: RefCountedTempFile,
+    ranges: Vec<Option<ByteRange>>,
+}
+
+impl SpillFile {
+    fn try_new(
+        partitions: Vec<Vec<RecordBatch>>,
+        schema: &SchemaRef,
+        compression: ShuffleCompression,
+        context: &TaskContext,
+    ) -> Result<Self> {
+        let file = context
+            .runtime_env()
+            .disk_manager
+            .create_tmp_file("ShuffleWriterExec spill")?;
+        debug!("Spilling shuffle output partitions to {:?}", file.path());
+
+        let mut writer = BufWriter::new(File::create(file.path())?);
+        let mut ranges = Vec::with_capacity(partitions.len());
+        for batches in &partitions {
+            if batches.is_empty() {
+                ranges.push(None);
+                continue;
+            }
+            let offset = writer.stream_position()?;
+            write_ipc_stream(&mut writer, schema, batches, compression)?;
+            let length = writer.stream_position()? - offset;
+            ranges.push(Some(ByteRange::new(offset, length)));
+        }
+        writer.flush()?;
+        Ok(Self { file, ranges })
+    }
+
+    /// Read the spilled batches of an output partition
+    fn read_partition(&self, output_partition: usize) -> Result<Vec<RecordBatch>> {
+        match &self.ranges[output_partition] {
+            Some(range) => {
+                let file = utils::open_shuffle_file(
+                    &self.file.path().to_string_lossy(),
+                    Some(*range),
+                )?;
+                let reader = StreamReader::try_new(file, None)?;
+                Ok(reader.collect::<std::result::Result<Vec<_>, ArrowError>>()?)
+            }
+            None => Ok(vec![]),
+        }
+    }
+}
+
+/// Write the batches as a complete Arrow IPC stream
+fn write_ipc_stream<W: Write>(
+    writer: &mut W,
+    schema: &SchemaRef,
+    batches: &[RecordBatch],
+    compression: ShuffleCompression,
+) -> Result<()> {
+    let mut writer = StreamWriter::try_new_with_options(
+        writer,
+        schema.as_ref(),
+        utils::ipc_write_options(compression)?,
+    )?;
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Splits the output of a map task into the shuffle output partitions
+enum ShufflePartitioner {
+    Hash(BatchPartitioner),
+    Range(RangePartitioner),
+}
+
+impl ShufflePartitioner {
+    async fn try_new(
+        partitioning: Partitioning,
+        range_partitioning: Option<RangePartitioning>,
+        input_schema: &Schema,
+        context: Arc<TaskContext>,
+        write_metrics: &ShuffleWriteMetrics,
+    ) -> Result<Self> {
+        match (partitioning, range_partitioning) {
+            (_, Some(range_partitioning)) => {
+                // every map task reads the complete sample, so that all of them compute
+                // the same bounds for the output partitions
+                let samples = range_partitioning.samples();
+                let mut sample_batches = vec![];
+                for i in 0..samples.properties().output_partitioning().partition_count() {
+                    let stream = samples.execute(i, context.clone())?;
+                    sample_batches.extend(common::collect(stream).await?);
+                }
+                Ok(Self::Range(RangePartitioner::try_new(
+                    range_partitioning.sort_exprs().clone(),
+                    range_partitioning.partition_count(),
+                    input_schema,
+                    &sample_batches,
+                    write_metrics.repart_time.clone(),
+                )?))
+            }
+            (partitioning @ Partitioning::Hash(_, _), None) => {
+                Ok(Self::Hash(BatchPartitioner::try_new(
+                    partitioning,
+                    write_metrics.repart_time.clone(),
+                )?))
+            }
+            _ => Err(DataFusionError::Execution(
+                "Invalid shuffle partitioning scheme".to_owned(),
+            )),
+        }
+    }
+
+    fn partition<F>(&mut self, batch: RecordBatch, f: F) -> Result<()>
+    where
+        F: FnMut(usize, RecordBatch) -> Result<()>,
+    {
+        match self {
+            Self::Hash(partitioner) => partitioner.partition(batch, f),
+            Self::Range(partitioner) => partitioner.partition(batch, f),
+        }
     }
 }
 
@@ -351,7 +940,21 @@ impl DisplayAs for ShuffleWriterExec {
                     f,
                     "ShuffleWriterExec: {:?}",
                     self.shuffle_output_partitioning
-                )
+                )?;
+                if let Some(range_partitioning) = &self.range_partitioning {
+                    write!(
+                        f,
+                        ", range_partitioning=[{}]",
+                        range_partitioning.sort_exprs()
+                    )?;
+                }
+                if self.sort_based_shuffle {
+                    write!(f, ", sort_based_shuffle=true")?;
+                }
+                if self.streaming {
+                    write!(f, ", streaming=true")?;
+                }
+                Ok(())
             }
         }
     }
@@ -375,20 +978,34 @@ impl ExecutionPlan for ShuffleWriterExec {
     }
 
     fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
-        vec![&self.plan]
+        match &self.range_partitioning {
+            Some(range_partitioning) => vec![&self.plan, range_partitioning.samples()],
+            None => vec![&self.plan],
+        }
     }
 
     fn with_new_children(
         self: Arc<Self>,
         children: Vec<Arc<dyn ExecutionPlan>>,
     ) -> Result<Arc<dyn ExecutionPlan>> {
-        Ok(Arc::new(ShuffleWriterExec::try_new(
+        let mut shuffle_writer = ShuffleWriterExec::try_new(
             self.job_id.clone(),
             self.stage_id,
             children[0].clone(),
             self.work_dir.clone(),
             self.shuffle_output_partitioning.clone(),
-        )?))
+        )?
+        .with_sort_based_shuffle(self.sort_based_shuffle)
+        .with_buffer_size(self.buffer_size)
+        .with_compression(self.compression)
+        .with_remote_storage(self.remote_storage_url.clone())
+        .with_streaming(self.streaming);
+        if let Some(range_partitioning) = &self.range_partitioning {
+            shuffle_writer = shuffle_writer.with_range_partitioning(
+                range_partitioning.with_samples(children[1].clone()),
+            );
+        }
+        Ok(Arc::new(shuffle_writer))
     }
 
     fn execute(
@@ -463,6 +1080,196 @@ impl ExecutionPlan for ShuffleWriterExec {
     }
 }
 
+/// Write the output partitions of a map task into a single Arrow IPC data file. The data
+/// of an output partition is read from the spill files in the order they were written,
+/// followed by the batches still buffered in memory.
+///
+/// Every non-empty output partition is written as a complete IPC stream, so that it can be
+/// read on its own given the byte range recorded in its `ShuffleWritePartition`.
+fn write_sort_based_shuffle(
+    data_path: &Path,
+    schema: SchemaRef,
+    spills: &[SpillFile],
+    buffered: Vec<Vec<RecordBatch>>,
+    compression: ShuffleCompression,
+    write_metrics: &ShuffleWriteMetrics,
+) -> Result<Vec<ShuffleWritePartition>> {
+    debug!("Writing sort-based shuffle results to {:?}", data_path);
+
+    let mut file = BufWriter::new(File::create(data_path)?);
+    let mut part_locs = vec![];
+
+    for (output_partition, batches) in buffered.into_iter().enumerate() {
+        let offset = file.stream_position()?;
+        if batches.is_empty()
+            && spills
+                .iter()
+                .all(|spill| spill.ranges[output_partition].is_none())
+        {
+            continue;
+        }
+
+        let mut num_rows = 0;
+        let mut num_batches = 0;
+        let checksum = {
+            let mut writer = StreamWriter::try_new_with_options(
+                ChecksumWriter::new(&mut file),
+                schema.as_ref(),
+                utils::ipc_write_options(compression)?,
+            )?;
+            for spill in spills {
+                for batch in spill.read_partition(output_partition)? {
+                    num_rows += batch.num_rows();
+                    num_batches += 1;
+                    write_metrics
+                        .uncompressed_bytes
+                        .add(batch.get_array_memory_size());
+                    writer.write(&batch)?;
+                }
+            }
+            for batch in &batches {
+                num_rows += batch.num_rows();
+                num_batches += 1;
+                write_metrics
+                    .uncompressed_bytes
+                    .add(batch.get_array_memory_size());
+                writer.write(batch)?;
+            }
+            writer.finish()?;
+            writer.into_inner()?.checksum()
+        };
+        let length = file.stream_position()? - offset;
+        write_metrics.output_rows.add(num_rows);
+
+        debug!(
+            "Finished writing shuffle partition {} at {:?}[{}..{}]. Batches: {}. Rows: {}.",
+            output_partition,
+            data_path,
+            offset,
+            offset + length,
+            num_batches,
+            num_rows
+        );
+
+        part_locs.push(ShuffleWritePartition {
+            partition_id: output_partition as u64,
+            path: data_path.to_string_lossy().to_string(),
+            num_batches: num_batches as u64,
+            num_rows: num_rows as u64,
+            num_bytes: length,
+            range: Some(protobuf::ByteRange { offset, length }),
+            remote_storage: false,
+            checksum: Some(checksum),
+        });
+    }
+    let length = file.stream_position()?;
+    file.flush()?;
+    write_metrics.compressed_bytes.add(length as usize);
+
+    Ok(part_locs)
+}
+
+/// Upload the shuffle files of a map task to the remote shuffle storage at `url`, keeping
+/// their layout relative to the work_dir with the files put in a directory of the task
+/// attempt, and point the partitions at the uploaded objects. The local files are removed
+/// once uploaded.
+async fn upload_to_remote_storage(
+    mut part_locs: Vec<ShuffleWritePartition>,
+    work_dir: &Path,
+    url: &str,
+    context: &TaskContext,
+) -> Result<Vec<ShuffleWritePartition>> {
+    let store = context
+        .runtime_env()
+        .object_store_registry
+        .get_store(&parse_url(url)?)?;
+
+    // a speculative copy or a late attempt of the map task must not overwrite the files
+    // the scheduler recorded the checksums of
+    let task_id = context
+        .session_config()
+        .get_extension::<ShuffleWriterTaskId>()
+        .map(|task_id| task_id.0);
+
+    // the partitions of a sort-based shuffle share a single data file
+    let mut uploaded: HashMap<String, String> = HashMap::new();
+    for part_loc in part_locs.iter_mut() {
+        if !uploaded.contains_key(&part_loc.path) {
+            let local_path = PathBuf::from(&part_loc.path);
+            let relative_path = local_path.strip_prefix(work_dir).map_err(|_| {
+                DataFusionError::Internal(format!(
+                    "Shuffle file {local_path:?} is not in the work_dir {work_dir:?}"
+                ))
+            })?;
+            let relative_path = match (task_id, relative_path.parent()) {
+                (Some(task_id), Some(dir)) => dir
+                    .join(format!("task-{task_id}"))
+                    .join(relative_path.file_name().unwrap_or_default()),
+                _ => relative_path.to_path_buf(),
+            };
+            let remote_url = format!(
+                "{}/{}",
+                url.trim_end_matches('/'),
+                relative_path.to_string_lossy()
+            );
+            let location =
+                object_store::path::Path::from_url_path(parse_url(&remote_url)?.path())?;
+            debug!("Uploading shuffle file {:?} to {}", local_path, remote_url);
+
+            let mut file = tokio::fs::File::open(&local_path).await?;
+            let mut upload = WriteMultipart::new(store.put_multipart(&location).await?);
+            let mut buf = vec![0u8; UPLOAD_BUFFER_SIZE];
+            loop {
+                let n = file.read(&mut buf).await?;
+                if n == 0 {
+                    break;
+                }
+                upload
+                    .wait_for_capacity(MAX_CONCURRENT_UPLOAD_PARTS)
+                    .await?;
+                upload.write(&buf[..n]);
+            }
+            upload.finish().await?;
+            tokio::fs::remove_file(&local_path).await?;
+
+            uploaded.insert(part_loc.path.clone(), remote_url);
+        }
+        part_loc.path = uploaded[&part_loc.path].clone();
+        part_loc.remote_storage = true;
+    }
+    Ok(part_locs)
+}
+
+/// Delete the shuffle files of a job uploaded to the remote shuffle storage at `url`
+pub async fn remove_remote_shuffle_data(
+    job_id: &str,
+    url: &str,
+    object_store_registry: &dyn ObjectStoreRegistry,
+) -> Result<()> {
+    let store = object_store_registry.get_store(&parse_url(url)?)?;
+    let job_url = format!("{}/{job_id}", url.trim_end_matches('/'));
+    let prefix = object_store::path::Path::from_url_path(parse_url(&job_url)?.path())?;
+    debug!("Removing the shuffle files of job {job_id} from {job_url}");
+
+    let locations = store
+        .list(Some(&prefix))
+        .map_ok(|object| object.location)
+        .boxed();
+    store
+        .delete_stream(locations)
+        .try_collect::<Vec<_>>()
+        .await?;
+    Ok(())
+}
+
+fn parse_url(url: &str) -> Result<Url> {
+    Url::parse(url).map_err(|e| {
+        DataFusionError::Configuration(format!(
+            "Invalid remote shuffle storage url {url}: {e}"
+        ))
+    })
+}
+
 fn result_schema() -> SchemaRef {
     let stats = PartitionStats::default();
     Arc::new(Schema::new(vec![
@@ -475,12 +1282,16 @@ fn result_schema() -> SchemaRef {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::execution_plans::RangeSampleExec;
     use datafusion::arrow::array::{StringArray, StructArray, UInt32Array, UInt64Array};
+    use datafusion::arrow::compute::SortOptions;
+    use datafusion::arrow::ipc::reader::StreamReader;
+    use datafusion::physical_expr::{LexOrdering, PhysicalSortExpr};
     use datafusion::physical_plan::coalesce_partitions::CoalescePartitionsExec;
     use datafusion::physical_plan::expressions::Column;
 
     use datafusion::physical_plan::memory::MemoryExec;
-    use datafusion::prelude::SessionContext;
+    use datafusion::prelude::{SessionConfig, SessionContext};
     use tempfile::TempDir;
 
     #[tokio::test]
@@ -580,6 +1391,362 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_streaming() -> Result<()> {
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+
+        let input_plan = create_input_plan()?;
+        let work_dir = TempDir::new()?;
+        let query_stage = ShuffleWriterExec::try_new(
+            "jobStreaming".to_owned(),
+            1,
+            input_plan,
+            work_dir.path().to_str().unwrap().to_owned(),
+            Some(Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 2)),
+        )?
+        .with_streaming(true);
+        let mut stream = query_stage.execute(0, task_ctx)?;
+        let batches = utils::collect_stream(&mut stream)
+            .await
+            .map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
+        assert_eq!(1, batches.len());
+        assert_eq!(2, batches[0].num_rows());
+        // nothing is written to the work dir
+        assert_eq!(0, std::fs::read_dir(work_dir.path())?.count());
+
+        let mut num_rows = 0;
+        for output_partition in 0..2 {
+            let key = ShuffleStreamKey {
+                job_id: "jobStreaming".to_owned(),
+                stage_id: 1,
+                map_partition: 0,
+                output_partition,
+            };
+            let mut stream = ShuffleStreams::global().read(key).await?;
+            num_rows += utils::collect_stream(&mut stream)
+                .await
+                .map_err(|e| DataFusionError::Execution(format!("{e:?}")))?
+                .iter()
+                .map(|batch| batch.num_rows())
+                .sum::<usize>();
+        }
+        assert_eq!(4, num_rows);
+        ShuffleStreams::global().remove_job("jobStreaming");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    // number of rows in each partition is a function of the hash output, so don't test here
+    #[cfg(not(feature = "force_hash_collisions"))]
+    async fn test_sort_based() -> Result<()> {
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+
+        let input_plan = create_input_plan()?;
+        let work_dir = TempDir::new()?;
+        let query_stage = ShuffleWriterExec::try_new(
+            "jobOne".to_owned(),
+            1,
+            input_plan,
+            work_dir.path().to_str().unwrap().to_owned(),
+            Some(Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 2)),
+        )?
+        .with_sort_based_shuffle(true);
+
+        let part_locs = query_stage.execute_shuffle_write(0, task_ctx).await?;
+        assert_eq!(2, part_locs.len());
+
+        let stage_dir = work_dir.path().join("jobOne").join("1");
+        let data_path = stage_dir.join("data-0.arrow");
+        // a single data file, no per-partition directories
+        assert_eq!(1, fs::read_dir(&stage_dir)?.count());
+
+        // the output partitions are laid out one after the other in the data file
+        let mut offset = 0;
+        for (i, loc) in part_locs.iter().enumerate() {
+            assert_eq!(i as u64, loc.partition_id);
+            assert_eq!(data_path.to_str().unwrap(), loc.path);
+
+            let range: ByteRange = loc.range.unwrap().into();
+            assert_eq!(offset, range.offset);
+            assert_eq!(range.length, loc.num_bytes);
+            offset += range.length;
+
+            let reader = StreamReader::try_new(
+                utils::open_shuffle_file(&loc.path, Some(range))?,
+                None,
+            )?;
+            let num_rows: usize = reader
+                .map(|batch| batch.map(|b| b.num_rows()))
+                .collect::<std::result::Result<Vec<_>, _>>()?
+                .into_iter()
+                .sum();
+            assert_eq!(2, num_rows);
+            assert_eq!(2, loc.num_rows);
+        }
+        assert_eq!(offset, fs::metadata(&data_path)?.len());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    // number of rows in each partition is a function of the hash output, so don't test here
+    #[cfg(not(feature = "force_hash_collisions"))]
+    async fn test_buffered() -> Result<()> {
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+
+        // the small output batches of both input batches are coalesced into one
+        let work_dir = TempDir::new()?;
+        let part_locs =
+            execute_partitioned(task_ctx.clone(), &work_dir, false, 1024 * 1024).await?;
+        assert_eq!(2, part_locs.len());
+        for loc in &part_locs {
+            assert_eq!(2, loc.num_rows);
+            assert_eq!(1, loc.num_batches);
+            assert_eq!(1, read_partition(loc)?.len());
+        }
+
+        let work_dir = TempDir::new()?;
+        let part_locs =
+            execute_partitioned(task_ctx, &work_dir, true, 1024 * 1024).await?;
+        assert_eq!(2, part_locs.len());
+        for loc in &part_locs {
+            assert_eq!(2, loc.num_rows);
+            assert_eq!(1, loc.num_batches);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    // number of rows in each partition is a function of the hash output, so don't test here
+    #[cfg(not(feature = "force_hash_collisions"))]
+    async fn test_spill() -> Result<()> {
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+
+        for sort_based_shuffle in [false, true] {
+            // none of the output batches fit into the buffer
+            let work_dir = TempDir::new()?;
+            let query_stage = ShuffleWriterExec::try_new(
+                "jobOne".to_owned(),
+                1,
+                create_input_plan()?,
+                work_dir.path().to_str().unwrap().to_owned(),
+                Some(Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 2)),
+            )?
+            .with_sort_based_shuffle(sort_based_shuffle)
+            .with_buffer_size(0);
+
+            let part_locs = query_stage
+                .execute_shuffle_write(0, task_ctx.clone())
+                .await?;
+            assert_eq!(2, part_locs.len());
+            for loc in &part_locs {
+                assert_eq!(2, loc.num_rows);
+                assert_eq!(2, loc.num_batches);
+                let batches = read_partition(loc)?;
+                assert_eq!(2, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+            }
+
+            let metrics = query_stage.metrics().unwrap();
+            assert_eq!(Some(4), metrics.spill_count());
+            assert!(metrics.spilled_bytes().unwrap() > 0);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_compression() -> Result<()> {
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+
+        let schema = Arc::new(Schema::new(vec![Field::new("b", DataType::Utf8, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(StringArray::from(vec!["hello world"; 1000]))],
+        )?;
+        let input_plan: Arc<dyn ExecutionPlan> =
+            Arc::new(MemoryExec::try_new(&[vec![batch]], schema, None)?);
+
+        for output_partitioning in [
+            None,
+            Some(Partitioning::Hash(vec![Arc::new(Column::new("b", 0))], 2)),
+        ] {
+            for sort_based_shuffle in [false, true] {
+                let mut compressed_bytes = vec![];
+                for compression in [
+                    ShuffleCompression::None,
+                    ShuffleCompression::Lz4,
+                    ShuffleCompression::Zstd,
+                ] {
+                    let work_dir = TempDir::new()?;
+                    let query_stage = ShuffleWriterExec::try_new(
+                        "jobOne".to_owned(),
+                        1,
+                        input_plan.clone(),
+                        work_dir.path().to_str().unwrap().to_owned(),
+                        output_partitioning.clone(),
+                    )?
+                    .with_sort_based_shuffle(sort_based_shuffle)
+                    .with_compression(compression);
+
+                    let part_locs = query_stage
+                        .execute_shuffle_write(0, task_ctx.clone())
+                        .await?;
+                    let mut num_rows = 0;
+                    for loc in &part_locs {
+                        num_rows += read_partition(loc)?
+                            .iter()
+                            .map(|b| b.num_rows())
+                            .sum::<usize>();
+                    }
+                    assert_eq!(1000, num_rows);
+
+                    let metrics = query_stage.metrics().unwrap();
+                    assert!(
+                        metrics
+                            .sum_by_name("uncompressed_bytes")
+                            .unwrap()
+                            .as_usize()
+                            > 0
+                    );
+                    compressed_bytes.push(
+                        metrics.sum_by_name("compressed_bytes").unwrap().as_usize(),
+                    );
+                }
+                // repeated values compress well with either codec
+                assert!(compressed_bytes[1] < compressed_bytes[0]);
+                assert!(compressed_bytes[2] < compressed_bytes[0]);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_range_partitioned() -> Result<()> {
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+
+        let input_plan = create_input_plan()?;
+        let sort_exprs = LexOrdering::new(vec![PhysicalSortExpr {
+            expr: Arc::new(Column::new("a", 0)),
+            options: SortOptions::default(),
+        }]);
+        let samples = Arc::new(RangeSampleExec::try_new(
+            input_plan.clone(),
+            sort_exprs.clone(),
+            10,
+        )?);
+        let work_dir = TempDir::new()?;
+        let query_stage = ShuffleWriterExec::try_new(
+            "jobOne".to_owned(),
+            1,
+            input_plan,
+            work_dir.path().to_str().unwrap().to_owned(),
+            None,
+        )?
+        .with_range_partitioning(RangePartitioning::new(sort_exprs, 2, samples));
+        assert_eq!(2, query_stage.children().len());
+        assert_eq!(
+            2,
+            query_stage
+                .properties()
+                .output_partitioning()
+                .partition_count()
+        );
+
+        let part_locs = query_stage.execute_shuffle_write(0, task_ctx).await?;
+        assert_eq!(2, part_locs.len());
+
+        // the samples split the rows evenly, with all rows of a key in the same partition
+        for (i, loc) in part_locs.iter().enumerate() {
+            assert_eq!(i as u64, loc.partition_id);
+            assert_eq!(2, loc.num_rows);
+
+            let reader = StreamReader::try_new(File::open(&loc.path)?, None)?;
+            for batch in reader {
+                let batch = batch?;
+                let a = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<UInt32Array>()
+                    .unwrap();
+                let expected = if i == 0 { 1 } else { 3 };
+                assert!(a.iter().all(|v| v == Some(expected)));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_attempts_to_distinct_paths() -> Result<()> {
+        let remote_dir = TempDir::new()?;
+        let remote_url = format!("file://{}", remote_dir.path().to_str().unwrap());
+
+        let mut paths = vec![];
+        for task_id in [1, 2] {
+            let session_ctx = SessionContext::new_with_config(
+                SessionConfig::new()
+                    .with_extension(Arc::new(ShuffleWriterTaskId(task_id))),
+            );
+            let work_dir = TempDir::new()?;
+            let query_stage = ShuffleWriterExec::try_new(
+                "jobOne".to_owned(),
+                1,
+                create_input_plan()?,
+                work_dir.path().to_str().unwrap().to_owned(),
+                Some(Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 2)),
+            )?
+            .with_remote_storage(Some(remote_url.clone()));
+            let part_locs = query_stage
+                .execute_shuffle_write(0, session_ctx.task_ctx())
+                .await?;
+            for loc in part_locs {
+                assert!(loc.path.contains(&format!("/task-{task_id}/")));
+                paths.push(loc.path);
+            }
+        }
+
+        // both attempts of the map task keep their own files
+        paths.sort();
+        paths.dedup();
+        assert_eq!(4, paths.len());
+        Ok(())
+    }
+
+    async fn execute_partitioned(
+        task_ctx: Arc<TaskContext>,
+        work_dir: &TempDir,
+        sort_based_shuffle: bool,
+        buffer_size: usize,
+    ) -> Result<Vec<ShuffleWritePartition>> {
+        let query_stage = ShuffleWriterExec::try_new(
+            "jobOne".to_owned(),
+            1,
+            create_input_plan()?,
+            work_dir.path().to_str().unwrap().to_owned(),
+            Some(Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 2)),
+        )?
+        .with_sort_based_shuffle(sort_based_shuffle)
+        .with_buffer_size(buffer_size);
+        query_stage.execute_shuffle_write(0, task_ctx).await
+    }
+
+    fn read_partition(loc: &ShuffleWritePartition) -> Result<Vec<RecordBatch>> {
+        let reader = StreamReader::try_new(
+            utils::open_shuffle_file(&loc.path, loc.range.map(Into::into))?,
+            None,
+        )?;
+        Ok(reader.collect::<std::result::Result<Vec<_>, _>>()?)
+    }
+
     fn create_input_plan() -> Result<Arc<dyn ExecutionPlan>> {
         let schema = Arc::new(Schema::new(vec![
             Field::new("a", DataType::UInt32, true),
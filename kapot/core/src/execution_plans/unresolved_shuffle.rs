@@ -18,6 +18,7 @@
 use std::any::Any;
 use std::sync::Arc;
 
+use crate::config::{KapotConfig, ShuffleCompression};
 use datafusion::arrow::datatypes::SchemaRef;
 use datafusion::error::{DataFusionError, Result};
 use datafusion::execution::context::TaskContext;
@@ -42,6 +43,15 @@ pub struct UnresolvedShuffleExec {
     // The partition count this node will have once it is replaced with a ShuffleReaderExec
     pub output_partition_count: usize,
 
+    // The codec the ShuffleReaderExec replacing this node uses to fetch remote partitions
+    pub compression: ShuffleCompression,
+
+    // The maximum number of remote partitions the ShuffleReaderExec fetches concurrently
+    pub max_requests: usize,
+
+    // The maximum number of bytes of remote partitions the ShuffleReaderExec reads ahead
+    pub max_bytes_in_flight: usize,
+
     properties: PlanProperties,
 }
 
@@ -60,13 +70,34 @@ impl UnresolvedShuffleExec {
             EmissionType::Both,
             Boundedness::Bounded,
         );
+        let config = KapotConfig::default();
         Self {
             stage_id,
             schema,
             output_partition_count,
+            compression: ShuffleCompression::default(),
+            max_requests: config.shuffle_reader_max_requests(),
+            max_bytes_in_flight: config.shuffle_reader_max_bytes_in_flight(),
             properties,
         }
     }
+
+    /// Set the codec used to fetch remote partitions once this node is resolved
+    pub fn with_compression(mut self, compression: ShuffleCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Set the limits of fetching remote partitions once this node is resolved
+    pub fn with_fetch_limits(
+        mut self,
+        max_requests: usize,
+        max_bytes_in_flight: usize,
+    ) -> Self {
+        self.max_requests = max_requests;
+        self.max_bytes_in_flight = max_bytes_in_flight;
+        self
+    }
 }
 
 impl DisplayAs for UnresolvedShuffleExec {
@@ -4,7 +4,7 @@
 /// /////////////////////////////////////////////////////////////////////////////////////////////////
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct KapotPhysicalPlanNode {
-    #[prost(oneof = "kapot_physical_plan_node::PhysicalPlanType", tags = "1, 2, 3")]
+    #[prost(oneof = "kapot_physical_plan_node::PhysicalPlanType", tags = "1, 2, 3, 4")]
     pub physical_plan_type: ::core::option::Option<
         kapot_physical_plan_node::PhysicalPlanType,
     >,
@@ -19,6 +19,8 @@ pub mod kapot_physical_plan_node {
         ShuffleReader(super::ShuffleReaderExecNode),
         #[prost(message, tag = "3")]
         UnresolvedShuffle(super::UnresolvedShuffleExecNode),
+        #[prost(message, tag = "4")]
+        RangeSample(super::RangeSampleExecNode),
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -35,6 +37,43 @@ pub struct ShuffleWriterExecNode {
     pub output_partitioning: ::core::option::Option<
         ::datafusion_proto::protobuf::PhysicalHashRepartition,
     >,
+    /// write all output partitions of a map task into a single data file plus an index
+    #[prost(bool, tag = "5")]
+    pub sort_based_shuffle: bool,
+    /// range partitioning of the output, the sampled sort keys are the second input of the node
+    #[prost(message, optional, tag = "6")]
+    pub range_partitioning: ::core::option::Option<RangePartitioning>,
+    /// memory budget in bytes for buffering the output partitions of a map task
+    #[prost(uint64, tag = "7")]
+    pub buffer_size: u64,
+    #[prost(enumeration = "CompressionCodec", tag = "8")]
+    pub compression: i32,
+    /// object store url the shuffle output is written to, empty for the executor work_dir
+    #[prost(string, tag = "9")]
+    pub remote_storage_url: ::prost::alloc::string::String,
+    /// stream the output partitions to the tasks reading them instead of writing shuffle files
+    #[prost(bool, tag = "10")]
+    pub streaming: bool,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RangePartitioning {
+    #[prost(message, repeated, tag = "1")]
+    pub sort_expr: ::prost::alloc::vec::Vec<
+        ::datafusion_proto::protobuf::PhysicalSortExprNode,
+    >,
+    #[prost(uint64, tag = "2")]
+    pub partition_count: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RangeSampleExecNode {
+    #[prost(message, optional, tag = "1")]
+    pub input: ::core::option::Option<::datafusion_proto::protobuf::PhysicalPlanNode>,
+    #[prost(message, repeated, tag = "2")]
+    pub sort_expr: ::prost::alloc::vec::Vec<
+        ::datafusion_proto::protobuf::PhysicalSortExprNode,
+    >,
+    #[prost(uint64, tag = "3")]
+    pub sample_size: u64,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct UnresolvedShuffleExecNode {
@@ -44,6 +83,12 @@ pub struct UnresolvedShuffleExecNode {
     pub schema: ::core::option::Option<::datafusion_proto_common::Schema>,
     #[prost(uint32, tag = "4")]
     pub output_partition_count: u32,
+    #[prost(enumeration = "CompressionCodec", tag = "5")]
+    pub compression: i32,
+    #[prost(uint64, tag = "6")]
+    pub max_requests: u64,
+    #[prost(uint64, tag = "7")]
+    pub max_bytes_in_flight: u64,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ShuffleReaderExecNode {
@@ -54,6 +99,18 @@ pub struct ShuffleReaderExecNode {
     /// The stage to read from
     #[prost(uint32, tag = "3")]
     pub stage_id: u32,
+    /// codec used to transfer remote partitions over Flight
+    #[prost(enumeration = "CompressionCodec", tag = "4")]
+    pub compression: i32,
+    /// maximum number of remote partitions fetched concurrently
+    #[prost(uint64, tag = "5")]
+    pub max_requests: u64,
+    /// maximum number of bytes of remote partitions fetched but not yet read, 0 for no limit
+    #[prost(uint64, tag = "6")]
+    pub max_bytes_in_flight: u64,
+    /// number of partitions of the shuffle, which the partitions read may be coalesced or split from
+    #[prost(uint64, tag = "7")]
+    pub shuffle_partition_count: u64,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ShuffleReaderPartition {
@@ -92,6 +149,76 @@ pub struct ExecutionGraph {
     pub end_time: u64,
     #[prost(uint64, tag = "13")]
     pub queued_at: u64,
+    /// target size of coalesced shuffle partitions, 0 when they are not coalesced
+    #[prost(uint64, tag = "14")]
+    pub coalesce_partitions_target_size: u64,
+    /// thresholds of the skewed join partitions split into several tasks, unset when they
+    /// are not split
+    #[prost(message, optional, tag = "15")]
+    pub skew_join: ::core::option::Option<SkewJoin>,
+    /// size in bytes below which the build side of a partitioned join is broadcast, 0 when
+    /// joins are not switched to broadcast joins
+    #[prost(uint64, tag = "16")]
+    pub broadcast_join_threshold: u64,
+    /// thresholds of the straggler tasks a copy is launched of, unset when tasks are not
+    /// speculated
+    #[prost(message, optional, tag = "17")]
+    pub speculation: ::core::option::Option<Speculation>,
+    /// how failed tasks and stages are retried, unset for graphs saved before it was
+    /// configurable
+    #[prost(message, optional, tag = "18")]
+    pub retry_policy: ::core::option::Option<RetryPolicy>,
+    /// scheduling pool of the job, empty for graphs saved before jobs had pools
+    #[prost(string, tag = "19")]
+    pub pool: ::prost::alloc::string::String,
+    /// priority of the job within its scheduling pool
+    #[prost(uint64, tag = "21")]
+    pub priority: u64,
+    /// tenant the scheduler quotas of the job are accounted to
+    #[prost(string, tag = "22")]
+    pub tenant: ::prost::alloc::string::String,
+    /// launch all the stages of the job at once, streaming the shuffle output between them
+    #[prost(bool, tag = "23")]
+    pub gang_scheduling: bool,
+    /// object store url the shuffle output of the job is uploaded to, empty when it is kept
+    /// in the executor work directories
+    #[prost(string, tag = "24")]
+    pub remote_shuffle_storage_url: ::prost::alloc::string::String,
+    /// absolute deadline of the job in milliseconds, 0 when it has no timeout nor deadline
+    #[prost(uint64, tag = "25")]
+    pub deadline: u64,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct SkewJoin {
+    #[prost(uint64, tag = "1")]
+    pub skewed_partition_factor: u64,
+    #[prost(uint64, tag = "2")]
+    pub skewed_partition_threshold: u64,
+    /// size in bytes of the pieces a skewed partition is split into
+    #[prost(uint64, tag = "3")]
+    pub target_size: u64,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct Speculation {
+    #[prost(double, tag = "1")]
+    pub multiplier: f64,
+    #[prost(double, tag = "2")]
+    pub quantile: f64,
+    #[prost(uint64, tag = "3")]
+    pub min_task_runtime_ms: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RetryPolicy {
+    #[prost(uint64, tag = "1")]
+    pub max_task_failures: u64,
+    #[prost(uint64, tag = "2")]
+    pub max_stage_failures: u64,
+    #[prost(uint64, tag = "3")]
+    pub backoff_ms: u64,
+    #[prost(uint64, tag = "4")]
+    pub max_backoff_ms: u64,
+    #[prost(string, repeated, tag = "5")]
+    pub non_retryable_errors: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct StageAttempts {
@@ -302,6 +429,22 @@ pub struct FetchPartition {
     pub host: ::prost::alloc::string::String,
     #[prost(uint32, tag = "6")]
     pub port: u32,
+    /// only set when the partition is a byte range of a shared data file
+    #[prost(message, optional, tag = "7")]
+    pub range: ::core::option::Option<ByteRange>,
+    /// codec used to transfer the partition
+    #[prost(enumeration = "CompressionCodec", tag = "8")]
+    pub compression: i32,
+    /// CRC32 checksum of the partition, verified while it is transferred, unset when the
+    /// partition isn't checksummed
+    #[prost(uint32, optional, tag = "9")]
+    pub checksum: ::core::option::Option<u32>,
+    /// set when the partition is streamed by the running map task map_partition_id rather
+    /// than read from path
+    #[prost(bool, tag = "10")]
+    pub streaming: bool,
+    #[prost(uint32, tag = "11")]
+    pub map_partition_id: u32,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct PartitionLocation {
@@ -317,6 +460,26 @@ pub struct PartitionLocation {
     pub partition_stats: ::core::option::Option<PartitionStats>,
     #[prost(string, tag = "5")]
     pub path: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "6")]
+    pub range: ::core::option::Option<ByteRange>,
+    /// set when the partition is stored on the remote shuffle storage and path is an object store url
+    #[prost(bool, tag = "7")]
+    pub remote_storage: bool,
+    /// CRC32 checksum of the partition file, or of its byte range of a shared data file,
+    /// unset when the partition isn't checksummed
+    #[prost(uint32, optional, tag = "8")]
+    pub checksum: ::core::option::Option<u32>,
+    /// set when the partition is streamed by the running map task rather than read from path
+    #[prost(bool, tag = "9")]
+    pub streaming: bool,
+}
+/// Byte range of a shuffle partition inside a data file shared by all partitions of a map task
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ByteRange {
+    #[prost(uint64, tag = "1")]
+    pub offset: u64,
+    #[prost(uint64, tag = "2")]
+    pub length: u64,
 }
 /// Unique identifier for a materialized partition of data
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -539,6 +702,59 @@ pub struct ExecutorTaskSlots {
     #[prost(message, repeated, tag = "1")]
     pub task_slots: ::prost::alloc::vec::Vec<AvailableTaskSlots>,
 }
+/// Lease of a scheduler on the leadership of the cluster or on a job it curates
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Lease {
+    #[prost(string, tag = "1")]
+    pub holder: ::prost::alloc::string::String,
+    /// Timestamp in seconds the lease expires at unless it is renewed
+    #[prost(uint64, tag = "2")]
+    pub expires_at: u64,
+}
+/// A job accepted by a scheduler but not yet planned, persisted so that it is queued
+/// again when the scheduler restarts
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct QueuedJobInfo {
+    #[prost(string, tag = "1")]
+    pub job_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub job_name: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub scheduler_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "4")]
+    pub queued_at: u64,
+    /// Timestamp in milliseconds the job is cancelled at, 0 if it has no deadline
+    #[prost(uint64, tag = "5")]
+    pub deadline: u64,
+    #[prost(message, optional, tag = "6")]
+    pub session: ::core::option::Option<JobSessionConfig>,
+    /// Logical plan of the job encoded with the logical extension codec of the scheduler
+    #[prost(bytes = "vec", tag = "7")]
+    pub logical_plan: ::prost::alloc::vec::Vec<u8>,
+}
+/// Portable dump of the cluster state saved in a key value store
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ClusterSnapshot {
+    /// Version of the snapshot format
+    #[prost(uint32, tag = "1")]
+    pub version: u32,
+    /// Timestamp in milliseconds the snapshot was taken at
+    #[prost(uint64, tag = "2")]
+    pub created_at: u64,
+    #[prost(message, repeated, tag = "3")]
+    pub entries: ::prost::alloc::vec::Vec<ClusterSnapshotEntry>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ClusterSnapshotEntry {
+    /// Name of the keyspace of the entry, e.g. JobStatus
+    #[prost(string, tag = "1")]
+    pub keyspace: ::prost::alloc::string::String,
+    /// Key of the entry relative to its keyspace
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(bytes = "vec", tag = "3")]
+    pub value: ::prost::alloc::vec::Vec<u8>,
+}
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ExecutorData {
     #[prost(string, tag = "1")]
@@ -629,6 +845,15 @@ pub struct ShuffleWritePartition {
     pub num_rows: u64,
     #[prost(uint64, tag = "5")]
     pub num_bytes: u64,
+    #[prost(message, optional, tag = "6")]
+    pub range: ::core::option::Option<ByteRange>,
+    /// set when the partition is stored on the remote shuffle storage and path is an object store url
+    #[prost(bool, tag = "7")]
+    pub remote_storage: bool,
+    /// CRC32 checksum of the partition file, or of its byte range of a shared data file,
+    /// unset when the partition isn't checksummed
+    #[prost(uint32, optional, tag = "8")]
+    pub checksum: ::core::option::Option<u32>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct TaskStatus {
@@ -802,6 +1027,10 @@ pub struct UpdateTaskStatusResult {
 pub struct ExecuteQueryParams {
     #[prost(message, repeated, tag = "4")]
     pub settings: ::prost::alloc::vec::Vec<KeyValuePair>,
+    /// absolute deadline of the job in milliseconds since the Unix epoch, after which it is
+    /// cancelled and failed with a timeout error, 0 for no deadline
+    #[prost(uint64, tag = "5")]
+    pub deadline: u64,
     #[prost(oneof = "execute_query_params::Query", tags = "1, 2")]
     pub query: ::core::option::Option<execute_query_params::Query>,
     #[prost(oneof = "execute_query_params::OptionalSessionId", tags = "3")]
@@ -885,7 +1114,7 @@ pub struct ExecuteQuerySuccessResult {
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ExecuteQueryFailureResult {
-    #[prost(oneof = "execute_query_failure_result::Failure", tags = "1, 2, 3")]
+    #[prost(oneof = "execute_query_failure_result::Failure", tags = "1, 2, 3, 4")]
     pub failure: ::core::option::Option<execute_query_failure_result::Failure>,
 }
 /// Nested message and enum types in `ExecuteQueryFailureResult`.
@@ -898,6 +1127,8 @@ pub mod execute_query_failure_result {
         PlanParsingFailure(::prost::alloc::string::String),
         #[prost(string, tag = "3")]
         SqlParsingFailure(::prost::alloc::string::String),
+        #[prost(string, tag = "4")]
+        QuotaExceeded(::prost::alloc::string::String),
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -941,6 +1172,9 @@ pub struct FailedJob {
     pub started_at: u64,
     #[prost(uint64, tag = "4")]
     pub ended_at: u64,
+    /// whether the job was cancelled because it did not finish before its deadline
+    #[prost(bool, tag = "5")]
+    pub timed_out: bool,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct JobStatus {
@@ -1060,6 +1294,36 @@ pub struct RunningTaskInfo {
     #[prost(uint32, tag = "4")]
     pub partition_id: u32,
 }
+/// Compression codec of shuffle files and Flight transfers of shuffle partitions
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum CompressionCodec {
+    Lz4 = 0,
+    Zstd = 1,
+    None = 2,
+}
+impl CompressionCodec {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Lz4 => "COMPRESSION_CODEC_LZ4",
+            Self::Zstd => "COMPRESSION_CODEC_ZSTD",
+            Self::None => "COMPRESSION_CODEC_NONE",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "COMPRESSION_CODEC_LZ4" => Some(Self::Lz4),
+            "COMPRESSION_CODEC_ZSTD" => Some(Self::Zstd),
+            "COMPRESSION_CODEC_NONE" => Some(Self::None),
+            _ => None,
+        }
+    }
+}
 /// Generated client implementations.
 pub mod scheduler_grpc_client {
     #![allow(
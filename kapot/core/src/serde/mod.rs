@@ -28,7 +28,10 @@ use datafusion_proto::logical_plan::file_formats::{
     ArrowLogicalExtensionCodec, AvroLogicalExtensionCodec, CsvLogicalExtensionCodec,
     JsonLogicalExtensionCodec, ParquetLogicalExtensionCodec,
 };
-use datafusion_proto::physical_plan::from_proto::parse_protobuf_hash_partitioning;
+use datafusion_proto::physical_plan::from_proto::{
+    parse_physical_sort_exprs, parse_protobuf_hash_partitioning,
+};
+use datafusion_proto::physical_plan::to_proto::serialize_physical_sort_exprs;
 use datafusion_proto::protobuf::proto_error;
 use datafusion_proto::protobuf::{LogicalPlanNode, PhysicalPlanNode};
 use datafusion_proto::{
@@ -44,7 +47,8 @@ use std::sync::Arc;
 use std::{convert::TryInto, io::Cursor};
 
 use crate::execution_plans::{
-    ShuffleReaderExec, ShuffleWriterExec, UnresolvedShuffleExec,
+    RangePartitioning, RangeSampleExec, ShuffleReaderExec, ShuffleWriterExec,
+    UnresolvedShuffleExec,
 };
 use crate::serde::protobuf::kapot_physical_plan_node::PhysicalPlanType;
 use crate::serde::scheduler::PartitionLocation;
@@ -252,13 +256,45 @@ impl PhysicalExtensionCodec for KapotPhysicalExtensionCodec {
                     &default_codec,
                 )?;
 
-                Ok(Arc::new(ShuffleWriterExec::try_new(
+                let mut shuffle_writer_exec = ShuffleWriterExec::try_new(
                     shuffle_writer.job_id.clone(),
                     shuffle_writer.stage_id as usize,
-                    input,
+                    input.clone(),
                     "".to_string(), // this is intentional but hacky - the executor will fill this in
                     shuffle_output_partitioning,
-                )?))
+                )?
+                .with_sort_based_shuffle(shuffle_writer.sort_based_shuffle)
+                .with_buffer_size(shuffle_writer.buffer_size as usize)
+                .with_compression(shuffle_writer.compression().into())
+                .with_remote_storage(
+                    Some(shuffle_writer.remote_storage_url.clone())
+                        .filter(|url| !url.is_empty()),
+                )
+                .with_streaming(shuffle_writer.streaming);
+
+                if let Some(range_partitioning) = &shuffle_writer.range_partitioning {
+                    let samples = inputs.get(1).cloned().ok_or_else(|| {
+                        DataFusionError::Internal(
+                            "Range partitioned ShuffleWriterExec is missing its samples input"
+                                .to_string(),
+                        )
+                    })?;
+                    let sort_exprs = parse_physical_sort_exprs(
+                        &range_partitioning.sort_expr,
+                        registry,
+                        input.schema().as_ref(),
+                        &default_codec,
+                    )?;
+                    shuffle_writer_exec = shuffle_writer_exec.with_range_partitioning(
+                        RangePartitioning::new(
+                            sort_exprs,
+                            range_partitioning.partition_count as usize,
+                            samples,
+                        ),
+                    );
+                }
+
+                Ok(Arc::new(shuffle_writer_exec))
             }
             PhysicalPlanType::ShuffleReader(shuffle_reader) => {
                 let stage_id = shuffle_reader.stage_id as usize;
@@ -279,17 +315,53 @@ impl PhysicalExtensionCodec for KapotPhysicalExtensionCodec {
                             .collect::<Result<Vec<_>, _>>()
                     })
                     .collect::<Result<Vec<_>, DataFusionError>>()?;
-                let shuffle_reader =
-                    ShuffleReaderExec::try_new(stage_id, partition_location, schema)?;
-                Ok(Arc::new(shuffle_reader))
+                let mut shuffle_reader_exec =
+                    ShuffleReaderExec::try_new(stage_id, partition_location, schema)?
+                        .with_compression(shuffle_reader.compression().into())
+                        .with_fetch_limits(
+                            shuffle_reader.max_requests as usize,
+                            shuffle_reader.max_bytes_in_flight as usize,
+                        );
+                if shuffle_reader.shuffle_partition_count > 0 {
+                    shuffle_reader_exec = shuffle_reader_exec
+                        .with_shuffle_partition_count(
+                            shuffle_reader.shuffle_partition_count as usize,
+                        );
+                }
+                Ok(Arc::new(shuffle_reader_exec))
             }
             PhysicalPlanType::UnresolvedShuffle(unresolved_shuffle) => {
                 let schema = Arc::new(convert_required!(unresolved_shuffle.schema)?);
-                Ok(Arc::new(UnresolvedShuffleExec::new(
-                    unresolved_shuffle.stage_id as usize,
-                    schema,
-                    unresolved_shuffle.output_partition_count as usize,
-                )))
+                Ok(Arc::new(
+                    UnresolvedShuffleExec::new(
+                        unresolved_shuffle.stage_id as usize,
+                        schema,
+                        unresolved_shuffle.output_partition_count as usize,
+                    )
+                    .with_compression(unresolved_shuffle.compression().into())
+                    .with_fetch_limits(
+                        unresolved_shuffle.max_requests as usize,
+                        unresolved_shuffle.max_bytes_in_flight as usize,
+                    ),
+                ))
+            }
+            PhysicalPlanType::RangeSample(range_sample) => {
+                let input = inputs[0].clone();
+
+                let default_codec =
+                    datafusion_proto::physical_plan::DefaultPhysicalExtensionCodec {};
+
+                let sort_exprs = parse_physical_sort_exprs(
+                    &range_sample.sort_expr,
+                    registry,
+                    input.schema().as_ref(),
+                    &default_codec,
+                )?;
+                Ok(Arc::new(RangeSampleExec::try_new(
+                    input,
+                    sort_exprs,
+                    range_sample.sample_size as usize,
+                )?))
             }
         }
     }
@@ -314,6 +386,12 @@ impl PhysicalExtensionCodec for KapotPhysicalExtensionCodec {
                         partition_count: *partition_count as u64,
                     })
                 }
+                // range partitioning is encoded separately
+                Some(Partitioning::UnknownPartitioning(_))
+                    if exec.range_partitioning().is_some() =>
+                {
+                    None
+                }
                 None => None,
                 other => {
                     return Err(DataFusionError::Internal(format!(
@@ -322,6 +400,21 @@ impl PhysicalExtensionCodec for KapotPhysicalExtensionCodec {
                 }
             };
 
+            let range_partitioning = exec
+                .range_partitioning()
+                .map(|range_partitioning| {
+                    let default_codec =
+                        datafusion_proto::physical_plan::DefaultPhysicalExtensionCodec {};
+                    Ok::<_, DataFusionError>(protobuf::RangePartitioning {
+                        sort_expr: serialize_physical_sort_exprs(
+                            range_partitioning.sort_exprs().iter().cloned(),
+                            &default_codec,
+                        )?,
+                        partition_count: range_partitioning.partition_count() as u64,
+                    })
+                })
+                .transpose()?;
+
             let proto = protobuf::KapotPhysicalPlanNode {
                 physical_plan_type: Some(PhysicalPlanType::ShuffleWriter(
                     protobuf::ShuffleWriterExecNode {
@@ -329,6 +422,16 @@ impl PhysicalExtensionCodec for KapotPhysicalExtensionCodec {
                         stage_id: exec.stage_id() as u32,
                         input: None,
                         output_partitioning,
+                        sort_based_shuffle: exec.sort_based_shuffle(),
+                        range_partitioning,
+                        buffer_size: exec.buffer_size() as u64,
+                        compression: protobuf::CompressionCodec::from(exec.compression())
+                            .into(),
+                        remote_storage_url: exec
+                            .remote_storage_url()
+                            .unwrap_or_default()
+                            .to_owned(),
+                        streaming: exec.streaming(),
                     },
                 )),
             };
@@ -363,6 +466,11 @@ impl PhysicalExtensionCodec for KapotPhysicalExtensionCodec {
                         stage_id,
                         partition,
                         schema: Some(exec.schema().as_ref().try_into()?),
+                        compression: protobuf::CompressionCodec::from(exec.compression())
+                            .into(),
+                        max_requests: exec.max_requests() as u64,
+                        max_bytes_in_flight: exec.max_bytes_in_flight() as u64,
+                        shuffle_partition_count: exec.shuffle_partition_count() as u64,
                     },
                 )),
             };
@@ -380,6 +488,10 @@ impl PhysicalExtensionCodec for KapotPhysicalExtensionCodec {
                         stage_id: exec.stage_id as u32,
                         schema: Some(exec.schema().as_ref().try_into()?),
                         output_partition_count: exec.output_partition_count as u32,
+                        compression: protobuf::CompressionCodec::from(exec.compression)
+                            .into(),
+                        max_requests: exec.max_requests as u64,
+                        max_bytes_in_flight: exec.max_bytes_in_flight as u64,
                     },
                 )),
             };
@@ -389,6 +501,28 @@ impl PhysicalExtensionCodec for KapotPhysicalExtensionCodec {
                 ))
             })?;
 
+            Ok(())
+        } else if let Some(exec) = node.as_any().downcast_ref::<RangeSampleExec>() {
+            let default_codec =
+                datafusion_proto::physical_plan::DefaultPhysicalExtensionCodec {};
+            let proto = protobuf::KapotPhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::RangeSample(
+                    protobuf::RangeSampleExecNode {
+                        input: None,
+                        sort_expr: serialize_physical_sort_exprs(
+                            exec.sort_exprs().iter().cloned(),
+                            &default_codec,
+                        )?,
+                        sample_size: exec.sample_size() as u64,
+                    },
+                )),
+            };
+            proto.encode(buf).map_err(|e| {
+                DataFusionError::Internal(format!(
+                    "failed to encode range sample execution plan: {e:?}"
+                ))
+            })?;
+
             Ok(())
         } else {
             Err(DataFusionError::Internal(format!(
@@ -30,10 +30,12 @@ use std::convert::TryInto;
 use std::sync::Arc;
 use std::time::Duration;
 
+use crate::config::ShuffleCompression;
 use crate::error::KapotError;
 use crate::serde::scheduler::{
-    Action, ExecutorData, ExecutorMetadata, ExecutorSpecification, PartitionId,
-    PartitionLocation, PartitionStats, SimpleFunctionRegistry, TaskDefinition,
+    Action, ByteRange, ExecutorData, ExecutorMetadata, ExecutorSpecification,
+    PartitionId, PartitionLocation, PartitionStats, SimpleFunctionRegistry,
+    TaskDefinition,
 };
 
 use crate::serde::{protobuf, KapotCodec};
@@ -45,6 +47,7 @@ impl TryInto<Action> for protobuf::Action {
     fn try_into(self) -> Result<Action, Self::Error> {
         match self.action_type {
             Some(protobuf::action::ActionType::FetchPartition(fetch)) => {
+                let compression = fetch.compression().into();
                 Ok(Action::FetchPartition {
                     job_id: fetch.job_id,
                     stage_id: fetch.stage_id as usize,
@@ -52,6 +55,11 @@ impl TryInto<Action> for protobuf::Action {
                     path: fetch.path,
                     host: fetch.host,
                     port: fetch.port as u16,
+                    range: fetch.range.map(|r| r.into()),
+                    compression,
+                    checksum: fetch.checksum,
+                    streaming: fetch.streaming,
+                    map_partition_id: fetch.map_partition_id as usize,
                 })
             }
             _ => Err(KapotError::General(
@@ -122,10 +130,31 @@ impl TryInto<PartitionLocation> for protobuf::PartitionLocation {
                 })?
                 .into(),
             path: self.path,
+            range: self.range.map(|r| r.into()),
+            remote_storage: self.remote_storage,
+            checksum: self.checksum,
+            streaming: self.streaming,
         })
     }
 }
 
+#[allow(clippy::from_over_into)]
+impl Into<ByteRange> for protobuf::ByteRange {
+    fn into(self) -> ByteRange {
+        ByteRange::new(self.offset, self.length)
+    }
+}
+
+impl From<protobuf::CompressionCodec> for ShuffleCompression {
+    fn from(codec: protobuf::CompressionCodec) -> Self {
+        match codec {
+            protobuf::CompressionCodec::None => ShuffleCompression::None,
+            protobuf::CompressionCodec::Lz4 => ShuffleCompression::Lz4,
+            protobuf::CompressionCodec::Zstd => ShuffleCompression::Zstd,
+        }
+    }
+}
+
 impl TryInto<MetricValue> for protobuf::OperatorMetric {
     type Error = KapotError;
 
@@ -31,6 +31,7 @@ use datafusion::physical_plan::ExecutionPlan;
 use datafusion::physical_plan::Partitioning;
 use serde::Serialize;
 
+use crate::config::ShuffleCompression;
 use crate::error::KapotError;
 
 pub mod from_proto;
@@ -47,6 +48,14 @@ pub enum Action {
         path: String,
         host: String,
         port: u16,
+        range: Option<ByteRange>,
+        compression: ShuffleCompression,
+        /// CRC32 checksum the partition is verified against while it is sent
+        checksum: Option<u32>,
+        /// Set when the partition is streamed by the running map task `map_partition_id`
+        /// rather than read from `path`
+        streaming: bool,
+        map_partition_id: usize,
     },
 }
 
@@ -75,6 +84,33 @@ pub struct PartitionLocation {
     pub executor_meta: ExecutorMetadata,
     pub partition_stats: PartitionStats,
     pub path: String,
+    /// Set when the partition is stored as a byte range of a shared data file,
+    /// as written by the sort-based shuffle writer
+    pub range: Option<ByteRange>,
+    /// Set when the partition is stored on the remote shuffle storage, in which case
+    /// `path` is an object store url rather than a path on the executor
+    pub remote_storage: bool,
+    /// CRC32 checksum of the partition file, or of its byte range, as written by the
+    /// shuffle writer. Unset when the partition isn't checksummed, e.g. when it is
+    /// streamed
+    pub checksum: Option<u32>,
+    /// Set when the partition is streamed from the memory of the executor by the running
+    /// map task rather than read from a shuffle file, in which case `path` is empty
+    pub streaming: bool,
+}
+
+/// Byte range of a shuffle partition inside a data file shared by all the
+/// output partitions of a map task
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl ByteRange {
+    pub fn new(offset: u64, length: u64) -> Self {
+        Self { offset, length }
+    }
 }
 
 /// Meta-data for an executor, used when fetching shuffle partitions from other executors
@@ -137,6 +173,11 @@ impl PartitionStats {
         }
     }
 
+    /// Number of bytes of the partition, if known
+    pub fn num_bytes(&self) -> Option<u64> {
+        self.num_bytes
+    }
+
     pub fn arrow_struct_repr(self) -> Field {
         Field::new(
             "partition_stats",
@@ -19,14 +19,15 @@ use datafusion::error::DataFusionError;
 use datafusion::physical_plan::metrics::{MetricValue, MetricsSet};
 use std::convert::TryInto;
 
+use crate::config::ShuffleCompression;
 use crate::error::KapotError;
 
 use crate::serde::protobuf;
 use datafusion_proto::protobuf as datafusion_protobuf;
 
 use crate::serde::scheduler::{
-    Action, ExecutorData, ExecutorMetadata, ExecutorSpecification, PartitionId,
-    PartitionLocation, PartitionStats,
+    Action, ByteRange, ExecutorData, ExecutorMetadata, ExecutorSpecification,
+    PartitionId, PartitionLocation, PartitionStats,
 };
 use datafusion::physical_plan::Partitioning;
 use protobuf::{action::ActionType, operator_metric, NamedCount, NamedGauge, NamedTime};
@@ -43,6 +44,11 @@ impl TryInto<protobuf::Action> for Action {
                 path,
                 host,
                 port,
+                range,
+                compression,
+                checksum,
+                streaming,
+                map_partition_id,
             } => Ok(protobuf::Action {
                 action_type: Some(ActionType::FetchPartition(protobuf::FetchPartition {
                     job_id,
@@ -51,6 +57,11 @@ impl TryInto<protobuf::Action> for Action {
                     path,
                     host,
                     port: port as u32,
+                    range: range.map(|r| r.into()),
+                    compression: protobuf::CompressionCodec::from(compression).into(),
+                    checksum,
+                    streaming,
+                    map_partition_id: map_partition_id as u32,
                 })),
                 settings: vec![],
             }),
@@ -79,10 +90,34 @@ impl TryInto<protobuf::PartitionLocation> for PartitionLocation {
             executor_meta: Some(self.executor_meta.into()),
             partition_stats: Some(self.partition_stats.into()),
             path: self.path,
+            range: self.range.map(|r| r.into()),
+            remote_storage: self.remote_storage,
+            checksum: self.checksum,
+            streaming: self.streaming,
         })
     }
 }
 
+#[allow(clippy::from_over_into)]
+impl Into<protobuf::ByteRange> for ByteRange {
+    fn into(self) -> protobuf::ByteRange {
+        protobuf::ByteRange {
+            offset: self.offset,
+            length: self.length,
+        }
+    }
+}
+
+impl From<ShuffleCompression> for protobuf::CompressionCodec {
+    fn from(compression: ShuffleCompression) -> Self {
+        match compression {
+            ShuffleCompression::None => protobuf::CompressionCodec::None,
+            ShuffleCompression::Lz4 => protobuf::CompressionCodec::Lz4,
+            ShuffleCompression::Zstd => protobuf::CompressionCodec::Zstd,
+        }
+    }
+}
+
 #[allow(clippy::from_over_into)]
 impl Into<protobuf::PartitionStats> for PartitionStats {
     fn into(self) -> protobuf::PartitionStats {
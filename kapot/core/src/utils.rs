@@ -15,16 +15,18 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use crate::config::KapotConfig;
+use crate::config::{KapotConfig, ShuffleCompression};
 use crate::error::{KapotError, Result};
 use crate::execution_plans::{
     DistributedQueryExec, ShuffleWriterExec, UnresolvedShuffleExec,
 };
 use crate::object_store_registry::KapotObjectStoreRegistry;
-use crate::serde::scheduler::PartitionStats;
+use crate::serde::scheduler::{ByteRange, PartitionStats};
 
 use async_trait::async_trait;
-use datafusion::arrow::datatypes::Schema;
+use datafusion::arrow::datatypes::{Schema, SchemaRef};
+use datafusion::arrow::error::ArrowError;
+use datafusion::arrow::ipc::reader::StreamReader;
 use datafusion::arrow::ipc::writer::IpcWriteOptions;
 use datafusion::arrow::ipc::writer::StreamWriter;
 use datafusion::arrow::ipc::CompressionType;
@@ -52,7 +54,7 @@ use datafusion_proto::logical_plan::{
 };
 use futures::StreamExt;
 use log::error;
-use std::io::{BufWriter, Write};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Take, Write};
 use std::marker::PhantomData;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
@@ -76,12 +78,14 @@ pub fn default_session_builder(config: SessionConfig) -> SessionState {
         .build()
 }
 
-/// Stream data to disk in Arrow IPC format
+/// Stream data to disk in Arrow IPC format, returning the statistics of the written
+/// batches along with the CRC32 checksum of the file
 pub async fn write_stream_to_disk(
     stream: &mut Pin<Box<dyn RecordBatchStream + Send>>,
     path: &str,
     disk_write_metric: &metrics::Time,
-) -> Result<PartitionStats> {
+    compression: ShuffleCompression,
+) -> Result<(PartitionStats, u32)> {
     let file = File::create(path).map_err(|e| {
         error!("Failed to create partition file at {}: {:?}", path, e);
         KapotError::IoError(e)
@@ -91,11 +95,13 @@ pub async fn write_stream_to_disk(
     let mut num_batches = 0;
     let mut num_bytes = 0;
 
-    let options = IpcWriteOptions::default()
-        .try_with_compression(Some(CompressionType::LZ4_FRAME))?;
+    let options = ipc_write_options(compression)?;
 
-    let mut writer =
-        StreamWriter::try_new_with_options(file, stream.schema().as_ref(), options)?;
+    let mut writer = StreamWriter::try_new_with_options(
+        ChecksumWriter::new(file),
+        stream.schema().as_ref(),
+        options,
+    )?;
 
     while let Some(result) = stream.next().await {
         let batch = result?;
@@ -111,14 +117,216 @@ pub async fn write_stream_to_disk(
     }
     let timer = disk_write_metric.timer();
     writer.finish()?;
+    let checksum = writer.into_inner()?.checksum();
     timer.done();
-    Ok(PartitionStats::new(
-        Some(num_rows as u64),
-        Some(num_batches),
-        Some(num_bytes as u64),
+    Ok((
+        PartitionStats::new(
+            Some(num_rows as u64),
+            Some(num_batches),
+            Some(num_bytes as u64),
+        ),
+        checksum,
     ))
 }
 
+/// Arrow IPC write options compressing the record batches with the given codec
+pub fn ipc_write_options(
+    compression: ShuffleCompression,
+) -> std::result::Result<IpcWriteOptions, ArrowError> {
+    let compression = match compression {
+        ShuffleCompression::None => None,
+        ShuffleCompression::Lz4 => Some(CompressionType::LZ4_FRAME),
+        ShuffleCompression::Zstd => Some(CompressionType::ZSTD),
+    };
+    IpcWriteOptions::default().try_with_compression(compression)
+}
+
+/// Open a shuffle file for reading. When `range` is set the file is shared by several
+/// output partitions and only the bytes of the requested partition are exposed.
+pub fn open_shuffle_file(
+    path: &str,
+    range: Option<ByteRange>,
+) -> std::io::Result<BufReader<Take<File>>> {
+    let mut file = File::open(path)?;
+    let reader = match range {
+        Some(range) => {
+            file.seek(SeekFrom::Start(range.offset))?;
+            file.take(range.length)
+        }
+        None => file.take(u64::MAX),
+    };
+    Ok(BufReader::new(reader))
+}
+
+/// Writer computing the CRC32 checksum of the bytes written through it
+pub struct ChecksumWriter<W: Write> {
+    inner: W,
+    hasher: crc32fast::Hasher,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: crc32fast::Hasher::new(),
+        }
+    }
+
+    /// Checksum of the bytes written so far
+    pub fn checksum(&self) -> u32 {
+        self.hasher.clone().finalize()
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader computing the CRC32 checksum of the bytes read through it
+pub struct ChecksumReader<R: Read> {
+    inner: R,
+    hasher: crc32fast::Hasher,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: crc32fast::Hasher::new(),
+        }
+    }
+
+    /// Read the remaining bytes and compare the checksum of all the bytes read with the
+    /// one recorded by the shuffle writer
+    pub fn verify(&mut self, path: &str, expected: u32) -> Result<()> {
+        std::io::copy(self, &mut std::io::sink())?;
+        verify_checksum(path, expected, self.hasher.clone().finalize())
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Reader of the record batches of a shuffle partition file, or of its byte range of a
+/// shared data file. The partition is verified against the checksum recorded by the
+/// shuffle writer while it is decoded: a batch is read ahead of the one handed out, so
+/// that a corrupted partition fails before its last batch reaches the consumer.
+pub struct ShuffleFileReader<R: Read = BufReader<Take<File>>> {
+    reader: StreamReader<ChecksumReader<R>>,
+    path: String,
+    checksum: Option<u32>,
+    next_batch: Option<RecordBatch>,
+    finished: bool,
+}
+
+impl ShuffleFileReader {
+    pub fn try_new(
+        path: &str,
+        range: Option<ByteRange>,
+        checksum: Option<u32>,
+    ) -> Result<Self> {
+        let file = open_shuffle_file(path, range).map_err(|e| {
+            KapotError::General(format!("Failed to open partition file at {path}: {e:?}"))
+        })?;
+        Self::try_new_from_reader(file, path, checksum)
+    }
+}
+
+impl<R: Read> ShuffleFileReader<R> {
+    /// Read a shuffle partition from `reader`, `path` being only used in errors
+    pub fn try_new_from_reader(
+        reader: R,
+        path: &str,
+        checksum: Option<u32>,
+    ) -> Result<Self> {
+        let reader =
+            StreamReader::try_new(ChecksumReader::new(reader), None).map_err(|e| {
+                KapotError::General(format!(
+                    "Failed to new arrow FileReader at {path}: {e:?}"
+                ))
+            })?;
+        Ok(Self {
+            reader,
+            path: path.to_owned(),
+            checksum,
+            next_batch: None,
+            finished: false,
+        })
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.reader.schema()
+    }
+
+    /// Decode the next batch, verifying the checksum once the partition is read to
+    /// the end
+    fn read_batch(&mut self) -> Option<std::result::Result<RecordBatch, ArrowError>> {
+        if self.finished {
+            return None;
+        }
+        match self.reader.next() {
+            Some(Ok(batch)) => Some(Ok(batch)),
+            Some(Err(e)) => {
+                self.finished = true;
+                Some(Err(e))
+            }
+            None => {
+                self.finished = true;
+                let checksum = self.checksum?;
+                self.reader
+                    .get_mut()
+                    .verify(&self.path, checksum)
+                    .err()
+                    .map(|e| Err(ArrowError::ExternalError(Box::new(e))))
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for ShuffleFileReader<R> {
+    type Item = std::result::Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch = match self.next_batch.take() {
+            Some(batch) => batch,
+            None => match self.read_batch()? {
+                Ok(batch) => batch,
+                Err(e) => return Some(Err(e)),
+            },
+        };
+        match self.read_batch() {
+            Some(Ok(next_batch)) => self.next_batch = Some(next_batch),
+            // the error takes the place of the batch read before it
+            Some(Err(e)) => return Some(Err(e)),
+            None => {}
+        }
+        Some(Ok(batch))
+    }
+}
+
+/// Compare the checksum of a shuffle partition with the one recorded by its writer
+pub fn verify_checksum(path: &str, expected: u32, actual: u32) -> Result<()> {
+    if expected != actual {
+        return Err(KapotError::General(format!(
+            "Checksum mismatch of shuffle partition at {path}: expected {expected:#010x}, got {actual:#010x}"
+        )));
+    }
+    Ok(())
+}
+
 pub async fn collect_stream(
     stream: &mut Pin<Box<dyn RecordBatchStream + Send>>,
 ) -> Result<Vec<RecordBatch>> {
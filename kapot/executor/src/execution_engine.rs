@@ -75,6 +75,22 @@ impl ExecutionEngine for DefaultExecutionEngine {
                 work_dir.to_string(),
                 shuffle_writer.shuffle_output_partitioning().cloned(),
             )
+            .map(|exec| {
+                let exec = exec
+                    .with_sort_based_shuffle(shuffle_writer.sort_based_shuffle())
+                    .with_buffer_size(shuffle_writer.buffer_size())
+                    .with_compression(shuffle_writer.compression())
+                    .with_remote_storage(
+                        shuffle_writer.remote_storage_url().map(str::to_owned),
+                    )
+                    .with_streaming(shuffle_writer.streaming());
+                match shuffle_writer.range_partitioning() {
+                    Some(range_partitioning) => {
+                        exec.with_range_partitioning(range_partitioning.clone())
+                    }
+                    None => exec,
+                }
+            })
         } else {
             Err(DataFusionError::Internal(
                 "Plan passed to new_query_stage_exec is not a ShuffleWriterExec"
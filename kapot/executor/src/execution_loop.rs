@@ -29,6 +29,7 @@ use crate::cpu_bound_executor::DedicatedExecutor;
 use crate::executor::Executor;
 use crate::{as_task_status, TaskExecutionTimes};
 use kapot_core::error::KapotError;
+use kapot_core::execution_plans::ShuffleWriterTaskId;
 use kapot_core::serde::scheduler::{ExecutorSpecification, PartitionId};
 use kapot_core::serde::KapotCodec;
 use datafusion::execution::context::TaskContext;
@@ -182,7 +183,8 @@ async fn run_received_task<T: 'static + AsLogicalPlan, U: 'static + AsExecutionP
     for (k, v) in task_props {
         config.set(&k, &v)?;
     }
-    let session_config = SessionConfig::from(config);
+    let session_config = SessionConfig::from(config)
+        .with_extension(Arc::new(ShuffleWriterTaskId(task_id as usize)));
 
     let mut task_scalar_functions = HashMap::new();
     let mut task_aggregate_functions = HashMap::new();
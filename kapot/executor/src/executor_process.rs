@@ -288,6 +288,7 @@ pub async fn start_executor_process(opt: Arc<ExecutorProcessConfig>) -> Result<(
 
     let scheduler_policy = opt.task_scheduling_policy;
     let job_data_ttl_seconds = opt.job_data_ttl_seconds;
+    let flight_work_dir = work_dir.clone();
 
     // Graceful shutdown notification
     let shutdown_noti = ShutdownNotifier::new();
@@ -353,6 +354,7 @@ pub async fn start_executor_process(opt: Arc<ExecutorProcessConfig>) -> Result<(
     };
     service_handlers.push(tokio::spawn(flight_server_run(
         addr,
+        flight_work_dir,
         shutdown_noti.subscribe_for_shutdown(),
     )));
 
@@ -456,9 +458,10 @@ pub async fn start_executor_process(opt: Arc<ExecutorProcessConfig>) -> Result<(
 // Arrow flight service
 async fn flight_server_run(
     addr: SocketAddr,
+    work_dir: String,
     mut grpc_shutdown: Shutdown,
 ) -> Result<(), KapotError> {
-    let service = KapotFlightService::new();
+    let service = KapotFlightService::new(work_dir);
     let server = FlightServiceServer::new(service);
     info!(
         "kapot v{} Rust Executor Flight Server listening on {:?}",
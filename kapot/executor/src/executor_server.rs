@@ -30,6 +30,7 @@ use tonic::{Request, Response, Status};
 
 use kapot_core::config::KAPOT_DATA_CACHE_ENABLED;
 use kapot_core::error::KapotError;
+use kapot_core::execution_plans::{ShuffleStreams, ShuffleWriterTaskId};
 use kapot_core::serde::protobuf::{
     executor_grpc_server::{ExecutorGrpc, ExecutorGrpcServer},
     executor_metric, executor_status,
@@ -354,7 +355,8 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> ExecutorServer<T,
                     debug!("Fail to set session config for ({},{}): {:?}", k, v, e);
                 }
             }
-            let session_config = SessionConfig::from(config);
+            let session_config = SessionConfig::from(config)
+                .with_extension(Arc::new(ShuffleWriterTaskId(task_id)));
 
             let function_registry = task.function_registry;
             if data_cache {
@@ -754,6 +756,9 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> ExecutorGrpc
     ) -> Result<Response<RemoveJobDataResult>, Status> {
         let job_id = request.into_inner().job_id;
 
+        // the shuffle streams of gang scheduled jobs are only kept in memory
+        ShuffleStreams::global().remove_job(&job_id);
+
         let work_dir = PathBuf::from(&self.executor.work_dir);
         let mut path = work_dir.clone();
         path.push(&job_id);
@@ -17,19 +17,17 @@
 
 //! Implementation of the Apache Arrow Flight protocol that wraps an executor.
 
-use arrow::ipc::reader::StreamReader;
 use std::convert::TryFrom;
-use std::fs::File;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 
-use arrow::ipc::CompressionType;
 use arrow_flight::encode::FlightDataEncoderBuilder;
 use arrow_flight::error::FlightError;
-use kapot_core::error::KapotError;
+use kapot_core::execution_plans::{ShuffleStreamKey, ShuffleStreams};
 use kapot_core::serde::decode_protobuf;
 use kapot_core::serde::scheduler::Action as kapotAction;
+use kapot_core::utils;
 
-use arrow::ipc::writer::IpcWriteOptions;
 use arrow_flight::{
     flight_service_server::FlightService, Action, ActionType, Criteria, Empty,
     FlightData, FlightDescriptor, FlightInfo, HandshakeRequest, HandshakeResponse,
@@ -38,7 +36,6 @@ use arrow_flight::{
 use datafusion::arrow::{error::ArrowError, record_batch::RecordBatch};
 use futures::{Stream, StreamExt, TryStreamExt};
 use log::{debug, info};
-use std::io::{Read, Seek};
 use tokio::sync::mpsc::channel;
 use tokio::sync::mpsc::error::SendError;
 use tokio::{sync::mpsc::Sender, task};
@@ -49,17 +46,16 @@ use tracing::warn;
 
 /// Service implementing the Apache Arrow Flight Protocol
 #[derive(Clone)]
-pub struct KapotFlightService {}
-
-impl KapotFlightService {
-    pub fn new() -> Self {
-        Self {}
-    }
+pub struct KapotFlightService {
+    /// Work directory of the executor, only shuffle files below it are served
+    work_dir: PathBuf,
 }
 
-impl Default for KapotFlightService {
-    fn default() -> Self {
-        Self::new()
+impl KapotFlightService {
+    pub fn new(work_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            work_dir: work_dir.into(),
+        }
     }
 }
 
@@ -86,30 +82,88 @@ impl FlightService for KapotFlightService {
             decode_protobuf(&ticket.ticket).map_err(|e| from_kapot_err(&e))?;
 
         match &action {
-            kapotAction::FetchPartition { path, .. } => {
-                debug!("FetchPartition reading {}", path);
-                let file = File::open(path)
-                    .map_err(|e| {
-                        KapotError::General(format!(
-                            "Failed to open partition file at {path}: {e:?}"
-                        ))
-                    })
-                    .map(|file| std::io::BufReader::new(file))
-                    .map_err(|e| from_kapot_err(&e))?;
-                let reader =
-                    StreamReader::try_new(file, None).map_err(|e| from_arrow_err(&e))?;
+            kapotAction::FetchPartition {
+                job_id,
+                stage_id,
+                partition_id,
+                path,
+                range,
+                compression,
+                checksum,
+                streaming,
+                map_partition_id,
+                ..
+            } => {
+                let write_options = utils::ipc_write_options(*compression)
+                    .map_err(|e| from_arrow_err(&e))?;
+                if *streaming {
+                    debug!(
+                        "FetchPartition streaming {}/{}/{} of map task {}",
+                        job_id, stage_id, partition_id, map_partition_id
+                    );
+                    let key = ShuffleStreamKey {
+                        job_id: job_id.clone(),
+                        stage_id: *stage_id,
+                        map_partition: *map_partition_id,
+                        output_partition: *partition_id,
+                    };
+                    let stream = ShuffleStreams::global()
+                        .read(key)
+                        .await
+                        .map_err(|e| from_kapot_err(&e.into()))?;
+                    let flight_data_stream = FlightDataEncoderBuilder::new()
+                        .with_schema(stream.schema())
+                        .with_options(write_options)
+                        .build(
+                            stream.map_err(|e| FlightError::ExternalError(Box::new(e))),
+                        )
+                        .map_err(|err| Status::from_error(Box::new(err)));
+                    return Ok(Response::new(
+                        Box::pin(flight_data_stream) as Self::DoGetStream
+                    ));
+                }
+
+                debug!("FetchPartition reading {} {:?}", path, range);
+                let path = check_partition_path(
+                    &self.work_dir,
+                    path,
+                    job_id,
+                    *stage_id,
+                    *partition_id,
+                )?
+                .to_string_lossy()
+                .into_owned();
+                // the partition is verified while it is sent, a corrupted partition
+                // failing the fetch so that the map task is rerun. The IPC reader is
+                // synchronous and decodes the schema when the partition is opened
+                let (range, checksum) = (*range, *checksum);
+                let open_path = path.clone();
+                let reader = task::spawn_blocking(move || {
+                    utils::ShuffleFileReader::try_new(&open_path, range, checksum)
+                })
+                .await
+                .map_err(|e| Status::internal(format!("Failed to open {path}: {e}")))?
+                .map_err(|e| from_kapot_err(&e))?;
 
                 let (tx, rx) = channel(2);
                 let schema = reader.schema();
-                task::spawn_blocking(move || {
-                    if let Err(e) = read_partition(reader, tx) {
+                let task_tx = tx.clone();
+                let task = task::spawn_blocking(move || {
+                    if let Err(e) = read_partition(reader, task_tx) {
                         warn!(error = %e, "error streaming shuffle partition");
                     }
                 });
+                // a task failing to decode the partition, e.g. on corrupted data, must
+                // not end the stream as if the partition was read to the end
+                tokio::spawn(async move {
+                    if let Err(e) = task.await {
+                        let status = Status::internal(format!(
+                            "Failed to read shuffle partition {path}: {e}"
+                        ));
+                        let _ = tx.send(Err(FlightError::Tonic(status))).await;
+                    }
+                });
 
-                let write_options: IpcWriteOptions = IpcWriteOptions::default()
-                    .try_with_compression(Some(CompressionType::LZ4_FRAME))
-                    .map_err(|e| from_arrow_err(&e))?;
                 let flight_data_stream = FlightDataEncoderBuilder::new()
                     .with_schema(schema)
                     .with_options(write_options)
@@ -213,13 +267,10 @@ impl FlightService for KapotFlightService {
     }
 }
 
-fn read_partition<T>(
-    reader: StreamReader<std::io::BufReader<T>>,
+fn read_partition(
+    reader: utils::ShuffleFileReader,
     tx: Sender<Result<RecordBatch, FlightError>>,
-) -> Result<(), FlightError>
-where
-    T: Read + Seek,
-{
+) -> Result<(), FlightError> {
     if tx.is_closed() {
         return Err(FlightError::Tonic(Status::internal(
             "Can't send a batch, channel is closed",
@@ -241,6 +292,58 @@ where
     Ok(())
 }
 
+/// Resolve the path of a shuffle partition requested by a FetchPartition ticket,
+/// rejecting paths outside of the work_dir and paths not matching the job, stage and
+/// partition of the ticket. The shuffle files of a stage are laid out as
+/// `<job_id>/<stage_id>/<partition_id>/data*.arrow`, or as
+/// `<job_id>/<stage_id>/data-*.arrow` for the sort-based shuffle.
+fn check_partition_path(
+    work_dir: &Path,
+    path: &str,
+    job_id: &str,
+    stage_id: usize,
+    partition_id: usize,
+) -> Result<PathBuf, Status> {
+    let invalid_path =
+        || Status::permission_denied(format!("Invalid shuffle partition path {path}"));
+
+    // canonicalization resolves `..` and symbolic links
+    let work_dir = work_dir.canonicalize().map_err(|e| {
+        Status::internal(format!("Failed to resolve the work_dir {work_dir:?}: {e}"))
+    })?;
+    // don't tell whether files outside of the work_dir exist
+    let canonical_path = Path::new(path).canonicalize().map_err(|_| {
+        if Path::new(path).starts_with(&work_dir) && !path.contains("..") {
+            Status::not_found(format!("Shuffle partition {path} not found"))
+        } else {
+            invalid_path()
+        }
+    })?;
+    let relative_path = canonical_path
+        .strip_prefix(&work_dir)
+        .map_err(|_| invalid_path())?;
+
+    let components = relative_path
+        .iter()
+        .map(|component| component.to_str().ok_or_else(invalid_path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (file_name, dirs) = components.split_last().ok_or_else(invalid_path)?;
+    let stage_id = stage_id.to_string();
+    let partition_id = partition_id.to_string();
+    let matches_ticket = match dirs {
+        [job, stage] => *job == job_id && *stage == stage_id,
+        [job, stage, partition] => {
+            *job == job_id && *stage == stage_id && *partition == partition_id
+        }
+        _ => false,
+    };
+    if !matches_ticket || !file_name.starts_with("data") || !file_name.ends_with(".arrow")
+    {
+        return Err(invalid_path());
+    }
+    Ok(canonical_path)
+}
+
 fn from_arrow_err(e: &ArrowError) -> Status {
     Status::internal(format!("ArrowError: {e:?}"))
 }
@@ -248,3 +351,94 @@ fn from_arrow_err(e: &ArrowError) -> Status {
 fn from_kapot_err(e: &kapot_core::error::KapotError) -> Status {
     Status::internal(format!("kapot Error: {e:?}"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+    use tonic::Code;
+
+    fn create_file(path: &Path) -> String {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, b"data").unwrap();
+        path.to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn test_check_partition_path() {
+        let work_dir = TempDir::new().unwrap();
+        let hash_path = create_file(&work_dir.path().join("job/1/2/data.arrow"));
+        let spill_path = create_file(&work_dir.path().join("job/1/2/data-3.arrow"));
+        let sort_path = create_file(&work_dir.path().join("job/1/data-0.arrow"));
+
+        for path in [&hash_path, &spill_path, &sort_path] {
+            let resolved =
+                check_partition_path(work_dir.path(), path, "job", 1, 2).unwrap();
+            assert_eq!(Path::new(path).canonicalize().unwrap(), resolved);
+        }
+
+        // the job, stage and partition of the ticket must match the path
+        for (job_id, stage_id, partition_id) in
+            [("other", 1, 2), ("job", 2, 2), ("job", 1, 3)]
+        {
+            let err = check_partition_path(
+                work_dir.path(),
+                &hash_path,
+                job_id,
+                stage_id,
+                partition_id,
+            )
+            .unwrap_err();
+            assert_eq!(Code::PermissionDenied, err.code());
+        }
+
+        // only the data files of a stage are served
+        let index_path = create_file(&work_dir.path().join("job/1/index-0.arrow"));
+        let err =
+            check_partition_path(work_dir.path(), &index_path, "job", 1, 2).unwrap_err();
+        assert_eq!(Code::PermissionDenied, err.code());
+    }
+
+    #[test]
+    fn test_check_partition_path_traversal() {
+        let root = TempDir::new().unwrap();
+        let work_dir = root.path().join("work_dir");
+        create_file(&work_dir.join("job/1/2/data.arrow"));
+        let outside_path = create_file(&root.path().join("job/1/2/data.arrow"));
+
+        let traversal_path = format!(
+            "{}/job/1/2/../../../../job/1/2/data.arrow",
+            work_dir.display()
+        );
+        for path in [
+            traversal_path.as_str(),
+            outside_path.as_str(),
+            "/etc/passwd",
+        ] {
+            let err = check_partition_path(&work_dir, path, "job", 1, 2).unwrap_err();
+            assert_eq!(Code::PermissionDenied, err.code(), "{path}");
+        }
+
+        // a symbolic link below the work_dir pointing outside of it
+        #[cfg(unix)]
+        {
+            let link_dir = work_dir.join("job/1/3");
+            std::os::unix::fs::symlink(root.path().join("job/1/2"), &link_dir).unwrap();
+            let link_path = link_dir.join("data.arrow");
+            let err =
+                check_partition_path(&work_dir, link_path.to_str().unwrap(), "job", 1, 3)
+                    .unwrap_err();
+            assert_eq!(Code::PermissionDenied, err.code());
+        }
+
+        let missing_path = format!("{}/job/1/2/data-9.arrow", work_dir.display());
+        let err =
+            check_partition_path(&work_dir, &missing_path, "job", 1, 2).unwrap_err();
+        assert_eq!(Code::NotFound, err.code());
+        let missing_path = format!("{}/missing/data.arrow", root.path().display());
+        let err =
+            check_partition_path(&work_dir, &missing_path, "job", 1, 2).unwrap_err();
+        assert_eq!(Code::PermissionDenied, err.code());
+    }
+}
@@ -89,7 +89,7 @@ pub async fn new_standalone_executor<
         None,
     ));
 
-    let service = KapotFlightService::new();
+    let service = KapotFlightService::new(work_dir.clone());
     let server = FlightServiceServer::new(service);
     tokio::spawn(
         create_grpc_server()
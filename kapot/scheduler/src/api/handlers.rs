@@ -14,8 +14,9 @@ use crate::scheduler_server::event::QueryStageSchedulerEvent;
 use crate::scheduler_server::SchedulerServer;
 use crate::state::execution_graph::ExecutionStage;
 use crate::state::execution_graph_dot::ExecutionGraphDot;
+use crate::state::job_history::JobHistoryFilter;
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     response::{IntoResponse, Response},
     Json,
 };
@@ -42,6 +43,9 @@ pub struct ExecutorMetaResponse {
     pub host: String,
     pub port: u16,
     pub last_seen: u128,
+    /// Time in seconds the exclusion of the executor from task scheduling expires at,
+    /// if it is excluded
+    pub excluded_until: Option<u64>,
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -54,6 +58,13 @@ pub struct JobResponse {
     pub percent_complete: u8,
 }
 
+#[derive(Debug, serde::Serialize)]
+pub struct TenantResponse {
+    pub tenant: String,
+    pub queued_jobs: usize,
+    pub running_jobs: usize,
+}
+
 #[derive(Debug, serde::Serialize)]
 struct CancelJobResponse {
     pub cancelled: bool,
@@ -88,6 +99,7 @@ pub async fn get_executors<
     State(data_server): State<Arc<SchedulerServer<T, U>>>,
 ) -> impl IntoResponse {
     let state = &data_server.state;
+    let excluded_executors = state.executor_manager.get_excluded_executors();
     let executors: Vec<ExecutorMetaResponse> = state
         .executor_manager
         .get_executor_state()
@@ -95,6 +107,7 @@ pub async fn get_executors<
         .unwrap_or_default()
         .into_iter()
         .map(|(metadata, duration)| ExecutorMetaResponse {
+            excluded_until: excluded_executors.get(&metadata.id).copied(),
             id: metadata.id,
             host: metadata.host,
             port: metadata.port,
@@ -105,6 +118,72 @@ pub async fn get_executors<
     Json(executors)
 }
 
+pub async fn get_tenants<
+    T: AsLogicalPlan + Clone + Send + Sync + 'static,
+    U: AsExecutionPlan + Send + Sync + 'static,
+>(
+    State(data_server): State<Arc<SchedulerServer<T, U>>>,
+) -> impl IntoResponse {
+    let mut tenants: Vec<TenantResponse> = data_server
+        .state
+        .task_manager
+        .tenant_job_numbers()
+        .into_iter()
+        .map(|(tenant, (queued_jobs, running_jobs))| TenantResponse {
+            tenant,
+            queued_jobs,
+            running_jobs,
+        })
+        .collect();
+    tenants.sort_by(|a, b| a.tenant.cmp(&b.tenant));
+
+    Json(tenants)
+}
+
+pub async fn get_job_history<
+    T: AsLogicalPlan + Clone + Send + Sync + 'static,
+    U: AsExecutionPlan + Send + Sync + 'static,
+>(
+    State(data_server): State<Arc<SchedulerServer<T, U>>>,
+    Query(filter): Query<JobHistoryFilter>,
+) -> Result<impl IntoResponse, StatusCode> {
+    // 404 if the job history is not enabled
+    let job_history = data_server
+        .state
+        .task_manager
+        .job_history()
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let page = job_history
+        .list(&filter)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(page))
+}
+
+pub async fn get_job_history_summary<
+    T: AsLogicalPlan + Clone + Send + Sync + 'static,
+    U: AsExecutionPlan + Send + Sync + 'static,
+>(
+    State(data_server): State<Arc<SchedulerServer<T, U>>>,
+    Path(job_id): Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let job_history = data_server
+        .state
+        .task_manager
+        .job_history()
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let job = job_history
+        .get(&job_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(job))
+}
+
 pub async fn get_jobs<
     T: AsLogicalPlan + Clone + Send + Sync + 'static,
     U: AsExecutionPlan + Send + Sync + 'static,
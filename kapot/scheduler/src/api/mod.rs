@@ -29,6 +29,12 @@ pub fn get_routes<
         .route("/api/state", get(handlers::get_scheduler_state::<T, U>))
         .route("/api/executors", get(handlers::get_executors::<T, U>))
         .route("/api/jobs", get(handlers::get_jobs::<T, U>))
+        .route("/api/tenants", get(handlers::get_tenants::<T, U>))
+        .route("/api/history", get(handlers::get_job_history::<T, U>))
+        .route(
+            "/api/history/:job_id",
+            get(handlers::get_job_history_summary::<T, U>),
+        )
         .route("/api/job/:job_id", patch(handlers::cancel_job::<T, U>))
         .route(
             "/api/job/:job_id/stages",
@@ -17,12 +17,13 @@
 
 use crate::cluster::storage::{KeyValueStore, Keyspace, Lock, Operation, WatchEvent};
 use crate::cluster::{
-    bind_task_bias, bind_task_consistent_hash, bind_task_round_robin, get_scan_files,
+    bind_gang_tasks, bind_speculative_tasks, bind_task_bias, bind_task_consistent_hash,
+    bind_task_round_robin, get_scan_files, has_pending_gang_jobs,
     is_skip_consistent_hash, BoundTask, ClusterState, ExecutorHeartbeatStream,
     ExecutorSlot, JobState, JobStateEvent, JobStateEventStream, JobStatus,
     TaskDistributionPolicy, TopologyNode,
 };
-use crate::scheduler_server::{timestamp_secs, SessionBuilder};
+use crate::scheduler_server::{timestamp_millis, timestamp_secs, SessionBuilder};
 use crate::state::execution_graph::ExecutionGraph;
 use crate::state::session_manager::create_datafusion_context;
 use crate::state::task_manager::JobInfoCache;
@@ -34,7 +35,7 @@ use kapot_core::error::{KapotError, Result};
 use kapot_core::serde::protobuf::job_status::Status;
 use kapot_core::serde::protobuf::{
     self, AvailableTaskSlots, ExecutorHeartbeat, ExecutorTaskSlots, FailedJob,
-    KeyValuePair, QueuedJob,
+    KeyValuePair, Lease, QueuedJob, QueuedJobInfo,
 };
 use kapot_core::serde::scheduler::{ExecutorData, ExecutorMetadata};
 use kapot_core::serde::KapotCodec;
@@ -52,6 +53,9 @@ use std::collections::{HashMap, HashSet};
 use std::future::Future;
 use std::sync::Arc;
 
+/// Key of the lease on the leadership of the cluster
+const LEADER_LEASE: &str = "leader";
+
 /// State implementation based on underlying `KeyValueStore`
 pub struct KeyValueState<
     S: KeyValueStore,
@@ -67,7 +71,6 @@ pub struct KeyValueState<
     /// Codec used to serialize/deserialize execution plan
     codec: KapotCodec<T, U>,
     /// Name of current scheduler. Should be `{host}:{port}`
-    #[allow(dead_code)]
     scheduler: String,
     /// In-memory store of queued jobs. Map from Job ID -> (Job Name, queued_at timestamp)
     queued_jobs: DashMap<String, (String, u64)>,
@@ -139,6 +142,31 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
             .boxed())
     }
 
+    /// Get the lease stored under `key`, if any
+    async fn get_lease(&self, keyspace: Keyspace, key: &str) -> Result<Option<Lease>> {
+        let value = self.store.get(keyspace, key).await?;
+
+        (!value.is_empty())
+            .then(|| decode_protobuf(value.as_slice()))
+            .transpose()
+    }
+
+    /// Store the lease of this scheduler under `key`, lasting for `lease_seconds`
+    async fn put_lease(
+        &self,
+        keyspace: Keyspace,
+        key: &str,
+        lease_seconds: u64,
+    ) -> Result<()> {
+        let lease = Lease {
+            holder: self.scheduler.clone(),
+            expires_at: timestamp_secs() + lease_seconds,
+        };
+        self.store
+            .put(keyspace, key.to_owned(), lease.encode_to_vec())
+            .await
+    }
+
     /// Get the topology nodes of the cluster for consistent hashing
     fn get_topology_nodes(
         &self,
@@ -232,6 +260,32 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
                     ))
                 })?;
 
+            // Launch the gang scheduled jobs first, as they need all their slots at once.
+            // The metadata of the executors is only read when such a job is waiting
+            let mut bound_tasks = vec![];
+            if has_pending_gang_jobs(&active_jobs).await {
+                let mut executors_metadata = HashMap::new();
+                for data in slots.task_slots.iter() {
+                    if executors
+                        .as_ref()
+                        .map(|executors| executors.contains(&data.executor_id))
+                        .unwrap_or(true)
+                    {
+                        if let Ok(metadata) =
+                            self.get_executor_metadata(&data.executor_id).await
+                        {
+                            executors_metadata.insert(data.executor_id.clone(), metadata);
+                        }
+                    }
+                }
+                bound_tasks = bind_gang_tasks(
+                    slots.task_slots.iter_mut().collect(),
+                    active_jobs.clone(),
+                    &executors_metadata,
+                )
+                .await;
+            }
+
             let available_slots: Vec<&mut AvailableTaskSlots> = slots
                 .task_slots
                 .iter_mut()
@@ -245,12 +299,13 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
                 })
                 .collect();
 
-            let bound_tasks = match distribution {
+            bound_tasks.extend(match distribution {
                 TaskDistributionPolicy::Bias => {
-                    bind_task_bias(available_slots, active_jobs, |_| false).await
+                    bind_task_bias(available_slots, active_jobs.clone(), |_| false).await
                 }
                 TaskDistributionPolicy::RoundRobin => {
-                    bind_task_round_robin(available_slots, active_jobs, |_| false).await
+                    bind_task_round_robin(available_slots, active_jobs.clone(), |_| false)
+                        .await
                 }
                 TaskDistributionPolicy::ConsistentHash {
                     num_replicas,
@@ -272,10 +327,10 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
                     info!("{} tasks bound by round robin policy", bound_tasks.len());
                     let (bound_tasks_consistent_hash, ch_topology) =
                         bind_task_consistent_hash(
-                            self.get_topology_nodes(&slots.task_slots, executors),
+                            self.get_topology_nodes(&slots.task_slots, executors.clone()),
                             num_replicas,
                             tolerance,
-                            active_jobs,
+                            active_jobs.clone(),
                             |_, plan| get_scan_files(plan),
                         )
                         .await?;
@@ -304,7 +359,23 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
                     }
                     bound_tasks
                 }
-            };
+            });
+
+            // Launch a copy of the straggler tasks in the slots left over
+            let available_slots: Vec<&mut AvailableTaskSlots> = slots
+                .task_slots
+                .iter_mut()
+                .filter_map(|data| {
+                    (data.slots > 0
+                        && executors
+                            .as_ref()
+                            .map(|executors| executors.contains(&data.executor_id))
+                            .unwrap_or(true))
+                    .then_some(data)
+                })
+                .collect();
+            bound_tasks
+                .extend(bind_speculative_tasks(available_slots, active_jobs).await);
 
             if !bound_tasks.is_empty() {
                 self.store
@@ -505,6 +576,27 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
         Ok(())
     }
 
+    async fn save_queued_job(&self, job: QueuedJobInfo) -> Result<()> {
+        self.store
+            .put(
+                Keyspace::QueuedJobs,
+                job.job_id.clone(),
+                job.encode_to_vec(),
+            )
+            .await
+    }
+
+    async fn get_queued_jobs(&self) -> Result<Vec<QueuedJobInfo>> {
+        let mut queued_jobs = vec![];
+        for (_, value) in self.store.scan(Keyspace::QueuedJobs, None).await? {
+            let job: QueuedJobInfo = decode_protobuf(&value)?;
+            if job.scheduler_id == self.scheduler {
+                queued_jobs.push(job);
+            }
+        }
+        Ok(queued_jobs)
+    }
+
     fn pending_job_number(&self) -> usize {
         self.queued_jobs.len()
     }
@@ -527,6 +619,7 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
                         Keyspace::ExecutionGraph,
                         job_id.clone(),
                     ),
+                    (Operation::Delete, Keyspace::QueuedJobs, job_id.clone()),
                 ])
                 .await?;
 
@@ -600,7 +693,12 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
             .await
     }
 
-    async fn fail_unscheduled_job(&self, job_id: &str, reason: String) -> Result<()> {
+    async fn fail_unscheduled_job(
+        &self,
+        job_id: &str,
+        reason: String,
+        timed_out: bool,
+    ) -> Result<()> {
         if let Some((job_id, (job_name, queued_at))) = self.queued_jobs.remove(job_id) {
             let status = JobStatus {
                 job_id: job_id.clone(),
@@ -609,12 +707,20 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
                     error: reason,
                     queued_at,
                     started_at: 0,
-                    ended_at: 0,
+                    ended_at: timestamp_millis(),
+                    timed_out,
                 })),
             };
 
             self.store
-                .put(Keyspace::JobStatus, job_id, status.encode_to_vec())
+                .apply_txn(vec![
+                    (
+                        Operation::Put(status.encode_to_vec()),
+                        Keyspace::JobStatus,
+                        job_id.clone(),
+                    ),
+                    (Operation::Delete, Keyspace::QueuedJobs, job_id),
+                ])
                 .await
         } else {
             Err(KapotError::Internal(format!(
@@ -633,17 +739,104 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
                         Keyspace::ExecutionGraph,
                         job_id.to_string(),
                     ),
+                    (Operation::Delete, Keyspace::JobLeases, job_id.to_string()),
                 ])
                 .await
         } else {
-            Ok(())
+            self.store.delete(Keyspace::QueuedJobs, job_id).await
         }
     }
 
-    async fn try_acquire_job(&self, _job_id: &str) -> Result<Option<ExecutionGraph>> {
-        Err(KapotError::NotImplemented(
-            "Work stealing is not currently implemented".to_string(),
-        ))
+    async fn try_acquire_job(
+        &self,
+        job_id: &str,
+        lease_seconds: u64,
+    ) -> Result<Option<ExecutionGraph>> {
+        let lock = self.store.lock(Keyspace::JobLeases, job_id).await?;
+
+        with_lock(lock, async {
+            match self.get_lease(Keyspace::JobLeases, job_id).await? {
+                Some(lease)
+                    if lease.holder != self.scheduler
+                        && lease.expires_at <= timestamp_secs() => {}
+                _ => return Ok(None),
+            }
+
+            let graph = self.get_execution_graph(job_id).await?;
+            if let Some(graph) = graph
+                .filter(|graph| matches!(graph.status().status, Some(Status::Running(_))))
+            {
+                self.put_lease(Keyspace::JobLeases, job_id, lease_seconds)
+                    .await?;
+                Ok(Some(graph))
+            } else {
+                // the job finished before the lease of its scheduler expired
+                self.store.delete(Keyspace::JobLeases, job_id).await?;
+                Ok(None)
+            }
+        })
+        .await
+    }
+
+    async fn try_acquire_leadership(&self, lease_seconds: u64) -> Result<bool> {
+        let lock = self.store.lock(Keyspace::Leases, LEADER_LEASE).await?;
+
+        with_lock(lock, async {
+            match self.get_lease(Keyspace::Leases, LEADER_LEASE).await? {
+                Some(lease)
+                    if lease.holder != self.scheduler
+                        && lease.expires_at > timestamp_secs() =>
+                {
+                    Ok(false)
+                }
+                _ => {
+                    self.put_lease(Keyspace::Leases, LEADER_LEASE, lease_seconds)
+                        .await?;
+                    Ok(true)
+                }
+            }
+        })
+        .await
+    }
+
+    async fn renew_job_leases(
+        &self,
+        job_ids: &[String],
+        lease_seconds: u64,
+    ) -> Result<Vec<String>> {
+        let mut lost_jobs = vec![];
+        for job_id in job_ids {
+            let lock = self.store.lock(Keyspace::JobLeases, job_id).await?;
+
+            with_lock(lock, async {
+                match self.get_lease(Keyspace::JobLeases, job_id).await? {
+                    Some(lease) if lease.holder != self.scheduler => {
+                        lost_jobs.push(job_id.clone());
+                        Ok(())
+                    }
+                    _ => {
+                        self.put_lease(Keyspace::JobLeases, job_id, lease_seconds)
+                            .await
+                    }
+                }
+            })
+            .await?;
+        }
+        Ok(lost_jobs)
+    }
+
+    async fn get_expired_jobs(&self) -> Result<Vec<String>> {
+        let now = timestamp_secs();
+        let mut expired_jobs = vec![];
+        for (key, value) in self.store.scan(Keyspace::JobLeases, None).await? {
+            let lease: Lease = decode_protobuf(&value)?;
+            if lease.expires_at <= now {
+                if let Some(job_id) = key.rsplit('/').next() {
+                    expired_jobs.push(job_id.to_owned());
+                }
+            }
+        }
+        Ok(expired_jobs)
     }
 
     async fn job_state_events(&self) -> Result<JobStateEventStream> {
@@ -772,7 +965,10 @@ mod test {
 
     use crate::cluster::kv::KeyValueState;
     use crate::cluster::storage::sled::SledClient;
+    #[cfg(feature = "sqlite")]
+    use crate::cluster::storage::sqlite::SqliteClient;
     use crate::cluster::test_util::{test_job_lifecycle, test_job_planning_failure};
+    use crate::cluster::JobState;
     use crate::test_utils::{
         test_aggregation_plan, test_join_plan, test_two_aggregations_plan,
     };
@@ -805,6 +1001,78 @@ mod test {
         Ok(())
     }
 
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_job_lifecycle() -> Result<()> {
+        test_job_lifecycle(make_sqlite_state()?, test_aggregation_plan(4).await).await?;
+        test_job_lifecycle(make_sqlite_state()?, test_two_aggregations_plan(4).await)
+            .await?;
+        test_job_lifecycle(make_sqlite_state()?, test_join_plan(4).await).await?;
+        Ok(())
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_job_planning_failure() -> Result<()> {
+        test_job_planning_failure(make_sqlite_state()?, test_aggregation_plan(4).await)
+            .await?;
+        test_job_planning_failure(
+            make_sqlite_state()?,
+            test_two_aggregations_plan(4).await,
+        )
+        .await?;
+        test_job_planning_failure(make_sqlite_state()?, test_join_plan(4).await).await?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "sled")]
+    #[tokio::test]
+    async fn test_sled_job_leases() -> Result<()> {
+        let store = SledClient::try_new_temporary()?;
+        let state_a = make_shared_sled_state("scheduler-a", store.clone());
+        let state_b = make_shared_sled_state("scheduler-b", store);
+
+        let graph = test_aggregation_plan(4).await;
+        let job_id = graph.job_id().to_owned();
+        state_a.accept_job(&job_id, "", 0)?;
+        state_a.submit_job(job_id.clone(), &graph).await?;
+
+        assert!(state_a.try_acquire_leadership(60).await?);
+        assert!(!state_b.try_acquire_leadership(60).await?);
+        assert!(state_a.try_acquire_leadership(60).await?);
+
+        // the lease of scheduler-a on the job expires right away
+        let lost_jobs = state_a
+            .renew_job_leases(std::slice::from_ref(&job_id), 0)
+            .await?;
+        assert!(lost_jobs.is_empty());
+        assert_eq!(vec![job_id.clone()], state_b.get_expired_jobs().await?);
+
+        assert!(state_b.try_acquire_job(&job_id, 60).await?.is_some());
+        assert!(state_b.get_expired_jobs().await?.is_empty());
+        assert!(state_a.try_acquire_job(&job_id, 60).await?.is_none());
+        let lost_jobs = state_a
+            .renew_job_leases(std::slice::from_ref(&job_id), 60)
+            .await?;
+        assert_eq!(vec![job_id], lost_jobs);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "sled")]
+    fn make_shared_sled_state(
+        scheduler: &str,
+        store: SledClient,
+    ) -> KeyValueState<SledClient> {
+        KeyValueState::new(
+            scheduler,
+            store,
+            KapotCodec::default(),
+            default_session_builder,
+        )
+    }
+
     #[cfg(feature = "sled")]
     fn make_sled_state() -> Result<KeyValueState<SledClient>> {
         Ok(KeyValueState::new(
@@ -814,4 +1082,14 @@ mod test {
             default_session_builder,
         ))
     }
+
+    #[cfg(feature = "sqlite")]
+    fn make_sqlite_state() -> Result<KeyValueState<SqliteClient>> {
+        Ok(KeyValueState::new(
+            "",
+            SqliteClient::try_new_temporary()?,
+            KapotCodec::default(),
+            default_session_builder,
+        ))
+    }
 }
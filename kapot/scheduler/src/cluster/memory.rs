@@ -16,9 +16,10 @@
 // under the License.
 
 use crate::cluster::{
-    bind_task_bias, bind_task_consistent_hash, bind_task_round_robin, get_scan_files,
-    is_skip_consistent_hash, BoundTask, ClusterState, ExecutorSlot, JobState,
-    JobStateEvent, JobStateEventStream, JobStatus, TaskDistributionPolicy, TopologyNode,
+    bind_gang_tasks, bind_speculative_tasks, bind_task_bias, bind_task_consistent_hash,
+    bind_task_round_robin, get_scan_files, is_skip_consistent_hash, BoundTask,
+    ClusterState, ExecutorSlot, JobState, JobStateEvent, JobStateEventStream, JobStatus,
+    TaskDistributionPolicy, TopologyNode,
 };
 use crate::state::execution_graph::ExecutionGraph;
 use async_trait::async_trait;
@@ -26,7 +27,7 @@ use kapot_core::config::KapotConfig;
 use kapot_core::error::{KapotError, Result};
 use kapot_core::serde::protobuf::{
     executor_status, AvailableTaskSlots, ExecutorHeartbeat, ExecutorStatus, FailedJob,
-    QueuedJob,
+    QueuedJob, QueuedJobInfo,
 };
 use kapot_core::serde::scheduler::{ExecutorData, ExecutorMetadata};
 use dashmap::DashMap;
@@ -105,6 +106,28 @@ impl ClusterState for InMemoryClusterState {
     ) -> Result<Vec<BoundTask>> {
         let mut guard = self.task_slots.lock().await;
 
+        // Launch the gang scheduled jobs first, as they need all their slots at once
+        let executors_metadata = guard
+            .keys()
+            .filter(|executor_id| {
+                executors
+                    .as_ref()
+                    .map(|executors| executors.contains(*executor_id))
+                    .unwrap_or(true)
+            })
+            .filter_map(|executor_id| {
+                self.executors
+                    .get(executor_id)
+                    .map(|metadata| (executor_id.clone(), metadata.clone()))
+            })
+            .collect::<HashMap<_, _>>();
+        let mut bound_tasks = bind_gang_tasks(
+            guard.values_mut().collect(),
+            active_jobs.clone(),
+            &executors_metadata,
+        )
+        .await;
+
         let available_slots: Vec<&mut AvailableTaskSlots> = guard
             .values_mut()
             .filter_map(|data| {
@@ -117,12 +140,13 @@ impl ClusterState for InMemoryClusterState {
             })
             .collect();
 
-        let bound_tasks = match distribution {
+        bound_tasks.extend(match distribution {
             TaskDistributionPolicy::Bias => {
-                bind_task_bias(available_slots, active_jobs, |_| false).await
+                bind_task_bias(available_slots, active_jobs.clone(), |_| false).await
             }
             TaskDistributionPolicy::RoundRobin => {
-                bind_task_round_robin(available_slots, active_jobs, |_| false).await
+                bind_task_round_robin(available_slots, active_jobs.clone(), |_| false)
+                    .await
             }
             TaskDistributionPolicy::ConsistentHash {
                 num_replicas,
@@ -144,10 +168,10 @@ impl ClusterState for InMemoryClusterState {
                 info!("{} tasks bound by round robin policy", bound_tasks.len());
                 let (bound_tasks_consistent_hash, ch_topology) =
                     bind_task_consistent_hash(
-                        self.get_topology_nodes(&guard, executors),
+                        self.get_topology_nodes(&guard, executors.clone()),
                         num_replicas,
                         tolerance,
-                        active_jobs,
+                        active_jobs.clone(),
                         |_, plan| get_scan_files(plan),
                     )
                     .await?;
@@ -169,7 +193,21 @@ impl ClusterState for InMemoryClusterState {
                 }
                 bound_tasks
             }
-        };
+        });
+
+        // Launch a copy of the straggler tasks in the slots left over
+        let available_slots: Vec<&mut AvailableTaskSlots> = guard
+            .values_mut()
+            .filter_map(|data| {
+                (data.slots > 0
+                    && executors
+                        .as_ref()
+                        .map(|executors| executors.contains(&data.executor_id))
+                        .unwrap_or(true))
+                .then_some(data)
+            })
+            .collect();
+        bound_tasks.extend(bind_speculative_tasks(available_slots, active_jobs).await);
 
         Ok(bound_tasks)
     }
@@ -357,12 +395,33 @@ impl JobState for InMemoryJobState {
             .and_then(|(_, graph)| graph.clone()))
     }
 
-    async fn try_acquire_job(&self, _job_id: &str) -> Result<Option<ExecutionGraph>> {
+    async fn try_acquire_job(
+        &self,
+        _job_id: &str,
+        _lease_seconds: u64,
+    ) -> Result<Option<ExecutionGraph>> {
         // Always return None. The only state stored here are for completed jobs
         // which cannot be acquired
         Ok(None)
     }
 
+    async fn try_acquire_leadership(&self, _lease_seconds: u64) -> Result<bool> {
+        // The state is not shared with other schedulers
+        Ok(true)
+    }
+
+    async fn renew_job_leases(
+        &self,
+        _job_ids: &[String],
+        _lease_seconds: u64,
+    ) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    async fn get_expired_jobs(&self) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+
     async fn save_job(&self, job_id: &str, graph: &ExecutionGraph) -> Result<()> {
         let status = graph.status().clone();
 
@@ -452,11 +511,24 @@ impl JobState for InMemoryJobState {
         Ok(())
     }
 
+    async fn save_queued_job(&self, _job: QueuedJobInfo) -> Result<()> {
+        Ok(())
+    }
+
+    async fn get_queued_jobs(&self) -> Result<Vec<QueuedJobInfo>> {
+        Ok(vec![])
+    }
+
     fn pending_job_number(&self) -> usize {
         self.queued_jobs.len()
     }
 
-    async fn fail_unscheduled_job(&self, job_id: &str, reason: String) -> Result<()> {
+    async fn fail_unscheduled_job(
+        &self,
+        job_id: &str,
+        reason: String,
+        timed_out: bool,
+    ) -> Result<()> {
         if let Some((job_id, (job_name, queued_at))) = self.queued_jobs.remove(job_id) {
             self.completed_jobs.insert(
                 job_id.clone(),
@@ -469,6 +541,7 @@ impl JobState for InMemoryJobState {
                             queued_at,
                             started_at: 0,
                             ended_at: timestamp_millis(),
+                            timed_out,
                         })),
                     },
                     None,
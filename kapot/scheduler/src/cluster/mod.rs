@@ -15,7 +15,8 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::collections::{HashMap, HashSet};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -38,7 +39,7 @@ use kapot_core::consistent_hash;
 use kapot_core::consistent_hash::ConsistentHash;
 use kapot_core::error::{KapotError, Result};
 use kapot_core::serde::protobuf::{
-    job_status, AvailableTaskSlots, ExecutorHeartbeat, JobStatus,
+    job_status, AvailableTaskSlots, ExecutorHeartbeat, JobStatus, QueuedJobInfo,
 };
 use kapot_core::serde::scheduler::{ExecutorData, ExecutorMetadata, PartitionId};
 use kapot_core::serde::KapotCodec;
@@ -46,8 +47,11 @@ use kapot_core::utils::default_session_builder;
 
 use crate::cluster::kv::KeyValueState;
 use crate::cluster::memory::{InMemoryClusterState, InMemoryJobState};
+use crate::cluster::snapshot::SnapshotStore;
 use crate::cluster::storage::etcd::EtcdClient;
 use crate::cluster::storage::sled::SledClient;
+#[cfg(feature = "sqlite")]
+use crate::cluster::storage::sqlite::SqliteClient;
 use crate::cluster::storage::KeyValueStore;
 use crate::config::{ClusterStorageConfig, SchedulerConfig, TaskDistributionPolicy};
 use crate::scheduler_server::SessionBuilder;
@@ -57,6 +61,7 @@ use crate::state::task_manager::JobInfoCache;
 pub mod event;
 pub mod kv;
 pub mod memory;
+pub mod snapshot;
 pub mod storage;
 
 #[cfg(test)]
@@ -70,6 +75,7 @@ pub enum ClusterStorage {
     Etcd,
     Memory,
     Sled,
+    Sqlite,
 }
 
 impl std::str::FromStr for ClusterStorage {
@@ -90,6 +96,7 @@ impl parse_arg::ParseArgFromStr for ClusterStorage {
 pub struct KapotCluster {
     cluster_state: Arc<dyn ClusterState>,
     job_state: Arc<dyn JobState>,
+    snapshot_store: Option<Arc<dyn SnapshotStore>>,
 }
 
 impl KapotCluster {
@@ -100,6 +107,7 @@ impl KapotCluster {
         Self {
             cluster_state,
             job_state,
+            snapshot_store: None,
         }
     }
 
@@ -110,6 +118,7 @@ impl KapotCluster {
         Self {
             cluster_state: Arc::new(InMemoryClusterState::default()),
             job_state: Arc::new(InMemoryJobState::new(scheduler, session_builder)),
+            snapshot_store: None,
         }
     }
 
@@ -123,11 +132,13 @@ impl KapotCluster {
         session_builder: SessionBuilder,
         codec: KapotCodec<T, U>,
     ) -> Self {
+        let snapshot_store = Arc::new(store.clone());
         let kv_state =
             Arc::new(KeyValueState::new(scheduler, store, codec, session_builder));
         Self {
             cluster_state: kv_state.clone(),
             job_state: kv_state,
+            snapshot_store: Some(snapshot_store),
         }
     }
 
@@ -188,6 +199,28 @@ impl KapotCluster {
                     "build the scheduler with the `sled` feature to use the sled config backend"
                 )
             }
+            #[cfg(feature = "sqlite")]
+            ClusterStorageConfig::Sqlite(path) => {
+                let sqlite = if let Some(path) = path.as_ref() {
+                    info!("Initializing SQLite database at {}", path);
+                    SqliteClient::try_new(path)?
+                } else {
+                    info!("Initializing SQLite database in memory");
+                    SqliteClient::try_new_temporary()?
+                };
+
+                Ok(Self::new_kv(
+                    sqlite,
+                    scheduler,
+                    default_session_builder,
+                    KapotCodec::default(),
+                ))
+            }
+            #[cfg(not(feature = "sqlite"))]
+            ClusterStorageConfig::Sqlite(_) => Err(KapotError::NotImplemented(
+                "build the scheduler with the `sqlite` feature to use the sqlite cluster storage"
+                    .to_string(),
+            )),
             ClusterStorageConfig::Memory => Ok(KapotCluster::new_memory(
                 scheduler,
                 default_session_builder,
@@ -199,6 +232,12 @@ impl KapotCluster {
         self.cluster_state.clone()
     }
 
+    /// The store the cluster state can be exported from and restored into, if the
+    /// state is persisted in a `KeyValueStore`
+    pub fn snapshot_store(&self) -> Option<Arc<dyn SnapshotStore>> {
+        self.snapshot_store.clone()
+    }
+
     pub fn job_state(&self) -> Arc<dyn JobState> {
         self.job_state.clone()
     }
@@ -314,6 +353,14 @@ pub trait JobState: Send + Sync {
     /// in global state
     fn accept_job(&self, job_id: &str, job_name: &str, queued_at: u64) -> Result<()>;
 
+    /// Persist a queued job with its logical plan and session settings so that it is
+    /// queued again if the scheduler restarts before planning it. The persisted job is
+    /// removed once the job is submitted, failed or removed
+    async fn save_queued_job(&self, job: QueuedJobInfo) -> Result<()>;
+
+    /// Get the persisted queued jobs which were accepted by this scheduler
+    async fn get_queued_jobs(&self) -> Result<Vec<QueuedJobInfo>>;
+
     /// Get the number of queued jobs. If it's big, then it means the scheduler is too busy.
     /// In normal case, it's better to be 0.
     fn pending_job_number(&self) -> usize;
@@ -340,16 +387,47 @@ pub trait JobState: Send + Sync {
     async fn save_job(&self, job_id: &str, graph: &ExecutionGraph) -> Result<()>;
 
     /// Mark a job which has not been submitted as failed. This should be called if a job fails
-    /// during planning (and does not yet have an `ExecutionGraph`)
-    async fn fail_unscheduled_job(&self, job_id: &str, reason: String) -> Result<()>;
+    /// during planning (and does not yet have an `ExecutionGraph`), or if it is cancelled
+    /// while queued, `timed_out` if it did not start before its deadline
+    async fn fail_unscheduled_job(
+        &self,
+        job_id: &str,
+        reason: String,
+        timed_out: bool,
+    ) -> Result<()>;
 
     /// Delete a job from the global state
     async fn remove_job(&self, job_id: &str) -> Result<()>;
 
     /// Attempt to acquire ownership of the given job. If the job is still in a running state
     /// and is successfully acquired by the caller, return the current `ExecutionGraph`,
-    /// otherwise return `None`
-    async fn try_acquire_job(&self, job_id: &str) -> Result<Option<ExecutionGraph>>;
+    /// otherwise return `None`.
+    ///
+    /// The job is only acquired once the lease of the scheduler curating it expired, the
+    /// lease of the caller on the job then lasts for `lease_seconds`
+    async fn try_acquire_job(
+        &self,
+        job_id: &str,
+        lease_seconds: u64,
+    ) -> Result<Option<ExecutionGraph>>;
+
+    /// Acquire or renew the lease of the caller on the leadership of the cluster for
+    /// `lease_seconds`. Returns true if the caller is the leader, which takes over the
+    /// jobs of the schedulers which stopped renewing their job leases
+    async fn try_acquire_leadership(&self, lease_seconds: u64) -> Result<bool>;
+
+    /// Acquire or renew the leases of the caller on the given jobs it curates for
+    /// `lease_seconds`. Returns the jobs whose lease is held by another scheduler, which
+    /// took them over
+    async fn renew_job_leases(
+        &self,
+        job_ids: &[String],
+        lease_seconds: u64,
+    ) -> Result<Vec<String>>;
+
+    /// Return the jobs whose lease expired, as the scheduler curating them stopped
+    /// renewing it
+    async fn get_expired_jobs(&self) -> Result<Vec<String>>;
 
     /// Get a stream of all `JobState` events. An event should be published any time that status
     /// of a job changes in state
@@ -378,6 +456,254 @@ pub trait JobState: Send + Sync {
     ) -> Result<Option<Arc<SessionContext>>>;
 }
 
+/// The running jobs of a scheduling pool, with the tasks the pool runs and the slots
+/// granted to it for fair sharing
+#[derive(Default)]
+struct PoolShare {
+    weight: u64,
+    running_tasks: usize,
+    available_tasks: usize,
+    granted_slots: usize,
+    // (job id, available tasks)
+    jobs: Vec<(String, usize)>,
+}
+
+/// A running job competing for the available slots
+struct JobShare {
+    job_id: String,
+    pool: String,
+    pool_weight: u64,
+    tenant: String,
+    tenant_max_task_slots: usize,
+    priority: u64,
+    queued_at: u64,
+    running_tasks: usize,
+    available_tasks: usize,
+}
+
+/// Order the running jobs for binding their tasks to `total_slots` available slots, along
+/// with the number of slots granted to each of them.
+///
+/// The slots are shared between the scheduling pools with runnable tasks in proportion to
+/// their weights, counting the tasks the pools already run. Within a pool, the slots are
+/// granted to the jobs with the highest priority first and in submission order for the
+/// jobs with the same priority.
+///
+/// When the tenant quota on task slots is set, the tasks of a tenant beyond its quota
+/// are not runnable, and the quota is taken by the jobs in the same order across pools.
+pub(crate) async fn fair_share_jobs(
+    active_jobs: &HashMap<String, JobInfoCache>,
+    total_slots: usize,
+) -> Vec<(String, usize)> {
+    let mut job_shares = vec![];
+    let mut tenant_running_tasks: HashMap<String, usize> = HashMap::new();
+    for (job_id, job_info) in active_jobs.iter() {
+        if !matches!(job_info.status, Some(job_status::Status::Running(_))) {
+            debug!(
+                "Job {} is not in running status and will be skipped",
+                job_id
+            );
+            continue;
+        }
+        let mut graph = job_info.execution_graph.write().await;
+        if graph.gang_scheduling_pending() {
+            // launched by bind_gang_tasks once all its tasks fit the available slots
+            continue;
+        }
+        graph.release_retry_backoffs();
+        // Like when fetching a running stage, the resolved stages only run once the
+        // running ones have no more tasks to schedule
+        if graph.available_tasks() == 0 {
+            graph.revive();
+        }
+        let running_tasks = graph.running_tasks().len();
+        *tenant_running_tasks
+            .entry(graph.tenant().to_string())
+            .or_default() += running_tasks;
+        job_shares.push(JobShare {
+            job_id: job_id.clone(),
+            pool: graph.pool().to_string(),
+            pool_weight: job_info.pool_weight,
+            tenant: graph.tenant().to_string(),
+            tenant_max_task_slots: job_info.tenant_max_task_slots,
+            priority: graph.priority(),
+            queued_at: graph.queued_at(),
+            running_tasks,
+            available_tasks: graph.available_tasks(),
+        });
+    }
+    job_shares.sort_by(|a, b| {
+        (Reverse(a.priority), a.queued_at, &a.job_id).cmp(&(
+            Reverse(b.priority),
+            b.queued_at,
+            &b.job_id,
+        ))
+    });
+
+    let mut pools: BTreeMap<String, PoolShare> = BTreeMap::new();
+    for job in job_shares {
+        let mut available_tasks = job.available_tasks;
+        if job.tenant_max_task_slots > 0 {
+            let tenant_tasks = tenant_running_tasks.entry(job.tenant).or_default();
+            available_tasks = available_tasks
+                .min(job.tenant_max_task_slots.saturating_sub(*tenant_tasks));
+            *tenant_tasks += available_tasks;
+        }
+        let pool = pools.entry(job.pool).or_default();
+        pool.weight = job.pool_weight;
+        pool.running_tasks += job.running_tasks;
+        pool.available_tasks += available_tasks;
+        if available_tasks > 0 {
+            pool.jobs.push((job.job_id, available_tasks));
+        }
+    }
+
+    // Grant the slots one by one to the pool running the fewest tasks for its weight
+    for _ in 0..total_slots {
+        let Some(pool) = pools
+            .values_mut()
+            .filter(|pool| pool.granted_slots < pool.available_tasks)
+            .min_by(|a, b| {
+                let a_share =
+                    (a.running_tasks + a.granted_slots) as u128 * b.weight as u128;
+                let b_share =
+                    (b.running_tasks + b.granted_slots) as u128 * a.weight as u128;
+                a_share.cmp(&b_share)
+            })
+        else {
+            break;
+        };
+        pool.granted_slots += 1;
+    }
+
+    let mut jobs = vec![];
+    for pool in pools.into_values() {
+        let mut granted_slots = pool.granted_slots;
+        for (job_id, available_tasks) in pool.jobs {
+            if granted_slots == 0 {
+                break;
+            }
+            let job_slots = available_tasks.min(granted_slots);
+            granted_slots -= job_slots;
+            jobs.push((job_id, job_slots));
+        }
+    }
+    jobs
+}
+
+/// Whether any of the running jobs is a gang scheduled job waiting to be launched
+pub(crate) async fn has_pending_gang_jobs(
+    active_jobs: &HashMap<String, JobInfoCache>,
+) -> bool {
+    for job_info in active_jobs.values() {
+        if matches!(job_info.status, Some(job_status::Status::Running(_)))
+            && job_info.execution_graph.read().await.gang_scheduling_pending()
+        {
+            return true;
+        }
+    }
+    false
+}
+
+/// Launch the gang scheduled jobs all of whose tasks fit the available slots, in the
+/// order of their priority and submission, and spread their tasks round robin over the
+/// executors.
+///
+/// A job with more tasks than the `executors` have task slots, or than the quota of its
+/// tenant, could never be launched at once and falls back to running its stages one
+/// after another.
+pub(crate) async fn bind_gang_tasks(
+    mut slots: Vec<&mut AvailableTaskSlots>,
+    active_jobs: Arc<HashMap<String, JobInfoCache>>,
+    executors: &HashMap<String, ExecutorMetadata>,
+) -> Vec<BoundTask> {
+    let mut schedulable_tasks: Vec<BoundTask> = vec![];
+    slots.retain(|slot| slot.slots > 0 && executors.contains_key(&slot.executor_id));
+    let total_task_slots: usize = executors
+        .values()
+        .map(|executor| executor.specification.task_slots as usize)
+        .sum();
+
+    let mut tenant_running_tasks: HashMap<String, usize> = HashMap::new();
+    let mut pending_jobs = vec![];
+    for (job_id, job_info) in active_jobs.iter() {
+        if !matches!(job_info.status, Some(job_status::Status::Running(_))) {
+            continue;
+        }
+        let graph = job_info.execution_graph.read().await;
+        *tenant_running_tasks
+            .entry(graph.tenant().to_string())
+            .or_default() += graph.running_tasks().len();
+        if graph.gang_scheduling_pending() {
+            pending_jobs.push((Reverse(graph.priority()), graph.queued_at(), job_id));
+        }
+    }
+    pending_jobs.sort();
+
+    for (_, _, job_id) in pending_jobs {
+        let job_info = &active_jobs[job_id];
+        let mut graph = job_info.execution_graph.write().await;
+        let num_tasks = graph.gang_task_count();
+        let max_task_slots = job_info.tenant_max_task_slots;
+        if num_tasks > total_task_slots {
+            graph.disable_gang_scheduling(&format!(
+                "it has {num_tasks} tasks but the executors only have {total_task_slots} task slots"
+            ));
+            continue;
+        }
+        if max_task_slots > 0 && num_tasks > max_task_slots {
+            let reason = format!(
+                "it has {num_tasks} tasks but tenant {} may only use {max_task_slots} task slots",
+                graph.tenant()
+            );
+            graph.disable_gang_scheduling(&reason);
+            continue;
+        }
+        let free_slots: usize = slots.iter().map(|slot| slot.slots as usize).sum();
+        let tenant_tasks = tenant_running_tasks
+            .entry(graph.tenant().to_string())
+            .or_default();
+        if num_tasks == 0
+            || num_tasks > free_slots
+            || (max_task_slots > 0 && *tenant_tasks + num_tasks > max_task_slots)
+        {
+            debug!(
+                "Gang scheduled job {job_id} waits for {num_tasks} task slots, {free_slots} are available"
+            );
+            continue;
+        }
+
+        let mut free: Vec<u32> = slots.iter().map(|slot| slot.slots).collect();
+        let mut assignment = Vec::with_capacity(num_tasks);
+        let mut idx_slot = 0;
+        while assignment.len() < num_tasks {
+            if free[idx_slot] > 0 {
+                free[idx_slot] -= 1;
+                assignment.push(idx_slot);
+            }
+            idx_slot = (idx_slot + 1) % free.len();
+        }
+        let task_executors = assignment
+            .iter()
+            .map(|idx_slot| executors[&slots[*idx_slot].executor_id].clone())
+            .collect::<Vec<_>>();
+        match graph.launch_gang(&task_executors) {
+            Ok(tasks) => {
+                for idx_slot in assignment {
+                    slots[idx_slot].slots -= 1;
+                }
+                *tenant_tasks += tasks.len();
+                schedulable_tasks.extend(tasks);
+            }
+            Err(e) => {
+                graph.disable_gang_scheduling(&format!("it failed to launch: {e}"));
+            }
+        }
+    }
+
+    schedulable_tasks
+}
+
 pub(crate) async fn bind_task_bias(
     mut slots: Vec<&mut AvailableTaskSlots>,
     active_jobs: Arc<HashMap<String, JobInfoCache>>,
@@ -396,15 +722,9 @@ pub(crate) async fn bind_task_bias(
 
     let mut idx_slot = 0usize;
     let mut slot = &mut slots[idx_slot];
-    for (job_id, job_info) in active_jobs.iter() {
-        if !matches!(job_info.status, Some(job_status::Status::Running(_))) {
-            debug!(
-                "Job {} is not in running status and will be skipped",
-                job_id
-            );
-            continue;
-        }
-        let mut graph = job_info.execution_graph.write().await;
+    let jobs = fair_share_jobs(&active_jobs, total_slots as usize).await;
+    for (job_id, mut job_slots) in jobs {
+        let mut graph = active_jobs[&job_id].execution_graph.write().await;
         let session_id = graph.session_id().to_string();
         let mut black_list = vec![];
         while let Some((running_stage, task_id_gen)) =
@@ -425,7 +745,7 @@ pub(crate) async fn bind_task_bias(
                 .iter_mut()
                 .enumerate()
                 .filter(|(_partition, info)| info.is_none())
-                .take(total_slots as usize)
+                .take(job_slots)
                 .collect::<Vec<_>>();
             for (partition_id, task_info) in runnable_tasks {
                 // Assign [`slot`] with a slot available slot number larger than 0
@@ -451,13 +771,20 @@ pub(crate) async fn bind_task_bias(
                     partition,
                     stage_attempt_num: running_stage.stage_attempt_num,
                     task_id,
-                    task_attempt: running_stage.task_failure_numbers[partition_id],
+                    // the task infos are borrowed, so the attempt number is summed up
+                    // like in `RunningStage::task_attempt_num`
+                    task_attempt: running_stage.task_failure_numbers[partition_id]
+                        + running_stage.speculative_task_numbers[partition_id],
                     data_cache: false,
                     plan: running_stage.plan.clone(),
                 };
                 schedulable_tasks.push((executor_id, task_desc));
 
                 slot.slots -= 1;
+                job_slots -= 1;
+            }
+            if job_slots == 0 {
+                break;
             }
         }
     }
@@ -483,15 +810,9 @@ pub(crate) async fn bind_task_round_robin(
     slots.sort_by(|a, b| Ord::cmp(&b.slots, &a.slots));
 
     let mut idx_slot = 0usize;
-    for (job_id, job_info) in active_jobs.iter() {
-        if !matches!(job_info.status, Some(job_status::Status::Running(_))) {
-            debug!(
-                "Job {} is not in running status and will be skipped",
-                job_id
-            );
-            continue;
-        }
-        let mut graph = job_info.execution_graph.write().await;
+    let jobs = fair_share_jobs(&active_jobs, total_slots as usize).await;
+    for (job_id, mut job_slots) in jobs {
+        let mut graph = active_jobs[&job_id].execution_graph.write().await;
         let session_id = graph.session_id().to_string();
         let mut black_list = vec![];
         while let Some((running_stage, task_id_gen)) =
@@ -512,7 +833,7 @@ pub(crate) async fn bind_task_round_robin(
                 .iter_mut()
                 .enumerate()
                 .filter(|(_partition, info)| info.is_none())
-                .take(total_slots as usize)
+                .take(job_slots)
                 .collect::<Vec<_>>();
             for (partition_id, task_info) in runnable_tasks {
                 // Move to the index which has available slots
@@ -540,7 +861,10 @@ pub(crate) async fn bind_task_round_robin(
                     partition,
                     stage_attempt_num: running_stage.stage_attempt_num,
                     task_id,
-                    task_attempt: running_stage.task_failure_numbers[partition_id],
+                    // the task infos are borrowed, so the attempt number is summed up
+                    // like in `RunningStage::task_attempt_num`
+                    task_attempt: running_stage.task_failure_numbers[partition_id]
+                        + running_stage.speculative_task_numbers[partition_id],
                     data_cache: false,
                     plan: running_stage.plan.clone(),
                 };
@@ -552,6 +876,10 @@ pub(crate) async fn bind_task_round_robin(
                 if total_slots == 0 {
                     return schedulable_tasks;
                 }
+                job_slots -= 1;
+            }
+            if job_slots == 0 {
+                break;
             }
         }
     }
@@ -559,6 +887,38 @@ pub(crate) async fn bind_task_round_robin(
     schedulable_tasks
 }
 
+/// Bind a speculative copy of the straggler tasks of the running jobs to the available
+/// slots, always on another executor than the one running the original task
+pub(crate) async fn bind_speculative_tasks(
+    mut slots: Vec<&mut AvailableTaskSlots>,
+    active_jobs: Arc<HashMap<String, JobInfoCache>>,
+) -> Vec<BoundTask> {
+    let mut schedulable_tasks: Vec<BoundTask> = vec![];
+    if slots.iter().all(|slot| slot.slots == 0) {
+        return schedulable_tasks;
+    }
+
+    for (job_id, job_info) in active_jobs.iter() {
+        if !matches!(job_info.status, Some(job_status::Status::Running(_))) {
+            debug!(
+                "Job {} is not in running status and will be skipped",
+                job_id
+            );
+            continue;
+        }
+        let mut graph = job_info.execution_graph.write().await;
+        schedulable_tasks.extend(graph.bind_speculative_tasks(&mut |executor_id| {
+            let slot = slots
+                .iter_mut()
+                .find(|slot| slot.slots > 0 && slot.executor_id != executor_id)?;
+            slot.slots -= 1;
+            Some(slot.executor_id.clone())
+        }));
+    }
+
+    schedulable_tasks
+}
+
 type GetScanFilesFunc = fn(
     &str,
     Arc<dyn ExecutionPlan>,
@@ -589,21 +949,15 @@ pub(crate) async fn bind_task_consistent_hash(
         ConsistentHash::new(node_replicas);
 
     let mut schedulable_tasks: Vec<BoundTask> = vec![];
-    for (job_id, job_info) in active_jobs.iter() {
-        if !matches!(job_info.status, Some(job_status::Status::Running(_))) {
-            debug!(
-                "Job {} is not in running status and will be skipped",
-                job_id
-            );
-            continue;
-        }
-        let mut graph = job_info.execution_graph.write().await;
+    let jobs = fair_share_jobs(&active_jobs, total_slots).await;
+    for (job_id, mut job_slots) in jobs {
+        let mut graph = active_jobs[&job_id].execution_graph.write().await;
         let session_id = graph.session_id().to_string();
         let mut black_list = vec![];
         while let Some((running_stage, task_id_gen)) =
             graph.fetch_running_stage(&black_list)
         {
-            let scan_files = get_scan_files(job_id, running_stage.plan.clone())?;
+            let scan_files = get_scan_files(&job_id, running_stage.plan.clone())?;
             if is_skip_consistent_hash(&scan_files) {
                 info!(
                     "Will skip stage {}/{} for consistent hashing task binding",
@@ -623,7 +977,7 @@ pub(crate) async fn bind_task_consistent_hash(
                     .iter_mut()
                     .enumerate()
                     .filter(|(_partition, info)| info.is_none())
-                    .take(total_slots)
+                    .take(job_slots)
                     .collect::<Vec<_>>();
                 for (partition_id, task_info) in runnable_tasks {
                     let partition_files = &scan_files[partition_id];
@@ -653,7 +1007,8 @@ pub(crate) async fn bind_task_consistent_hash(
                             stage_attempt_num: running_stage.stage_attempt_num,
                             task_id,
                             task_attempt: running_stage.task_failure_numbers
-                                [partition_id],
+                                [partition_id]
+                                + running_stage.speculative_task_numbers[partition_id],
                             data_cache,
                             plan: running_stage.plan.clone(),
                         };
@@ -664,6 +1019,7 @@ pub(crate) async fn bind_task_consistent_hash(
                         if total_slots == 0 {
                             return Ok((schedulable_tasks, Some(ch_topology)));
                         }
+                        job_slots -= 1;
                     }
                 }
             }
@@ -672,6 +1028,9 @@ pub(crate) async fn bind_task_consistent_hash(
             if pre_total_slots == total_slots {
                 black_list.push(running_stage.stage_id);
             }
+            if job_slots == 0 {
+                break;
+            }
         }
     }
 
@@ -754,19 +1113,26 @@ mod test {
     use object_store::path::Path;
     use object_store::ObjectMeta;
 
+    use datafusion::common::tree_node::{TreeNode, TreeNodeRecursion};
+    use kapot_core::config::{
+        KapotConfig, KAPOT_JOB_GANG_SCHEDULING_ENABLED, KAPOT_JOB_POOL,
+        KAPOT_JOB_PRIORITY, KAPOT_JOB_TENANT,
+    };
     use kapot_core::error::Result;
+    use kapot_core::execution_plans::{ShuffleReaderExec, ShuffleWriterExec};
     use kapot_core::serde::protobuf::AvailableTaskSlots;
     use kapot_core::serde::scheduler::{ExecutorMetadata, ExecutorSpecification};
 
     use crate::cluster::{
-        bind_task_bias, bind_task_consistent_hash, bind_task_round_robin, BoundTask,
+        bind_gang_tasks, bind_task_bias, bind_task_consistent_hash,
+        bind_task_round_robin, fair_share_jobs, has_pending_gang_jobs, BoundTask,
         TopologyNode,
     };
     use crate::state::execution_graph::ExecutionGraph;
     use crate::state::task_manager::JobInfoCache;
     use crate::test_utils::{
         mock_completed_task, revive_graph_and_complete_next_stage,
-        test_aggregation_plan_with_job_id,
+        test_aggregation_plan_with_config,
     };
 
     #[tokio::test]
@@ -995,6 +1361,179 @@ mod test {
         result
     }
 
+    #[tokio::test]
+    async fn test_fair_share_jobs() -> Result<()> {
+        let mut active_jobs = HashMap::new();
+        for (job_id, pool, weight, priority, num_pending_task) in [
+            ("job_etl", "etl", 1, "0", 7),
+            ("job_dashboard_low", "dashboards", 3, "0", 7),
+            ("job_dashboard_high", "dashboards", 3, "5", 2),
+        ] {
+            let config = KapotConfig::builder()
+                .set(KAPOT_JOB_POOL, pool)
+                .set(KAPOT_JOB_PRIORITY, priority)
+                .build()?;
+            let graph =
+                mock_graph_with_config(job_id, 8, num_pending_task, &config).await?;
+            let mut job_info = JobInfoCache::new(graph);
+            job_info.pool_weight = weight;
+            active_jobs.insert(job_id.to_string(), job_info);
+        }
+
+        // The dashboards pool gets three times the slots of the etl pool, first for its
+        // job with the highest priority
+        let jobs = fair_share_jobs(&active_jobs, 8).await;
+        assert_eq!(
+            vec![
+                ("job_dashboard_high".to_string(), 2),
+                ("job_dashboard_low".to_string(), 4),
+                ("job_etl".to_string(), 2),
+            ],
+            jobs
+        );
+
+        // The slots the etl pool does not use are left to the dashboards pool
+        let jobs = fair_share_jobs(&active_jobs, 20).await;
+        assert_eq!(
+            vec![
+                ("job_dashboard_high".to_string(), 2),
+                ("job_dashboard_low".to_string(), 7),
+                ("job_etl".to_string(), 7),
+            ],
+            jobs
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fair_share_jobs_tenant_quota() -> Result<()> {
+        let mut active_jobs = HashMap::new();
+        for (job_id, tenant, priority) in [
+            ("job_a", "analytics", "5"),
+            ("job_b", "analytics", "0"),
+            ("job_c", "reporting", "0"),
+        ] {
+            let config = KapotConfig::builder()
+                .set(KAPOT_JOB_TENANT, tenant)
+                .set(KAPOT_JOB_PRIORITY, priority)
+                .build()?;
+            let graph = mock_graph_with_config(job_id, 8, 7, &config).await?;
+            let mut job_info = JobInfoCache::new(graph);
+            job_info.tenant_max_task_slots = 5;
+            active_jobs.insert(job_id.to_string(), job_info);
+        }
+
+        // The job with the highest priority takes the whole quota of its tenant
+        let jobs = fair_share_jobs(&active_jobs, 20).await;
+        assert_eq!(
+            vec![("job_a".to_string(), 5), ("job_c".to_string(), 5)],
+            jobs
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bind_gang_tasks() -> Result<()> {
+        let config = KapotConfig::builder()
+            .set(KAPOT_JOB_GANG_SCHEDULING_ENABLED, "true")
+            .build()?;
+        let graph_a = test_aggregation_plan_with_config(4, "job_a", &config).await;
+        let graph_b = test_aggregation_plan_with_config(16, "job_b", &config).await;
+        // 2 scan tasks and 4 aggregation tasks
+        assert_eq!(6, graph_a.gang_task_count());
+        let active_jobs = Arc::new(HashMap::from([
+            ("job_a".to_string(), JobInfoCache::new(graph_a)),
+            ("job_b".to_string(), JobInfoCache::new(graph_b)),
+        ]));
+        assert!(has_pending_gang_jobs(&active_jobs).await);
+        let executors = mock_available_slots()
+            .into_iter()
+            .map(|slots| {
+                let executor = ExecutorMetadata {
+                    id: slots.executor_id.clone(),
+                    host: "localhost".to_string(),
+                    port: 50051,
+                    grpc_port: 50052,
+                    specification: ExecutorSpecification {
+                        task_slots: slots.slots,
+                    },
+                };
+                (slots.executor_id, executor)
+            })
+            .collect::<HashMap<_, _>>();
+
+        // job_b has more tasks than the 15 task slots of the executors and falls back
+        // to running its stages one after another, job_a waits for enough free slots
+        let mut available_slots = mock_available_slots();
+        for slots in available_slots.iter_mut() {
+            slots.slots = 1;
+        }
+        let bound_tasks = bind_gang_tasks(
+            available_slots.iter_mut().collect(),
+            active_jobs.clone(),
+            &executors,
+        )
+        .await;
+        assert!(bound_tasks.is_empty());
+        let graph_b = active_jobs["job_b"].execution_graph.read().await;
+        assert!(!graph_b.gang_scheduling());
+        drop(graph_b);
+        let graph_a = active_jobs["job_a"].execution_graph.read().await;
+        assert!(graph_a.gang_scheduling_pending());
+        drop(graph_a);
+        // and none of its tasks is bound by the other policies
+        assert_eq!(
+            vec![("job_b".to_string(), 2)],
+            fair_share_jobs(&active_jobs, 3).await
+        );
+
+        let mut available_slots = mock_available_slots();
+        let bound_tasks = bind_gang_tasks(
+            available_slots.iter_mut().collect(),
+            active_jobs.clone(),
+            &executors,
+        )
+        .await;
+        assert_eq!(6, bound_tasks.len());
+        assert_eq!(
+            9,
+            available_slots.iter().map(|slots| slots.slots).sum::<u32>()
+        );
+        let graph_a = active_jobs["job_a"].execution_graph.read().await;
+        assert!(!graph_a.gang_scheduling_pending());
+        assert_eq!(2, graph_a.running_stages().len());
+        drop(graph_a);
+        assert!(!has_pending_gang_jobs(&active_jobs).await);
+
+        // the scan tasks stream their output to the aggregation tasks
+        for (executor_id, task) in bound_tasks {
+            let shuffle_writer = task
+                .plan
+                .as_any()
+                .downcast_ref::<ShuffleWriterExec>()
+                .unwrap();
+            if task.partition.stage_id == 1 {
+                assert!(shuffle_writer.streaming());
+                continue;
+            }
+            assert!(!shuffle_writer.streaming());
+            task.plan.apply(|plan| {
+                if let Some(reader) = plan.as_any().downcast_ref::<ShuffleReaderExec>() {
+                    for locations in &reader.partition {
+                        assert_eq!(2, locations.len());
+                        assert!(locations.iter().all(|loc| loc.streaming));
+                    }
+                }
+                Ok(TreeNodeRecursion::Continue)
+            })?;
+            assert!(executors.contains_key(&executor_id));
+        }
+
+        Ok(())
+    }
+
     async fn mock_active_jobs(
         num_partition: usize,
     ) -> Result<HashMap<String, JobInfoCache>> {
@@ -1013,9 +1552,25 @@ mod test {
         job_id: &str,
         num_target_partitions: usize,
         num_pending_task: usize,
+    ) -> Result<ExecutionGraph> {
+        mock_graph_with_config(
+            job_id,
+            num_target_partitions,
+            num_pending_task,
+            &KapotConfig::default(),
+        )
+        .await
+    }
+
+    async fn mock_graph_with_config(
+        job_id: &str,
+        num_target_partitions: usize,
+        num_pending_task: usize,
+        config: &KapotConfig,
     ) -> Result<ExecutionGraph> {
         let mut graph =
-            test_aggregation_plan_with_job_id(num_target_partitions, job_id).await;
+            test_aggregation_plan_with_config(num_target_partitions, job_id, config)
+                .await;
         let executor = ExecutorMetadata {
             id: "executor_0".to_string(),
             host: "localhost".to_string(),
@@ -1030,7 +1585,7 @@ mod test {
         for _ in 0..num_target_partitions - num_pending_task {
             if let Some(task) = graph.pop_next_task(&executor.id)? {
                 let task_status = mock_completed_task(task, &executor.id);
-                graph.update_task_status(&executor, vec![task_status], 1, 1)?;
+                graph.update_task_status(&executor, vec![task_status])?;
             }
         }
 
@@ -0,0 +1,196 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Export of the cluster state saved in a [`KeyValueStore`] to a portable snapshot,
+//! which can be restored into any other [`KeyValueStore`] implementation
+
+use std::path::Path;
+
+use async_trait::async_trait;
+use kapot_core::error::{KapotError, Result};
+use kapot_core::serde::protobuf::{ClusterSnapshot, ClusterSnapshotEntry};
+use prost::Message;
+
+use crate::cluster::storage::{KeyValueStore, Keyspace, Operation};
+use crate::scheduler_server::timestamp_millis;
+use crate::state::decode_protobuf;
+
+/// Version of the snapshot format written by this scheduler
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Keyspaces saved in a snapshot. The executor heartbeats and slots, and the scheduler
+/// and job leases, are only meaningful to the running processes which wrote them, so
+/// they are neither exported nor restored
+pub const SNAPSHOT_KEYSPACES: [Keyspace; 5] = [
+    Keyspace::Executors,
+    Keyspace::JobStatus,
+    Keyspace::ExecutionGraph,
+    Keyspace::Sessions,
+    Keyspace::QueuedJobs,
+];
+
+/// Number of entries restored in a single transaction, kept below the default limit of
+/// operations in an etcd transaction
+const RESTORE_BATCH_SIZE: usize = 64;
+
+/// A store of cluster state which can be exported to and restored from a snapshot
+#[async_trait]
+pub trait SnapshotStore: Send + Sync {
+    /// Dump the entries of the [`SNAPSHOT_KEYSPACES`] into a snapshot
+    async fn export_snapshot(&self) -> Result<ClusterSnapshot>;
+
+    /// Load the entries of the [`SNAPSHOT_KEYSPACES`] in a snapshot, overwriting the
+    /// entries with the same keys. Returns the number of entries restored
+    async fn restore_snapshot(&self, snapshot: ClusterSnapshot) -> Result<usize>;
+}
+
+#[async_trait]
+impl<S: KeyValueStore> SnapshotStore for S {
+    async fn export_snapshot(&self) -> Result<ClusterSnapshot> {
+        let mut entries = vec![];
+        for keyspace in SNAPSHOT_KEYSPACES {
+            // the keys returned by the store may be prefixed by a namespace
+            let prefix = format!("/{keyspace:?}/");
+            for (key, value) in self.scan(keyspace.clone(), None).await? {
+                let Some((_, key)) = key.split_once(&prefix) else {
+                    return Err(KapotError::Internal(format!(
+                        "Key {key} is not in keyspace {keyspace:?}"
+                    )));
+                };
+                entries.push(ClusterSnapshotEntry {
+                    keyspace: format!("{keyspace:?}"),
+                    key: key.to_owned(),
+                    value,
+                });
+            }
+        }
+
+        Ok(ClusterSnapshot {
+            version: SNAPSHOT_VERSION,
+            created_at: timestamp_millis(),
+            entries,
+        })
+    }
+
+    async fn restore_snapshot(&self, snapshot: ClusterSnapshot) -> Result<usize> {
+        if snapshot.version > SNAPSHOT_VERSION {
+            return Err(KapotError::General(format!(
+                "Snapshot version {} is newer than the supported version {SNAPSHOT_VERSION}",
+                snapshot.version
+            )));
+        }
+
+        let mut ops = vec![];
+        for entry in snapshot.entries {
+            let keyspace = Keyspace::from_name(&entry.keyspace).ok_or_else(|| {
+                KapotError::General(format!(
+                    "Unknown keyspace {} in snapshot",
+                    entry.keyspace
+                ))
+            })?;
+            // skip the transient state a snapshot may have been exported with
+            if SNAPSHOT_KEYSPACES.contains(&keyspace) {
+                ops.push((Operation::Put(entry.value), keyspace, entry.key));
+            }
+        }
+
+        let restored = ops.len();
+        let mut ops = ops.into_iter().peekable();
+        while ops.peek().is_some() {
+            self.apply_txn(ops.by_ref().take(RESTORE_BATCH_SIZE).collect())
+                .await?;
+        }
+
+        Ok(restored)
+    }
+}
+
+/// Write a snapshot to a file
+pub fn write_snapshot<P: AsRef<Path>>(snapshot: &ClusterSnapshot, path: P) -> Result<()> {
+    Ok(std::fs::write(path, snapshot.encode_to_vec())?)
+}
+
+/// Read a snapshot from a file
+pub fn read_snapshot<P: AsRef<Path>>(path: P) -> Result<ClusterSnapshot> {
+    decode_protobuf(&std::fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SnapshotStore, SNAPSHOT_VERSION};
+    use kapot_core::serde::protobuf::ClusterSnapshotEntry;
+
+    #[cfg(feature = "sled")]
+    use crate::cluster::storage::sled::SledClient;
+    #[cfg(feature = "sqlite")]
+    use crate::cluster::storage::sqlite::SqliteClient;
+    use crate::cluster::storage::{KeyValueStore, Keyspace};
+    use kapot_core::error::Result;
+
+    #[cfg(all(feature = "sled", feature = "sqlite"))]
+    #[tokio::test]
+    async fn test_snapshot_round_trip() -> Result<()> {
+        let source = SledClient::try_new_temporary()?;
+        source
+            .put(Keyspace::JobStatus, "job".to_owned(), b"status".to_vec())
+            .await?;
+        source
+            .put(
+                Keyspace::ExecutionGraph,
+                "job".to_owned(),
+                b"graph".to_vec(),
+            )
+            .await?;
+        source
+            .put(Keyspace::Slots, "executor/1".to_owned(), b"slots".to_vec())
+            .await?;
+
+        // the transient slots are not exported
+        let mut snapshot = source.export_snapshot().await?;
+        assert_eq!(snapshot.version, SNAPSHOT_VERSION);
+        assert_eq!(snapshot.entries.len(), 2);
+
+        // nor restored from a snapshot which has them
+        snapshot.entries.push(ClusterSnapshotEntry {
+            keyspace: "Heartbeats".to_owned(),
+            key: "executor/1".to_owned(),
+            value: b"heartbeat".to_vec(),
+        });
+        let target = SqliteClient::try_new_temporary()?;
+        assert_eq!(target.restore_snapshot(snapshot).await?, 2);
+        assert_eq!(target.get(Keyspace::JobStatus, "job").await?, b"status");
+        assert_eq!(target.get(Keyspace::ExecutionGraph, "job").await?, b"graph");
+        assert!(target
+            .get(Keyspace::Heartbeats, "executor/1")
+            .await?
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_restore_newer_snapshot() -> Result<()> {
+        let store = SqliteClient::try_new_temporary()?;
+        let mut snapshot = store.export_snapshot().await?;
+        snapshot.version = SNAPSHOT_VERSION + 1;
+
+        assert!(store.restore_snapshot(snapshot).await.is_err());
+
+        Ok(())
+    }
+}
@@ -19,6 +19,8 @@
 pub mod etcd;
 #[cfg(feature = "sled")]
 pub mod sled;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 
 use async_trait::async_trait;
 use kapot_core::error::Result;
@@ -34,9 +36,32 @@ pub enum Keyspace {
     Slots,
     Sessions,
     Heartbeats,
+    Leases,
+    JobLeases,
+    QueuedJobs,
 }
 
 impl Keyspace {
+    /// All the keyspaces of the cluster state
+    pub const ALL: [Keyspace; 9] = [
+        Keyspace::Executors,
+        Keyspace::JobStatus,
+        Keyspace::ExecutionGraph,
+        Keyspace::Slots,
+        Keyspace::Sessions,
+        Keyspace::Heartbeats,
+        Keyspace::Leases,
+        Keyspace::JobLeases,
+        Keyspace::QueuedJobs,
+    ];
+
+    /// Get the keyspace with the given name, e.g. `JobStatus`
+    pub fn from_name(name: &str) -> Option<Keyspace> {
+        Self::ALL
+            .into_iter()
+            .find(|keyspace| format!("{keyspace:?}") == name)
+    }
+
     pub fn strip_prefix<'a>(&'a self, key: &'a str) -> Option<&'a str> {
         key.strip_prefix(&format!("{self:?}/"))
     }
@@ -0,0 +1,548 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::collections::{HashMap, HashSet};
+use std::{sync::Arc, task::Poll};
+
+use kapot_core::error::{KapotError, Result};
+
+use crate::cluster::storage::KeyValueStore;
+use async_trait::async_trait;
+use futures::Stream;
+use log::warn;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Mutex;
+
+use crate::cluster::storage::{Keyspace, Lock, Operation, Watch, WatchEvent};
+
+/// Prefixes watched and the senders notifying their watches of changes
+type Watchers = Vec<(String, UnboundedSender<WatchEvent>)>;
+
+/// A [`StateBackendClient`] implementation that uses a SQLite database to save cluster
+/// state.
+///
+/// Watches are notified of the changes made through this client, so a database file
+/// should not be shared by several scheduler processes. The queries block on the file
+/// I/O of SQLite and run on the blocking thread pool of tokio.
+#[derive(Clone)]
+pub struct SqliteClient {
+    conn: Arc<parking_lot::Mutex<Connection>>,
+    locks: Arc<Mutex<HashMap<String, Arc<Mutex<()>>>>>,
+    watchers: Arc<parking_lot::Mutex<Watchers>>,
+}
+
+impl SqliteClient {
+    /// Creates a SqliteClient that saves data to the specified database file.
+    pub fn try_new<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        Self::try_new_with_connection(
+            Connection::open(path).map_err(sqlite_to_kapot_error)?,
+        )
+    }
+
+    /// Creates a SqliteClient that saves data to an in-memory database.
+    pub fn try_new_temporary() -> Result<Self> {
+        Self::try_new_with_connection(
+            Connection::open_in_memory().map_err(sqlite_to_kapot_error)?,
+        )
+    }
+
+    fn try_new_with_connection(conn: Connection) -> Result<Self> {
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS kv (key TEXT PRIMARY KEY, value BLOB NOT NULL);",
+        )
+        .map_err(sqlite_to_kapot_error)?;
+
+        Ok(Self {
+            conn: Arc::new(parking_lot::Mutex::new(conn)),
+            locks: Arc::new(Mutex::new(HashMap::new())),
+            watchers: Arc::new(parking_lot::Mutex::new(vec![])),
+        })
+    }
+
+    /// Notify the watches whose prefix matches the key of each event
+    fn notify(&self, events: Vec<WatchEvent>) {
+        let mut watchers = self.watchers.lock();
+        watchers.retain(|(_, sender)| !sender.is_closed());
+        for event in events {
+            let key = match &event {
+                WatchEvent::Put(key, _) | WatchEvent::Delete(key) => key,
+            };
+            for (prefix, sender) in watchers.iter() {
+                if key.starts_with(prefix.as_str()) {
+                    let _ = sender.send(event.clone());
+                }
+            }
+        }
+    }
+
+    /// Run `f` with the connection on a blocking thread
+    async fn with_conn<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || f(&mut conn.lock())).await?
+    }
+
+    async fn scan_prefix(
+        &self,
+        prefix: String,
+        limit: Option<usize>,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        self.with_conn(move |conn| {
+            let limit = limit.map(|limit| limit as i64).unwrap_or(-1);
+            // a range of the primary key, rather than a function of it, so that the scan
+            // only reads the matching rows of its index
+            let upper = prefix_upper_bound(&prefix);
+            let mut statement = conn
+                .prepare_cached(if upper.is_some() {
+                    "SELECT key, value FROM kv WHERE key >= ?1 AND key < ?2
+                     ORDER BY key LIMIT ?3"
+                } else {
+                    "SELECT key, value FROM kv WHERE key >= ?1 ORDER BY key LIMIT ?2"
+                })
+                .map_err(sqlite_to_kapot_error)?;
+            let rows = match &upper {
+                Some(upper) => {
+                    statement.query_map(params![prefix, upper, limit], key_value)
+                }
+                None => statement.query_map(params![prefix, limit], key_value),
+            }
+            .map_err(sqlite_to_kapot_error)?;
+
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(sqlite_to_kapot_error)
+        })
+        .await
+    }
+}
+
+/// The smallest string greater than all the strings starting with `prefix`, if any.
+/// SQLite compares text keys by their UTF-8 bytes, which orders them as their chars
+fn prefix_upper_bound(prefix: &str) -> Option<String> {
+    let mut upper = prefix.to_owned();
+    while let Some(last) = upper.pop() {
+        if let Some(next) = (last as u32 + 1..=char::MAX as u32).find_map(char::from_u32)
+        {
+            upper.push(next);
+            return Some(upper);
+        }
+    }
+    None
+}
+
+fn key_value(row: &Row) -> rusqlite::Result<(String, Vec<u8>)> {
+    Ok((row.get(0)?, row.get(1)?))
+}
+
+fn sqlite_to_kapot_error(e: rusqlite::Error) -> KapotError {
+    KapotError::General(format!("sqlite error {e:?}"))
+}
+
+#[async_trait]
+impl KeyValueStore for SqliteClient {
+    async fn get(&self, keyspace: Keyspace, key: &str) -> Result<Vec<u8>> {
+        let key = format!("/{keyspace:?}/{key}");
+        self.with_conn(move |conn| {
+            Ok(conn
+                .query_row("SELECT value FROM kv WHERE key = ?1", [key], |row| {
+                    row.get(0)
+                })
+                .optional()
+                .map_err(sqlite_to_kapot_error)?
+                .unwrap_or_default())
+        })
+        .await
+    }
+
+    async fn get_from_prefix(
+        &self,
+        keyspace: Keyspace,
+        prefix: &str,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        let prefix = format!("/{keyspace:?}/{prefix}");
+        self.scan_prefix(prefix, None).await
+    }
+
+    async fn scan(
+        &self,
+        keyspace: Keyspace,
+        limit: Option<usize>,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        let prefix = format!("/{keyspace:?}/");
+        self.scan_prefix(prefix, limit).await
+    }
+
+    async fn scan_keys(&self, keyspace: Keyspace) -> Result<HashSet<String>> {
+        let prefix = format!("/{keyspace:?}/");
+        Ok(self
+            .scan_prefix(prefix.clone(), None)
+            .await?
+            .into_iter()
+            .map(|(key, _value)| key.strip_prefix(&prefix).unwrap().to_owned())
+            .collect())
+    }
+
+    async fn put(&self, keyspace: Keyspace, key: String, value: Vec<u8>) -> Result<()> {
+        let key = format!("/{keyspace:?}/{key}");
+        let (key, value) = self
+            .with_conn(move |conn| {
+                conn.execute(
+                    "INSERT OR REPLACE INTO kv (key, value) VALUES (?1, ?2)",
+                    params![key, value],
+                )
+                .map_err(|e| {
+                    warn!("sqlite insert failed: {}", e);
+                    sqlite_to_kapot_error(e)
+                })?;
+                Ok((key, value))
+            })
+            .await?;
+        self.notify(vec![WatchEvent::Put(key, value)]);
+        Ok(())
+    }
+
+    async fn apply_txn(&self, ops: Vec<(Operation, Keyspace, String)>) -> Result<()> {
+        let events = self
+            .with_conn(move |conn| {
+                let mut events = vec![];
+                let txn = conn.transaction().map_err(sqlite_to_kapot_error)?;
+
+                for (op, keyspace, key_str) in ops {
+                    let key = format!("/{:?}/{}", &keyspace, key_str);
+                    match op {
+                        Operation::Put(value) => {
+                            txn.execute(
+                                "INSERT OR REPLACE INTO kv (key, value) VALUES (?1, ?2)",
+                                params![key, value],
+                            )
+                            .map_err(sqlite_to_kapot_error)?;
+                            events.push(WatchEvent::Put(key, value));
+                        }
+                        Operation::Delete => {
+                            txn.execute("DELETE FROM kv WHERE key = ?1", [&key])
+                                .map_err(sqlite_to_kapot_error)?;
+                            events.push(WatchEvent::Delete(key));
+                        }
+                    }
+                }
+
+                txn.commit().map_err(|e| {
+                    warn!("sqlite transaction failed: {}", e);
+                    sqlite_to_kapot_error(e)
+                })?;
+                Ok(events)
+            })
+            .await?;
+        self.notify(events);
+        Ok(())
+    }
+
+    async fn mv(
+        &self,
+        from_keyspace: Keyspace,
+        to_keyspace: Keyspace,
+        key: &str,
+    ) -> Result<()> {
+        let from_key = format!("/{from_keyspace:?}/{key}");
+        let to_key = format!("/{to_keyspace:?}/{key}");
+
+        let moved_value = {
+            let from_key = from_key.clone();
+            let to_key = to_key.clone();
+            self.with_conn(move |conn| {
+                let txn = conn.transaction().map_err(sqlite_to_kapot_error)?;
+
+                let current_value: Option<Vec<u8>> = txn
+                    .query_row(
+                        "SELECT value FROM kv WHERE key = ?1",
+                        [&from_key],
+                        |row| row.get(0),
+                    )
+                    .optional()
+                    .map_err(sqlite_to_kapot_error)?;
+
+                if let Some(value) = current_value {
+                    txn.execute("DELETE FROM kv WHERE key = ?1", [&from_key])
+                        .map_err(sqlite_to_kapot_error)?;
+                    txn.execute(
+                        "INSERT OR REPLACE INTO kv (key, value) VALUES (?1, ?2)",
+                        params![to_key, value],
+                    )
+                    .map_err(sqlite_to_kapot_error)?;
+                    txn.commit().map_err(|e| {
+                        warn!("sqlite transaction failed: {}", e);
+                        sqlite_to_kapot_error(e)
+                    })?;
+                    Ok(Some(value))
+                } else {
+                    Ok(None)
+                }
+            })
+            .await?
+        };
+
+        if let Some(value) = moved_value {
+            self.notify(vec![
+                WatchEvent::Delete(from_key),
+                WatchEvent::Put(to_key, value),
+            ]);
+        } else {
+            warn!("Cannot move value at {}, does not exist", from_key);
+        }
+        Ok(())
+    }
+
+    async fn lock(&self, keyspace: Keyspace, key: &str) -> Result<Box<dyn Lock>> {
+        let mut mlock = self.locks.lock().await;
+        let lock_key = format!("/{keyspace:?}/{key}");
+        if let Some(lock) = mlock.get(&lock_key) {
+            Ok(Box::new(lock.clone().lock_owned().await))
+        } else {
+            let new_lock = Arc::new(Mutex::new(()));
+            mlock.insert(lock_key, new_lock.clone());
+            Ok(Box::new(new_lock.lock_owned().await))
+        }
+    }
+
+    async fn watch(&self, keyspace: Keyspace, prefix: String) -> Result<Box<dyn Watch>> {
+        let prefix = format!("/{keyspace:?}/{prefix}");
+        let (sender, receiver) = unbounded_channel();
+        self.watchers.lock().push((prefix, sender));
+
+        Ok(Box::new(SqliteWatch { receiver }))
+    }
+
+    async fn delete(&self, keyspace: Keyspace, key: &str) -> Result<()> {
+        let key = format!("/{keyspace:?}/{key}");
+        let key = self
+            .with_conn(move |conn| {
+                conn.execute("DELETE FROM kv WHERE key = ?1", [&key])
+                    .map_err(|e| {
+                        warn!("sqlite delete failed: {:?}", e);
+                        sqlite_to_kapot_error(e)
+                    })?;
+                Ok(key)
+            })
+            .await?;
+        self.notify(vec![WatchEvent::Delete(key)]);
+        Ok(())
+    }
+}
+
+struct SqliteWatch {
+    receiver: UnboundedReceiver<WatchEvent>,
+}
+
+#[tonic::async_trait]
+impl Watch for SqliteWatch {
+    async fn cancel(&mut self) -> Result<()> {
+        self.receiver.close();
+        Ok(())
+    }
+}
+
+impl Stream for SqliteWatch {
+    type Item = WatchEvent;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.get_mut().receiver.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyValueStore, SqliteClient, Watch, WatchEvent};
+
+    use crate::cluster::storage::{Keyspace, Operation};
+
+    use futures::StreamExt;
+    use std::result::Result;
+
+    fn create_instance() -> Result<SqliteClient, Box<dyn std::error::Error>> {
+        Ok(SqliteClient::try_new_temporary()?)
+    }
+
+    #[tokio::test]
+    async fn put_read() -> Result<(), Box<dyn std::error::Error>> {
+        let client = create_instance()?;
+        let key = "key";
+        let value = "value".as_bytes();
+        client
+            .put(Keyspace::Slots, key.to_owned(), value.to_vec())
+            .await?;
+        assert_eq!(client.get(Keyspace::Slots, key).await?, value);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn multiple_operation() -> Result<(), Box<dyn std::error::Error>> {
+        let client = create_instance()?;
+        let key = "key".to_string();
+        let value = "value".as_bytes().to_vec();
+        {
+            let _locks = client
+                .acquire_locks(vec![(Keyspace::JobStatus, ""), (Keyspace::Slots, "")])
+                .await?;
+
+            let txn_ops = vec![
+                (Operation::Put(value.clone()), Keyspace::Slots, key.clone()),
+                (
+                    Operation::Put(value.clone()),
+                    Keyspace::JobStatus,
+                    key.clone(),
+                ),
+            ];
+            client.apply_txn(txn_ops).await?;
+        }
+
+        assert_eq!(client.get(Keyspace::Slots, key.as_str()).await?, value);
+        assert_eq!(client.get(Keyspace::JobStatus, key.as_str()).await?, value);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_empty() -> Result<(), Box<dyn std::error::Error>> {
+        let client = create_instance()?;
+        let key = "key";
+        let empty: &[u8] = &[];
+        assert_eq!(client.get(Keyspace::Slots, key).await?, empty);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_prefix() -> Result<(), Box<dyn std::error::Error>> {
+        let client = create_instance()?;
+        let key = "key";
+        let value = "value".as_bytes();
+        client
+            .put(Keyspace::Slots, format!("{key}/1"), value.to_vec())
+            .await?;
+        client
+            .put(Keyspace::Slots, format!("{key}/2"), value.to_vec())
+            .await?;
+        assert_eq!(
+            client.get_from_prefix(Keyspace::Slots, key).await?,
+            vec![
+                ("/Slots/key/1".to_owned(), value.to_vec()),
+                ("/Slots/key/2".to_owned(), value.to_vec())
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_prefix_range() -> Result<(), Box<dyn std::error::Error>> {
+        let client = create_instance()?;
+        let value = "value".as_bytes();
+        for key in ["ke", "key", "key/1", "key0", "kez", "key\u{10FFFF}"] {
+            client
+                .put(Keyspace::Slots, key.to_owned(), value.to_vec())
+                .await?;
+        }
+        let keys = |entries: Vec<(String, Vec<u8>)>| -> Vec<String> {
+            entries.into_iter().map(|(key, _)| key).collect()
+        };
+        assert_eq!(
+            keys(client.get_from_prefix(Keyspace::Slots, "key").await?),
+            vec![
+                "/Slots/key",
+                "/Slots/key/1",
+                "/Slots/key0",
+                "/Slots/key\u{10FFFF}"
+            ]
+        );
+        assert_eq!(
+            keys(client.get_from_prefix(Keyspace::Slots, "key/").await?),
+            vec!["/Slots/key/1"]
+        );
+        assert_eq!(client.scan(Keyspace::Slots, Some(2)).await?.len(), 2);
+        assert!(client.scan(Keyspace::Heartbeats, None).await?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn prefix_upper_bound() {
+        assert_eq!(
+            super::prefix_upper_bound("/Slots/"),
+            Some("/Slots0".to_owned())
+        );
+        assert_eq!(
+            super::prefix_upper_bound("a\u{D7FF}"),
+            Some("a\u{E000}".to_owned())
+        );
+        assert_eq!(
+            super::prefix_upper_bound("a\u{10FFFF}"),
+            Some("b".to_owned())
+        );
+        assert_eq!(super::prefix_upper_bound("\u{10FFFF}"), None);
+        assert_eq!(super::prefix_upper_bound(""), None);
+    }
+
+    #[tokio::test]
+    async fn move_value() -> Result<(), Box<dyn std::error::Error>> {
+        let client = create_instance()?;
+        let key = "key";
+        let value = "value".as_bytes();
+        let empty: &[u8] = &[];
+        client
+            .put(Keyspace::JobStatus, key.to_owned(), value.to_vec())
+            .await?;
+        client
+            .mv(Keyspace::JobStatus, Keyspace::ExecutionGraph, key)
+            .await?;
+        assert_eq!(client.get(Keyspace::JobStatus, key).await?, empty);
+        assert_eq!(client.get(Keyspace::ExecutionGraph, key).await?, value);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_watch() -> Result<(), Box<dyn std::error::Error>> {
+        let client = create_instance()?;
+        let key = "key";
+        let value = "value".as_bytes();
+        let mut watch: Box<dyn Watch<Item = WatchEvent>> =
+            client.watch(Keyspace::Slots, key.to_owned()).await?;
+        client
+            .put(Keyspace::Slots, key.to_owned(), value.to_vec())
+            .await?;
+        assert_eq!(
+            watch.next().await,
+            Some(WatchEvent::Put(
+                format!("/{:?}/{}", Keyspace::Slots, key.to_owned()),
+                value.to_owned()
+            ))
+        );
+        client.delete(Keyspace::Slots, key).await?;
+        assert_eq!(
+            watch.next().await,
+            Some(WatchEvent::Delete(format!(
+                "/{:?}/{}",
+                Keyspace::Slots,
+                key.to_owned()
+            )))
+        );
+        watch.cancel().await?;
+        Ok(())
+    }
+}
@@ -59,7 +59,7 @@ impl<S: JobState> JobStateTest<S> {
 
     pub async fn fail_planning(self, job_id: &str) -> Result<Self> {
         self.state
-            .fail_unscheduled_job(job_id, "failed planning".to_string())
+            .fail_unscheduled_job(job_id, "failed planning".to_string(), false)
             .await?;
         Ok(self)
     }
@@ -118,9 +118,9 @@ impl<S: JobState> JobStateTest<S> {
         let status = status.unwrap();
         assert!(
             matches!(&status, JobStatus {
-            job_id: status_job_id, status: Some(Status::Failed(_)), ..
-        } if status_job_id.as_str() == job_id),
-            "Expected failed status but found {:?}",
+            job_id: status_job_id, status: Some(Status::Failed(failed)), ..
+        } if status_job_id.as_str() == job_id && failed.ended_at > 0),
+            "Expected failed status with an end time but found {:?}",
             status
         );
 
@@ -207,7 +207,7 @@ fn drain_tasks(graph: &mut ExecutionGraph) -> Result<()> {
     let executor = mock_executor("executor-id1".to_string());
     while let Some(task) = graph.pop_next_task(&executor.id)? {
         let task_status = mock_completed_task(task, &executor.id);
-        graph.update_task_status(&executor, vec![task_status], 1, 1)?;
+        graph.update_task_status(&executor, vec![task_status])?;
     }
 
     Ok(())
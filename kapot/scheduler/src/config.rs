@@ -20,6 +20,7 @@
 
 use kapot_core::config::TaskSchedulingPolicy;
 use clap::ValueEnum;
+use std::collections::HashMap;
 use std::fmt;
 
 /// Configurations for the kapot scheduler of scheduling jobs and tasks
@@ -62,6 +63,36 @@ pub struct SchedulerConfig {
     pub executor_timeout_seconds: u64,
     /// The interval to check expired or dead executors
     pub expire_dead_executor_interval_seconds: u64,
+    /// The interval in milliseconds to check the running jobs for straggler tasks to launch
+    /// a speculative copy of, 0 means the check is disabled
+    pub speculation_interval_ms: u64,
+    /// Number of failed tasks of a single job on an executor before the executor is
+    /// excluded from task scheduling, 0 means the exclusion is disabled
+    pub executor_exclusion_max_failures_per_job: u32,
+    /// Number of failed tasks of all jobs on an executor before the executor is excluded
+    /// from task scheduling, 0 means the exclusion is disabled
+    pub executor_exclusion_max_failures: u32,
+    /// Time in seconds an executor stays excluded from task scheduling
+    pub executor_exclusion_timeout_seconds: u64,
+    /// Number of jobs of a tenant which run at the same time, the other jobs of the tenant
+    /// wait in the queue. 0 means no limit
+    pub tenant_max_running_jobs: u32,
+    /// Number of jobs of a tenant which wait in the queue, the jobs submitted beyond are
+    /// rejected. 0 means no limit
+    pub tenant_max_queued_jobs: u32,
+    /// Number of task slots the running jobs of a tenant use at the same time. 0 means
+    /// no limit
+    pub tenant_max_task_slots: u32,
+    /// Weights of the scheduling pools, set with `kapot.job.pool`, the task slots are
+    /// shared between in proportion to. The pools not listed have a weight of 1
+    pub scheduling_pool_weights: HashMap<String, u64>,
+    /// Time in seconds the leases of a scheduler on the leadership of the cluster and on
+    /// the jobs it curates last unless they are renewed, which they are every third of
+    /// it. The leader takes over the jobs whose lease expired. 0 means disabled
+    pub job_lease_seconds: u64,
+    /// Local directory or object store URL to archive a summary of every job to when it
+    /// succeeds or fails. Disabled if not set
+    pub job_history_path: Option<String>,
 }
 
 impl Default for SchedulerConfig {
@@ -84,6 +115,16 @@ impl Default for SchedulerConfig {
             grpc_server_max_encoding_message_size: 16777216,
             executor_timeout_seconds: 180,
             expire_dead_executor_interval_seconds: 15,
+            speculation_interval_ms: 1000,
+            executor_exclusion_max_failures_per_job: 0,
+            executor_exclusion_max_failures: 0,
+            executor_exclusion_timeout_seconds: 600,
+            tenant_max_running_jobs: 0,
+            tenant_max_queued_jobs: 0,
+            tenant_max_task_slots: 0,
+            scheduling_pool_weights: HashMap::new(),
+            job_lease_seconds: 0,
+            job_history_path: None,
         }
     }
 }
@@ -175,6 +216,88 @@ impl SchedulerConfig {
         self.grpc_server_max_encoding_message_size = value;
         self
     }
+
+    pub fn with_speculation_interval_ms(mut self, interval_ms: u64) -> Self {
+        self.speculation_interval_ms = interval_ms;
+        self
+    }
+
+    pub fn with_executor_exclusion_max_failures_per_job(
+        mut self,
+        max_failures: u32,
+    ) -> Self {
+        self.executor_exclusion_max_failures_per_job = max_failures;
+        self
+    }
+
+    pub fn with_executor_exclusion_max_failures(mut self, max_failures: u32) -> Self {
+        self.executor_exclusion_max_failures = max_failures;
+        self
+    }
+
+    pub fn with_executor_exclusion_timeout_seconds(
+        mut self,
+        timeout_seconds: u64,
+    ) -> Self {
+        self.executor_exclusion_timeout_seconds = timeout_seconds;
+        self
+    }
+
+    pub fn with_tenant_max_running_jobs(mut self, max_jobs: u32) -> Self {
+        self.tenant_max_running_jobs = max_jobs;
+        self
+    }
+
+    pub fn with_tenant_max_queued_jobs(mut self, max_jobs: u32) -> Self {
+        self.tenant_max_queued_jobs = max_jobs;
+        self
+    }
+
+    pub fn with_tenant_max_task_slots(mut self, max_slots: u32) -> Self {
+        self.tenant_max_task_slots = max_slots;
+        self
+    }
+
+    pub fn with_scheduling_pool_weight(
+        mut self,
+        pool: impl Into<String>,
+        weight: u64,
+    ) -> Self {
+        self.scheduling_pool_weights.insert(pool.into(), weight);
+        self
+    }
+
+    pub fn with_job_lease_seconds(mut self, lease_seconds: u64) -> Self {
+        self.job_lease_seconds = lease_seconds;
+        self
+    }
+
+    pub fn with_job_history_path(mut self, path: Option<String>) -> Self {
+        self.job_history_path = path;
+        self
+    }
+}
+
+/// Parse a comma separated list of scheduling pools with their weight, such as
+/// `etl:1,dashboards:3`
+pub fn parse_scheduling_pool_weights(
+    pools: &str,
+) -> std::result::Result<HashMap<String, u64>, String> {
+    pools
+        .split(',')
+        .map(str::trim)
+        .filter(|pool| !pool.is_empty())
+        .map(|pool| {
+            let weight = pool.rsplit_once(':').and_then(|(name, weight)| {
+                let weight = weight.trim().parse::<u64>().ok()?;
+                (!name.trim().is_empty() && weight > 0)
+                    .then(|| (name.trim().to_string(), weight))
+            });
+            weight.ok_or_else(|| {
+                format!("Invalid scheduling pool '{pool}', expected <name>:<weight> with a weight greater than 0")
+            })
+        })
+        .collect()
 }
 
 #[derive(Clone, Debug)]
@@ -184,6 +307,7 @@ pub enum ClusterStorageConfig {
     Etcd(Vec<String>),
     #[cfg(feature = "sled")]
     Sled(Option<String>),
+    Sqlite(Option<String>),
 }
 
 /// Policy of distributing tasks to available executor slots
@@ -235,3 +359,21 @@ pub enum TaskDistributionPolicy {
         tolerance: usize,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::parse_scheduling_pool_weights;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse_scheduling_pool_weights() {
+        assert_eq!(
+            HashMap::from([("etl".to_string(), 1), ("dashboards".to_string(), 3)]),
+            parse_scheduling_pool_weights(" etl:1, dashboards:3,").unwrap()
+        );
+        assert!(parse_scheduling_pool_weights("").unwrap().is_empty());
+        assert!(parse_scheduling_pool_weights("etl").is_err());
+        assert!(parse_scheduling_pool_weights("etl:0").is_err());
+        assert!(parse_scheduling_pool_weights(":2").is_err());
+    }
+}
@@ -53,6 +53,7 @@ use arrow_flight::sql::ProstMessageExt;
 use arrow_flight::utils::batches_to_flight_data;
 use arrow_flight::SchemaAsIpc;
 use kapot_core::config::KapotConfig;
+use kapot_core::error::KapotError;
 use kapot_core::serde::protobuf;
 use kapot_core::serde::protobuf::action::ActionType::FetchPartition;
 use kapot_core::serde::protobuf::job_status;
@@ -288,6 +289,13 @@ impl FlightSqlServiceImpl {
                     // Use executor ip:port for routing to flight result
                     host: exec_host.clone(),
                     port: exec_port,
+                    range: loc.range,
+                    // Flight SQL clients receive the results as LZ4 compressed batches
+                    compression: protobuf::CompressionCodec::Lz4.into(),
+                    checksum: loc.checksum,
+                    // the job output is written to shuffle files, never streamed
+                    streaming: false,
+                    map_partition_id: 0,
                 };
                 protobuf::Action {
                     action_type: Some(FetchPartition(fetch)),
@@ -322,6 +330,12 @@ impl FlightSqlServiceImpl {
             path: job_id.to_string(),
             host: host.clone(),
             port,
+            range: None,
+            compression: protobuf::CompressionCodec::Lz4.into(),
+            // answered by the scheduler itself, no shuffle file is read
+            checksum: None,
+            streaming: false,
+            map_partition_id: 0,
         };
         let fetch = protobuf::Action {
             action_type: Some(FetchPartition(fetch)),
@@ -388,10 +402,14 @@ impl FlightSqlServiceImpl {
         self.server
             .submit_job(&job_id, &job_name, ctx, plan)
             .await
-            .map_err(|e| {
-                let msg = format!("Failed to send JobQueued event for {job_id}: {e:?}");
-                error!("{}", msg);
-                Status::internal(msg)
+            .map_err(|e| match e {
+                KapotError::QuotaExceeded(msg) => Status::resource_exhausted(msg),
+                e => {
+                    let msg =
+                        format!("Failed to send JobQueued event for {job_id}: {e:?}");
+                    error!("{}", msg);
+                    Status::internal(msg)
+                }
             })?;
         Ok(job_id)
     }
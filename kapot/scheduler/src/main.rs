@@ -25,10 +25,12 @@ use anyhow::Result;
 use crate::config::{Config, ResultExt};
 use kapot_core::config::LogRotationPolicy;
 use kapot_core::print_version;
+use kapot_scheduler::cluster::snapshot::{read_snapshot, write_snapshot};
 use kapot_scheduler::cluster::KapotCluster;
 use kapot_scheduler::cluster::ClusterStorage;
 use kapot_scheduler::config::{
-    ClusterStorageConfig, SchedulerConfig, TaskDistribution, TaskDistributionPolicy,
+    parse_scheduling_pool_weights, ClusterStorageConfig, SchedulerConfig,
+    TaskDistribution, TaskDistributionPolicy,
 };
 use kapot_scheduler::scheduler_process::start_server;
 use tracing_subscriber::EnvFilter;
@@ -113,9 +115,6 @@ async fn inner() -> Result<()> {
             .init();
     }
 
-    let addr = format!("{}:{}", opt.bind_host, opt.bind_port);
-    let addr = tokio::net::TcpListener::bind(addr).await?;
-
     let cluster_storage_config = match opt.cluster_backend {
         ClusterStorage::Memory => ClusterStorageConfig::Memory,
         ClusterStorage::Etcd => ClusterStorageConfig::Etcd(
@@ -131,6 +130,13 @@ async fn inner() -> Result<()> {
                 ClusterStorageConfig::Sled(Some(opt.sled_dir))
             }
         }
+        ClusterStorage::Sqlite => {
+            if opt.sqlite_path.is_empty() {
+                ClusterStorageConfig::Sqlite(None)
+            } else {
+                ClusterStorageConfig::Sqlite(Some(opt.sqlite_path))
+            }
+        }
     };
 
     let task_distribution = match opt.task_distribution {
@@ -168,10 +174,48 @@ async fn inner() -> Result<()> {
         grpc_server_max_encoding_message_size: opt.grpc_server_max_encoding_message_size,
         executor_timeout_seconds: opt.executor_timeout_seconds,
         expire_dead_executor_interval_seconds: opt.expire_dead_executor_interval_seconds,
+        speculation_interval_ms: opt.speculation_interval_ms,
+        executor_exclusion_max_failures_per_job: opt
+            .executor_exclusion_max_failures_per_job,
+        executor_exclusion_max_failures: opt.executor_exclusion_max_failures,
+        executor_exclusion_timeout_seconds: opt.executor_exclusion_timeout_seconds,
+        tenant_max_running_jobs: opt.tenant_max_running_jobs,
+        tenant_max_queued_jobs: opt.tenant_max_queued_jobs,
+        tenant_max_task_slots: opt.tenant_max_task_slots,
+        scheduling_pool_weights: parse_scheduling_pool_weights(&opt.scheduling_pools)
+            .map_err(anyhow::Error::msg)?,
+        job_lease_seconds: opt.job_lease_seconds,
+        job_history_path: opt.job_history_path,
     };
 
     let cluster = KapotCluster::new_from_config(&config).await?;
 
+    if !opt.export_snapshot.is_empty() || !opt.restore_snapshot.is_empty() {
+        let Some(snapshot_store) = cluster.snapshot_store() else {
+            anyhow::bail!("The memory cluster backend does not support snapshots");
+        };
+        if !opt.export_snapshot.is_empty() {
+            let snapshot = snapshot_store.export_snapshot().await?;
+            write_snapshot(&snapshot, &opt.export_snapshot)?;
+            println!(
+                "Exported {} entries of the cluster state to {}",
+                snapshot.entries.len(),
+                opt.export_snapshot
+            );
+            return Ok(());
+        }
+        let restored = snapshot_store
+            .restore_snapshot(read_snapshot(&opt.restore_snapshot)?)
+            .await?;
+        println!(
+            "Restored {} entries of the cluster state from {}",
+            restored, opt.restore_snapshot
+        );
+    }
+
+    let addr = format!("{}:{}", opt.bind_host, opt.bind_port);
+    let addr = tokio::net::TcpListener::bind(addr).await?;
+
     start_server(cluster, addr, Arc::new(config)).await?;
     Ok(())
 }
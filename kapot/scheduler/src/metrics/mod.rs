@@ -48,6 +48,10 @@ pub trait SchedulerMetricsCollector: Send + Sync {
     /// Record that job with `job_id` was cancelled.
     fn record_cancelled(&self, job_id: &str);
 
+    /// Record that job with `job_id` was cancelled because it did not finish before its
+    /// deadline.
+    fn record_timed_out(&self, job_id: &str);
+
     /// Set the current number of pending tasks in scheduler. A pending task is a task that is available
     /// to schedule on an executor but cannot be scheduled because no resources are available.
     fn set_pending_tasks_queue_size(&self, value: u64);
@@ -67,6 +71,7 @@ impl SchedulerMetricsCollector for NoopMetricsCollector {
     fn record_completed(&self, _job_id: &str, _queued_at: u64, _completed_att: u64) {}
     fn record_failed(&self, _job_id: &str, _queued_at: u64, _failed_at: u64) {}
     fn record_cancelled(&self, _job_id: &str) {}
+    fn record_timed_out(&self, _job_id: &str) {}
     fn set_pending_tasks_queue_size(&self, _value: u64) {}
 
     fn gather_metrics(&self) -> Result<Option<(Vec<u8>, String)>> {
@@ -29,12 +29,13 @@ use std::sync::Arc;
 static COLLECTOR: OnceCell<Arc<dyn SchedulerMetricsCollector>> = OnceCell::new();
 
 /// SchedulerMetricsCollector implementation based on Prometheus. By default this will track
-/// 7 metrics:
+/// 8 metrics:
 /// *job_exec_time_seconds* - Histogram of successful job execution time in seconds
 /// *planning_time_ms* - Histogram of job planning time in milliseconds
 /// *failed* - Counter of failed jobs
 /// *job_failed_total* - Counter of failed jobs
 /// *job_cancelled_total* - Counter of cancelled jobs
+/// *job_timed_out_total* - Counter of jobs cancelled by their timeout or deadline
 /// *job_completed_total* - Counter of completed jobs
 /// *job_submitted_total* - Counter of submitted jobs
 /// *pending_task_queue_size* - Number of pending tasks
@@ -43,6 +44,7 @@ pub struct PrometheusMetricsCollector {
     planning_time: Histogram,
     failed: Counter,
     cancelled: Counter,
+    timed_out: Counter,
     completed: Counter,
     submitted: Counter,
     pending_queue_size: Gauge,
@@ -88,6 +90,15 @@ impl PrometheusMetricsCollector {
             kapotError::Internal(format!("Error registering metric: {e:?}"))
         })?;
 
+        let timed_out = register_counter_with_registry!(
+            "job_timed_out_total",
+            "Counter of jobs cancelled by their timeout or deadline",
+            registry
+        )
+        .map_err(|e| {
+            kapotError::Internal(format!("Error registering metric: {e:?}"))
+        })?;
+
         let completed = register_counter_with_registry!(
             "job_completed_total",
             "Counter of completed jobs",
@@ -120,6 +131,7 @@ impl PrometheusMetricsCollector {
             planning_time,
             failed,
             cancelled,
+            timed_out,
             completed,
             submitted,
             pending_queue_size,
@@ -158,6 +170,10 @@ impl SchedulerMetricsCollector for PrometheusMetricsCollector {
         self.cancelled.inc();
     }
 
+    fn record_timed_out(&self, _job_id: &str) {
+        self.timed_out.inc();
+    }
+
     fn set_pending_tasks_queue_size(&self, value: u64) {
         self.pending_queue_size.set(value as f64);
     }
@@ -17,33 +17,59 @@
 
 //! Distributed query execution
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
+use kapot_core::config::KapotConfig;
 use kapot_core::error::{KapotError, Result};
 use kapot_core::{
-    execution_plans::{ShuffleReaderExec, ShuffleWriterExec, UnresolvedShuffleExec},
+    execution_plans::{
+        RangePartitioning, RangeSampleExec, ShuffleReaderExec, ShuffleWriterExec,
+        UnresolvedShuffleExec,
+    },
     serde::scheduler::PartitionLocation,
 };
+use datafusion::common::JoinType;
+use datafusion::physical_plan::aggregates::{AggregateExec, AggregateMode};
+use datafusion::physical_plan::coalesce_batches::CoalesceBatchesExec;
 use datafusion::physical_plan::coalesce_partitions::CoalescePartitionsExec;
+use datafusion::physical_plan::filter::FilterExec;
+use datafusion::physical_plan::joins::{HashJoinExec, PartitionMode};
+use datafusion::physical_plan::projection::ProjectionExec;
 use datafusion::physical_plan::repartition::RepartitionExec;
+use datafusion::physical_plan::sorts::sort::SortExec;
 use datafusion::physical_plan::sorts::sort_preserving_merge::SortPreservingMergeExec;
-use datafusion::physical_plan::windows::WindowAggExec;
 use datafusion::physical_plan::{
-    with_new_children_if_necessary, ExecutionPlan, Partitioning,
+    with_new_children_if_necessary, Distribution, ExecutionPlan, Partitioning,
 };
 
 use log::{debug, info};
 
 type PartialQueryStageResult = (Arc<dyn ExecutionPlan>, Vec<Arc<ShuffleWriterExec>>);
+type ShuffleReaderReplacement<'a> =
+    &'a dyn Fn(&ShuffleReaderExec) -> Result<Arc<dyn ExecutionPlan>>;
+type BroadcastJoinStages = (Arc<dyn ExecutionPlan>, Arc<dyn ExecutionPlan>);
 
 pub struct DistributedPlanner {
     next_stage_id: usize,
+    config: KapotConfig,
+    // Whether the output of the plan being planned must keep its hash partitioning,
+    // which an operator above it relies on
+    hash_partitioning_required: bool,
 }
 
 impl DistributedPlanner {
     pub fn new() -> Self {
-        Self { next_stage_id: 0 }
+        Self::with_config(KapotConfig::default())
+    }
+
+    /// Create a planner which plans the query stages according to the job configuration
+    pub fn with_config(config: KapotConfig) -> Self {
+        Self {
+            next_stage_id: 0,
+            config,
+            hash_partitioning_required: false,
+        }
     }
 }
 
@@ -64,13 +90,22 @@ impl DistributedPlanner {
     ) -> Result<Vec<Arc<ShuffleWriterExec>>> {
         info!("planning query stages for job {}", job_id);
         let (new_plan, mut stages) =
-            self.plan_query_stages_internal(job_id, execution_plan)?;
-        stages.push(create_shuffle_writer(
+            match self.plan_range_partitioned_sort(job_id, execution_plan.clone())? {
+                Some(result) => result,
+                None => self.plan_query_stages_internal(job_id, execution_plan)?,
+            };
+        // the output of the final stage is fetched by the client as soon as the job
+        // completes, so it is kept on the executors rather than the remote storage
+        let final_stage = create_shuffle_writer(
             job_id,
             self.next_stage_id(),
             new_plan,
             None,
-        )?);
+            &self.config,
+        )?;
+        stages.push(Arc::new(
+            final_stage.as_ref().clone().with_remote_storage(None),
+        ));
         Ok(stages)
     }
 
@@ -87,11 +122,32 @@ impl DistributedPlanner {
             return Ok((execution_plan, vec![]));
         }
 
+        if let Some(join) = execution_plan.as_any().downcast_ref::<HashJoinExec>() {
+            if let Some(result) = self.plan_broadcast_join(job_id, join)? {
+                return Ok(result);
+            }
+        }
+
+        // the partitioning of the output of an operator follows the one of its input,
+        // unless the operator changes it
+        let keeps_partitioning = self.hash_partitioning_required
+            && !execution_plan.as_any().is::<RepartitionExec>()
+            && !execution_plan.as_any().is::<CoalescePartitionsExec>()
+            && !execution_plan.as_any().is::<SortPreservingMergeExec>();
         let mut stages = vec![];
         let mut children = vec![];
-        for child in execution_plan.children() {
+        for (child, distribution) in execution_plan
+            .children()
+            .into_iter()
+            .zip(execution_plan.required_input_distribution())
+        {
+            let hash_partitioning_required = match distribution {
+                Distribution::HashPartitioned(_) => true,
+                Distribution::SinglePartition => false,
+                Distribution::UnspecifiedDistribution => keeps_partitioning,
+            };
             let (new_child, mut child_stages) =
-                self.plan_query_stages_internal(job_id, child.clone())?;
+                self.plan_child(job_id, child.clone(), hash_partitioning_required)?;
             children.push(new_child);
             stages.append(&mut child_stages);
         }
@@ -105,8 +161,10 @@ impl DistributedPlanner {
                 self.next_stage_id(),
                 children[0].clone(),
                 None,
+                &self.config,
             )?;
-            let unresolved_shuffle = create_unresolved_shuffle(&shuffle_writer);
+            let unresolved_shuffle =
+                create_unresolved_shuffle(&shuffle_writer, &self.config);
             stages.push(shuffle_writer);
             Ok((
                 with_new_children_if_necessary(execution_plan, vec![unresolved_shuffle])?,
@@ -121,8 +179,10 @@ impl DistributedPlanner {
                 self.next_stage_id(),
                 children[0].clone(),
                 None,
+                &self.config,
             )?;
-            let unresolved_shuffle = create_unresolved_shuffle(&shuffle_writer);
+            let unresolved_shuffle =
+                create_unresolved_shuffle(&shuffle_writer, &self.config);
             stages.push(shuffle_writer);
             Ok((
                 with_new_children_if_necessary(execution_plan, vec![unresolved_shuffle])?,
@@ -138,8 +198,10 @@ impl DistributedPlanner {
                         self.next_stage_id(),
                         children[0].clone(),
                         Some(repart.partitioning().to_owned()),
+                        &self.config,
                     )?;
-                    let unresolved_shuffle = create_unresolved_shuffle(&shuffle_writer);
+                    let unresolved_shuffle =
+                        create_unresolved_shuffle(&shuffle_writer, &self.config);
                     stages.push(shuffle_writer);
                     Ok((unresolved_shuffle, stages))
                 }
@@ -148,12 +210,6 @@ impl DistributedPlanner {
                     Ok((children[0].clone(), stages))
                 }
             }
-        } else if let Some(window) =
-            execution_plan.as_any().downcast_ref::<WindowAggExec>()
-        {
-            Err(KapotError::NotImplemented(format!(
-                "WindowAggExec with window {window:?}"
-            )))
         } else {
             Ok((
                 with_new_children_if_necessary(execution_plan, children)?,
@@ -162,6 +218,226 @@ impl DistributedPlanner {
         }
     }
 
+    /// Plans a global sort at the root of the plan as a range partitioned shuffle when
+    /// [`KAPOT_SHUFFLE_RANGE_PARTITIONING_ENABLED`] is set, so that the data is sorted in
+    /// parallel instead of being merged into a single partition.
+    ///
+    /// The sort input is sampled by a [RangeSampleExec] stage, from which the map tasks of the
+    /// range partitioned stage compute the partition bounds. The final stage sorts each range
+    /// and its output partitions, read in order, are the sorted result.
+    ///
+    /// [`KAPOT_SHUFFLE_RANGE_PARTITIONING_ENABLED`]: kapot_core::config::KAPOT_SHUFFLE_RANGE_PARTITIONING_ENABLED
+    fn plan_range_partitioned_sort<'a>(
+        &'a mut self,
+        job_id: &'a str,
+        execution_plan: Arc<dyn ExecutionPlan>,
+    ) -> Result<Option<PartialQueryStageResult>> {
+        if !self.config.shuffle_range_partitioning_enabled() {
+            return Ok(None);
+        }
+        let merge = match execution_plan
+            .as_any()
+            .downcast_ref::<SortPreservingMergeExec>()
+        {
+            Some(merge) if merge.fetch().is_none() => merge,
+            _ => return Ok(None),
+        };
+        let sort = match merge.input().as_any().downcast_ref::<SortExec>() {
+            Some(sort) if sort.preserve_partitioning() && sort.fetch().is_none() => sort,
+            _ => return Ok(None),
+        };
+
+        let (input, mut stages) = self.plan_child(job_id, sort.input().clone(), false)?;
+
+        // the sort input is read by both the sample stage and the range partitioned stage,
+        // so it's materialized first unless it already is the output of another stage
+        let input = if input.as_any().is::<UnresolvedShuffleExec>() {
+            input
+        } else {
+            let shuffle_writer = create_shuffle_writer(
+                job_id,
+                self.next_stage_id(),
+                input,
+                None,
+                &self.config,
+            )?;
+            let unresolved_shuffle: Arc<dyn ExecutionPlan> =
+                create_unresolved_shuffle(&shuffle_writer, &self.config);
+            stages.push(shuffle_writer);
+            unresolved_shuffle
+        };
+
+        let sample = Arc::new(RangeSampleExec::try_new(
+            input.clone(),
+            sort.expr().clone(),
+            self.config.shuffle_range_partitioning_sample_size(),
+        )?);
+        let sample_writer = create_shuffle_writer(
+            job_id,
+            self.next_stage_id(),
+            sample,
+            None,
+            &self.config,
+        )?;
+        let samples = create_unresolved_shuffle(&sample_writer, &self.config);
+        stages.push(sample_writer);
+
+        let range_partitioning = RangePartitioning::new(
+            sort.expr().clone(),
+            self.config.default_shuffle_partitions(),
+            samples,
+        );
+        let shuffle_writer = Arc::new(
+            ShuffleWriterExec::try_new(
+                job_id.to_owned(),
+                self.next_stage_id(),
+                input,
+                "".to_owned(), // executor will decide on the work_dir path
+                None,
+            )?
+            .with_sort_based_shuffle(self.config.shuffle_sort_based_enabled())
+            .with_buffer_size(self.config.shuffle_writer_buffer_size())
+            .with_compression(self.config.shuffle_compression())
+            .with_remote_storage(self.config.shuffle_remote_storage_url())
+            .with_range_partitioning(range_partitioning),
+        );
+        let unresolved_shuffle = create_unresolved_shuffle(&shuffle_writer, &self.config);
+        stages.push(shuffle_writer);
+
+        Ok(Some((
+            with_new_children_if_necessary(
+                merge.input().clone(),
+                vec![unresolved_shuffle],
+            )?,
+            stages,
+        )))
+    }
+
+    /// Plans a [HashJoinExec] as a broadcast join: the build side is materialized once by its
+    /// own stage and every task of the probe side reads the complete output of that stage.
+    ///
+    /// Joins collecting their build side are always broadcast, the stage boundary of the
+    /// coalescing of their build side being reused when there is one. Partitioned joins
+    /// are broadcast when the statistics of the build side show that it is smaller than
+    /// [`KAPOT_HASH_JOIN_SINGLE_PARTITION_THRESHOLD`], in which case the hash
+    /// repartitioning of the probe side is removed unless an operator above the join
+    /// relies on the hash partitioning of its output.
+    ///
+    /// [`KAPOT_HASH_JOIN_SINGLE_PARTITION_THRESHOLD`]: kapot_core::config::KAPOT_HASH_JOIN_SINGLE_PARTITION_THRESHOLD
+    fn plan_broadcast_join<'a>(
+        &'a mut self,
+        job_id: &'a str,
+        join: &HashJoinExec,
+    ) -> Result<Option<PartialQueryStageResult>> {
+        // unmatched build side rows can't be tracked across the tasks of the probe side
+        if !matches!(
+            join.join_type(),
+            JoinType::Inner | JoinType::Right | JoinType::RightSemi | JoinType::RightAnti
+        ) {
+            return Ok(None);
+        }
+
+        let (build, probe) = match join.partition_mode() {
+            PartitionMode::CollectLeft => (join.left().clone(), join.right().clone()),
+            PartitionMode::Partitioned => match hash_repartition_input(join.left()) {
+                Some(input) if self.is_broadcast_candidate(&input)? => {
+                    let probe = match hash_repartition_input(join.right()) {
+                        Some(probe) if !self.hash_partitioning_required => probe,
+                        _ => join.right().clone(),
+                    };
+                    (input, probe)
+                }
+                _ => return Ok(None),
+            },
+            PartitionMode::Auto => return Ok(None),
+        };
+
+        let (build, mut stages) = if build.as_any().is::<CoalescePartitionsExec>() {
+            // the coalescing of the build side already is a stage boundary
+            self.plan_child(job_id, build, false)?
+        } else {
+            let (build, stages) = self.plan_child(job_id, build, false)?;
+            self.plan_broadcast_stage(job_id, build, stages)?
+        };
+
+        // the output partitioning of the broadcast join is the one of its probe side
+        let (probe, mut probe_stages) = self.plan_query_stages_internal(job_id, probe)?;
+        stages.append(&mut probe_stages);
+
+        debug!("planning broadcast join {join:?}");
+        let broadcast_join = HashJoinExec::try_new(
+            build,
+            probe,
+            join.on().to_vec(),
+            join.filter().cloned(),
+            join.join_type(),
+            join.projection.clone(),
+            PartitionMode::CollectLeft,
+            join.null_equals_null(),
+        )?;
+        Ok(Some((Arc::new(broadcast_join), stages)))
+    }
+
+    /// Materializes the planned build side of a broadcast join as a single stage, unless
+    /// it already is the output of another stage
+    fn plan_broadcast_stage<'a>(
+        &'a mut self,
+        job_id: &'a str,
+        build: Arc<dyn ExecutionPlan>,
+        mut stages: Vec<Arc<ShuffleWriterExec>>,
+    ) -> Result<PartialQueryStageResult> {
+        let build = if build.as_any().is::<UnresolvedShuffleExec>() {
+            build
+        } else {
+            let shuffle_writer = create_shuffle_writer(
+                job_id,
+                self.next_stage_id(),
+                build,
+                None,
+                &self.config,
+            )?;
+            let unresolved_shuffle: Arc<dyn ExecutionPlan> =
+                create_unresolved_shuffle(&shuffle_writer, &self.config);
+            stages.push(shuffle_writer);
+            unresolved_shuffle
+        };
+        let build: Arc<dyn ExecutionPlan> =
+            if build.properties().output_partitioning().partition_count() > 1 {
+                Arc::new(CoalescePartitionsExec::new(build))
+            } else {
+                build
+            };
+        Ok((build, stages))
+    }
+
+    /// Plans the input of an operator, `hash_partitioning_required` telling whether the
+    /// operator relies on the hash partitioning of its input
+    fn plan_child<'a>(
+        &'a mut self,
+        job_id: &'a str,
+        child: Arc<dyn ExecutionPlan>,
+        hash_partitioning_required: bool,
+    ) -> Result<PartialQueryStageResult> {
+        let parent_required = std::mem::replace(
+            &mut self.hash_partitioning_required,
+            hash_partitioning_required,
+        );
+        let result = self.plan_query_stages_internal(job_id, child);
+        self.hash_partitioning_required = parent_required;
+        result
+    }
+
+    /// Returns whether the statistics of the plan show that its output is small enough to be
+    /// broadcast. As in DataFusion, a size of zero is treated as unknown.
+    fn is_broadcast_candidate(&self, plan: &Arc<dyn ExecutionPlan>) -> Result<bool> {
+        let threshold = self.config.hash_join_single_partition_threshold();
+        Ok(plan
+            .statistics()?
+            .total_byte_size
+            .get_value()
+            .is_some_and(|size| *size != 0 && *size < threshold))
+    }
+
     /// Generate a new stage ID
     fn next_stage_id(&mut self) -> usize {
         self.next_stage_id += 1;
@@ -171,15 +447,23 @@ impl DistributedPlanner {
 
 fn create_unresolved_shuffle(
     shuffle_writer: &ShuffleWriterExec,
+    config: &KapotConfig,
 ) -> Arc<UnresolvedShuffleExec> {
-    Arc::new(UnresolvedShuffleExec::new(
-        shuffle_writer.stage_id(),
-        shuffle_writer.schema(),
-        shuffle_writer
-            .properties()
-            .output_partitioning()
-            .partition_count(),
-    ))
+    Arc::new(
+        UnresolvedShuffleExec::new(
+            shuffle_writer.stage_id(),
+            shuffle_writer.schema(),
+            shuffle_writer
+                .properties()
+                .output_partitioning()
+                .partition_count(),
+        )
+        .with_compression(shuffle_writer.compression())
+        .with_fetch_limits(
+            config.shuffle_reader_max_requests(),
+            config.shuffle_reader_max_bytes_in_flight(),
+        ),
+    )
 }
 
 /// Returns the unresolved shuffles in the execution plan
@@ -242,11 +526,18 @@ pub fn remove_unresolved_shuffles(
                     .collect::<Vec<_>>()
                     .join("\n")
             );
-            new_children.push(Arc::new(ShuffleReaderExec::try_new(
-                unresolved_shuffle.stage_id,
-                relevant_locations,
-                unresolved_shuffle.schema().clone(),
-            )?))
+            new_children.push(Arc::new(
+                ShuffleReaderExec::try_new(
+                    unresolved_shuffle.stage_id,
+                    relevant_locations,
+                    unresolved_shuffle.schema().clone(),
+                )?
+                .with_compression(unresolved_shuffle.compression)
+                .with_fetch_limits(
+                    unresolved_shuffle.max_requests,
+                    unresolved_shuffle.max_bytes_in_flight,
+                ),
+            ))
         } else {
             new_children.push(remove_unresolved_shuffles(
                 child.clone(),
@@ -266,17 +557,21 @@ pub fn rollback_resolved_shuffles(
     let mut new_children: Vec<Arc<dyn ExecutionPlan>> = vec![];
     for child in stage.children() {
         if let Some(shuffle_reader) = child.as_any().downcast_ref::<ShuffleReaderExec>() {
-            let output_partition_count = shuffle_reader
-                .properties()
-                .output_partitioning()
-                .partition_count();
+            let output_partition_count = shuffle_reader.shuffle_partition_count();
             let stage_id = shuffle_reader.stage_id;
 
-            let unresolved_shuffle = Arc::new(UnresolvedShuffleExec::new(
-                stage_id,
-                shuffle_reader.schema(),
-                output_partition_count,
-            ));
+            let unresolved_shuffle = Arc::new(
+                UnresolvedShuffleExec::new(
+                    stage_id,
+                    shuffle_reader.schema(),
+                    output_partition_count,
+                )
+                .with_compression(shuffle_reader.compression())
+                .with_fetch_limits(
+                    shuffle_reader.max_requests(),
+                    shuffle_reader.max_bytes_in_flight(),
+                ),
+            );
             new_children.push(unresolved_shuffle);
         } else {
             new_children.push(rollback_resolved_shuffles(child.clone())?);
@@ -285,36 +580,646 @@ pub fn rollback_resolved_shuffles(
     Ok(with_new_children_if_necessary(stage, new_children)?)
 }
 
+/// Coalesce adjacent shuffle partitions read by a resolved stage, until the total size
+/// of every group of partitions, as recorded in their statistics, reaches `target_size`.
+///
+/// All shuffles read by the stage are coalesced the same way, so that co-partitioned
+/// inputs, such as both sides of a partitioned hash join, remain co-partitioned. The
+/// stage is left as it is unless all of its leaves are shuffles with the same number of
+/// partitions. As the number of partitions of the stage output shrinks along with its
+/// tasks when it isn't repartitioned, the stage is also left as it is unless it
+/// repartitions its output, and when any of its operators requires an ordering of its
+/// input, which the concatenated partitions wouldn't keep.
+pub fn coalesce_shuffle_partitions(
+    stage: Arc<dyn ExecutionPlan>,
+    target_size: usize,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    let input = match stage.as_any().downcast_ref::<ShuffleWriterExec>() {
+        Some(writer) if writer.shuffle_output_partitioning().is_some() => {
+            writer.children()[0]
+        }
+        _ => return Ok(stage),
+    };
+    if requires_input_ordering(input) {
+        return Ok(stage);
+    }
+    let mut shuffle_readers = vec![];
+    if !find_shuffle_readers(input, &mut shuffle_readers) {
+        return Ok(stage);
+    }
+    let partition_count = match shuffle_readers.first() {
+        Some(reader) => reader.partition.len(),
+        None => return Ok(stage),
+    };
+    if shuffle_readers.iter().any(|reader| {
+        reader.partition.len() != partition_count
+            || reader.shuffle_partition_count() != partition_count
+    }) {
+        return Ok(stage);
+    }
+
+    let mut partition_sizes = vec![0; partition_count];
+    for reader in &shuffle_readers {
+        for (size, locations) in partition_sizes.iter_mut().zip(&reader.partition) {
+            for location in locations {
+                match location.partition_stats.num_bytes() {
+                    Some(num_bytes) => *size += num_bytes as usize,
+                    None => return Ok(stage),
+                }
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = vec![];
+    let mut group_size = 0;
+    for (partition, size) in partition_sizes.into_iter().enumerate() {
+        match groups.last_mut() {
+            Some(group) if group_size + size <= target_size => {
+                group.push(partition);
+                group_size += size;
+            }
+            _ => {
+                groups.push(vec![partition]);
+                group_size = size;
+            }
+        }
+    }
+    if groups.len() == partition_count {
+        return Ok(stage);
+    }
+
+    info!(
+        "Coalescing {} shuffle partitions into {} partitions of up to {} bytes",
+        partition_count,
+        groups.len(),
+        target_size
+    );
+    replace_shuffle_readers(stage, &|shuffle_reader| {
+        let partition = groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .flat_map(|p| shuffle_reader.partition[*p].clone())
+                    .collect()
+            })
+            .collect();
+        with_shuffle_reader_partitions(shuffle_reader, partition)
+    })
+}
+
+/// Thresholds above which a partition of a join input is split into several tasks, see
+/// [`split_skewed_partitions`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SkewJoinConfig {
+    /// A partition is skewed when it is larger than this factor times the median size of
+    /// the partitions of the join input
+    pub skewed_partition_factor: usize,
+    /// Minimum size in bytes of a skewed partition
+    pub skewed_partition_threshold: usize,
+    /// Size in bytes of the pieces a skewed partition is split into
+    pub target_size: usize,
+}
+
+/// Split the skewed partitions of the partitioned hash join of a resolved stage into
+/// pieces of up to `target_size` bytes, each joined with the matching partition of the
+/// other side of the join in its own task.
+///
+/// A skewed partition is split along the map tasks which wrote it, as recorded in its
+/// locations. Only the sides of the join which don't emit unmatched rows of the other
+/// side can be split, since the other side is replicated to every piece. The stage is
+/// left as it is unless it repartitions its output, and only reads the two shuffles
+/// joined through operators which don't depend on the partitioning of their input.
+pub fn split_skewed_partitions(
+    stage: Arc<dyn ExecutionPlan>,
+    config: &SkewJoinConfig,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    let join = match stage.as_any().downcast_ref::<ShuffleWriterExec>() {
+        Some(writer) if writer.shuffle_output_partitioning().is_some() => {
+            find_partitioned_join(writer.children()[0])
+        }
+        _ => None,
+    };
+    let Some(join) = join else {
+        return Ok(stage);
+    };
+    let (Some(left), Some(right)) = (
+        find_join_input::<ShuffleReaderExec>(join.left()),
+        find_join_input::<ShuffleReaderExec>(join.right()),
+    ) else {
+        return Ok(stage);
+    };
+    let partition_count = left.partition.len();
+    if right.partition.len() != partition_count {
+        return Ok(stage);
+    }
+    let (Some(left_sizes), Some(right_sizes)) =
+        (partition_sizes(left), partition_sizes(right))
+    else {
+        return Ok(stage);
+    };
+
+    let join_type = join.join_type();
+    let split_left = matches!(
+        join_type,
+        JoinType::Inner
+            | JoinType::Left
+            | JoinType::LeftSemi
+            | JoinType::LeftAnti
+            | JoinType::LeftMark
+    );
+    let split_right = matches!(
+        join_type,
+        JoinType::Inner | JoinType::Right | JoinType::RightSemi | JoinType::RightAnti
+    );
+    let left_skew_size = skewed_partition_size(&left_sizes, config);
+    let right_skew_size = skewed_partition_size(&right_sizes, config);
+
+    let mut left_partitions = vec![];
+    let mut right_partitions = vec![];
+    let mut skewed_partitions = 0;
+    for p in 0..partition_count {
+        let left_pieces = if split_left && left_sizes[p] > left_skew_size {
+            split_locations(&left.partition[p], config.target_size)
+        } else {
+            vec![left.partition[p].clone()]
+        };
+        let right_pieces = if split_right && right_sizes[p] > right_skew_size {
+            split_locations(&right.partition[p], config.target_size)
+        } else {
+            vec![right.partition[p].clone()]
+        };
+        if left_pieces.len() > 1 || right_pieces.len() > 1 {
+            skewed_partitions += 1;
+        }
+        for left_piece in &left_pieces {
+            for right_piece in &right_pieces {
+                left_partitions.push(left_piece.clone());
+                right_partitions.push(right_piece.clone());
+            }
+        }
+    }
+    if skewed_partitions == 0 {
+        return Ok(stage);
+    }
+
+    info!(
+        "Splitting {} skewed partitions of a {} join into {} partitions",
+        skewed_partitions,
+        join_type,
+        left_partitions.len()
+    );
+    let split_join = HashJoinExec::try_new(
+        with_join_input_partitions(join.left(), left_partitions)?,
+        with_join_input_partitions(join.right(), right_partitions)?,
+        join.on().to_vec(),
+        join.filter().cloned(),
+        join_type,
+        join.projection.clone(),
+        PartitionMode::Partitioned,
+        join.null_equals_null(),
+    )?;
+    replace_partitioned_join(stage.clone(), Arc::new(split_join))
+}
+
+/// Switch the partitioned hash join of a resolved stage to a broadcast join when the
+/// output of its build side, as recorded in the statistics of its partitions, is smaller
+/// than `threshold` bytes.
+///
+/// Every task of the broadcast join reads the complete build side, so the probe side no
+/// longer needs to be partitioned like the build side: each task reads the output of a
+/// single map task of the probe side rather than one of its hash partitions. Only the
+/// joins which don't emit unmatched rows of the build side are switched, as these can't
+/// be tracked across the tasks of the probe side. As when splitting skewed partitions,
+/// the stage is left as it is unless it repartitions its output, and only reads the two
+/// shuffles joined through operators which don't depend on the partitioning of their
+/// input.
+pub fn broadcast_small_join_build_side(
+    stage: Arc<dyn ExecutionPlan>,
+    threshold: usize,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    let join = match stage.as_any().downcast_ref::<ShuffleWriterExec>() {
+        Some(writer) if writer.shuffle_output_partitioning().is_some() => {
+            find_partitioned_join(writer.children()[0])
+        }
+        _ => None,
+    };
+    let Some(join) = join else {
+        return Ok(stage);
+    };
+    if !matches!(
+        join.join_type(),
+        JoinType::Inner | JoinType::Right | JoinType::RightSemi | JoinType::RightAnti
+    ) {
+        return Ok(stage);
+    }
+    let (Some(build), Some(probe)) = (
+        find_join_input::<ShuffleReaderExec>(join.left()),
+        find_join_input::<ShuffleReaderExec>(join.right()),
+    ) else {
+        return Ok(stage);
+    };
+    let build_size: usize = match partition_sizes(build) {
+        Some(sizes) => sizes.iter().sum(),
+        None => return Ok(stage),
+    };
+    if build_size >= threshold {
+        return Ok(stage);
+    }
+
+    let mut map_outputs: BTreeMap<usize, Vec<PartitionLocation>> = BTreeMap::new();
+    for location in probe.partition.iter().flatten() {
+        map_outputs
+            .entry(location.map_partition_id)
+            .or_default()
+            .push(location.clone());
+    }
+    if map_outputs.is_empty() {
+        return Ok(stage);
+    }
+
+    info!(
+        "Switching a partitioned {} join to a broadcast join of {} bytes, reading the \
+         output of {} map tasks of the probe side",
+        join.join_type(),
+        build_size,
+        map_outputs.len()
+    );
+    let build_partition = vec![build.partition.iter().flatten().cloned().collect()];
+    let probe_partition = map_outputs.into_values().collect();
+    let broadcast_join = HashJoinExec::try_new(
+        with_join_input_partitions(join.left(), build_partition)?,
+        with_join_input_partitions(join.right(), probe_partition)?,
+        join.on().to_vec(),
+        join.filter().cloned(),
+        join.join_type(),
+        join.projection.clone(),
+        PartitionMode::CollectLeft,
+        join.null_equals_null(),
+    )?;
+    replace_partitioned_join(stage.clone(), Arc::new(broadcast_join))
+}
+
+/// Returns the stage of the probe side of the partitioned hash join of an unresolved stage,
+/// when its build side is the output of stage `build_stage_id` and the join could be
+/// switched to a broadcast join, see [`broadcast_join_build_stage`]
+pub fn find_broadcast_join_probe_stage(
+    stage: &Arc<dyn ExecutionPlan>,
+    build_stage_id: usize,
+) -> Option<usize> {
+    let join = match stage.as_any().downcast_ref::<ShuffleWriterExec>() {
+        Some(writer) if writer.shuffle_output_partitioning().is_some() => {
+            find_partitioned_join(writer.children()[0])?
+        }
+        _ => return None,
+    };
+    if !matches!(
+        join.join_type(),
+        JoinType::Inner | JoinType::Right | JoinType::RightSemi | JoinType::RightAnti
+    ) {
+        return None;
+    }
+    let build = find_join_input::<UnresolvedShuffleExec>(join.left())?;
+    let probe = find_join_input::<UnresolvedShuffleExec>(join.right())?;
+    (build.stage_id == build_stage_id && probe.stage_id != build_stage_id)
+        .then_some(probe.stage_id)
+}
+
+/// Switch the partitioned hash join of an unresolved stage to a broadcast join once its
+/// build side turns out to be small, while the stage of its probe side is unresolved too.
+///
+/// Unlike [`broadcast_small_join_build_side`], which can only read the output of each map
+/// task of a probe side which has already been hash repartitioned, the probe stage is
+/// re-planned to write the output of each of its tasks as it is. Returns the join stage
+/// and the probe stage re-planned, or `None` when the probe stage doesn't hash
+/// repartition its output.
+pub fn broadcast_join_build_stage(
+    stage: Arc<dyn ExecutionPlan>,
+    probe_stage: &Arc<dyn ExecutionPlan>,
+) -> Result<Option<BroadcastJoinStages>> {
+    let probe_writer = match probe_stage.as_any().downcast_ref::<ShuffleWriterExec>() {
+        Some(writer)
+            if matches!(
+                writer.shuffle_output_partitioning(),
+                Some(Partitioning::Hash(_, _))
+            ) =>
+        {
+            writer.without_output_partitioning()?
+        }
+        _ => return Ok(None),
+    };
+    let join = match stage.as_any().downcast_ref::<ShuffleWriterExec>() {
+        Some(writer) => find_partitioned_join(writer.children()[0]),
+        None => None,
+    };
+    let Some(join) = join else {
+        return Ok(None);
+    };
+
+    let probe_partition_count = probe_writer
+        .properties()
+        .output_partitioning()
+        .partition_count();
+    let build: Arc<dyn ExecutionPlan> =
+        Arc::new(CoalescePartitionsExec::new(join.left().clone()));
+    let broadcast_join = HashJoinExec::try_new(
+        build,
+        with_join_input_partition_count(join.right(), probe_partition_count)?,
+        join.on().to_vec(),
+        join.filter().cloned(),
+        join.join_type(),
+        join.projection.clone(),
+        PartitionMode::CollectLeft,
+        join.null_equals_null(),
+    )?;
+    Ok(Some((
+        replace_partitioned_join(stage.clone(), Arc::new(broadcast_join))?,
+        Arc::new(probe_writer),
+    )))
+}
+
+/// Returns a copy of a join input whose unresolved shuffle reads `partition_count`
+/// partitions of the same stage
+fn with_join_input_partition_count(
+    input: &Arc<dyn ExecutionPlan>,
+    partition_count: usize,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    match input.as_any().downcast_ref::<UnresolvedShuffleExec>() {
+        Some(unresolved_shuffle) => Ok(Arc::new(
+            UnresolvedShuffleExec::new(
+                unresolved_shuffle.stage_id,
+                unresolved_shuffle.schema.clone(),
+                partition_count,
+            )
+            .with_compression(unresolved_shuffle.compression)
+            .with_fetch_limits(
+                unresolved_shuffle.max_requests,
+                unresolved_shuffle.max_bytes_in_flight,
+            ),
+        )),
+        None => {
+            let child =
+                with_join_input_partition_count(input.children()[0], partition_count)?;
+            Ok(with_new_children_if_necessary(input.clone(), vec![child])?)
+        }
+    }
+}
+
+/// Returns a copy of a join input whose shuffle reader reads other partitions of the
+/// same shuffle
+fn with_join_input_partitions(
+    input: &Arc<dyn ExecutionPlan>,
+    partition: Vec<Vec<PartitionLocation>>,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    match input.as_any().downcast_ref::<ShuffleReaderExec>() {
+        Some(shuffle_reader) => with_shuffle_reader_partitions(shuffle_reader, partition),
+        None => {
+            let child = with_join_input_partitions(input.children()[0], partition)?;
+            Ok(with_new_children_if_necessary(input.clone(), vec![child])?)
+        }
+    }
+}
+
+/// Rebuild a stage with its partitioned hash join replaced by `join`
+fn replace_partitioned_join(
+    plan: Arc<dyn ExecutionPlan>,
+    join: Arc<dyn ExecutionPlan>,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    if plan.as_any().is::<HashJoinExec>() {
+        return Ok(join);
+    }
+    let child = replace_partitioned_join(plan.children()[0].clone(), join)?;
+    Ok(with_new_children_if_necessary(plan, vec![child])?)
+}
+
+/// Returns the partitioned hash join below operators which process each partition of
+/// their input on its own
+fn find_partitioned_join(plan: &Arc<dyn ExecutionPlan>) -> Option<&HashJoinExec> {
+    let any = plan.as_any();
+    if let Some(join) = any.downcast_ref::<HashJoinExec>() {
+        return (*join.partition_mode() == PartitionMode::Partitioned).then_some(join);
+    }
+    let partition_wise = any.is::<CoalesceBatchesExec>()
+        || any.is::<ProjectionExec>()
+        || any.is::<FilterExec>()
+        || any
+            .downcast_ref::<AggregateExec>()
+            .is_some_and(|aggregate| *aggregate.mode() == AggregateMode::Partial);
+    if partition_wise {
+        find_partitioned_join(plan.children()[0])
+    } else {
+        None
+    }
+}
+
+/// Returns the shuffle reader, or the unresolved shuffle, of a join input, below operators
+/// which process each partition of their input on its own
+fn find_join_input<T: ExecutionPlan + 'static>(
+    plan: &Arc<dyn ExecutionPlan>,
+) -> Option<&T> {
+    let any = plan.as_any();
+    if let Some(shuffle) = any.downcast_ref::<T>() {
+        return Some(shuffle);
+    }
+    if any.is::<CoalesceBatchesExec>()
+        || any.is::<ProjectionExec>()
+        || any.is::<FilterExec>()
+    {
+        find_join_input(plan.children()[0])
+    } else {
+        None
+    }
+}
+
+/// Size in bytes of every partition of a shuffle reader, `None` when the statistics of
+/// a partition are missing
+fn partition_sizes(shuffle_reader: &ShuffleReaderExec) -> Option<Vec<usize>> {
+    shuffle_reader
+        .partition
+        .iter()
+        .map(|locations| {
+            locations
+                .iter()
+                .map(|location| location.partition_stats.num_bytes().map(|n| n as usize))
+                .sum()
+        })
+        .collect()
+}
+
+/// Size in bytes above which a partition is skewed
+fn skewed_partition_size(partition_sizes: &[usize], config: &SkewJoinConfig) -> usize {
+    let mut sorted_sizes = partition_sizes.to_vec();
+    sorted_sizes.sort_unstable();
+    let median = sorted_sizes.get(sorted_sizes.len() / 2).copied().unwrap_or(0);
+    median
+        .saturating_mul(config.skewed_partition_factor)
+        .max(config.skewed_partition_threshold)
+}
+
+/// Split the locations of a partition into groups of adjacent map outputs of up to
+/// `target_size` bytes
+fn split_locations(
+    locations: &[PartitionLocation],
+    target_size: usize,
+) -> Vec<Vec<PartitionLocation>> {
+    let mut pieces: Vec<Vec<PartitionLocation>> = vec![];
+    let mut piece_size = 0;
+    for location in locations {
+        let size = location.partition_stats.num_bytes().unwrap_or(0) as usize;
+        match pieces.last_mut() {
+            Some(piece) if piece_size + size <= target_size => {
+                piece.push(location.clone());
+                piece_size += size;
+            }
+            _ => {
+                pieces.push(vec![location.clone()]);
+                piece_size = size;
+            }
+        }
+    }
+    pieces
+}
+
+/// Returns a copy of a shuffle reader reading other partitions of the same shuffle
+fn with_shuffle_reader_partitions(
+    shuffle_reader: &ShuffleReaderExec,
+    partition: Vec<Vec<PartitionLocation>>,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    Ok(Arc::new(
+        ShuffleReaderExec::try_new(
+            shuffle_reader.stage_id,
+            partition,
+            shuffle_reader.schema(),
+        )?
+        .with_compression(shuffle_reader.compression())
+        .with_fetch_limits(
+            shuffle_reader.max_requests(),
+            shuffle_reader.max_bytes_in_flight(),
+        )
+        .with_shuffle_partition_count(shuffle_reader.shuffle_partition_count()),
+    ))
+}
+
+/// Rebuild a stage with its shuffle readers replaced by the ones returned by `replace`
+fn replace_shuffle_readers(
+    stage: Arc<dyn ExecutionPlan>,
+    replace: ShuffleReaderReplacement,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    let mut new_children: Vec<Arc<dyn ExecutionPlan>> = vec![];
+    for child in stage.children() {
+        if let Some(shuffle_reader) = child.as_any().downcast_ref::<ShuffleReaderExec>() {
+            new_children.push(replace(shuffle_reader)?);
+        } else {
+            new_children.push(replace_shuffle_readers(child.clone(), replace)?);
+        }
+    }
+    Ok(with_new_children_if_necessary(stage, new_children)?)
+}
+
+/// Collects the shuffle readers of a stage, returns false when the stage has any other
+/// leaf, whose partitions could not be coalesced
+fn find_shuffle_readers<'a>(
+    plan: &'a Arc<dyn ExecutionPlan>,
+    shuffle_readers: &mut Vec<&'a ShuffleReaderExec>,
+) -> bool {
+    if let Some(shuffle_reader) = plan.as_any().downcast_ref::<ShuffleReaderExec>() {
+        shuffle_readers.push(shuffle_reader);
+        return true;
+    }
+    let children = plan.children();
+    !children.is_empty()
+        && children
+            .into_iter()
+            .all(|child| find_shuffle_readers(child, shuffle_readers))
+}
+
+/// Returns true when any operator of a plan requires an ordering of its input
+fn requires_input_ordering(plan: &Arc<dyn ExecutionPlan>) -> bool {
+    plan.required_input_ordering().iter().any(Option::is_some)
+        || plan.children().into_iter().any(requires_input_ordering)
+}
+
+/// Returns the input of a hash repartitioning, skipping any batch coalescing on top of it
+fn hash_repartition_input(
+    plan: &Arc<dyn ExecutionPlan>,
+) -> Option<Arc<dyn ExecutionPlan>> {
+    let plan = match plan.as_any().downcast_ref::<CoalesceBatchesExec>() {
+        Some(coalesce) => coalesce.input(),
+        None => plan,
+    };
+    match plan.as_any().downcast_ref::<RepartitionExec>() {
+        Some(repart) if matches!(repart.partitioning(), Partitioning::Hash(_, _)) => {
+            Some(repart.input().clone())
+        }
+        _ => None,
+    }
+}
+
 fn create_shuffle_writer(
     job_id: &str,
     stage_id: usize,
     plan: Arc<dyn ExecutionPlan>,
     partitioning: Option<Partitioning>,
+    config: &KapotConfig,
 ) -> Result<Arc<ShuffleWriterExec>> {
-    Ok(Arc::new(ShuffleWriterExec::try_new(
-        job_id.to_owned(),
-        stage_id,
-        plan,
-        "".to_owned(), // executor will decide on the work_dir path
-        partitioning,
-    )?))
+    Ok(Arc::new(
+        ShuffleWriterExec::try_new(
+            job_id.to_owned(),
+            stage_id,
+            plan,
+            "".to_owned(), // executor will decide on the work_dir path
+            partitioning,
+        )?
+        .with_sort_based_shuffle(config.shuffle_sort_based_enabled())
+        .with_buffer_size(config.shuffle_writer_buffer_size())
+        .with_compression(config.shuffle_compression())
+        .with_remote_storage(config.shuffle_remote_storage_url()),
+    ))
 }
 
 #[cfg(test)]
 mod test {
-    use crate::planner::DistributedPlanner;
+    use crate::planner::{
+        broadcast_small_join_build_side, coalesce_shuffle_partitions,
+        find_unresolved_shuffles, rollback_resolved_shuffles, split_skewed_partitions,
+        DistributedPlanner, SkewJoinConfig,
+    };
     use crate::test_utils::datafusion_test_context;
+    use kapot_core::config::{
+        KapotConfig, KAPOT_DEFAULT_SHUFFLE_PARTITIONS,
+        KAPOT_HASH_JOIN_SINGLE_PARTITION_THRESHOLD,
+        KAPOT_SHUFFLE_RANGE_PARTITIONING_ENABLED,
+        KAPOT_SHUFFLE_READER_MAX_BYTES_IN_FLIGHT, KAPOT_SHUFFLE_READER_MAX_REQUESTS,
+        KAPOT_SHUFFLE_REMOTE_STORAGE_URL,
+    };
     use kapot_core::error::KapotError;
-    use kapot_core::execution_plans::UnresolvedShuffleExec;
+    use kapot_core::execution_plans::{
+        RangeSampleExec, ShuffleReaderExec, ShuffleWriterExec, UnresolvedShuffleExec,
+    };
+    use kapot_core::serde::scheduler::{
+        ExecutorMetadata, ExecutorSpecification, PartitionId, PartitionLocation,
+        PartitionStats,
+    };
     use kapot_core::serde::KapotCodec;
+    use datafusion::arrow::array::{Int32Array, StringArray};
+    use datafusion::arrow::compute::SortOptions;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+    use datafusion::datasource::MemTable;
     use datafusion::physical_plan::aggregates::{AggregateExec, AggregateMode};
     use datafusion::physical_plan::coalesce_batches::CoalesceBatchesExec;
-    use datafusion::physical_plan::joins::HashJoinExec;
+    use datafusion::common::JoinType;
+    use datafusion::physical_expr::{LexOrdering, PhysicalExprRef, PhysicalSortExpr};
+    use datafusion::physical_plan::expressions::Column;
+    use datafusion::physical_plan::joins::utils::JoinOn;
+    use datafusion::physical_plan::joins::{HashJoinExec, PartitionMode};
+    use datafusion::physical_plan::memory::MemoryExec;
     use datafusion::physical_plan::projection::ProjectionExec;
     use datafusion::physical_plan::sorts::sort::SortExec;
     use datafusion::physical_plan::sorts::sort_preserving_merge::SortPreservingMergeExec;
-    use datafusion::physical_plan::{displayable, ExecutionPlan};
-    use datafusion::prelude::SessionContext;
+    use datafusion::physical_plan::windows::{BoundedWindowAggExec, WindowAggExec};
+    use datafusion::physical_plan::{displayable, ExecutionPlan, Partitioning};
+    use datafusion::prelude::{SessionConfig, SessionContext};
     use datafusion_proto::physical_plan::AsExecutionPlan;
     use datafusion_proto::protobuf::LogicalPlanNode;
     use datafusion_proto::protobuf::PhysicalPlanNode;
@@ -592,6 +1497,833 @@ order by
         Ok(())
     }
 
+    #[tokio::test]
+    async fn distributed_window_plan() -> Result<(), KapotError> {
+        let ctx = datafusion_test_context("testdata").await?;
+        let session_state = ctx.state();
+
+        let df = ctx
+            .sql(
+                "select l_orderkey,
+    row_number() over (partition by l_shipmode order by l_shipdate) as rn,
+    sum(l_quantity) over (partition by l_shipmode order by l_shipdate) as running_total,
+    lag(l_quantity) over (partition by l_shipmode order by l_shipdate) as previous,
+    sum(l_quantity) over (partition by l_returnflag) as total
+from lineitem",
+            )
+            .await?;
+
+        let plan = df.into_optimized_plan()?;
+        let plan = session_state.optimize(&plan)?;
+        let plan = session_state.create_physical_plan(&plan).await?;
+
+        let mut planner = DistributedPlanner::new();
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid.to_string(), plan)?;
+        for (i, stage) in stages.iter().enumerate() {
+            println!("Stage {i}:\n{}", displayable(stage.as_ref()).indent(false));
+        }
+
+        /* Expected result:
+
+        ShuffleWriterExec: Some(Hash([Column { name: "l_returnflag", index: 2 }], 2))
+          CsvExec: file_groups={2 groups: [[testdata/lineitem/partition0.tbl], [testdata/lineitem/partition1.tbl]]}, projection=[l_orderkey, l_quantity, l_returnflag, l_shipdate, l_shipmode], has_header=false
+
+        ShuffleWriterExec: Some(Hash([Column { name: "l_shipmode", index: 3 }], 2))
+          ProjectionExec: expr=[l_orderkey@0 as l_orderkey, l_quantity@1 as l_quantity, l_shipdate@3 as l_shipdate, l_shipmode@4 as l_shipmode, sum(lineitem.l_quantity) PARTITION BY [lineitem.l_returnflag] ...]
+            WindowAggExec: wdw=[sum(lineitem.l_quantity) PARTITION BY [lineitem.l_returnflag] ROWS BETWEEN UNBOUNDED PRECEDING AND UNBOUNDED FOLLOWING: ...]
+              SortExec: expr=[l_returnflag@2 ASC NULLS LAST], preserve_partitioning=[true]
+                CoalesceBatchesExec: target_batch_size=8192
+                  UnresolvedShuffleExec
+
+        ShuffleWriterExec: None
+          ProjectionExec: expr=[l_orderkey@0 as l_orderkey, row_number() ... as rn, sum(lineitem.l_quantity) ... as running_total, lag(lineitem.l_quantity) ... as previous, sum(lineitem.l_quantity) ... as total]
+            BoundedWindowAggExec: wdw=[row_number() PARTITION BY [lineitem.l_shipmode] ..., sum(lineitem.l_quantity) PARTITION BY [lineitem.l_shipmode] ..., lag(lineitem.l_quantity) PARTITION BY [lineitem.l_shipmode] ...], mode=[Sorted]
+              SortExec: expr=[l_shipmode@3 ASC NULLS LAST, l_shipdate@2 ASC NULLS LAST], preserve_partitioning=[true]
+                CoalesceBatchesExec: target_batch_size=8192
+                  UnresolvedShuffleExec
+        */
+
+        assert_eq!(3, stages.len());
+
+        // verify stage 0
+        assert_eq!(
+            2,
+            stages[0]
+                .shuffle_output_partitioning()
+                .expect("stage 0")
+                .partition_count()
+        );
+
+        // verify stage 1
+        assert_eq!(
+            2,
+            stages[1]
+                .shuffle_output_partitioning()
+                .expect("stage 1")
+                .partition_count()
+        );
+        let projection = stages[1].children()[0].clone();
+        let projection = downcast_exec!(projection, ProjectionExec);
+        let window = projection.children()[0].clone();
+        let window = downcast_exec!(window, WindowAggExec);
+        let sort = window.children()[0].clone();
+        let sort = downcast_exec!(sort, SortExec);
+        assert!(sort.preserve_partitioning());
+        let coalesce = sort.children()[0].clone();
+        let coalesce = downcast_exec!(coalesce, CoalesceBatchesExec);
+        let unresolved_shuffle = coalesce.children()[0].clone();
+        let unresolved_shuffle =
+            downcast_exec!(unresolved_shuffle, UnresolvedShuffleExec);
+        assert_eq!(unresolved_shuffle.stage_id, 1);
+        assert_eq!(unresolved_shuffle.output_partition_count, 2);
+
+        // verify stage 2
+        let projection = stages[2].children()[0].clone();
+        let projection = downcast_exec!(projection, ProjectionExec);
+        let window = projection.children()[0].clone();
+        let window = downcast_exec!(window, BoundedWindowAggExec);
+        assert_eq!(3, window.window_expr().len());
+        let sort = window.children()[0].clone();
+        let sort = downcast_exec!(sort, SortExec);
+        assert!(sort.preserve_partitioning());
+        let coalesce = sort.children()[0].clone();
+        let coalesce = downcast_exec!(coalesce, CoalesceBatchesExec);
+        let unresolved_shuffle = coalesce.children()[0].clone();
+        let unresolved_shuffle =
+            downcast_exec!(unresolved_shuffle, UnresolvedShuffleExec);
+        assert_eq!(unresolved_shuffle.stage_id, 2);
+        assert_eq!(unresolved_shuffle.output_partition_count, 2);
+
+        // the window stages survive serialization
+        for stage in stages {
+            let stage: Arc<dyn ExecutionPlan> = stage;
+            let stage_serde = roundtrip_operator(&ctx, stage.clone())?;
+            assert_eq!(
+                format!("{}", displayable(stage.as_ref()).indent(false)),
+                format!("{}", displayable(stage_serde.as_ref()).indent(false))
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn distributed_broadcast_join_plan() -> Result<(), KapotError> {
+        let ctx = datafusion_test_context("testdata").await?;
+        ctx.sql("SET datafusion.optimizer.repartition_joins = false")
+            .await?;
+        let session_state = ctx.state();
+
+        let df = ctx
+            .sql(
+                "select l_shipmode, o_orderpriority
+from
+    orders
+        join
+    lineitem
+    on
+            l_orderkey = o_orderkey",
+            )
+            .await?;
+
+        let plan = df.into_optimized_plan()?;
+        let plan = session_state.optimize(&plan)?;
+        let plan = session_state.create_physical_plan(&plan).await?;
+
+        let mut planner = DistributedPlanner::new();
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid.to_string(), plan)?;
+        for (i, stage) in stages.iter().enumerate() {
+            println!("Stage {i}:\n{}", displayable(stage.as_ref()).indent(false));
+        }
+
+        /* Expected result:
+
+        ShuffleWriterExec: None
+          CsvExec: file_groups={1 group: [[testdata/orders/orders.tbl]]}, projection=[o_orderkey, o_orderpriority], has_header=false
+
+        ShuffleWriterExec: None
+          ProjectionExec: expr=[l_shipmode@1 as l_shipmode, o_orderpriority@0 as o_orderpriority]
+            CoalesceBatchesExec: target_batch_size=8192
+              HashJoinExec: mode=CollectLeft, join_type=Inner, on=[(o_orderkey@0, l_orderkey@0)], projection=[o_orderpriority@1, l_shipmode@3]
+                UnresolvedShuffleExec
+                CsvExec: file_groups={2 groups: [[testdata/lineitem/partition0.tbl], [testdata/lineitem/partition1.tbl]]}, projection=[l_orderkey, l_shipmode], has_header=false
+        */
+
+        assert_eq!(2, stages.len());
+
+        // the build side is materialized once instead of being scanned by every join task
+        assert!(stages[0].shuffle_output_partitioning().is_none());
+
+        let projection = stages[1].children()[0].clone();
+        let coalesce_batches = projection.children()[0].clone();
+        let join = coalesce_batches.children()[0].clone();
+        let join = downcast_exec!(join, HashJoinExec);
+        assert_eq!(&PartitionMode::CollectLeft, join.partition_mode());
+
+        let build = downcast_exec!(join.left(), UnresolvedShuffleExec);
+        assert_eq!(stages[0].stage_id(), build.stage_id);
+        assert_eq!(1, build.output_partition_count);
+        assert_eq!(
+            2,
+            join.right()
+                .properties()
+                .output_partitioning()
+                .partition_count()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn distributed_broadcast_join_from_statistics() -> Result<(), KapotError> {
+        let ctx = SessionContext::new_with_config(
+            SessionConfig::new()
+                .with_target_partitions(2)
+                .set_usize(
+                    "datafusion.optimizer.hash_join_single_partition_threshold",
+                    0,
+                )
+                .set_usize(
+                    "datafusion.optimizer.hash_join_single_partition_threshold_rows",
+                    0,
+                ),
+        );
+        let dim_schema = Arc::new(Schema::new(vec![
+            Field::new("d_id", DataType::Int32, false),
+            Field::new("d_name", DataType::Utf8, false),
+        ]));
+        let dim = RecordBatch::try_new(
+            dim_schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![1, 2])),
+                Arc::new(StringArray::from(vec!["a", "b"])),
+            ],
+        )?;
+        let fact_schema = Arc::new(Schema::new(vec![
+            Field::new("f_dim", DataType::Int32, false),
+            Field::new("f_value", DataType::Int32, false),
+        ]));
+        let fact = RecordBatch::try_new(
+            fact_schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![1, 2, 1, 2])),
+                Arc::new(Int32Array::from(vec![1, 2, 3, 4])),
+            ],
+        )?;
+        ctx.register_table(
+            "dim",
+            Arc::new(MemTable::try_new(dim_schema, vec![vec![dim]])?),
+        )?;
+        ctx.register_table(
+            "fact",
+            Arc::new(MemTable::try_new(
+                fact_schema,
+                vec![vec![fact.clone()], vec![fact]],
+            )?),
+        )?;
+        // DataFusion plans a partitioned join, the statistics of the in-memory "dim" table
+        // show that it is small enough to be broadcast
+        let plan = ctx
+            .sql("select d_name, f_value from dim join fact on d_id = f_dim")
+            .await?
+            .create_physical_plan()
+            .await?;
+
+        let job_uuid = Uuid::new_v4();
+        let config = KapotConfig::builder()
+            .set(KAPOT_HASH_JOIN_SINGLE_PARTITION_THRESHOLD, "0")
+            .build()?;
+        let mut planner = DistributedPlanner::with_config(config);
+        let stages = planner.plan_query_stages(&job_uuid.to_string(), plan.clone())?;
+        assert_eq!(3, stages.len());
+        assert!(stages[0].shuffle_output_partitioning().is_some());
+
+        let config = KapotConfig::builder()
+            .set(KAPOT_HASH_JOIN_SINGLE_PARTITION_THRESHOLD, "1048576")
+            .build()?;
+        let mut planner = DistributedPlanner::with_config(config.clone());
+        let stages = planner.plan_query_stages(&job_uuid.to_string(), plan)?;
+        for (i, stage) in stages.iter().enumerate() {
+            println!("Stage {i}:\n{}", displayable(stage.as_ref()).indent(false));
+        }
+
+        /* Expected result:
+
+        ShuffleWriterExec: None
+          MemoryExec: partitions=1, partition_sizes=[1]
+
+        ShuffleWriterExec: None
+          CoalesceBatchesExec: target_batch_size=8192
+            HashJoinExec: mode=CollectLeft, join_type=Inner, on=[(d_id@0, f_dim@0)], projection=[d_name@1, f_value@3]
+              UnresolvedShuffleExec
+              MemoryExec: partitions=2, partition_sizes=[1, 1]
+        */
+
+        // the probe side is no longer shuffled
+        assert_eq!(2, stages.len());
+        assert!(stages[0].shuffle_output_partitioning().is_none());
+
+        let coalesce_batches = stages[1].children()[0].clone();
+        let join = coalesce_batches.children()[0].clone();
+        let join = downcast_exec!(join, HashJoinExec);
+        assert_eq!(&PartitionMode::CollectLeft, join.partition_mode());
+
+        let build = downcast_exec!(join.left(), UnresolvedShuffleExec);
+        assert_eq!(stages[0].stage_id(), build.stage_id);
+        downcast_exec!(join.right(), MemoryExec);
+        assert_eq!(2, join.properties().output_partitioning().partition_count());
+
+        // the probe side keeps its hash repartitioning when the aggregation above the
+        // join relies on the hash partitioning of the join output
+        let plan = ctx
+            .sql("select f_dim, sum(f_value) from dim join fact on d_id = f_dim group by f_dim")
+            .await?
+            .create_physical_plan()
+            .await?;
+        let mut planner = DistributedPlanner::with_config(config);
+        let stages = planner.plan_query_stages(&job_uuid.to_string(), plan)?;
+        assert_eq!(3, stages.len());
+        assert!(stages[0].shuffle_output_partitioning().is_none());
+        assert_eq!(
+            2,
+            stages[1]
+                .shuffle_output_partitioning()
+                .expect("stage 1")
+                .partition_count()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn distributed_range_sort_plan() -> Result<(), KapotError> {
+        let ctx = datafusion_test_context("testdata").await?;
+        let session_state = ctx.state();
+
+        let df = ctx
+            .sql("select l_returnflag, l_extendedprice from lineitem order by l_extendedprice")
+            .await?;
+
+        let plan = df.into_optimized_plan()?;
+        let plan = session_state.optimize(&plan)?;
+        let plan = session_state.create_physical_plan(&plan).await?;
+
+        let config = KapotConfig::builder()
+            .set(KAPOT_SHUFFLE_RANGE_PARTITIONING_ENABLED, "true")
+            .set(KAPOT_DEFAULT_SHUFFLE_PARTITIONS, "3")
+            .build()?;
+        let mut planner = DistributedPlanner::with_config(config);
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid.to_string(), plan)?;
+        for (i, stage) in stages.iter().enumerate() {
+            println!("Stage {i}:\n{}", displayable(stage.as_ref()).indent(false));
+        }
+
+        /* Expected result:
+
+        ShuffleWriterExec: None
+          CsvExec: file_groups={2 groups: [[kapot/scheduler/testdata/lineitem/partition0.tbl], [kapot/scheduler/testdata/lineitem/partition1.tbl]]}, projection=[l_extendedprice, l_returnflag], has_header=false
+
+        ShuffleWriterExec: None
+          RangeSampleExec: expr=[l_extendedprice@1 ASC NULLS LAST], sample_size=1000
+            UnresolvedShuffleExec
+
+        ShuffleWriterExec: Some(UnknownPartitioning(3)), range_partitioning=[l_extendedprice@1 ASC NULLS LAST]
+          UnresolvedShuffleExec
+          UnresolvedShuffleExec
+
+        ShuffleWriterExec: None
+          SortExec: expr=[l_extendedprice@1 ASC NULLS LAST], preserve_partitioning=[true]
+            UnresolvedShuffleExec
+        */
+
+        assert_eq!(4, stages.len());
+
+        // the sort input is materialized
+        assert!(stages[0].shuffle_output_partitioning().is_none());
+        assert!(stages[0].range_partitioning().is_none());
+
+        // then sampled
+        let sample = downcast_exec!(stages[1].children()[0], RangeSampleExec);
+        let unresolved_shuffle =
+            downcast_exec!(sample.children()[0], UnresolvedShuffleExec);
+        assert_eq!(stages[0].stage_id(), unresolved_shuffle.stage_id);
+
+        // and range partitioned using the samples
+        let range_partitioning = stages[2].range_partitioning().unwrap();
+        assert_eq!(3, range_partitioning.partition_count());
+        assert_eq!(
+            3,
+            stages[2]
+                .properties()
+                .output_partitioning()
+                .partition_count()
+        );
+        let unresolved_shuffle =
+            downcast_exec!(stages[2].children()[0], UnresolvedShuffleExec);
+        assert_eq!(stages[0].stage_id(), unresolved_shuffle.stage_id);
+        let unresolved_shuffle =
+            downcast_exec!(stages[2].children()[1], UnresolvedShuffleExec);
+        assert_eq!(stages[1].stage_id(), unresolved_shuffle.stage_id);
+
+        // the final stage sorts each range without merging them
+        let sort = downcast_exec!(stages[3].children()[0], SortExec);
+        assert!(sort.preserve_partitioning());
+        let unresolved_shuffle =
+            downcast_exec!(sort.children()[0], UnresolvedShuffleExec);
+        assert_eq!(stages[2].stage_id(), unresolved_shuffle.stage_id);
+        assert_eq!(3, unresolved_shuffle.output_partition_count);
+
+        // the range partitioned stage survives serialization
+        let range_stage: Arc<dyn ExecutionPlan> = stages[2].clone();
+        let range_stage_serde = roundtrip_operator(&ctx, range_stage)?;
+        let range_stage_serde = downcast_exec!(range_stage_serde, ShuffleWriterExec);
+        assert_eq!(2, range_stage_serde.children().len());
+        assert_eq!(
+            range_partitioning.sort_exprs(),
+            range_stage_serde.range_partitioning().unwrap().sort_exprs()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn distributed_remote_storage_plan() -> Result<(), KapotError> {
+        let ctx = datafusion_test_context("testdata").await?;
+        let session_state = ctx.state();
+
+        let df = ctx
+            .sql(
+                "select l_returnflag, sum(l_extendedprice * 1) as sum_disc_price
+            from lineitem
+            group by l_returnflag
+            order by l_returnflag",
+            )
+            .await?;
+
+        let plan = df.into_optimized_plan()?;
+        let plan = session_state.optimize(&plan)?;
+        let plan = session_state.create_physical_plan(&plan).await?;
+
+        let config = KapotConfig::builder()
+            .set(KAPOT_SHUFFLE_REMOTE_STORAGE_URL, "s3://bucket/shuffle")
+            .build()?;
+        let mut planner = DistributedPlanner::with_config(config);
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid.to_string(), plan)?;
+        assert_eq!(3, stages.len());
+
+        // the intermediate stages write their output to the remote storage
+        for stage in &stages[..2] {
+            assert_eq!(Some("s3://bucket/shuffle"), stage.remote_storage_url());
+        }
+        // while the output of the final stage stays on the executors
+        assert_eq!(None, stages[2].remote_storage_url());
+
+        // the remote storage survives serialization
+        let stage: Arc<dyn ExecutionPlan> = stages[0].clone();
+        let stage_serde = roundtrip_operator(&ctx, stage)?;
+        let stage_serde = downcast_exec!(stage_serde, ShuffleWriterExec);
+        assert_eq!(Some("s3://bucket/shuffle"), stage_serde.remote_storage_url());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn distributed_fetch_limits_plan() -> Result<(), KapotError> {
+        let ctx = datafusion_test_context("testdata").await?;
+        let session_state = ctx.state();
+
+        let df = ctx
+            .sql(
+                "select l_returnflag, sum(l_extendedprice * 1) as sum_disc_price
+            from lineitem
+            group by l_returnflag
+            order by l_returnflag",
+            )
+            .await?;
+
+        let plan = df.into_optimized_plan()?;
+        let plan = session_state.optimize(&plan)?;
+        let plan = session_state.create_physical_plan(&plan).await?;
+
+        let config = KapotConfig::builder()
+            .set(KAPOT_SHUFFLE_READER_MAX_REQUESTS, "8")
+            .set(KAPOT_SHUFFLE_READER_MAX_BYTES_IN_FLIGHT, "1048576")
+            .build()?;
+        let mut planner = DistributedPlanner::with_config(config);
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid.to_string(), plan)?;
+
+        // the readers of every stage input fetch within the configured limits
+        for stage in &stages[1..] {
+            let stage: Arc<dyn ExecutionPlan> = stage.clone();
+            let unresolved_shuffles = find_unresolved_shuffles(&stage)?;
+            assert_eq!(1, unresolved_shuffles.len());
+            assert_eq!(8, unresolved_shuffles[0].max_requests);
+            assert_eq!(1048576, unresolved_shuffles[0].max_bytes_in_flight);
+
+            // the limits survive serialization
+            let stage_serde = roundtrip_operator(&ctx, stage)?;
+            let unresolved_shuffle = find_unresolved_shuffles(&stage_serde)?.remove(0);
+            assert_eq!(8, unresolved_shuffle.max_requests);
+            assert_eq!(1048576, unresolved_shuffle.max_bytes_in_flight);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn coalesce_small_shuffle_partitions() -> Result<(), KapotError> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let shuffle_reader = |partition_sizes: &[Option<u64>]| {
+            let partition = partition_sizes
+                .iter()
+                .enumerate()
+                .map(|(partition_id, num_bytes)| {
+                    vec![test_partition_location(partition_id, 0, *num_bytes)]
+                })
+                .collect();
+            ShuffleReaderExec::try_new(1, partition, schema.clone()).map(Arc::new)
+        };
+
+        let stage = |input: Arc<dyn ExecutionPlan>, repartitioned: bool| {
+            let output_partitioning = repartitioned
+                .then(|| Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 2));
+            ShuffleWriterExec::try_new(
+                "job".to_owned(),
+                2,
+                input,
+                "".to_owned(),
+                output_partitioning,
+            )
+            .map(|writer| Arc::new(writer) as Arc<dyn ExecutionPlan>)
+        };
+
+        // a large partition between small ones is kept on its own
+        let coalesced = coalesce_shuffle_partitions(
+            stage(
+                shuffle_reader(&[Some(10), Some(10), Some(100), Some(10), Some(10)])?,
+                true,
+            )?,
+            30,
+        )?;
+        let shuffle_reader_exec = downcast_exec!(coalesced.children()[0], ShuffleReaderExec);
+        let partition_ids = shuffle_reader_exec
+            .partition
+            .iter()
+            .map(|locations| {
+                locations
+                    .iter()
+                    .map(|l| l.partition_id.partition_id)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![vec![0, 1], vec![2], vec![3, 4]], partition_ids);
+        assert_eq!(5, shuffle_reader_exec.shuffle_partition_count());
+
+        // the stage reads all partitions of the shuffle again once rolled back
+        let rolled_back = rollback_resolved_shuffles(coalesced)?;
+        assert_eq!(5, find_unresolved_shuffles(&rolled_back)?[0].output_partition_count);
+
+        // partitions without statistics are not coalesced
+        let coalesced = coalesce_shuffle_partitions(
+            stage(shuffle_reader(&[Some(10), None, Some(10)])?, true)?,
+            30,
+        )?;
+        let shuffle_reader_exec = downcast_exec!(coalesced.children()[0], ShuffleReaderExec);
+        assert_eq!(3, shuffle_reader_exec.partition.len());
+
+        // the output partitions of a stage which doesn't repartition its output, such as
+        // the final stage, are its input partitions
+        let coalesced = coalesce_shuffle_partitions(
+            stage(shuffle_reader(&[Some(10), Some(10), Some(10)])?, false)?,
+            30,
+        )?;
+        let shuffle_reader_exec =
+            downcast_exec!(coalesced.children()[0], ShuffleReaderExec);
+        assert_eq!(3, shuffle_reader_exec.partition.len());
+
+        // the concatenated partitions wouldn't keep the ordering a merge requires
+        let sort_exprs = LexOrdering::new(vec![PhysicalSortExpr {
+            expr: Arc::new(Column::new("a", 0)),
+            options: SortOptions::default(),
+        }]);
+        let merge = Arc::new(SortPreservingMergeExec::new(
+            sort_exprs,
+            shuffle_reader(&[Some(10), Some(10), Some(10)])?,
+        ));
+        let coalesced = coalesce_shuffle_partitions(stage(merge, true)?, 30)?;
+        let merge = downcast_exec!(coalesced.children()[0], SortPreservingMergeExec);
+        let shuffle_reader_exec = downcast_exec!(merge.children()[0], ShuffleReaderExec);
+        assert_eq!(3, shuffle_reader_exec.partition.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn split_skewed_join_partitions() -> Result<(), KapotError> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        // the first partition of the left side is written by 3 map tasks, 100 bytes each
+        let left_partition = (0..4)
+            .map(|partition_id| {
+                if partition_id == 0 {
+                    (0..3)
+                        .map(|map_id| test_partition_location(0, map_id, Some(100)))
+                        .collect()
+                } else {
+                    vec![test_partition_location(partition_id, 0, Some(10))]
+                }
+            })
+            .collect::<Vec<_>>();
+        let right_partition = (0..4)
+            .map(|partition_id| vec![test_partition_location(partition_id, 0, Some(10))])
+            .collect::<Vec<_>>();
+        let join_stage = |join_type| -> Result<Arc<dyn ExecutionPlan>, KapotError> {
+            let left = Arc::new(ShuffleReaderExec::try_new(
+                1,
+                left_partition.clone(),
+                schema.clone(),
+            )?);
+            let right = Arc::new(ShuffleReaderExec::try_new(
+                2,
+                right_partition.clone(),
+                schema.clone(),
+            )?);
+            let on: JoinOn = vec![(
+                Arc::new(Column::new("a", 0)) as PhysicalExprRef,
+                Arc::new(Column::new("a", 0)) as PhysicalExprRef,
+            )];
+            let join = Arc::new(HashJoinExec::try_new(
+                left,
+                right,
+                on,
+                None,
+                &join_type,
+                Some(vec![0]),
+                PartitionMode::Partitioned,
+                false,
+            )?);
+            Ok(Arc::new(ShuffleWriterExec::try_new(
+                "job".to_owned(),
+                3,
+                join,
+                "".to_owned(),
+                Some(Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 4)),
+            )?))
+        };
+        let config = SkewJoinConfig {
+            skewed_partition_factor: 5,
+            skewed_partition_threshold: 100,
+            target_size: 100,
+        };
+
+        // the skewed partition is split into a piece per map task, each joined with the
+        // first partition of the right side
+        let split = split_skewed_partitions(join_stage(JoinType::Inner)?, &config)?;
+        let join = downcast_exec!(split.children()[0], HashJoinExec);
+        let left = downcast_exec!(join.left(), ShuffleReaderExec);
+        let right = downcast_exec!(join.right(), ShuffleReaderExec);
+        let partition_ids = |shuffle_reader: &ShuffleReaderExec| {
+            shuffle_reader
+                .partition
+                .iter()
+                .map(|locations| {
+                    locations
+                        .iter()
+                        .map(|l| (l.partition_id.partition_id, l.map_partition_id))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![
+                vec![(0, 0)],
+                vec![(0, 1)],
+                vec![(0, 2)],
+                vec![(1, 0)],
+                vec![(2, 0)],
+                vec![(3, 0)]
+            ],
+            partition_ids(left)
+        );
+        assert_eq!(
+            vec![
+                vec![(0, 0)],
+                vec![(0, 0)],
+                vec![(0, 0)],
+                vec![(1, 0)],
+                vec![(2, 0)],
+                vec![(3, 0)]
+            ],
+            partition_ids(right)
+        );
+        assert_eq!(4, left.shuffle_partition_count());
+        assert_eq!(4, right.shuffle_partition_count());
+
+        // unmatched rows of the right side would be emitted by every piece of the left
+        let split = split_skewed_partitions(join_stage(JoinType::Right)?, &config)?;
+        let join = downcast_exec!(split.children()[0], HashJoinExec);
+        assert_eq!(
+            4,
+            downcast_exec!(join.left(), ShuffleReaderExec)
+                .partition
+                .len()
+        );
+
+        // a partition below the threshold is not skewed
+        let config = SkewJoinConfig {
+            skewed_partition_threshold: 1000,
+            ..config
+        };
+        let split = split_skewed_partitions(join_stage(JoinType::Inner)?, &config)?;
+        let join = downcast_exec!(split.children()[0], HashJoinExec);
+        assert_eq!(
+            4,
+            downcast_exec!(join.left(), ShuffleReaderExec)
+                .partition
+                .len()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn broadcast_small_join_build_side_at_runtime() -> Result<(), KapotError> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let left_partition = (0..4)
+            .map(|partition_id| vec![test_partition_location(partition_id, 0, Some(10))])
+            .collect::<Vec<_>>();
+        // every partition of the right side is written by 2 map tasks
+        let right_partition = (0..4)
+            .map(|partition_id| {
+                (0..2)
+                    .map(|map_id| {
+                        test_partition_location(partition_id, map_id, Some(100))
+                    })
+                    .collect()
+            })
+            .collect::<Vec<_>>();
+        let join_stage = |join_type| -> Result<Arc<dyn ExecutionPlan>, KapotError> {
+            let left = Arc::new(ShuffleReaderExec::try_new(
+                1,
+                left_partition.clone(),
+                schema.clone(),
+            )?);
+            let right = Arc::new(ShuffleReaderExec::try_new(
+                2,
+                right_partition.clone(),
+                schema.clone(),
+            )?);
+            let on: JoinOn = vec![(
+                Arc::new(Column::new("a", 0)) as PhysicalExprRef,
+                Arc::new(Column::new("a", 0)) as PhysicalExprRef,
+            )];
+            let join = Arc::new(HashJoinExec::try_new(
+                left,
+                right,
+                on,
+                None,
+                &join_type,
+                Some(vec![0]),
+                PartitionMode::Partitioned,
+                false,
+            )?);
+            Ok(Arc::new(ShuffleWriterExec::try_new(
+                "job".to_owned(),
+                3,
+                Arc::new(CoalesceBatchesExec::new(join, 4096)),
+                "".to_owned(),
+                Some(Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 4)),
+            )?))
+        };
+
+        // the 40 bytes of the left side are read by every task, each reading the output
+        // of a map task of the right side
+        let stage = broadcast_small_join_build_side(join_stage(JoinType::Inner)?, 100)?;
+        let coalesce = downcast_exec!(stage.children()[0], CoalesceBatchesExec);
+        let join = downcast_exec!(coalesce.input(), HashJoinExec);
+        assert_eq!(PartitionMode::CollectLeft, *join.partition_mode());
+        assert_eq!(Some(&vec![0]), join.projection.as_ref());
+        let left = downcast_exec!(join.left(), ShuffleReaderExec);
+        let right = downcast_exec!(join.right(), ShuffleReaderExec);
+        let partition_ids = |shuffle_reader: &ShuffleReaderExec| {
+            shuffle_reader
+                .partition
+                .iter()
+                .map(|locations| {
+                    locations
+                        .iter()
+                        .map(|l| (l.partition_id.partition_id, l.map_partition_id))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![vec![(0, 0), (1, 0), (2, 0), (3, 0)]],
+            partition_ids(left)
+        );
+        assert_eq!(
+            vec![
+                vec![(0, 0), (1, 0), (2, 0), (3, 0)],
+                vec![(0, 1), (1, 1), (2, 1), (3, 1)]
+            ],
+            partition_ids(right)
+        );
+        assert_eq!(4, left.shuffle_partition_count());
+        assert_eq!(4, right.shuffle_partition_count());
+
+        // the stage reads all partitions of both shuffles again once rolled back
+        let rolled_back = rollback_resolved_shuffles(stage)?;
+        let unresolved_shuffles = find_unresolved_shuffles(&rolled_back)?;
+        assert_eq!(4, unresolved_shuffles[0].output_partition_count);
+        assert_eq!(4, unresolved_shuffles[1].output_partition_count);
+
+        // unmatched rows of the left side would be emitted by every task
+        let stage = broadcast_small_join_build_side(join_stage(JoinType::Left)?, 100)?;
+        let coalesce = downcast_exec!(stage.children()[0], CoalesceBatchesExec);
+        let join = downcast_exec!(coalesce.input(), HashJoinExec);
+        assert_eq!(PartitionMode::Partitioned, *join.partition_mode());
+
+        // a build side reaching the threshold is not broadcast
+        let stage = broadcast_small_join_build_side(join_stage(JoinType::Inner)?, 40)?;
+        let coalesce = downcast_exec!(stage.children()[0], CoalesceBatchesExec);
+        let join = downcast_exec!(coalesce.input(), HashJoinExec);
+        assert_eq!(PartitionMode::Partitioned, *join.partition_mode());
+
+        Ok(())
+    }
+
+    fn test_partition_location(
+        partition_id: usize,
+        map_partition_id: usize,
+        num_bytes: Option<u64>,
+    ) -> PartitionLocation {
+        PartitionLocation {
+            map_partition_id,
+            partition_id: PartitionId {
+                job_id: "job".to_owned(),
+                stage_id: 1,
+                partition_id,
+            },
+            executor_meta: ExecutorMetadata {
+                id: "executor".to_owned(),
+                host: "localhost".to_owned(),
+                port: 50051,
+                grpc_port: 50052,
+                specification: ExecutorSpecification { task_slots: 1 },
+            },
+            partition_stats: PartitionStats::new(Some(1), Some(1), num_bytes),
+            path: format!("/job/1/{partition_id}/data.arrow"),
+            range: None,
+            remote_storage: false,
+            checksum: None,
+            streaming: false,
+        }
+    }
+
     #[ignore]
     // enable when upgrading Datafusion, a bug is fixed with https://github.com/apache/datafusion/pull/11926/
     #[tokio::test]
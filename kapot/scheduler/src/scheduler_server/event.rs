@@ -26,12 +26,17 @@ use std::sync::Arc;
 
 #[derive(Clone)]
 pub enum QueryStageSchedulerEvent {
+    /// A job accepted by `TaskManager::queue_job`, which already enforced the quota of
+    /// its tenant on queued jobs, or restored by `TaskManager::requeue_job`. Its handler
+    /// only enforces the quota on running jobs
     JobQueued {
         job_id: String,
         job_name: String,
         session_ctx: Arc<SessionContext>,
         plan: Box<LogicalPlan>,
         queued_at: u64,
+        // Absolute deadline of the job in milliseconds, set by the client
+        deadline: Option<u64>,
     },
     JobSubmitted {
         job_id: String,
@@ -57,11 +62,22 @@ pub enum QueryStageSchedulerEvent {
         queued_at: u64,
         failed_at: u64,
     },
+    // For a job taken over from a scheduler which stopped renewing its leases, with the
+    // absolute deadline in milliseconds the job is timed out at
+    JobTakenOver {
+        job_id: String,
+        deadline: Option<u64>,
+    },
     JobUpdated(String),
     JobCancel(String),
+    // For a job which did not finish before its deadline
+    JobTimeout(String),
     JobDataClean(String),
     TaskUpdating(String, Vec<TaskStatus>),
     ReviveOffers,
+    // Revive offers once the given number of milliseconds elapsed, for the failed tasks
+    // waiting out their retry backoff
+    ReviveOffersAfter(u64),
     ExecutorLost(String, Option<String>),
     CancelTasks(Vec<RunningTaskInfo>),
 }
@@ -109,12 +125,18 @@ impl Debug for QueryStageSchedulerEvent {
                     "JobRunningFailed : job_id={job_id}, fail_message={fail_message}, queued_at={queued_at}, failed_at={failed_at}.",
                 )
             }
+            QueryStageSchedulerEvent::JobTakenOver { job_id, deadline } => {
+                write!(f, "JobTakenOver : job_id={job_id}, deadline={deadline:?}.")
+            }
             QueryStageSchedulerEvent::JobUpdated(job_id) => {
                 write!(f, "JobUpdated : job_id={job_id}.")
             }
             QueryStageSchedulerEvent::JobCancel(job_id) => {
                 write!(f, "JobCancel : job_id={job_id}.")
             }
+            QueryStageSchedulerEvent::JobTimeout(job_id) => {
+                write!(f, "JobTimeout : job_id={job_id}.")
+            }
             QueryStageSchedulerEvent::JobDataClean(job_id) => {
                 write!(f, "JobDataClean : job_id={job_id}.")
             }
@@ -124,6 +146,9 @@ impl Debug for QueryStageSchedulerEvent {
             QueryStageSchedulerEvent::ReviveOffers => {
                 write!(f, "ReviveOffers.")
             }
+            QueryStageSchedulerEvent::ReviveOffersAfter(delay_ms) => {
+                write!(f, "ReviveOffersAfter : delay_ms={delay_ms}.")
+            }
             QueryStageSchedulerEvent::ExecutorLost(executor_id, reason) => {
                 write!(
                     f,
@@ -17,6 +17,7 @@
 
 use axum::extract::ConnectInfo;
 use kapot_core::config::{KapotConfig, KAPOT_JOB_NAME};
+use kapot_core::error::KapotError;
 use kapot_core::serde::protobuf::execute_query_params::{OptionalSessionId, Query};
 use std::collections::HashMap;
 use std::convert::TryInto;
@@ -49,7 +50,7 @@ use object_store::{local::LocalFileSystem, path::Path, ObjectStore};
 use std::ops::Deref;
 use std::sync::Arc;
 
-use crate::cluster::{bind_task_bias, bind_task_round_robin};
+use crate::cluster::{bind_speculative_tasks, bind_task_bias, bind_task_round_robin};
 use crate::config::TaskDistributionPolicy;
 use crate::scheduler_server::event::QueryStageSchedulerEvent;
 use datafusion::prelude::SessionContext;
@@ -118,24 +119,37 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerGrpc
                     Status::internal(msg)
                 })?;
 
-            let mut available_slots = [AvailableTaskSlots {
+            // An excluded executor is not given any task until its exclusion expires
+            if self
+                .state
+                .executor_manager
+                .is_excluded_executor(&executor_id)
+            {
+                return Ok(Response::new(PollWorkResult { tasks: vec![] }));
+            }
+
+            let mut executor_slots = [AvailableTaskSlots {
                 executor_id,
                 slots: num_free_slots,
             }];
-            let available_slots = available_slots.iter_mut().collect();
             let active_jobs = self.state.task_manager.get_running_job_cache();
-            let schedulable_tasks = match self.state.config.task_distribution {
+            let available_slots = executor_slots.iter_mut().collect();
+            let mut schedulable_tasks = match self.state.config.task_distribution {
                 TaskDistributionPolicy::Bias => {
-                    bind_task_bias(available_slots, active_jobs, |_| false).await
+                    bind_task_bias(available_slots, active_jobs.clone(), |_| false).await
                 }
                 TaskDistributionPolicy::RoundRobin => {
-                    bind_task_round_robin(available_slots, active_jobs, |_| false).await
+                    bind_task_round_robin(available_slots, active_jobs.clone(), |_| false).await
                 }
                 TaskDistributionPolicy::ConsistentHash{..} => {
                     return Err(Status::unimplemented(
                         "ConsistentHash TaskDistribution is not feasible for pull-based task scheduling"))
                 }
             };
+            // Launch a copy of the straggler tasks in the slots left over
+            let available_slots = executor_slots.iter_mut().collect();
+            schedulable_tasks
+                .extend(bind_speculative_tasks(available_slots, active_jobs).await);
 
             let mut tasks = vec![];
             for (_, task) in schedulable_tasks {
@@ -421,6 +435,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerGrpc
             query: Some(query),
             optional_session_id,
             settings,
+            deadline,
         } = query_params
         {
             let mut query_settings = HashMap::new();
@@ -520,15 +535,44 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerGrpc
                 .cloned()
                 .unwrap_or_else(|| "None".to_string());
 
-            self.submit_job(&job_id, &job_name, session_ctx, &plan)
+            let deadline = (deadline > 0).then_some(deadline);
+            match self
+                .submit_job_with_deadline(
+                    &job_id,
+                    &job_name,
+                    session_ctx,
+                    &plan,
+                    deadline,
+                )
                 .await
-                .map_err(|e| {
+            {
+                Ok(()) => {}
+                Err(KapotError::QuotaExceeded(msg)) => {
+                    warn!("{}", msg);
+                    return Ok(Response::new(ExecuteQueryResult {
+                        result: Some(execute_query_result::Result::Failure(
+                            ExecuteQueryFailureResult {
+                                failure: Some(
+                                    execute_query_failure_result::Failure::QuotaExceeded(
+                                        msg,
+                                    ),
+                                ),
+                            },
+                        )),
+                    }));
+                }
+                Err(KapotError::NotImplemented(msg)) => {
+                    warn!("{}", msg);
+                    return Err(Status::unimplemented(msg));
+                }
+                Err(e) => {
                     let msg =
                         format!("Failed to send JobQueued event for {job_id}: {e:?}");
                     error!("{}", msg);
 
-                    Status::internal(msg)
-                })?;
+                    return Err(Status::internal(msg));
+                }
+            }
 
             Ok(Response::new(ExecuteQueryResult {
                 result: Some(execute_query_result::Result::Success(
@@ -655,14 +699,17 @@ mod test {
     use crate::config::SchedulerConfig;
     use crate::metrics::default_metrics_collector;
     use kapot_core::error::KapotError;
+    use kapot_core::serde::protobuf::execute_query_params::Query;
     use kapot_core::serde::protobuf::{
-        executor_registration::OptionalHost, executor_status, ExecutorRegistration,
-        ExecutorStatus, ExecutorStoppedParams, HeartBeatParams, PollWorkParams,
+        execute_query_result, executor_registration::OptionalHost, executor_status,
+        job_status, ExecuteQueryParams, ExecutorRegistration, ExecutorStatus,
+        ExecutorStoppedParams, FailedJob, HeartBeatParams, PollWorkParams,
         RegisterExecutorParams,
     };
     use kapot_core::serde::scheduler::ExecutorSpecification;
     use kapot_core::serde::KapotCodec;
 
+    use crate::scheduler_server::timestamp_millis;
     use crate::state::SchedulerState;
     use crate::test_utils::await_condition;
     use crate::test_utils::test_cluster_context;
@@ -893,6 +940,54 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_execute_query_with_deadline() -> Result<(), KapotError> {
+        let cluster = test_cluster_context();
+
+        let config = SchedulerConfig::default();
+        let mut scheduler: SchedulerServer<LogicalPlanNode, PhysicalPlanNode> =
+            SchedulerServer::new(
+                "localhost:50050".to_owned(),
+                cluster.clone(),
+                KapotCodec::default(),
+                Arc::new(config),
+                default_metrics_collector().unwrap(),
+            );
+        scheduler.init().await?;
+
+        // no executor runs the tasks of the job, so it can't finish before its deadline
+        let request = Request::new(ExecuteQueryParams {
+            query: Some(Query::Sql("SELECT 1".to_owned())),
+            settings: vec![],
+            optional_session_id: None,
+            deadline: timestamp_millis() + 100,
+        });
+        let response = scheduler
+            .execute_query(request)
+            .await
+            .expect("Received error response")
+            .into_inner();
+        let job_id = match response.result {
+            Some(execute_query_result::Result::Success(success)) => success.job_id,
+            other => panic!("Expected the job to be submitted but got {other:?}"),
+        };
+
+        let timed_out = await_condition(Duration::from_millis(50), 100, || async {
+            let status = scheduler.state.task_manager.get_job_status(&job_id).await?;
+            Ok(matches!(
+                status.and_then(|status| status.status),
+                Some(job_status::Status::Failed(FailedJob {
+                    timed_out: true,
+                    ..
+                }))
+            ))
+        })
+        .await?;
+        assert!(timed_out, "Expected job {job_id} to time out");
+
+        Ok(())
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_expired_executor() -> Result<(), KapotError> {
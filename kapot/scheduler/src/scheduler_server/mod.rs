@@ -18,7 +18,8 @@
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use kapot_core::error::Result;
+use kapot_core::config::KapotConfig;
+use kapot_core::error::{KapotError, Result};
 use kapot_core::event_loop::{EventLoop, EventSender};
 use kapot_core::serde::protobuf::TaskStatus;
 use kapot_core::serde::KapotCodec;
@@ -40,7 +41,7 @@ use crate::scheduler_server::query_stage_scheduler::QueryStageScheduler;
 
 use crate::state::executor_manager::ExecutorManager;
 
-use crate::state::task_manager::TaskLauncher;
+use crate::state::task_manager::{job_tenant, TaskLauncher};
 use crate::state::SchedulerState;
 
 // include the generated protobuf source as a submodule
@@ -141,7 +142,13 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerServer<T
     pub async fn init(&mut self) -> Result<()> {
         self.state.init().await?;
         self.query_stage_event_loop.start()?;
+        let sender = self.query_stage_event_loop.get_sender()?;
+        for event in self.state.recover_queued_jobs().await? {
+            sender.post_event(event).await?;
+        }
         self.expire_dead_executors()?;
+        self.speculate_straggler_tasks()?;
+        self.renew_leases()?;
 
         Ok(())
     }
@@ -165,6 +172,50 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerServer<T
         ctx: Arc<SessionContext>,
         plan: &LogicalPlan,
     ) -> Result<()> {
+        self.submit_job_with_deadline(job_id, job_name, ctx, plan, None)
+            .await
+    }
+
+    /// Submit a job which is cancelled and failed with a timeout error if it does not
+    /// finish before `deadline`, in milliseconds since the Unix epoch
+    pub(crate) async fn submit_job_with_deadline(
+        &self,
+        job_id: &str,
+        job_name: &str,
+        ctx: Arc<SessionContext>,
+        plan: &LogicalPlan,
+        deadline: Option<u64>,
+    ) -> Result<()> {
+        // A polling executor is only given the tasks fitting its own free slots, so all
+        // the tasks of a gang scheduled job could never be launched at once
+        let gang_scheduling = ctx
+            .state()
+            .config()
+            .get_extension::<KapotConfig>()
+            .is_some_and(|config| config.gang_scheduling_enabled());
+        if gang_scheduling && !self.state.config.is_push_staged_scheduling() {
+            return Err(KapotError::NotImplemented(format!(
+                "Job {job_id} enables gang scheduling, which requires the push-staged task scheduling policy"
+            )));
+        }
+        let queued_at = timestamp_millis();
+        self.state.task_manager.queue_job(
+            job_id,
+            job_name,
+            &job_tenant(&ctx),
+            queued_at,
+        )?;
+        // plans over tables which cannot be serialized, like in-memory tables, are only
+        // kept in memory and are lost if the scheduler restarts before planning them
+        if let Err(e) = self
+            .state
+            .task_manager
+            .save_queued_job(job_id, job_name, &ctx, plan, queued_at, deadline)
+            .await
+        {
+            warn!("Could not persist queued job {job_id}: {e}");
+        }
+
         self.query_stage_event_loop
             .get_sender()?
             .post_event(QueryStageSchedulerEvent::JobQueued {
@@ -172,7 +223,8 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerServer<T
                 job_name: job_name.to_owned(),
                 session_ctx: ctx,
                 plan: Box::new(plan.clone()),
-                queued_at: timestamp_millis(),
+                queued_at,
+                deadline,
             })
             .await
     }
@@ -271,6 +323,75 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerServer<T
         Ok(())
     }
 
+    /// Spawn an async task which periodically checks the running jobs for straggler tasks
+    /// and revives offers to launch a speculative copy of them. Executors polling for
+    /// work are offered the copies without it.
+    fn speculate_straggler_tasks(&self) -> Result<()> {
+        if !self.state.config.is_push_staged_scheduling()
+            || self.state.config.speculation_interval_ms == 0
+        {
+            return Ok(());
+        }
+        let state = self.state.clone();
+        let event_sender = self.query_stage_event_loop.get_sender()?;
+        tokio::task::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_millis(
+                    state.config.speculation_interval_ms,
+                ))
+                .await;
+                let mut has_speculatable_tasks = false;
+                for job_info in state.task_manager.get_running_job_cache().values() {
+                    if job_info
+                        .execution_graph
+                        .read()
+                        .await
+                        .has_speculatable_tasks()
+                    {
+                        has_speculatable_tasks = true;
+                        break;
+                    }
+                }
+                if has_speculatable_tasks {
+                    if let Err(e) = event_sender
+                        .post_event(QueryStageSchedulerEvent::ReviveOffers)
+                        .await
+                    {
+                        error!("Fail to send revive offers event due to {:?}", e);
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Spawn an async task which periodically renews the leases of this scheduler and
+    /// takes over the jobs of the schedulers which stopped renewing theirs
+    fn renew_leases(&self) -> Result<()> {
+        let lease_seconds = self.state.config.job_lease_seconds;
+        if lease_seconds == 0 {
+            return Ok(());
+        }
+        let state = self.state.clone();
+        let event_sender = self.query_stage_event_loop.get_sender()?;
+        tokio::task::spawn(async move {
+            loop {
+                match state.renew_leases().await {
+                    Ok(events) => {
+                        for event in events {
+                            if let Err(e) = event_sender.post_event(event).await {
+                                error!("Fail to send event due to {:?}", e);
+                            }
+                        }
+                    }
+                    Err(e) => error!("Fail to renew the leases due to {:?}", e),
+                }
+                tokio::time::sleep(Duration::from_millis(lease_seconds * 1000 / 3)).await;
+            }
+        });
+        Ok(())
+    }
+
     pub(crate) fn remove_executor(
         executor_manager: ExecutorManager,
         event_sender: EventSender<QueryStageSchedulerEvent>,
@@ -339,11 +460,13 @@ pub fn timestamp_millis() -> u64 {
 
 #[cfg(all(test, feature = "sled"))]
 mod test {
+    use std::sync::atomic::{AtomicBool, Ordering};
     use std::sync::Arc;
+    use std::time::Duration;
 
     use datafusion::arrow::datatypes::{DataType, Field, Schema};
     use datafusion::functions_aggregate::sum::sum;
-    use datafusion::logical_expr::{col, LogicalPlan};
+    use datafusion::logical_expr::{col, lit, LogicalPlan, LogicalPlanBuilder};
 
     use datafusion::test_util::scan_empty_with_partitions;
     use datafusion_proto::protobuf::LogicalPlanNode;
@@ -351,27 +474,37 @@ mod test {
 
     use kapot_core::config::{
         KapotConfig, TaskSchedulingPolicy, KAPOT_DEFAULT_SHUFFLE_PARTITIONS,
+        KAPOT_JOB_GANG_SCHEDULING_ENABLED, KAPOT_JOB_TENANT, KAPOT_JOB_TIMEOUT,
+        KAPOT_SHUFFLE_REMOTE_STORAGE_URL, KAPOT_SPECULATION_ENABLED,
+        KAPOT_SPECULATION_MIN_TASK_RUNTIME_MS, KAPOT_SPECULATION_QUANTILE,
     };
-    use kapot_core::error::Result;
+    use kapot_core::error::{KapotError, Result};
 
     use crate::config::SchedulerConfig;
 
     use kapot_core::serde::protobuf::{
-        failed_task, job_status, task_status, ExecutionError, FailedTask, JobStatus,
-        MultiTaskDefinition, ShuffleWritePartition, SuccessfulJob, SuccessfulTask,
-        TaskId, TaskStatus,
+        failed_task, job_status, task_status, ExecutionError, FailedTask, IoError,
+        JobStatus, MultiTaskDefinition, ShuffleWritePartition, SuccessfulJob,
+        SuccessfulTask, TaskId, TaskStatus,
     };
     use kapot_core::serde::scheduler::{
         ExecutorData, ExecutorMetadata, ExecutorSpecification,
     };
     use kapot_core::serde::KapotCodec;
 
+    use kapot_core::utils::default_session_builder;
+
+    use crate::cluster::storage::sled::SledClient;
+    use crate::cluster::KapotCluster;
     use crate::scheduler_server::{timestamp_millis, SchedulerServer};
+    use crate::state::job_history::JobHistory;
+    use crate::state::task_manager::job_tenant;
 
     use crate::test_utils::{
         assert_completed_event, assert_failed_event, assert_no_submitted_event,
-        assert_submitted_event, test_cluster_context, ExplodingTableProvider,
-        SchedulerTest, TaskRunnerFn, TestMetricsCollector,
+        assert_submitted_event, assert_timed_out_event, default_task_runner,
+        test_cluster_context, BlackholeTaskLauncher, ExplodingTableProvider,
+        SchedulerTest, TaskRunner, TaskRunnerFn, TestMetricsCollector,
     };
 
     #[tokio::test]
@@ -401,15 +534,17 @@ mod test {
         let job_id = "job";
 
         // Enqueue job
-        scheduler
-            .state
-            .task_manager
-            .queue_job(job_id, "", timestamp_millis())?;
+        scheduler.state.task_manager.queue_job(
+            job_id,
+            "",
+            &ctx.session_id(),
+            timestamp_millis(),
+        )?;
 
         // Submit job
         scheduler
             .state
-            .submit_job(job_id, "", ctx, &plan, 0)
+            .submit_job(job_id, "", ctx, &plan, 0, None)
             .await
             .expect("submitting plan");
 
@@ -435,6 +570,9 @@ mod test {
                         num_batches: 1,
                         num_rows: 1,
                         num_bytes: 1,
+                        range: None,
+                        remote_storage: false,
+                        checksum: None,
                     })
                 }
 
@@ -518,6 +656,671 @@ mod test {
         Ok(())
     }
 
+    // Run a gang scheduled job and ensure all of its tasks are launched exactly once
+    #[tokio::test]
+    async fn test_gang_scheduling() -> Result<()> {
+        let plan = test_plan();
+
+        let launched = Arc::new(std::sync::Mutex::new(vec![]));
+        let runner = {
+            let launched = launched.clone();
+            let runner = default_task_runner();
+            Arc::new(TaskRunnerFn::new(
+                move |executor_id: String, task: MultiTaskDefinition| {
+                    launched.lock().unwrap().extend(
+                        task.task_ids
+                            .iter()
+                            .map(|id| (task.stage_id, id.partition_id)),
+                    );
+                    runner.run(executor_id, task)
+                },
+            ))
+        };
+
+        let metrics_collector = Arc::new(TestMetricsCollector::default());
+
+        let mut test = SchedulerTest::new(
+            SchedulerConfig::default()
+                .with_scheduler_policy(TaskSchedulingPolicy::PushStaged),
+            metrics_collector.clone(),
+            8,
+            1,
+            Some(runner),
+        )
+        .await?
+        .with_session_setting(KAPOT_JOB_GANG_SCHEDULING_ENABLED, "true")?
+        // leave enough task slots to launch both stages of the job at once
+        .with_session_setting(KAPOT_DEFAULT_SHUFFLE_PARTITIONS, "4")?;
+
+        let status = test.run("job", "", &plan).await.expect("running plan");
+
+        match status.status {
+            Some(job_status::Status::Successful(SuccessfulJob {
+                partition_location,
+                ..
+            })) => {
+                assert_eq!(partition_location.len(), 4);
+            }
+            other => {
+                panic!("Expected success status but found {:?}", other);
+            }
+        }
+
+        let mut launched = launched.lock().unwrap().clone();
+        launched.sort();
+        assert_eq!(
+            launched,
+            vec![(1, 0), (1, 1), (2, 0), (2, 1), (2, 2), (2, 3)]
+        );
+
+        assert_completed_event("job", &metrics_collector);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_gang_scheduling_pull_staged() -> Result<()> {
+        let plan = test_plan();
+
+        let metrics_collector = Arc::new(TestMetricsCollector::default());
+
+        let mut test = SchedulerTest::new(
+            SchedulerConfig::default()
+                .with_scheduler_policy(TaskSchedulingPolicy::PullStaged),
+            metrics_collector.clone(),
+            8,
+            1,
+            None,
+        )
+        .await?
+        .with_session_setting(KAPOT_JOB_GANG_SCHEDULING_ENABLED, "true")?;
+
+        let result = test.submit("job", "", &plan).await;
+        assert!(
+            matches!(result, Err(KapotError::NotImplemented(_))),
+            "Expected gang scheduled job to be rejected but found {result:?}"
+        );
+        assert_eq!(test.pending_job_number(), 0);
+        assert_no_submitted_event("job", &metrics_collector);
+
+        Ok(())
+    }
+
+    // Simulate a task which never finishes and ensure a speculative copy of it completes
+    // the job
+    #[tokio::test]
+    async fn test_speculative_execution() -> Result<()> {
+        let plan = test_plan();
+
+        let hung = Arc::new(AtomicBool::new(false));
+        let runner = Arc::new(TaskRunnerFn::new(
+            move |executor_id: String, mut task: MultiTaskDefinition| {
+                // The first task launched never reports its status
+                if !hung.swap(true, Ordering::SeqCst) {
+                    task.task_ids.remove(0);
+                }
+                default_task_runner().run(executor_id, task)
+            },
+        ));
+
+        let metrics_collector = Arc::new(TestMetricsCollector::default());
+
+        let mut test = SchedulerTest::new(
+            SchedulerConfig::default()
+                .with_scheduler_policy(TaskSchedulingPolicy::PushStaged)
+                .with_speculation_interval_ms(100),
+            metrics_collector.clone(),
+            4,
+            1,
+            Some(runner),
+        )
+        .await?
+        .with_session_setting(KAPOT_SPECULATION_ENABLED, "true")?
+        .with_session_setting(KAPOT_SPECULATION_QUANTILE, "0.5")?
+        .with_session_setting(KAPOT_SPECULATION_MIN_TASK_RUNTIME_MS, "0")?;
+
+        let status =
+            tokio::time::timeout(Duration::from_secs(30), test.run("job", "", &plan))
+                .await
+                .expect("the hung task should be speculated")?;
+
+        assert!(
+            matches!(
+                status,
+                JobStatus {
+                    status: Some(job_status::Status::Successful(_)),
+                    ..
+                }
+            ),
+            "Expected job status to be successful but it was {status:?}"
+        );
+
+        assert_submitted_event("job", &metrics_collector);
+        assert_completed_event("job", &metrics_collector);
+
+        Ok(())
+    }
+
+    // Simulate an executor failing all its tasks and ensure it is excluded so that the
+    // job completes on the other executor
+    #[tokio::test]
+    async fn test_executor_exclusion() -> Result<()> {
+        let plan = test_plan();
+
+        let runner = Arc::new(TaskRunnerFn::new(
+            |executor_id: String, task: MultiTaskDefinition| {
+                if executor_id != "virtual-executor-0" {
+                    return default_task_runner().run(executor_id, task);
+                }
+                let timestamp = timestamp_millis();
+                task.task_ids
+                    .into_iter()
+                    .map(|task_id| TaskStatus {
+                        task_id: task_id.task_id,
+                        job_id: task.job_id.clone(),
+                        stage_id: task.stage_id,
+                        stage_attempt_num: task.stage_attempt_num,
+                        partition_id: task_id.partition_id,
+                        launch_time: timestamp,
+                        start_exec_time: timestamp,
+                        end_exec_time: timestamp,
+                        metrics: vec![],
+                        status: Some(task_status::Status::Failed(FailedTask {
+                            error: "No space left on device".to_string(),
+                            retryable: true,
+                            count_to_failures: true,
+                            failed_reason: Some(failed_task::FailedReason::IoError(
+                                IoError {},
+                            )),
+                        })),
+                    })
+                    .collect()
+            },
+        ));
+
+        let metrics_collector = Arc::new(TestMetricsCollector::default());
+
+        let mut test = SchedulerTest::new(
+            SchedulerConfig::default()
+                .with_scheduler_policy(TaskSchedulingPolicy::PushStaged)
+                .with_executor_exclusion_max_failures_per_job(2),
+            metrics_collector.clone(),
+            2,
+            2,
+            Some(runner),
+        )
+        .await?;
+
+        let status = test.run("job", "", &plan).await.expect("running plan");
+
+        assert!(
+            matches!(
+                status,
+                JobStatus {
+                    status: Some(job_status::Status::Successful(_)),
+                    ..
+                }
+            ),
+            "Expected job status to be successful but it was {status:?}"
+        );
+        assert!(test.excluded_executors().contains_key("virtual-executor-0"));
+        assert!(!test.excluded_executors().contains_key("virtual-executor-1"));
+
+        Ok(())
+    }
+
+    // Submit jobs beyond the quotas of a tenant and ensure the job over the queued jobs
+    // quota is rejected while the other ones run one after the other
+    #[tokio::test]
+    async fn test_tenant_quotas() -> Result<()> {
+        let plan = test_plan();
+
+        let metrics_collector = Arc::new(TestMetricsCollector::default());
+
+        let mut test = SchedulerTest::new(
+            SchedulerConfig::default()
+                .with_scheduler_policy(TaskSchedulingPolicy::PushStaged)
+                .with_tenant_max_running_jobs(1)
+                .with_tenant_max_queued_jobs(1),
+            metrics_collector.clone(),
+            4,
+            1,
+            None,
+        )
+        .await?
+        .with_session_setting(KAPOT_JOB_TENANT, "analytics")?;
+
+        test.submit("job_1", "", &plan).await?;
+        await_tenant_job_numbers(&test, Some((0, 1))).await;
+        test.submit("job_2", "", &plan).await?;
+        await_tenant_job_numbers(&test, Some((1, 1))).await;
+
+        let result = test.submit("job_3", "", &plan).await;
+        assert!(
+            matches!(result, Err(KapotError::QuotaExceeded(_))),
+            "Expected the job to be rejected but it was {result:?}"
+        );
+
+        for job_id in ["job_1", "job_2"] {
+            let status =
+                tokio::time::timeout(Duration::from_secs(30), test.run_submitted(job_id))
+                    .await
+                    .expect("the queued job should run once the running one finishes")?;
+            assert!(
+                matches!(
+                    status,
+                    JobStatus {
+                        status: Some(job_status::Status::Successful(_)),
+                        ..
+                    }
+                ),
+                "Expected job status to be successful but it was {status:?}"
+            );
+        }
+        assert_submitted_event("job_2", &metrics_collector);
+        await_tenant_job_numbers(&test, None).await;
+
+        Ok(())
+    }
+
+    // Simulate tasks which never finish and ensure the job is cancelled and failed with a
+    // timeout error once its timeout expires
+    #[tokio::test]
+    async fn test_job_timeout() -> Result<()> {
+        let plan = test_plan();
+
+        let runner = Arc::new(TaskRunnerFn::new(
+            |_executor_id: String, _task: MultiTaskDefinition| vec![],
+        ));
+
+        let metrics_collector = Arc::new(TestMetricsCollector::default());
+
+        let mut test = SchedulerTest::new(
+            SchedulerConfig::default()
+                .with_scheduler_policy(TaskSchedulingPolicy::PushStaged),
+            metrics_collector.clone(),
+            4,
+            1,
+            Some(runner),
+        )
+        .await?
+        .with_session_setting(KAPOT_JOB_TIMEOUT, "1")?;
+
+        let status =
+            tokio::time::timeout(Duration::from_secs(30), test.run("job", "", &plan))
+                .await
+                .expect("the job should time out")?;
+
+        match status.status {
+            Some(job_status::Status::Failed(failed)) => {
+                assert!(failed.timed_out);
+                assert!(
+                    failed.error.starts_with("Job job timed out"),
+                    "Expected a timeout error but it was {}",
+                    failed.error
+                );
+            }
+            other => {
+                panic!("Expected failed status but found {:?}", other);
+            }
+        }
+
+        assert_timed_out_event("job", &metrics_collector);
+
+        Ok(())
+    }
+
+    // Fail a job which uploaded its shuffle output to a remote storage and ensure the
+    // scheduler removes the output along with the job data, as the executors which
+    // uploaded it may be gone
+    #[tokio::test]
+    async fn test_remote_shuffle_data_removed() -> Result<()> {
+        let plan = test_plan();
+        let remote_dir =
+            std::env::temp_dir().join(format!("remote-shuffle-{}", uuid::Uuid::new_v4()));
+        let job_file = remote_dir.join("job/1/0/data-0.arrow");
+        let other_job_file = remote_dir.join("other/1/0/data-0.arrow");
+        for file in [&job_file, &other_job_file] {
+            std::fs::create_dir_all(file.parent().unwrap())?;
+            std::fs::write(file, b"data")?;
+        }
+
+        let runner = Arc::new(TaskRunnerFn::new(
+            |_executor_id: String, _task: MultiTaskDefinition| vec![],
+        ));
+        let mut test = SchedulerTest::new(
+            SchedulerConfig::default()
+                .with_scheduler_policy(TaskSchedulingPolicy::PushStaged),
+            Arc::new(TestMetricsCollector::default()),
+            4,
+            1,
+            Some(runner),
+        )
+        .await?
+        .with_session_setting(KAPOT_JOB_TIMEOUT, "1")?
+        .with_session_setting(
+            KAPOT_SHUFFLE_REMOTE_STORAGE_URL,
+            &format!("file://{}", remote_dir.display()),
+        )?;
+
+        let status =
+            tokio::time::timeout(Duration::from_secs(30), test.run("job", "", &plan))
+                .await
+                .expect("the job should time out")?;
+        assert!(matches!(status.status, Some(job_status::Status::Failed(_))));
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while job_file.exists() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the shuffle output of the job should be removed");
+        assert!(other_job_file.exists());
+
+        std::fs::remove_dir_all(remote_dir)?;
+        Ok(())
+    }
+
+    // A job is archived to the job history when it finishes, not when its state is cleaned
+    // up
+    #[tokio::test]
+    async fn test_job_archived_on_completion() -> Result<()> {
+        let plan = test_plan();
+        let history_dir =
+            std::env::temp_dir().join(format!("job-history-{}", uuid::Uuid::new_v4()));
+        let history_path = history_dir.to_str().unwrap().to_owned();
+
+        let mut test = SchedulerTest::new(
+            SchedulerConfig::default()
+                .with_scheduler_policy(TaskSchedulingPolicy::PushStaged)
+                .with_finished_job_state_clean_up_interval_seconds(0)
+                .with_job_history_path(Some(history_path.clone())),
+            Arc::new(TestMetricsCollector::default()),
+            4,
+            1,
+            None,
+        )
+        .await?;
+
+        let status = test.run("job", "", &plan).await?;
+        assert!(matches!(
+            status.status,
+            Some(job_status::Status::Successful(_))
+        ));
+
+        let history = JobHistory::try_new(&history_path)?;
+        let summary = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let Some(summary) = history.get("job").await? {
+                    return Ok::<_, KapotError>(summary);
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the job should be archived")?;
+        assert_eq!(summary.status, "Successful");
+
+        std::fs::remove_dir_all(history_dir)?;
+        Ok(())
+    }
+
+    // Stop renewing the leases of the scheduler curating a job, as if it died, and ensure
+    // another scheduler sharing the cluster state takes the job over and completes it
+    #[tokio::test]
+    async fn test_job_takeover() -> Result<()> {
+        let plan = test_plan();
+        let store = SledClient::try_new_temporary()?;
+        let config = SchedulerConfig::default()
+            .with_scheduler_policy(TaskSchedulingPolicy::PushStaged)
+            .with_job_lease_seconds(1);
+
+        let mut test = SchedulerTest::new_with_cluster(
+            "scheduler-b",
+            KapotCluster::new_kv(
+                store.clone(),
+                "scheduler-b",
+                default_session_builder,
+                KapotCodec::default(),
+            ),
+            config.clone(),
+            Arc::new(TestMetricsCollector::default()),
+            1,
+            4,
+            None,
+        )
+        .await?;
+
+        // the tasks launched by scheduler-a never report their status, and it is started
+        // without renewing its leases
+        let mut scheduler_a: SchedulerServer<LogicalPlanNode, PhysicalPlanNode> =
+            SchedulerServer::new_with_task_launcher(
+                "scheduler-a".to_owned(),
+                KapotCluster::new_kv(
+                    store,
+                    "scheduler-a",
+                    default_session_builder,
+                    KapotCodec::default(),
+                ),
+                KapotCodec::default(),
+                Arc::new(config),
+                Arc::new(TestMetricsCollector::default()),
+                Arc::new(BlackholeTaskLauncher::default()),
+            );
+        scheduler_a.state.init().await?;
+        scheduler_a.query_stage_event_loop.start()?;
+
+        scheduler_a
+            .submit_job("job", "", test.ctx().await?, &plan)
+            .await?;
+        while scheduler_a.running_job_number() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let status =
+            tokio::time::timeout(Duration::from_secs(30), test.run_submitted("job"))
+                .await
+                .expect("the job should be taken over")?;
+
+        match status.status {
+            Some(job_status::Status::Successful(SuccessfulJob {
+                partition_location,
+                ..
+            })) => {
+                assert_eq!(partition_location.len(), 4);
+            }
+            other => {
+                panic!("Expected success status but found {:?}", other);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Take over a job with a timeout whose tasks never finish and ensure the scheduler
+    // taking it over accounts it to its tenant and times it out
+    #[tokio::test]
+    async fn test_job_takeover_timeout() -> Result<()> {
+        let plan = test_plan();
+        let store = SledClient::try_new_temporary()?;
+        let config = SchedulerConfig::default()
+            .with_scheduler_policy(TaskSchedulingPolicy::PushStaged)
+            .with_job_lease_seconds(1);
+
+        let runner = Arc::new(TaskRunnerFn::new(
+            |_executor_id: String, _task: MultiTaskDefinition| vec![],
+        ));
+        let metrics_collector = Arc::new(TestMetricsCollector::default());
+        let mut test = SchedulerTest::new_with_cluster(
+            "scheduler-b",
+            KapotCluster::new_kv(
+                store.clone(),
+                "scheduler-b",
+                default_session_builder,
+                KapotCodec::default(),
+            ),
+            config.clone(),
+            metrics_collector.clone(),
+            1,
+            4,
+            Some(runner),
+        )
+        .await?
+        .with_session_setting(KAPOT_JOB_TENANT, "analytics")?
+        .with_session_setting(KAPOT_JOB_TIMEOUT, "5")?;
+
+        let mut scheduler_a: SchedulerServer<LogicalPlanNode, PhysicalPlanNode> =
+            SchedulerServer::new_with_task_launcher(
+                "scheduler-a".to_owned(),
+                KapotCluster::new_kv(
+                    store,
+                    "scheduler-a",
+                    default_session_builder,
+                    KapotCodec::default(),
+                ),
+                KapotCodec::default(),
+                Arc::new(config),
+                Arc::new(TestMetricsCollector::default()),
+                Arc::new(BlackholeTaskLauncher::default()),
+            );
+        scheduler_a.state.init().await?;
+        scheduler_a.query_stage_event_loop.start()?;
+
+        scheduler_a
+            .submit_job("job", "", test.ctx().await?, &plan)
+            .await?;
+        while scheduler_a.running_job_number() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        // scheduler-a can't time the job out itself
+        scheduler_a.query_stage_event_loop.stop();
+
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            await_tenant_job_numbers(&test, Some((0, 1))),
+        )
+        .await
+        .expect("the job should be accounted to its tenant");
+
+        let status =
+            tokio::time::timeout(Duration::from_secs(30), test.run_submitted("job"))
+                .await
+                .expect("the job should time out")?;
+        match status.status {
+            Some(job_status::Status::Failed(failed)) => assert!(failed.timed_out),
+            other => {
+                panic!("Expected failed status but found {:?}", other);
+            }
+        }
+        assert_timed_out_event("job", &metrics_collector);
+        await_tenant_job_numbers(&test, None).await;
+
+        Ok(())
+    }
+
+    // Persist a queued job without planning it, as if the scheduler stopped right after
+    // accepting it, and ensure the job is queued again and completed once it restarts
+    #[tokio::test]
+    async fn test_queued_job_recovery() -> Result<()> {
+        // the plan of a persisted job can only scan tables which can be serialized
+        let plan = LogicalPlanBuilder::empty(true)
+            .project(vec![lit("a").alias("id"), lit(1u64).alias("gmv")])?
+            .aggregate(vec![col("id")], vec![sum(col("gmv"))])?
+            .build()?;
+        let store = SledClient::try_new_temporary()?;
+        let config = SchedulerConfig::default()
+            .with_scheduler_policy(TaskSchedulingPolicy::PushStaged);
+        let cluster = || {
+            KapotCluster::new_kv(
+                store.clone(),
+                "scheduler-a",
+                default_session_builder,
+                KapotCodec::default(),
+            )
+        };
+
+        let stopped: SchedulerServer<LogicalPlanNode, PhysicalPlanNode> =
+            SchedulerServer::new_with_task_launcher(
+                "scheduler-a".to_owned(),
+                cluster(),
+                KapotCodec::default(),
+                Arc::new(config.clone()),
+                Arc::new(TestMetricsCollector::default()),
+                Arc::new(BlackholeTaskLauncher::default()),
+            );
+        stopped.state.init().await?;
+        stopped
+            .state
+            .executor_manager
+            .register_executor(
+                ExecutorMetadata {
+                    id: "virtual-executor-0".to_owned(),
+                    host: String::default(),
+                    port: 0,
+                    grpc_port: 0,
+                    specification: ExecutorSpecification { task_slots: 4 },
+                },
+                ExecutorData {
+                    executor_id: "virtual-executor-0".to_owned(),
+                    total_task_slots: 4,
+                    available_task_slots: 4,
+                },
+            )
+            .await?;
+
+        let ctx = stopped
+            .state
+            .session_manager
+            .create_session(
+                &KapotConfig::builder()
+                    .set(KAPOT_DEFAULT_SHUFFLE_PARTITIONS, "4")
+                    .build()?,
+            )
+            .await?;
+        let queued_at = timestamp_millis();
+        let task_manager = &stopped.state.task_manager;
+        task_manager.queue_job("job", "", &job_tenant(&ctx), queued_at)?;
+        task_manager
+            .save_queued_job("job", "", &ctx, &plan, queued_at, None)
+            .await?;
+        drop(stopped);
+
+        let mut test = SchedulerTest::new_with_cluster(
+            "scheduler-a",
+            cluster(),
+            config,
+            Arc::new(TestMetricsCollector::default()),
+            1,
+            4,
+            None,
+        )
+        .await?;
+
+        let status =
+            tokio::time::timeout(Duration::from_secs(30), test.run_submitted("job"))
+                .await
+                .expect("the queued job should be recovered")?;
+
+        assert!(
+            matches!(status.status, Some(job_status::Status::Successful(_))),
+            "Expected success status but found {:?}",
+            status.status
+        );
+        assert!(cluster().job_state().get_queued_jobs().await?.is_empty());
+
+        Ok(())
+    }
+
+    async fn await_tenant_job_numbers(
+        test: &SchedulerTest,
+        numbers: Option<(usize, usize)>,
+    ) {
+        while test.tenant_job_numbers().get("analytics").copied() != numbers {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
     // Simulate a task failure and ensure the job status is updated correctly
     #[tokio::test]
     async fn test_job_failure() -> Result<()> {
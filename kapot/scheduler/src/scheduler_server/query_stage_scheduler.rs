@@ -19,8 +19,10 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use async_trait::async_trait;
+use dashmap::DashMap;
 use log::{debug, error, info, warn};
 
+use kapot_core::config::KapotConfig;
 use kapot_core::error::{KapotError, Result};
 use kapot_core::event_loop::{EventAction, EventSender};
 
@@ -34,6 +36,7 @@ use tokio::time::Instant;
 
 use crate::scheduler_server::event::QueryStageSchedulerEvent;
 
+use crate::state::task_manager::job_tenant;
 use crate::state::SchedulerState;
 
 pub(crate) struct QueryStageScheduler<
@@ -43,6 +46,10 @@ pub(crate) struct QueryStageScheduler<
     state: Arc<SchedulerState<T, U>>,
     metrics_collector: Arc<dyn SchedulerMetricsCollector>,
     config: Arc<SchedulerConfig>,
+    // Queued jobs waiting for a running job of their tenant to finish
+    waiting_jobs: DashMap<String, QueryStageSchedulerEvent>,
+    // Deadlines in milliseconds of the unfinished jobs with a timeout or a deadline
+    job_deadlines: Arc<DashMap<String, u64>>,
 }
 
 impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> QueryStageScheduler<T, U> {
@@ -55,12 +62,103 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> QueryStageSchedul
             state,
             metrics_collector,
             config,
+            waiting_jobs: DashMap::new(),
+            job_deadlines: Arc::new(DashMap::new()),
         }
     }
 
     pub(crate) fn metrics_collector(&self) -> &dyn SchedulerMetricsCollector {
         self.metrics_collector.as_ref()
     }
+
+    /// Track the deadline of a job and post a job timeout event once it expires, unless
+    /// the job finished in the meantime
+    fn track_job_deadline(
+        &self,
+        job_id: String,
+        deadline: u64,
+        event_sender: EventSender<QueryStageSchedulerEvent>,
+    ) {
+        self.job_deadlines.insert(job_id.clone(), deadline);
+        let job_deadlines = self.job_deadlines.clone();
+        tokio::spawn(async move {
+            let delay_ms = deadline.saturating_sub(timestamp_millis());
+            tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+            if job_deadlines.contains_key(&job_id) {
+                if let Err(e) = event_sender
+                    .post_event(QueryStageSchedulerEvent::JobTimeout(job_id))
+                    .await
+                {
+                    error!("Fail to send job timeout event due to {:?}", e);
+                }
+            }
+        });
+    }
+
+    /// Cancel a queued or running job and fail it with `reason`, `timed_out` if it did not
+    /// finish before its deadline
+    async fn cancel_job(
+        &self,
+        job_id: String,
+        reason: String,
+        timed_out: bool,
+        event_sender: &EventSender<QueryStageSchedulerEvent>,
+    ) -> Result<()> {
+        if timed_out {
+            self.metrics_collector.record_timed_out(&job_id);
+        } else {
+            self.metrics_collector.record_cancelled(&job_id);
+        }
+
+        if self.waiting_jobs.remove(&job_id).is_some() {
+            if let Err(e) = self
+                .state
+                .task_manager
+                .fail_unscheduled_job(&job_id, reason, timed_out)
+                .await
+            {
+                error!(
+                    "Fail to invoke fail_unscheduled_job for job {} due to {:?}",
+                    job_id, e
+                );
+            }
+        } else {
+            match self
+                .state
+                .task_manager
+                .abort_job(&job_id, reason, timed_out)
+                .await
+            {
+                Ok((running_tasks, _pending_tasks)) => {
+                    event_sender
+                        .post_event(QueryStageSchedulerEvent::CancelTasks(running_tasks))
+                        .await?;
+                }
+                Err(e) => {
+                    error!("Fail to invoke abort_job for job {} due to {:?}", job_id, e);
+                }
+            }
+        }
+        self.release_job(&job_id, event_sender).await?;
+        self.state.clean_up_failed_job(job_id).await;
+        Ok(())
+    }
+
+    /// Forget the deadline of a finished job, release the quota it took and queue again
+    /// the jobs of its tenant which waited for it
+    async fn release_job(
+        &self,
+        job_id: &str,
+        event_sender: &EventSender<QueryStageSchedulerEvent>,
+    ) -> Result<()> {
+        self.job_deadlines.remove(job_id);
+        for job_id in self.state.task_manager.release_job(job_id) {
+            if let Some((_, event)) = self.waiting_jobs.remove(&job_id) {
+                event_sender.post_event(event).await?;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -93,22 +191,66 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
                 session_ctx,
                 plan,
                 queued_at,
+                deadline,
             } => {
                 info!("Job {} queued with name {:?}", job_id, job_name);
 
-                if let Err(e) = self
-                    .state
-                    .task_manager
-                    .queue_job(&job_id, &job_name, queued_at)
-                {
-                    error!("Fail to queue job {} due to {:?}", job_id, e);
+                if !self.job_deadlines.contains_key(&job_id) {
+                    let timeout_seconds = session_ctx
+                        .state()
+                        .config()
+                        .get_extension::<KapotConfig>()
+                        .map(|config| config.job_timeout_seconds())
+                        .unwrap_or_default();
+                    let timeout_deadline =
+                        (timeout_seconds > 0).then(|| queued_at + timeout_seconds * 1000);
+                    if let Some(deadline) =
+                        deadline.into_iter().chain(timeout_deadline).min()
+                    {
+                        self.track_job_deadline(
+                            job_id.clone(),
+                            deadline,
+                            event_sender.clone(),
+                        );
+                    }
+                }
+
+                // the quota on queued jobs was enforced when the job was accepted, before
+                // posting this event
+                let tenant = job_tenant(&session_ctx);
+                if !self.state.task_manager.admit_job(&job_id, &tenant) {
+                    info!(
+                        "Job {} waits for a running job of tenant {} to finish",
+                        job_id, tenant
+                    );
+                    self.waiting_jobs.insert(
+                        job_id.clone(),
+                        QueryStageSchedulerEvent::JobQueued {
+                            job_id,
+                            job_name,
+                            session_ctx,
+                            plan,
+                            queued_at,
+                            deadline,
+                        },
+                    );
                     return Ok(());
                 }
 
+                // the deadline is saved with the job so that a scheduler taking it over
+                // times it out too
+                let deadline = self.job_deadlines.get(&job_id).map(|deadline| *deadline);
                 let state = self.state.clone();
                 tokio::spawn(async move {
                     let event = if let Err(e) = state
-                        .submit_job(&job_id, &job_name, session_ctx, &plan, queued_at)
+                        .submit_job(
+                            &job_id,
+                            &job_name,
+                            session_ctx,
+                            &plan,
+                            queued_at,
+                            deadline,
+                        )
                         .await
                     {
                         let fail_message = format!("Error planning job {job_id}: {e:?}");
@@ -160,7 +302,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
                 if let Err(e) = self
                     .state
                     .task_manager
-                    .fail_unscheduled_job(&job_id, fail_message)
+                    .fail_unscheduled_job(&job_id, fail_message, false)
                     .await
                 {
                     error!(
@@ -168,6 +310,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
                         job_id, e
                     );
                 }
+                self.release_job(&job_id, &event_sender).await?;
             }
             QueryStageSchedulerEvent::JobFinished {
                 job_id,
@@ -184,7 +327,8 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
                         job_id, e
                     );
                 }
-                self.state.clean_up_successful_job(job_id);
+                self.release_job(&job_id, &event_sender).await?;
+                self.state.clean_up_successful_job(job_id).await;
             }
             QueryStageSchedulerEvent::JobRunningFailed {
                 job_id,
@@ -199,7 +343,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
                 match self
                     .state
                     .task_manager
-                    .abort_job(&job_id, fail_message)
+                    .abort_job(&job_id, fail_message, false)
                     .await
                 {
                     Ok((running_tasks, _pending_tasks)) => {
@@ -218,7 +362,14 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
                         );
                     }
                 }
-                self.state.clean_up_failed_job(job_id);
+                self.release_job(&job_id, &event_sender).await?;
+                self.state.clean_up_failed_job(job_id).await;
+            }
+            QueryStageSchedulerEvent::JobTakenOver { job_id, deadline } => {
+                info!("Job {} taken over", job_id);
+                if let Some(deadline) = deadline {
+                    self.track_job_deadline(job_id, deadline, event_sender);
+                }
             }
             QueryStageSchedulerEvent::JobUpdated(job_id) => {
                 info!("Job {} Updated", job_id);
@@ -230,25 +381,18 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
                 }
             }
             QueryStageSchedulerEvent::JobCancel(job_id) => {
-                self.metrics_collector.record_cancelled(&job_id);
-
                 info!("Job {} Cancelled", job_id);
-                match self.state.task_manager.cancel_job(&job_id).await {
-                    Ok((running_tasks, _pending_tasks)) => {
-                        event_sender
-                            .post_event(QueryStageSchedulerEvent::CancelTasks(
-                                running_tasks,
-                            ))
-                            .await?;
-                    }
-                    Err(e) => {
-                        error!(
-                            "Fail to invoke cancel_job for job {} due to {:?}",
-                            job_id, e
-                        );
-                    }
+                self.cancel_job(job_id, "Cancelled".to_owned(), false, &event_sender)
+                    .await?;
+            }
+            QueryStageSchedulerEvent::JobTimeout(job_id) => {
+                if let Some(deadline) = self.job_deadlines.get(&job_id).map(|d| *d) {
+                    let reason = format!(
+                        "Job {job_id} timed out, it did not finish before its deadline at {deadline} ms"
+                    );
+                    warn!("{}", reason);
+                    self.cancel_job(job_id, reason, true, &event_sender).await?;
                 }
-                self.state.clean_up_failed_job(job_id);
             }
             QueryStageSchedulerEvent::TaskUpdating(executor_id, tasks_status) => {
                 debug!(
@@ -291,9 +435,22 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
             QueryStageSchedulerEvent::ReviveOffers => {
                 self.state.revive_offers(event_sender).await?;
             }
+            QueryStageSchedulerEvent::ReviveOffersAfter(delay_ms) => {
+                if self.state.config.is_push_staged_scheduling() {
+                    tokio::spawn(async move {
+                        tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                        if let Err(e) = event_sender
+                            .post_event(QueryStageSchedulerEvent::ReviveOffers)
+                            .await
+                        {
+                            error!("Fail to send revive offers event due to {:?}", e);
+                        }
+                    });
+                }
+            }
             QueryStageSchedulerEvent::ExecutorLost(executor_id, _) => {
                 match self.state.task_manager.executor_lost(&executor_id).await {
-                    Ok(tasks) => {
+                    Ok((tasks, events)) => {
                         if !tasks.is_empty() {
                             if let Err(e) = self
                                 .state
@@ -304,6 +461,9 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
                                 warn!("Fail to cancel running tasks due to {:?}", e);
                             }
                         }
+                        for event in events {
+                            event_sender.post_event(event).await?;
+                        }
                     }
                     Err(e) => {
                         let msg = format!(
@@ -324,7 +484,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
                 }
             }
             QueryStageSchedulerEvent::JobDataClean(job_id) => {
-                self.state.executor_manager.clean_up_job_data(job_id);
+                self.state.clean_up_job_data(job_id).await;
             }
         }
         if let Some((start, ec)) = time_recorder {
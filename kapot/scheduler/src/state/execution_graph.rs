@@ -28,6 +28,7 @@ use datafusion::prelude::SessionContext;
 use datafusion_proto::logical_plan::AsLogicalPlan;
 use log::{error, info, warn};
 
+use kapot_core::config::KapotConfig;
 use kapot_core::error::{KapotError, Result};
 use kapot_core::execution_plans::{ShuffleWriterExec, UnresolvedShuffleExec};
 use kapot_core::serde::protobuf::failed_task::FailedReason;
@@ -37,7 +38,7 @@ use kapot_core::serde::protobuf::{
     RunningJob, SuccessfulJob, TaskStatus,
 };
 use kapot_core::serde::protobuf::{job_status, FailedJob, ShuffleWritePartition};
-use kapot_core::serde::protobuf::{task_status, RunningTask};
+use kapot_core::serde::protobuf::{task_status, RunningTask, SuccessfulTask};
 use kapot_core::serde::scheduler::{
     ExecutorMetadata, PartitionId, PartitionLocation, PartitionStats,
 };
@@ -45,13 +46,13 @@ use kapot_core::serde::KapotCodec;
 use datafusion_proto::physical_plan::AsExecutionPlan;
 
 use crate::display::print_stage_metrics;
-use crate::planner::DistributedPlanner;
+use crate::planner::{DistributedPlanner, SkewJoinConfig};
 use crate::scheduler_server::event::QueryStageSchedulerEvent;
 use crate::scheduler_server::timestamp_millis;
 use crate::state::execution_graph::execution_stage::RunningStage;
 pub(crate) use crate::state::execution_graph::execution_stage::{
-    ExecutionStage, FailedStage, ResolvedStage, StageOutput, SuccessfulStage, TaskInfo,
-    UnresolvedStage,
+    ExecutionStage, FailedStage, ResolvedStage, RetryPolicy, SpeculationConfig,
+    StageOutput, SuccessfulStage, TaskInfo, UnresolvedStage,
 };
 use crate::state::task_manager::UpdatedStages;
 
@@ -129,6 +130,36 @@ pub struct ExecutionGraph {
     /// Failed stage attempts, record the failed stage attempts to limit the retry times.
     /// Map from Stage ID -> Set<Stage_ATTPMPT_NUM>
     failed_stage_attempts: HashMap<usize, HashSet<usize>>,
+    /// Target size in bytes of the shuffle partitions read by a task, when adjacent small
+    /// partitions are coalesced as stages are resolved
+    coalesce_partitions_target_size: Option<usize>,
+    /// Thresholds of the skewed join partitions split into several tasks as stages are
+    /// resolved
+    skew_join: Option<SkewJoinConfig>,
+    /// Size in bytes below which the build side of a partitioned join is broadcast, when
+    /// joins are switched to broadcast joins as their build stage completes or, failing
+    /// that, as their stage is resolved
+    broadcast_join_threshold: Option<usize>,
+    /// Thresholds of the straggler tasks a speculative copy is launched of on another
+    /// executor
+    speculation: Option<SpeculationConfig>,
+    /// How the failed tasks and stages of the job are retried
+    retry_policy: RetryPolicy,
+    /// Scheduling pool the executor slots are shared with in proportion to its weight
+    pool: String,
+    /// Priority of the job within its scheduling pool
+    priority: u64,
+    /// Tenant the scheduler quotas of the job are accounted to
+    tenant: String,
+    /// Whether all the stages of the job are launched at once, streaming the shuffle
+    /// output from the map tasks to the consumer tasks
+    gang_scheduling: bool,
+    /// Object store url the shuffle output of the job is uploaded to, removed along with
+    /// the job data
+    remote_shuffle_storage_url: Option<String>,
+    /// Absolute deadline of the job in milliseconds, kept so that a scheduler taking the
+    /// job over times it out too
+    deadline: Option<u64>,
 }
 
 #[derive(Clone, Debug)]
@@ -148,19 +179,25 @@ impl ExecutionGraph {
         session_id: &str,
         plan: Arc<dyn ExecutionPlan>,
         queued_at: u64,
+        config: &KapotConfig,
     ) -> Result<Self> {
-        let mut planner = DistributedPlanner::new();
-
-        let output_partitions = plan.properties().output_partitioning().partition_count();
+        let mut planner = DistributedPlanner::with_config(config.clone());
 
         let shuffle_stages = planner.plan_query_stages(job_id, plan)?;
 
+        // the final stage may be partitioned differently than the plan, e.g. when a global
+        // sort is range partitioned
+        let output_partitions = shuffle_stages
+            .last()
+            .map(|stage| stage.properties().output_partitioning().partition_count())
+            .unwrap_or(1);
+
         let builder = ExecutionStageBuilder::new();
         let stages = builder.build(shuffle_stages)?;
 
         let started_at = timestamp_millis();
 
-        Ok(Self {
+        let mut graph = Self {
             scheduler_id: Some(scheduler_id.to_string()),
             job_id: job_id.to_string(),
             job_name: job_name.to_string(),
@@ -182,7 +219,49 @@ impl ExecutionGraph {
             output_locations: vec![],
             task_id_gen: 0,
             failed_stage_attempts: HashMap::new(),
-        })
+            coalesce_partitions_target_size: config
+                .adaptive_coalesce_partitions_enabled()
+                .then(|| config.adaptive_advisory_partition_size()),
+            skew_join: config.adaptive_skew_join_enabled().then(|| SkewJoinConfig {
+                skewed_partition_factor: config
+                    .adaptive_skew_join_skewed_partition_factor(),
+                skewed_partition_threshold: config
+                    .adaptive_skew_join_skewed_partition_threshold(),
+                target_size: config.adaptive_advisory_partition_size(),
+            }),
+            broadcast_join_threshold: config
+                .adaptive_broadcast_join_enabled()
+                .then(|| config.hash_join_single_partition_threshold()),
+            speculation: config.speculation_enabled().then(|| SpeculationConfig {
+                multiplier: config.speculation_multiplier(),
+                quantile: config.speculation_quantile(),
+                min_task_runtime_ms: config.speculation_min_task_runtime_ms(),
+            }),
+            retry_policy: RetryPolicy {
+                max_task_failures: config.task_max_failures(),
+                max_stage_failures: config.stage_max_failures(),
+                backoff_ms: config.task_retry_backoff_ms(),
+                max_backoff_ms: config.task_retry_max_backoff_ms(),
+                non_retryable_errors: config.task_non_retryable_errors(),
+            },
+            pool: config.job_pool(),
+            priority: config.job_priority(),
+            tenant: config
+                .job_tenant()
+                .unwrap_or_else(|| session_id.to_string()),
+            gang_scheduling: config.gang_scheduling_enabled(),
+            remote_shuffle_storage_url: config.shuffle_remote_storage_url(),
+            deadline: None,
+        };
+        if graph.gang_scheduling {
+            // the plans of gang scheduled stages are fixed before any of their inputs
+            // ran, and a straggler can't be speculated on as its output is streamed
+            graph.coalesce_partitions_target_size = None;
+            graph.skew_join = None;
+            graph.broadcast_join_threshold = None;
+            graph.speculation = None;
+        }
+        Ok(graph)
     }
 
     pub fn job_id(&self) -> &str {
@@ -223,6 +302,253 @@ impl ExecutionGraph {
         &self.stages
     }
 
+    pub fn queued_at(&self) -> u64 {
+        self.queued_at
+    }
+
+    pub fn pool(&self) -> &str {
+        self.pool.as_str()
+    }
+
+    pub fn priority(&self) -> u64 {
+        self.priority
+    }
+
+    pub fn tenant(&self) -> &str {
+        self.tenant.as_str()
+    }
+
+    pub fn gang_scheduling(&self) -> bool {
+        self.gang_scheduling
+    }
+
+    pub fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// Set the absolute deadline in milliseconds the job is timed out at
+    pub fn with_deadline(mut self, deadline: Option<u64>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    pub fn remote_shuffle_storage_url(&self) -> Option<&str> {
+        self.remote_shuffle_storage_url.as_deref()
+    }
+
+    /// Returns true if this is a gang scheduled job none of whose tasks were launched yet
+    pub fn gang_scheduling_pending(&self) -> bool {
+        self.gang_scheduling
+            && matches!(self.status.status, Some(job_status::Status::Running(_)))
+            && self.stages.values().all(|stage| match stage {
+                ExecutionStage::UnResolved(_) | ExecutionStage::Resolved(_) => true,
+                ExecutionStage::Running(stage) => {
+                    stage.task_infos.iter().all(Option::is_none)
+                }
+                _ => false,
+            })
+    }
+
+    /// Number of tasks of all the stages of this graph, which a gang scheduled job needs
+    /// free task slots for to be launched
+    pub fn gang_task_count(&self) -> usize {
+        self.stages
+            .values()
+            .map(|stage| match stage {
+                ExecutionStage::UnResolved(stage) => stage.partitions(),
+                ExecutionStage::Resolved(stage) => stage.partitions,
+                ExecutionStage::Running(stage) => stage.partitions,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Fall back to scheduling the stages of this job one after another, e.g. when the
+    /// job needs more task slots than the cluster has
+    pub fn disable_gang_scheduling(&mut self, reason: &str) {
+        if self.gang_scheduling {
+            warn!(
+                "Disable gang scheduling of job {}, its stages are run one after another: {reason}",
+                self.job_id
+            );
+            self.gang_scheduling = false;
+        }
+    }
+
+    /// Returns true if a task of a running or successful stage was launched on the
+    /// executor
+    pub fn has_tasks_on_executor(&self, executor_id: &str) -> bool {
+        let on_executor = |task_info: &TaskInfo| match &task_info.task_status {
+            task_status::Status::Running(RunningTask { executor_id: id })
+            | task_status::Status::Successful(SuccessfulTask {
+                executor_id: id, ..
+            }) => id == executor_id,
+            _ => false,
+        };
+        self.stages.values().any(|stage| match stage {
+            ExecutionStage::Running(stage) => {
+                stage.task_infos.iter().flatten().any(on_executor)
+            }
+            ExecutionStage::Successful(stage) => stage.task_infos.iter().any(on_executor),
+            _ => false,
+        })
+    }
+
+    /// Launch all the tasks of a gang scheduled job at once, the n-th task on the n-th
+    /// of `executors`. The map stages stream their output partitions to the consumer
+    /// stages, which are resolved with the locations of the map tasks up front.
+    ///
+    /// The graph is left unchanged if the tasks can't be launched.
+    pub(crate) fn launch_gang(
+        &mut self,
+        executors: &[ExecutorMetadata],
+    ) -> Result<Vec<(String, TaskDescription)>> {
+        let stages = self.stages.clone();
+        let task_id_gen = self.task_id_gen;
+        let result = self.launch_gang_stages(executors);
+        if result.is_err() {
+            self.stages = stages;
+            self.task_id_gen = task_id_gen;
+        }
+        result
+    }
+
+    fn launch_gang_stages(
+        &mut self,
+        executors: &[ExecutorMetadata],
+    ) -> Result<Vec<(String, TaskDescription)>> {
+        let job_id = self.job_id.clone();
+        let mut executors = executors.iter();
+        let mut bound_tasks = vec![];
+        loop {
+            self.revive();
+            let launched_stages = self
+                .stages
+                .iter()
+                .filter_map(|(stage_id, stage)| match stage {
+                    ExecutionStage::Running(stage)
+                        if stage.task_infos.iter().all(Option::is_none) =>
+                    {
+                        Some(*stage_id)
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            if launched_stages.is_empty() {
+                break;
+            }
+
+            for stage_id in launched_stages {
+                let Some(ExecutionStage::Running(stage)) = self.stages.get_mut(&stage_id)
+                else {
+                    continue;
+                };
+                let shuffle_writer = stage
+                    .plan
+                    .as_any()
+                    .downcast_ref::<ShuffleWriterExec>()
+                    .cloned()
+                    .ok_or_else(|| {
+                        KapotError::Internal(format!(
+                            "Stage {job_id}/{stage_id} is not a ShuffleWriterExec"
+                        ))
+                    })?;
+                let streaming = !stage.output_links.is_empty();
+                if streaming {
+                    stage.plan = Arc::new(shuffle_writer.clone().with_streaming(true));
+                }
+
+                let mut locations = vec![];
+                for partition_id in 0..stage.partitions {
+                    let executor = executors.next().ok_or_else(|| {
+                        KapotError::Internal(format!(
+                            "Not enough executors to launch all the tasks of job {job_id}"
+                        ))
+                    })?;
+                    let task_id = self.task_id_gen;
+                    self.task_id_gen += 1;
+                    stage.task_infos[partition_id] =
+                        Some(create_task_info(executor.id.clone(), task_id));
+                    bound_tasks.push((
+                        executor.id.clone(),
+                        TaskDescription {
+                            session_id: self.session_id.clone(),
+                            partition: PartitionId {
+                                job_id: job_id.clone(),
+                                stage_id,
+                                partition_id,
+                            },
+                            stage_attempt_num: stage.stage_attempt_num,
+                            task_id,
+                            task_attempt: stage.task_attempt_num(partition_id),
+                            data_cache: false,
+                            plan: stage.plan.clone(),
+                        },
+                    ));
+
+                    if !streaming {
+                        continue;
+                    }
+                    let output_partitions =
+                        match shuffle_writer.shuffle_output_partitioning() {
+                            Some(partitioning) => {
+                                (0..partitioning.partition_count()).collect()
+                            }
+                            None => vec![partition_id],
+                        };
+                    for output_partition in output_partitions {
+                        locations.push(PartitionLocation {
+                            map_partition_id: partition_id,
+                            partition_id: PartitionId {
+                                job_id: job_id.clone(),
+                                stage_id,
+                                partition_id: output_partition,
+                            },
+                            executor_meta: executor.clone(),
+                            partition_stats: PartitionStats::default(),
+                            path: String::new(),
+                            range: None,
+                            remote_storage: false,
+                            checksum: None,
+                            streaming: true,
+                        });
+                    }
+                }
+
+                for link in stage.output_links.clone() {
+                    let Some(ExecutionStage::UnResolved(linked_stage)) =
+                        self.stages.get_mut(&link)
+                    else {
+                        return Err(KapotError::Internal(format!(
+                            "Error launching job {job_id}: The stage {link} as the output link of stage {stage_id} should be unresolved"
+                        )));
+                    };
+                    linked_stage.add_input_partitions(stage_id, locations.clone())?;
+                    linked_stage.complete_input(stage_id);
+                }
+            }
+
+            let resolvable_stages = self
+                .stages
+                .iter()
+                .filter_map(|(stage_id, stage)| match stage {
+                    ExecutionStage::UnResolved(stage) if stage.resolvable() => {
+                        Some(*stage_id)
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            for stage_id in resolvable_stages {
+                self.resolve_stage(stage_id)?;
+            }
+        }
+        info!(
+            "Launch all the {} tasks of gang scheduled job {job_id}",
+            bound_tasks.len()
+        );
+        Ok(bound_tasks)
+    }
+
     /// An ExecutionGraph is successful if all its stages are successful
     pub fn is_successful(&self) -> bool {
         self.stages
@@ -264,16 +590,36 @@ impl ExecutionGraph {
         }
     }
 
-    /// Update task statuses and task metrics in the graph.
+    /// Take over the job from the scheduler which curated it before and stopped renewing
+    /// its lease on it. The running tasks launched by the previous scheduler report their
+    /// status to it, so they are reset to be launched again. Returns the reset tasks
+    pub fn take_over(&mut self, scheduler_id: &str) -> Vec<RunningTaskInfo> {
+        self.scheduler_id = Some(scheduler_id.to_owned());
+        if let Some(Status::Running(running_job)) = self.status.status.as_mut() {
+            running_job.scheduler = scheduler_id.to_owned();
+        }
+        self.revive();
+
+        let running_tasks = self.running_tasks();
+        for stage in self.stages.values_mut() {
+            if let ExecutionStage::Running(stage) = stage {
+                stage.reset_running_tasks();
+            }
+        }
+        running_tasks
+    }
+
+    /// Update task statuses and task metrics in the graph, the failed tasks being retried
+    /// according to the retry policy of the job.
     /// This will also push shuffle partitions to their respective shuffle read stages.
     pub fn update_task_status(
         &mut self,
         executor: &ExecutorMetadata,
         task_statuses: Vec<TaskStatus>,
-        max_task_failures: usize,
-        max_stage_failures: usize,
     ) -> Result<Vec<QueryStageSchedulerEvent>> {
         let job_id = self.job_id().to_owned();
+        let max_task_failures = self.retry_policy.max_task_failures;
+        let max_stage_failures = self.retry_policy.max_stage_failures;
         // First of all, classify the statuses by stages
         let mut job_task_statuses: HashMap<usize, Vec<TaskStatus>> = HashMap::new();
         for task_status in task_statuses {
@@ -303,6 +649,10 @@ impl ExecutionGraph {
         let mut resubmit_successful_stages: HashMap<usize, HashSet<usize>> =
             HashMap::new();
         let mut reset_running_stages: HashMap<usize, HashSet<usize>> = HashMap::new();
+        // The attempts which lost against their speculative copy, or the other way round
+        let mut speculative_losers = vec![];
+        // The shortest retry backoff of the failed tasks to reschedule
+        let mut retry_delay_ms: Option<u64> = None;
 
         for (stage_id, stage_task_statuses) in job_task_statuses {
             if let Some(stage) = self.stages.get_mut(&stage_id) {
@@ -327,6 +677,24 @@ impl ExecutionGraph {
                         );
                         let operator_metrics = task_status.metrics.clone();
 
+                        if self.speculation.is_some() {
+                            let (apply, loser) = running_stage
+                                .update_speculative_task_info(partition_id, &task_status);
+                            if let Some((task_id, executor_id)) = loser {
+                                info!("Cancel TID {task_id} on executor {executor_id} as another attempt of task {task_identity} finished first");
+                                speculative_losers.push(RunningTaskInfo {
+                                    task_id,
+                                    job_id: job_id.clone(),
+                                    stage_id,
+                                    partition_id,
+                                    executor_id,
+                                });
+                            }
+                            if !apply {
+                                continue;
+                            }
+                        }
+
                         if !running_stage
                             .update_task_info(partition_id, task_status.clone())
                         {
@@ -336,6 +704,17 @@ impl ExecutionGraph {
                         if let Some(task_status::Status::Failed(failed_task)) =
                             task_status.status
                         {
+                            if self.gang_scheduling {
+                                // the other tasks of the job may already have consumed
+                                // part of the streamed output of the failed task
+                                let error_msg = format!(
+                                    "Task {task_identity} of gang scheduled job failed, fail the stage, failure reason: {:?}",
+                                    failed_task.error
+                                );
+                                error!("{}", error_msg);
+                                failed_stages.insert(stage_id, error_msg);
+                                continue;
+                            }
                             let failed_reason = failed_task.failed_reason;
 
                             match failed_reason {
@@ -402,15 +781,44 @@ impl ExecutionGraph {
                                     failed_stages.insert(stage_id, failed_task.error);
                                 }
                                 Some(_) => {
-                                    if failed_task.retryable
+                                    if self
+                                        .retry_policy
+                                        .is_non_retryable(&failed_task.error)
+                                    {
+                                        let error_msg = format!(
+                                            "Task {partition_id} in Stage {stage_id} failed with a non retryable error, fail the stage, failure reason: {:?}",
+                                            failed_task.error
+                                        );
+                                        error!("{}", error_msg);
+                                        failed_stages.insert(stage_id, error_msg);
+                                    } else if failed_task.retryable
                                         && failed_task.count_to_failures
                                     {
-                                        if running_stage.task_failure_number(partition_id)
-                                            < max_task_failures
-                                        {
-                                            // TODO add new struct to track all the failed task infos
-                                            // The failure TaskInfo is ignored and set to None here
-                                            running_stage.reset_task_info(partition_id);
+                                        let failures = running_stage
+                                            .task_failure_number(partition_id);
+                                        if failures < max_task_failures {
+                                            let delay_ms = self
+                                                .retry_policy
+                                                .retry_delay_ms(failures);
+                                            if delay_ms > 0 {
+                                                info!("Retry task {task_identity} in {delay_ms} ms");
+                                                running_stage.backoff_task_info(
+                                                    partition_id,
+                                                    timestamp_millis() as u128
+                                                        + delay_ms as u128,
+                                                );
+                                                retry_delay_ms = Some(
+                                                    retry_delay_ms
+                                                        .map_or(delay_ms, |d| {
+                                                            d.min(delay_ms)
+                                                        }),
+                                                );
+                                            } else {
+                                                // TODO add new struct to track all the failed task infos
+                                                // The failure TaskInfo is ignored and set to None here
+                                                running_stage
+                                                    .reset_task_info(partition_id);
+                                            }
                                         } else {
                                             let error_msg = format!(
                                                 "Task {} in Stage {} failed {} times, fail the stage, most recent failure reason: {:?}",
@@ -642,7 +1050,7 @@ impl ExecutionGraph {
             }
         }
 
-        self.processing_stages_update(UpdatedStages {
+        let mut events = self.processing_stages_update(UpdatedStages {
             resolved_stages,
             successful_stages,
             failed_stages,
@@ -651,7 +1059,14 @@ impl ExecutionGraph {
                 .keys()
                 .cloned()
                 .collect(),
-        })
+        })?;
+        if !speculative_losers.is_empty() {
+            events.push(QueryStageSchedulerEvent::CancelTasks(speculative_losers));
+        }
+        if let Some(delay_ms) = retry_delay_ms {
+            events.push(QueryStageSchedulerEvent::ReviveOffersAfter(delay_ms));
+        }
+        Ok(events)
     }
 
     /// Processing stage status update after task status changing
@@ -700,7 +1115,7 @@ impl ExecutionGraph {
 
         if !updated_stages.failed_stages.is_empty() {
             info!("Job {} is failed", job_id);
-            self.fail_job(job_err_msg.clone());
+            self.fail_job(job_err_msg.clone(), false);
             events.push(QueryStageSchedulerEvent::JobRunningFailed {
                 job_id,
                 fail_message: job_err_msg,
@@ -732,6 +1147,11 @@ impl ExecutionGraph {
     ) -> Result<Vec<usize>> {
         let mut resolved_stages = vec![];
         let job_id = &self.job_id;
+        if self.gang_scheduling && !output_links.is_empty() {
+            // the consumer stages of a gang scheduled job were resolved with the
+            // streamed locations when the job was launched
+            return Ok(resolved_stages);
+        }
         if output_links.is_empty() {
             // If `output_links` is empty, then this is a final stage
             self.output_locations.extend(locations);
@@ -765,10 +1185,91 @@ impl ExecutionGraph {
                     )));
                 }
             }
+            if is_completed {
+                self.broadcast_small_build_stage(stage_id, &output_links)?;
+            }
         }
         Ok(resolved_stages)
     }
 
+    /// Switch the partitioned joins whose build side is the output of the completed stage
+    /// `stage_id` to broadcast joins when it is smaller than the broadcast join threshold,
+    /// and the stage of their probe side is still unresolved, so that the output of the
+    /// probe stage is no longer hash repartitioned. The joins whose probe stage already
+    /// runs are switched, if at all, once they are resolved.
+    fn broadcast_small_build_stage(
+        &mut self,
+        stage_id: usize,
+        output_links: &[usize],
+    ) -> Result<()> {
+        let Some(threshold) = self.broadcast_join_threshold else {
+            return Ok(());
+        };
+        for link in output_links {
+            let Some(ExecutionStage::UnResolved(join_stage)) = self.stages.get(link)
+            else {
+                continue;
+            };
+            let build_size: Option<usize> =
+                join_stage.inputs.get(&stage_id).and_then(|input| {
+                    input
+                        .partition_locations
+                        .values()
+                        .flatten()
+                        .map(|location| {
+                            location.partition_stats.num_bytes().map(|n| n as usize)
+                        })
+                        .sum()
+                });
+            let Some(build_size) = build_size.filter(|size| *size < threshold) else {
+                continue;
+            };
+            let Some(probe_stage_id) = crate::planner::find_broadcast_join_probe_stage(
+                &join_stage.plan,
+                stage_id,
+            ) else {
+                continue;
+            };
+            let probe_stage = match self.stages.get(&probe_stage_id) {
+                Some(ExecutionStage::UnResolved(probe_stage))
+                    if probe_stage.output_links == [*link] =>
+                {
+                    probe_stage
+                }
+                _ => continue,
+            };
+            let Some((join_plan, probe_plan)) =
+                crate::planner::broadcast_join_build_stage(
+                    join_stage.plan.clone(),
+                    &probe_stage.plan,
+                )?
+            else {
+                continue;
+            };
+
+            info!(
+                "Switching the partitioned join of stage {}/{} to a broadcast join of the {} \
+                 bytes of stage {}, stage {} no longer repartitioning its output",
+                self.job_id,
+                link,
+                build_size,
+                stage_id,
+                probe_stage_id
+            );
+            if let Some(ExecutionStage::UnResolved(join_stage)) =
+                self.stages.get_mut(link)
+            {
+                join_stage.plan = join_plan;
+            }
+            if let Some(ExecutionStage::UnResolved(probe_stage)) =
+                self.stages.get_mut(&probe_stage_id)
+            {
+                probe_stage.plan = probe_plan;
+            }
+        }
+        Ok(())
+    }
+
     /// Return all the currently running stage ids
     pub fn running_stages(&self) -> Vec<usize> {
         self.stages
@@ -823,6 +1324,87 @@ impl ExecutionGraph {
             .sum()
     }
 
+    /// Make the failed tasks whose retry backoff is over available for scheduling again.
+    /// Returns the number of tasks made available
+    pub fn release_retry_backoffs(&mut self) -> usize {
+        let now = timestamp_millis() as u128;
+        self.stages
+            .values_mut()
+            .map(|stage| {
+                if let ExecutionStage::Running(stage) = stage {
+                    stage.release_retry_backoffs(now)
+                } else {
+                    0
+                }
+            })
+            .sum()
+    }
+
+    /// Returns true if a running task of this graph is slow enough to launch a
+    /// speculative copy of it
+    pub fn has_speculatable_tasks(&self) -> bool {
+        let Some(speculation) = self.speculation.as_ref() else {
+            return false;
+        };
+        let now = timestamp_millis() as u128;
+        self.stages.values().any(|stage| {
+            matches!(stage, ExecutionStage::Running(stage)
+                if !stage.speculatable_tasks(speculation, now).is_empty())
+        })
+    }
+
+    /// Bind a speculative copy of the straggler tasks of the running stages to the
+    /// executors picked by `select_executor`, which is given the executor running the
+    /// original task and returns another executor with an available slot, if any.
+    pub(crate) fn bind_speculative_tasks(
+        &mut self,
+        select_executor: &mut dyn FnMut(&str) -> Option<String>,
+    ) -> Vec<(String, TaskDescription)> {
+        let Some(speculation) = self.speculation else {
+            return vec![];
+        };
+        if !matches!(self.status.status, Some(job_status::Status::Running(_))) {
+            return vec![];
+        }
+        let now = timestamp_millis() as u128;
+        let mut bound_tasks = vec![];
+        for (stage_id, stage) in self.stages.iter_mut() {
+            let ExecutionStage::Running(stage) = stage else {
+                continue;
+            };
+            for (partition_id, executor) in stage.speculatable_tasks(&speculation, now) {
+                let Some(executor_id) = select_executor(&executor) else {
+                    return bound_tasks;
+                };
+                let task_id = self.task_id_gen;
+                self.task_id_gen += 1;
+                info!(
+                    "Launch TID {task_id} on executor {executor_id} as a speculative copy of task {}/{stage_id}/{partition_id} running on executor {executor}",
+                    self.job_id
+                );
+                stage.add_speculative_task(
+                    partition_id,
+                    create_task_info(executor_id.clone(), task_id),
+                );
+                let task_desc = TaskDescription {
+                    session_id: self.session_id.clone(),
+                    partition: PartitionId {
+                        job_id: self.job_id.clone(),
+                        stage_id: *stage_id,
+                        partition_id,
+                    },
+                    stage_attempt_num: stage.stage_attempt_num,
+                    task_id,
+                    task_attempt: stage.task_attempt_num(partition_id),
+                    data_cache: false,
+                    plan: stage.plan.clone(),
+                };
+                bound_tasks.push((executor_id, task_desc));
+            }
+        }
+        bound_tasks
+    }
+
     /// Get next task that can be assigned to the given executor.
     /// This method should only be called when the resulting task is immediately
     /// being launched as the status will be set to Running and it will not be
@@ -843,10 +1425,14 @@ impl ExecutionGraph {
             warn!("Call pop_next_task on failed Job");
             return Ok(None);
         }
+        if self.gang_scheduling_pending() {
+            return Ok(None);
+        }
 
         let job_id = self.job_id.clone();
         let session_id = self.session_id.clone();
 
+        self.release_retry_backoffs();
         let find_candidate = self.stages.iter().any(|(_stage_id, stage)| {
             if let ExecutionStage::Running(stage) = stage {
                 stage.available_tasks() > 0
@@ -884,7 +1470,7 @@ impl ExecutionGraph {
                 };
 
                 let task_id = next_task_id.unwrap();
-                let task_attempt = stage.task_failure_numbers[partition_id];
+                let task_attempt = stage.task_attempt_num(partition_id);
                 let task_info = TaskInfo {
                     task_id,
                     scheduled_time: SystemTime::now()
@@ -945,7 +1531,11 @@ impl ExecutionGraph {
             warn!("Call fetch_runnable_stage on failed Job");
             return None;
         }
+        if self.gang_scheduling_pending() {
+            return None;
+        }
 
+        self.release_retry_backoffs();
         let running_stage_id = self.get_running_stage_id(black_list);
         if let Some(running_stage_id) = running_stage_id {
             if let Some(ExecutionStage::Running(running_stage)) =
@@ -1067,7 +1657,10 @@ impl ExecutionGraph {
                     stage_output.partition_locations.iter_mut().for_each(
                         |(_partition, locs)| {
                             let before_len = locs.len();
-                            locs.retain(|loc| loc.executor_meta.id != executor_id);
+                            // partitions on the remote shuffle storage survive the executor
+                            locs.retain(|loc| {
+                                loc.remote_storage || loc.executor_meta.id != executor_id
+                            });
                             if locs.len() < before_len {
                                 match_found = true;
                             }
@@ -1151,8 +1744,14 @@ impl ExecutionGraph {
     /// Convert unresolved stage to be resolved
     pub fn resolve_stage(&mut self, stage_id: usize) -> Result<bool> {
         if let Some(ExecutionStage::UnResolved(stage)) = self.stages.remove(&stage_id) {
-            self.stages
-                .insert(stage_id, ExecutionStage::Resolved(stage.to_resolved()?));
+            self.stages.insert(
+                stage_id,
+                ExecutionStage::Resolved(stage.to_resolved(
+                    self.coalesce_partitions_target_size,
+                    self.skew_join.as_ref(),
+                    self.broadcast_join_threshold,
+                )?),
+            );
             Ok(true)
         } else {
             warn!(
@@ -1265,8 +1864,8 @@ impl ExecutionGraph {
         }
     }
 
-    /// fail job with error message
-    pub fn fail_job(&mut self, error: String) {
+    /// fail job with error message, `timed_out` if it did not finish before its deadline
+    pub fn fail_job(&mut self, error: String, timed_out: bool) {
         self.status = JobStatus {
             job_id: self.job_id.clone(),
             job_name: self.job_name.clone(),
@@ -1275,6 +1874,7 @@ impl ExecutionGraph {
                 queued_at: self.queued_at,
                 started_at: self.start_time,
                 ended_at: self.end_time,
+                timed_out,
             })),
         };
     }
@@ -1288,8 +1888,11 @@ impl ExecutionGraph {
             )));
         }
 
-        let partition_location = self
-            .output_locations()
+        // return the output partitions in the order of the final stage tasks, so that the
+        // result of a range partitioned sort is read in sort order
+        let mut output_locations = self.output_locations();
+        output_locations.sort_by_key(|l| l.map_partition_id);
+        let partition_location = output_locations
             .into_iter()
             .map(|l| l.try_into())
             .collect::<Result<Vec<_>>>()?;
@@ -1378,6 +1981,12 @@ impl ExecutionGraph {
             })
             .collect();
 
+        let tenant = if proto.tenant.is_empty() {
+            proto.session_id.clone()
+        } else {
+            proto.tenant
+        };
+
         Ok(ExecutionGraph {
             scheduler_id: (!proto.scheduler_id.is_empty()).then_some(proto.scheduler_id),
             job_id: proto.job_id,
@@ -1396,6 +2005,44 @@ impl ExecutionGraph {
             output_locations,
             task_id_gen: proto.task_id_gen as usize,
             failed_stage_attempts,
+            coalesce_partitions_target_size: (proto.coalesce_partitions_target_size > 0)
+                .then_some(proto.coalesce_partitions_target_size as usize),
+            skew_join: proto.skew_join.map(|skew_join| SkewJoinConfig {
+                skewed_partition_factor: skew_join.skewed_partition_factor as usize,
+                skewed_partition_threshold: skew_join.skewed_partition_threshold as usize,
+                target_size: skew_join.target_size as usize,
+            }),
+            broadcast_join_threshold: (proto.broadcast_join_threshold > 0)
+                .then_some(proto.broadcast_join_threshold as usize),
+            speculation: proto.speculation.map(|speculation| SpeculationConfig {
+                multiplier: speculation.multiplier,
+                quantile: speculation.quantile,
+                min_task_runtime_ms: speculation.min_task_runtime_ms,
+            }),
+            retry_policy: proto
+                .retry_policy
+                .map(|retry_policy| RetryPolicy {
+                    max_task_failures: retry_policy.max_task_failures as usize,
+                    max_stage_failures: retry_policy.max_stage_failures as usize,
+                    backoff_ms: retry_policy.backoff_ms,
+                    max_backoff_ms: retry_policy.max_backoff_ms,
+                    non_retryable_errors: retry_policy.non_retryable_errors,
+                })
+                .unwrap_or_default(),
+            pool: if proto.pool.is_empty() {
+                "default".to_string()
+            } else {
+                proto.pool
+            },
+            priority: proto.priority,
+            tenant,
+            gang_scheduling: proto.gang_scheduling,
+            remote_shuffle_storage_url: if proto.remote_shuffle_storage_url.is_empty() {
+                None
+            } else {
+                Some(proto.remote_shuffle_storage_url)
+            },
+            deadline: (proto.deadline > 0).then_some(proto.deadline),
         })
     }
 
@@ -1472,6 +2119,36 @@ impl ExecutionGraph {
             scheduler_id: graph.scheduler_id.unwrap_or_default(),
             task_id_gen: graph.task_id_gen as u32,
             failed_attempts,
+            coalesce_partitions_target_size: graph
+                .coalesce_partitions_target_size
+                .unwrap_or_default() as u64,
+            skew_join: graph.skew_join.map(|skew_join| protobuf::SkewJoin {
+                skewed_partition_factor: skew_join.skewed_partition_factor as u64,
+                skewed_partition_threshold: skew_join.skewed_partition_threshold as u64,
+                target_size: skew_join.target_size as u64,
+            }),
+            broadcast_join_threshold: graph.broadcast_join_threshold.unwrap_or_default()
+                as u64,
+            speculation: graph.speculation.map(|speculation| protobuf::Speculation {
+                multiplier: speculation.multiplier,
+                quantile: speculation.quantile,
+                min_task_runtime_ms: speculation.min_task_runtime_ms,
+            }),
+            retry_policy: Some(protobuf::RetryPolicy {
+                max_task_failures: graph.retry_policy.max_task_failures as u64,
+                max_stage_failures: graph.retry_policy.max_stage_failures as u64,
+                backoff_ms: graph.retry_policy.backoff_ms,
+                max_backoff_ms: graph.retry_policy.max_backoff_ms,
+                non_retryable_errors: graph.retry_policy.non_retryable_errors,
+            }),
+            pool: graph.pool,
+            priority: graph.priority,
+            tenant: graph.tenant,
+            gang_scheduling: graph.gang_scheduling,
+            remote_shuffle_storage_url: graph
+                .remote_shuffle_storage_url
+                .unwrap_or_default(),
+            deadline: graph.deadline.unwrap_or_default(),
         })
     }
 }
@@ -1677,6 +2354,10 @@ fn partition_to_location(
                 Some(shuffle.num_bytes),
             ),
             path: shuffle.path,
+            range: shuffle.range.map(|r| r.into()),
+            remote_storage: shuffle.remote_storage,
+            checksum: shuffle.checksum,
+            streaming: false,
         })
         .collect()
 }
@@ -1685,20 +2366,31 @@ fn partition_to_location(
 mod test {
     use std::collections::HashSet;
 
+    use crate::planner::find_unresolved_shuffles;
     use crate::scheduler_server::event::QueryStageSchedulerEvent;
+    use datafusion::physical_plan::displayable;
+    use kapot_core::config::{
+        KapotConfig, KAPOT_ADAPTIVE_BROADCAST_JOIN_ENABLED,
+        KAPOT_ADAPTIVE_COALESCE_PARTITIONS_ENABLED, KAPOT_ADAPTIVE_SKEW_JOIN_ENABLED,
+        KAPOT_SPECULATION_ENABLED, KAPOT_SPECULATION_MIN_TASK_RUNTIME_MS,
+        KAPOT_SPECULATION_MULTIPLIER, KAPOT_SPECULATION_QUANTILE,
+        KAPOT_TASK_NON_RETRYABLE_ERRORS, KAPOT_TASK_RETRY_BACKOFF_MS,
+    };
     use kapot_core::error::Result;
+    use kapot_core::execution_plans::ShuffleWriterExec;
     use kapot_core::serde::protobuf::{
-        self, failed_task, job_status, ExecutionError, FailedTask, FetchPartitionError,
-        IoError, JobStatus, TaskKilled,
+        self, failed_task, job_status, task_status, ExecutionError, FailedTask,
+        FetchPartitionError, IoError, JobStatus, TaskKilled,
     };
 
-    use crate::state::execution_graph::ExecutionGraph;
+    use crate::state::execution_graph::{ExecutionGraph, ExecutionStage};
     use crate::test_utils::{
         mock_completed_task, mock_executor, mock_failed_task,
         revive_graph_and_complete_next_stage,
         revive_graph_and_complete_next_stage_with_executor, test_aggregation_plan,
-        test_coalesce_plan, test_join_plan, test_two_aggregations_plan,
-        test_union_all_plan, test_union_plan,
+        test_coalesce_plan, test_join_aggregate_plan, test_join_group_by_plan,
+        test_join_plan, test_join_plan_with_config, test_range_sort_plan,
+        test_two_aggregations_plan, test_union_all_plan, test_union_plan,
     };
 
     #[tokio::test]
@@ -1780,6 +2472,34 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_finalize_range_sort() -> Result<()> {
+        let mut sort_graph = test_range_sort_plan(4).await;
+
+        // materialized input, sample, range partitioned shuffle and the final sort
+        assert_eq!(4, sort_graph.stage_count());
+        assert_eq!(4, sort_graph.output_partitions);
+
+        drain_tasks(&mut sort_graph)?;
+        assert!(
+            sort_graph.is_successful(),
+            "Failed to complete range sort plan"
+        );
+
+        let status = sort_graph.status();
+        let partitions = match &status.status {
+            Some(job_status::Status::Successful(successful)) => successful
+                .partition_location
+                .iter()
+                .map(|l| l.map_partition_id)
+                .collect::<Vec<_>>(),
+            other => panic!("Unexpected job status {other:?}"),
+        };
+        assert_eq!(vec![0, 1, 2, 3], partitions);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_reset_completed_stage_executor_lost() -> Result<()> {
         let executor1 = mock_executor("executor-id1".to_string());
@@ -1810,7 +2530,7 @@ mod test {
         // Complete 1 task
         if let Some(task) = join_graph.pop_next_task(&executor1.id)? {
             let task_status = mock_completed_task(task, &executor1.id);
-            join_graph.update_task_status(&executor1, vec![task_status], 1, 1)?;
+            join_graph.update_task_status(&executor1, vec![task_status])?;
         }
         // Mock 1 running task
         let _task = join_graph.pop_next_task(&executor1.id)?;
@@ -1827,6 +2547,366 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_reset_stages_executor_lost_with_remote_storage() -> Result<()> {
+        let executor1 = mock_executor("executor-id1".to_string());
+        let mut join_graph = test_join_plan(4).await;
+
+        // Complete the two input stages on executor1 with their output on the remote storage
+        join_graph.revive();
+        for _ in 0..4 {
+            let task = join_graph.pop_next_task(&executor1.id)?.unwrap();
+            let mut task_status = mock_completed_task(task, &executor1.id);
+            if let Some(task_status::Status::Successful(successful)) =
+                task_status.status.as_mut()
+            {
+                successful
+                    .partitions
+                    .iter_mut()
+                    .for_each(|p| p.remote_storage = true);
+            }
+            join_graph.update_task_status(&executor1, vec![task_status])?;
+        }
+
+        join_graph.revive();
+        assert_eq!(join_graph.available_tasks(), 4);
+
+        // The shuffle output survives executor1, so no stage is reset
+        let reset = join_graph.reset_stages_on_lost_executor(&executor1.id)?;
+        assert!(reset.0.is_empty());
+        assert_eq!(join_graph.available_tasks(), 4);
+
+        drain_tasks(&mut join_graph)?;
+        assert!(join_graph.is_successful(), "Failed to complete join plan");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_coalesce_shuffle_partitions() -> Result<()> {
+        let executor1 = mock_executor("executor-id1".to_string());
+        let executor2 = mock_executor("executor-id2".to_string());
+        let config = KapotConfig::builder()
+            .set(KAPOT_ADAPTIVE_COALESCE_PARTITIONS_ENABLED, "true")
+            .build()?;
+        let mut join_graph = test_join_group_by_plan(4, &config).await;
+
+        // Complete the two input stages, on different executors
+        join_graph.revive();
+        assert_eq!(revive_graph_and_complete_next_stage(&mut join_graph)?, 2);
+        assert_eq!(
+            revive_graph_and_complete_next_stage_with_executor(
+                &mut join_graph,
+                &executor2
+            )?,
+            2
+        );
+
+        // The 4 partitions of a few bytes read by the join are coalesced into a single task
+        join_graph.revive();
+        assert_eq!(join_graph.available_tasks(), 1);
+
+        // The rolled back join stage reads the 4 partitions of its inputs again
+        let reset = join_graph.reset_stages_on_lost_executor(&executor1.id)?;
+        assert_eq!(reset.0.len(), 2);
+        assert!(reset.0.contains(&3));
+        let unresolved_shuffles = match join_graph.stages().get(&3) {
+            Some(ExecutionStage::UnResolved(stage)) => {
+                find_unresolved_shuffles(&stage.plan)?
+            }
+            other => panic!("Expected the join stage to be unresolved, got {other:?}"),
+        };
+        assert_eq!(unresolved_shuffles.len(), 2);
+        for unresolved_shuffle in unresolved_shuffles {
+            assert_eq!(unresolved_shuffle.output_partition_count, 4);
+        }
+
+        drain_tasks(&mut join_graph)?;
+        assert!(join_graph.is_successful(), "Failed to complete join plan");
+
+        // Without coalescing, the join runs a task per partition
+        let mut join_graph = test_join_group_by_plan(4, &KapotConfig::default()).await;
+        join_graph.revive();
+        revive_graph_and_complete_next_stage(&mut join_graph)?;
+        revive_graph_and_complete_next_stage(&mut join_graph)?;
+        join_graph.revive();
+        assert_eq!(join_graph.available_tasks(), 4);
+
+        // The output partitions of a join which isn't repartitioned are merged by the
+        // next stage, so the join runs a task per partition too
+        let mut join_graph = test_join_plan_with_config(4, &config).await;
+        join_graph.revive();
+        revive_graph_and_complete_next_stage(&mut join_graph)?;
+        revive_graph_and_complete_next_stage(&mut join_graph)?;
+        join_graph.revive();
+        assert_eq!(join_graph.available_tasks(), 4);
+
+        drain_tasks(&mut join_graph)?;
+        assert!(join_graph.is_successful(), "Failed to complete join plan");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_split_skewed_join_partitions() -> Result<()> {
+        let executor = mock_executor("executor-id1".to_string());
+        let config = KapotConfig::builder()
+            .set(KAPOT_ADAPTIVE_SKEW_JOIN_ENABLED, "true")
+            .build()?;
+        let mut join_graph = test_join_group_by_plan(4, &config).await;
+
+        // Each map task of the left side writes 200MB to its first output partition
+        join_graph.revive();
+        for _ in 0..4 {
+            let task = join_graph.pop_next_task(&executor.id)?.unwrap();
+            let stage_id = task.partition.stage_id;
+            let mut task_status = mock_completed_task(task, &executor.id);
+            if let Some(task_status::Status::Successful(successful)) =
+                task_status.status.as_mut()
+            {
+                if stage_id == 1 {
+                    successful.partitions[0].num_bytes = 200 * 1024 * 1024;
+                }
+            }
+            join_graph.update_task_status(&executor, vec![task_status])?;
+        }
+
+        // The skewed partition is split into a task per map task of the left side
+        join_graph.revive();
+        assert_eq!(join_graph.available_tasks(), 5);
+
+        drain_tasks(&mut join_graph)?;
+        assert!(join_graph.is_successful(), "Failed to complete join plan");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_small_join_build_side() -> Result<()> {
+        let executor = mock_executor("executor-id1".to_string());
+        let config = KapotConfig::builder()
+            .set(KAPOT_ADAPTIVE_BROADCAST_JOIN_ENABLED, "true")
+            .build()?;
+        let mut join_graph = test_join_group_by_plan(4, &config).await;
+
+        join_graph.revive();
+        for _ in 0..4 {
+            let task = join_graph.pop_next_task(&executor.id)?.unwrap();
+            let task_status = mock_completed_task(task, &executor.id);
+            join_graph.update_task_status(&executor, vec![task_status])?;
+        }
+
+        // Each task of the broadcast join reads the output of a map task of the right side
+        join_graph.revive();
+        assert_eq!(join_graph.available_tasks(), 2);
+
+        drain_tasks(&mut join_graph)?;
+        assert!(join_graph.is_successful(), "Failed to complete join plan");
+
+        // The join stays partitioned when the left side is larger than the threshold
+        let mut join_graph = test_join_group_by_plan(4, &config).await;
+        join_graph.revive();
+        for _ in 0..4 {
+            let task = join_graph.pop_next_task(&executor.id)?.unwrap();
+            let stage_id = task.partition.stage_id;
+            let mut task_status = mock_completed_task(task, &executor.id);
+            if let Some(task_status::Status::Successful(successful)) =
+                task_status.status.as_mut()
+            {
+                if stage_id == 1 {
+                    successful.partitions[0].num_bytes = 1024 * 1024;
+                }
+            }
+            join_graph.update_task_status(&executor, vec![task_status])?;
+        }
+        join_graph.revive();
+        assert_eq!(join_graph.available_tasks(), 4);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_small_join_build_stage() -> Result<()> {
+        let executor = mock_executor("executor-id1".to_string());
+        let config = KapotConfig::builder()
+            .set(KAPOT_ADAPTIVE_BROADCAST_JOIN_ENABLED, "true")
+            .build()?;
+        let mut join_graph = test_join_aggregate_plan(4, &config).await;
+
+        join_graph.revive();
+        let tasks = (0..4)
+            .map(|_| Ok(join_graph.pop_next_task(&executor.id)?.unwrap()))
+            .collect::<Result<Vec<_>>>()?;
+        let (build_tasks, other_tasks): (Vec<_>, Vec<_>) = tasks
+            .into_iter()
+            .partition(|task| task.partition.stage_id == 1);
+        assert_eq!(build_tasks.len(), 2);
+        for task in build_tasks {
+            let task_status = mock_completed_task(task, &executor.id);
+            join_graph.update_task_status(&executor, vec![task_status])?;
+        }
+
+        // The probe stage is re-planned to keep the partitioning of its input as soon as
+        // the build stage completes
+        match join_graph.stages().get(&3) {
+            Some(ExecutionStage::UnResolved(stage)) => {
+                let writer = stage
+                    .plan
+                    .as_any()
+                    .downcast_ref::<ShuffleWriterExec>()
+                    .unwrap();
+                assert!(writer.shuffle_output_partitioning().is_none());
+            }
+            other => panic!("Expected the probe stage to be unresolved, got {other:?}"),
+        }
+        match join_graph.stages().get(&4) {
+            Some(ExecutionStage::UnResolved(stage)) => {
+                let plan = format!("{}", displayable(stage.plan.as_ref()).indent(false));
+                assert!(plan.contains("mode=CollectLeft"), "{plan}");
+            }
+            other => panic!("Expected the join stage to be unresolved, got {other:?}"),
+        }
+
+        for task in other_tasks {
+            let task_status = mock_completed_task(task, &executor.id);
+            join_graph.update_task_status(&executor, vec![task_status])?;
+        }
+
+        // Each task of the broadcast join reads the output of a task of the probe stage
+        revive_graph_and_complete_next_stage(&mut join_graph)?;
+        join_graph.revive();
+        assert_eq!(join_graph.available_tasks(), 4);
+
+        drain_tasks(&mut join_graph)?;
+        assert!(join_graph.is_successful(), "Failed to complete join plan");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_speculative_task_wins() -> Result<()> {
+        let executor1 = mock_executor("executor-id1".to_string());
+        let executor2 = mock_executor("executor-id2".to_string());
+        let config = KapotConfig::builder()
+            .set(KAPOT_SPECULATION_ENABLED, "true")
+            .set(KAPOT_SPECULATION_MULTIPLIER, "1")
+            .set(KAPOT_SPECULATION_QUANTILE, "0.5")
+            .set(KAPOT_SPECULATION_MIN_TASK_RUNTIME_MS, "0")
+            .build()?;
+        let mut join_graph = test_join_group_by_plan(4, &config).await;
+
+        join_graph.revive();
+        let mut tasks = (0..4)
+            .map(|_| Ok(join_graph.pop_next_task(&executor1.id)?.unwrap()))
+            .collect::<Result<Vec<_>>>()?;
+        let straggler = tasks.pop().unwrap();
+        for task in tasks {
+            let task_status = mock_completed_task(task, &executor1.id);
+            join_graph.update_task_status(&executor1, vec![task_status])?;
+        }
+
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        assert!(join_graph.has_speculatable_tasks());
+
+        // The copy is launched on another executor than the straggler
+        let copies = join_graph.bind_speculative_tasks(&mut |executor_id| {
+            assert_eq!(executor_id, executor1.id);
+            Some(executor2.id.clone())
+        });
+        assert_eq!(copies.len(), 1);
+        let (executor_id, copy) = copies.into_iter().next().unwrap();
+        assert_eq!(executor_id, executor2.id);
+        assert_eq!(copy.partition, straggler.partition);
+        assert_ne!(copy.task_id, straggler.task_id);
+        assert_ne!(copy.task_attempt, straggler.task_attempt);
+        assert_eq!(join_graph.running_tasks().len(), 2);
+        assert!(!join_graph.has_speculatable_tasks());
+
+        // The copy finishes first and the straggler is cancelled
+        let task_status = mock_completed_task(copy, &executor2.id);
+        let events = join_graph.update_task_status(&executor2, vec![task_status])?;
+        let cancelled = events
+            .iter()
+            .find_map(|event| match event {
+                QueryStageSchedulerEvent::CancelTasks(tasks) => Some(tasks),
+                _ => None,
+            })
+            .expect("the straggler should be cancelled");
+        assert_eq!(cancelled.len(), 1);
+        assert_eq!(cancelled[0].task_id, straggler.task_id);
+        assert_eq!(cancelled[0].executor_id, executor1.id);
+
+        // The late status of the straggler is ignored
+        let task_status = mock_failed_task(
+            straggler,
+            FailedTask {
+                error: "Killed".to_string(),
+                retryable: false,
+                count_to_failures: false,
+                failed_reason: Some(failed_task::FailedReason::TaskKilled(TaskKilled {})),
+            },
+        );
+        join_graph.update_task_status(&executor1, vec![task_status])?;
+
+        drain_tasks(&mut join_graph)?;
+        assert!(join_graph.is_successful(), "Failed to complete join plan");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_speculative_task_attempt_nums() -> Result<()> {
+        let executor1 = mock_executor("executor-id1".to_string());
+        let executor2 = mock_executor("executor-id2".to_string());
+        let config = KapotConfig::builder()
+            .set(KAPOT_SPECULATION_ENABLED, "true")
+            .set(KAPOT_SPECULATION_MULTIPLIER, "1")
+            .set(KAPOT_SPECULATION_QUANTILE, "0.5")
+            .set(KAPOT_SPECULATION_MIN_TASK_RUNTIME_MS, "0")
+            .build()?;
+        let mut join_graph = test_join_group_by_plan(4, &config).await;
+
+        join_graph.revive();
+        let mut tasks = (0..4)
+            .map(|_| Ok(join_graph.pop_next_task(&executor1.id)?.unwrap()))
+            .collect::<Result<Vec<_>>>()?;
+        let straggler = tasks.pop().unwrap();
+        for task in tasks {
+            let task_status = mock_completed_task(task, &executor1.id);
+            join_graph.update_task_status(&executor1, vec![task_status])?;
+        }
+
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        let copies =
+            join_graph.bind_speculative_tasks(&mut |_| Some(executor2.id.clone()));
+        let (_, copy) = copies.into_iter().next().unwrap();
+        assert_eq!(copy.task_attempt, straggler.task_attempt + 1);
+
+        // Both attempts fail, the straggler first, and the task is retried
+        let failed_task = FailedTask {
+            error: "IOError".to_string(),
+            retryable: true,
+            count_to_failures: true,
+            failed_reason: Some(failed_task::FailedReason::IoError(IoError {})),
+        };
+        let partition = straggler.partition.clone();
+        let straggler_attempt = straggler.task_attempt;
+        let task_status = mock_failed_task(straggler, failed_task.clone());
+        join_graph.update_task_status(&executor1, vec![task_status])?;
+        assert_eq!(join_graph.running_tasks().len(), 1);
+        let copy_attempt = copy.task_attempt;
+        let task_status = mock_failed_task(copy, failed_task);
+        join_graph.update_task_status(&executor2, vec![task_status])?;
+
+        // The retry is numbered after the copy
+        let retry = join_graph.pop_next_task(&executor1.id)?.unwrap();
+        assert_eq!(retry.partition, partition);
+        assert_ne!(retry.task_attempt, straggler_attempt);
+        assert_ne!(retry.task_attempt, copy_attempt);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_reset_resolved_stage_executor_lost() -> Result<()> {
         let executor1 = mock_executor("executor-id1".to_string());
@@ -1890,13 +2970,13 @@ mod test {
         // 1st task in the second stage
         if let Some(task) = agg_graph.pop_next_task(&executor2.id)? {
             let task_status = mock_completed_task(task, &executor2.id);
-            agg_graph.update_task_status(&executor2, vec![task_status], 1, 1)?;
+            agg_graph.update_task_status(&executor2, vec![task_status])?;
         }
 
         // 2rd task in the second stage
         if let Some(task) = agg_graph.pop_next_task(&executor1.id)? {
             let task_status = mock_completed_task(task, &executor1.id);
-            agg_graph.update_task_status(&executor1, vec![task_status], 1, 1)?;
+            agg_graph.update_task_status(&executor1, vec![task_status])?;
         }
 
         // 3rd task in the second stage, scheduled but not completed
@@ -1909,7 +2989,7 @@ mod test {
 
         // 3rd task status update comes later.
         let task_status = mock_completed_task(task.unwrap(), &executor1.id);
-        agg_graph.update_task_status(&executor1, vec![task_status], 1, 1)?;
+        agg_graph.update_task_status(&executor1, vec![task_status])?;
 
         // Two stages were reset, 1 Running stage rollback to Unresolved and 1 Completed stage move to Running
         assert_eq!(reset.0.len(), 2);
@@ -1952,12 +3032,7 @@ mod test {
             },
         );
 
-        agg_graph.update_task_status(
-            &executor,
-            vec![task_status1, task_status2],
-            4,
-            4,
-        )?;
+        agg_graph.update_task_status(&executor, vec![task_status1, task_status2])?;
 
         assert_eq!(agg_graph.available_tasks(), 2);
         drain_tasks(&mut agg_graph)?;
@@ -1996,12 +3071,7 @@ mod test {
             },
         );
 
-        agg_graph.update_task_status(
-            &executor,
-            vec![task_status1, task_status2],
-            4,
-            4,
-        )?;
+        agg_graph.update_task_status(&executor, vec![task_status1, task_status2])?;
 
         assert_eq!(agg_graph.available_tasks(), 1);
 
@@ -2026,7 +3096,7 @@ mod test {
                         )),
                     },
                 );
-                agg_graph.update_task_status(&executor, vec![task_status], 4, 4)?;
+                agg_graph.update_task_status(&executor, vec![task_status])?;
             }
         }
 
@@ -2051,6 +3121,104 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_task_retry_backoff() -> Result<()> {
+        let executor = mock_executor("executor-id1".to_string());
+        let config = KapotConfig::builder()
+            .set(KAPOT_TASK_RETRY_BACKOFF_MS, "50")
+            .build()?;
+        let mut join_graph = test_join_plan_with_config(4, &config).await;
+        join_graph.revive();
+
+        let mut tasks = vec![];
+        while let Some(task) = join_graph.pop_next_task(&executor.id)? {
+            tasks.push(task);
+        }
+        let io_error = FailedTask {
+            error: "IOError".to_string(),
+            retryable: true,
+            count_to_failures: true,
+            failed_reason: Some(failed_task::FailedReason::IoError(IoError {})),
+        };
+
+        // The failed task is not rescheduled before its backoff is over
+        let mut task = tasks.pop().unwrap();
+        for delay_ms in [50, 100] {
+            let partition = task.partition.clone();
+            let task_status = mock_failed_task(task, io_error.clone());
+            let events = join_graph.update_task_status(&executor, vec![task_status])?;
+            assert!(events.iter().any(|event| matches!(
+                event,
+                QueryStageSchedulerEvent::ReviveOffersAfter(delay) if *delay == delay_ms
+            )));
+            assert_eq!(join_graph.available_tasks(), 0);
+            assert!(join_graph.pop_next_task(&executor.id)?.is_none());
+
+            tokio::time::sleep(std::time::Duration::from_millis(delay_ms + 10)).await;
+            task = join_graph.pop_next_task(&executor.id)?.unwrap();
+            assert_eq!(task.partition, partition);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_non_retryable_task_failure() -> Result<()> {
+        let executor = mock_executor("executor-id1".to_string());
+        let config = KapotConfig::builder()
+            .set(KAPOT_TASK_NON_RETRYABLE_ERRORS, "Permission denied")
+            .build()?;
+        let mut join_graph = test_join_plan_with_config(4, &config).await;
+        join_graph.revive();
+
+        // A retryable failure matching none of the patterns is retried
+        let task = join_graph.pop_next_task(&executor.id)?.unwrap();
+        let task_status = mock_failed_task(
+            task,
+            FailedTask {
+                error: "Connection reset by peer".to_string(),
+                retryable: true,
+                count_to_failures: true,
+                failed_reason: Some(failed_task::FailedReason::IoError(IoError {})),
+            },
+        );
+        join_graph.update_task_status(&executor, vec![task_status])?;
+        assert!(matches!(
+            join_graph.status,
+            JobStatus {
+                status: Some(job_status::Status::Running(_)),
+                ..
+            }
+        ));
+
+        // A failure matching one of the patterns fails the job at once
+        let task = join_graph.pop_next_task(&executor.id)?.unwrap();
+        let task_status = mock_failed_task(
+            task,
+            FailedTask {
+                error: "Permission denied (os error 13)".to_string(),
+                retryable: true,
+                count_to_failures: true,
+                failed_reason: Some(failed_task::FailedReason::IoError(IoError {})),
+            },
+        );
+        join_graph.update_task_status(&executor, vec![task_status])?;
+        assert!(
+            matches!(
+                join_graph.status,
+                JobStatus {
+                    status: Some(job_status::Status::Failed(_)),
+                    ..
+                }
+            ),
+            "Expected job status to be Failed"
+        );
+        let failure_reason = format!("{:?}", join_graph.status);
+        assert!(failure_reason.contains("failed with a non retryable error"));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_long_delayed_failed_task_after_executor_lost() -> Result<()> {
         let executor1 = mock_executor("executor-id1".to_string());
@@ -2065,13 +3233,13 @@ mod test {
         // 1st task in the Stage 2
         if let Some(task) = agg_graph.pop_next_task(&executor2.id)? {
             let task_status = mock_completed_task(task, &executor2.id);
-            agg_graph.update_task_status(&executor2, vec![task_status], 1, 1)?;
+            agg_graph.update_task_status(&executor2, vec![task_status])?;
         }
 
         // 2rd task in the Stage 2
         if let Some(task) = agg_graph.pop_next_task(&executor1.id)? {
             let task_status = mock_completed_task(task, &executor1.id);
-            agg_graph.update_task_status(&executor1, vec![task_status], 1, 1)?;
+            agg_graph.update_task_status(&executor1, vec![task_status])?;
         }
 
         // 3rd task in the Stage 2, scheduled on executor 2 but not completed
@@ -2109,7 +3277,7 @@ mod test {
 
         // This long delayed failed task should not failure the stage/job and should not trigger any query stage events
         let query_stage_events =
-            agg_graph.update_task_status(&executor1, vec![task_status], 4, 4)?;
+            agg_graph.update_task_status(&executor1, vec![task_status])?;
         assert!(query_stage_events.is_empty());
 
         drain_tasks(&mut agg_graph)?;
@@ -2157,12 +3325,8 @@ mod test {
         }
         assert_eq!(running_task_count, 2);
 
-        let stage_events = agg_graph.update_task_status(
-            &executor2,
-            vec![task_status1, task_status2],
-            4,
-            4,
-        )?;
+        let stage_events =
+            agg_graph.update_task_status(&executor2, vec![task_status1, task_status2])?;
 
         assert_eq!(stage_events.len(), 1);
         assert!(matches!(
@@ -2198,14 +3362,14 @@ mod test {
         for _i in 0..5 {
             if let Some(task) = agg_graph.pop_next_task(&executor2.id)? {
                 let task_status = mock_completed_task(task, &executor2.id);
-                agg_graph.update_task_status(&executor2, vec![task_status], 4, 4)?;
+                agg_graph.update_task_status(&executor2, vec![task_status])?;
             }
         }
         assert_eq!(agg_graph.available_tasks(), 3);
         for _i in 0..3 {
             if let Some(task) = agg_graph.pop_next_task(&executor1.id)? {
                 let task_status = mock_completed_task(task, &executor1.id);
-                agg_graph.update_task_status(&executor1, vec![task_status], 4, 4)?;
+                agg_graph.update_task_status(&executor1, vec![task_status])?;
             }
         }
 
@@ -2234,7 +3398,7 @@ mod test {
             }
         }
         assert_eq!(many_fetch_failure_status.len(), 6);
-        agg_graph.update_task_status(&executor3, many_fetch_failure_status, 4, 4)?;
+        agg_graph.update_task_status(&executor3, many_fetch_failure_status)?;
 
         // The Running stage should be Stage 2 now
         let running_stage = agg_graph.running_stages();
@@ -2279,7 +3443,7 @@ mod test {
                 );
 
                 let stage_events =
-                    agg_graph.update_task_status(&executor2, vec![task_status1], 4, 4)?;
+                    agg_graph.update_task_status(&executor2, vec![task_status1])?;
 
                 if attempt < 3 {
                     // No JobRunningFailed stage events
@@ -2331,7 +3495,7 @@ mod test {
         for _i in 0..5 {
             if let Some(task) = agg_graph.pop_next_task(&executor2.id)? {
                 let task_status = mock_completed_task(task, &executor2.id);
-                agg_graph.update_task_status(&executor2, vec![task_status], 4, 4)?;
+                agg_graph.update_task_status(&executor2, vec![task_status])?;
             }
         }
         assert_eq!(agg_graph.available_tasks(), 3);
@@ -2339,13 +3503,13 @@ mod test {
         for _i in 0..2 {
             if let Some(task) = agg_graph.pop_next_task(&executor1.id)? {
                 let task_status = mock_completed_task(task, &executor1.id);
-                agg_graph.update_task_status(&executor1, vec![task_status], 4, 4)?;
+                agg_graph.update_task_status(&executor1, vec![task_status])?;
             }
         }
 
         if let Some(task) = agg_graph.pop_next_task(&executor3.id)? {
             let task_status = mock_completed_task(task, &executor3.id);
-            agg_graph.update_task_status(&executor3, vec![task_status], 4, 4)?;
+            agg_graph.update_task_status(&executor3, vec![task_status])?;
         }
         assert_eq!(agg_graph.available_tasks(), 0);
 
@@ -2377,7 +3541,7 @@ mod test {
                 )),
             },
         );
-        agg_graph.update_task_status(&executor3, vec![task_status_1], 4, 4)?;
+        agg_graph.update_task_status(&executor3, vec![task_status_1])?;
 
         // The Running stage is Stage 2 now
         let running_stage = agg_graph.running_stages();
@@ -2402,7 +3566,7 @@ mod test {
             },
         );
         // This task update should be ignored
-        agg_graph.update_task_status(&executor3, vec![task_status_2], 4, 4)?;
+        agg_graph.update_task_status(&executor3, vec![task_status_2])?;
         let running_stage = agg_graph.running_stages();
         assert_eq!(running_stage.len(), 1);
         assert_eq!(running_stage[0], 2);
@@ -2425,7 +3589,7 @@ mod test {
             },
         );
         // This task update should be handled because it has a different failure reason
-        agg_graph.update_task_status(&executor3, vec![task_status_3], 4, 4)?;
+        agg_graph.update_task_status(&executor3, vec![task_status_3])?;
         // Running stage is still Stage 2, but available tasks changed to 7
         assert_eq!(running_stage.len(), 1);
         assert_eq!(running_stage[0], 2);
@@ -2435,7 +3599,7 @@ mod test {
         for _i in 0..4 {
             if let Some(task) = agg_graph.pop_next_task(&executor1.id)? {
                 let task_status = mock_completed_task(task, &executor1.id);
-                agg_graph.update_task_status(&executor1, vec![task_status], 4, 4)?;
+                agg_graph.update_task_status(&executor1, vec![task_status])?;
             }
         }
         assert_eq!(running_stage.len(), 1);
@@ -2459,7 +3623,7 @@ mod test {
             },
         );
         // This task update should be ignored because the same failure reason is already handled
-        agg_graph.update_task_status(&executor3, vec![task_status_4], 4, 4)?;
+        agg_graph.update_task_status(&executor3, vec![task_status_4])?;
         let running_stage = agg_graph.running_stages();
         assert_eq!(running_stage.len(), 1);
         assert_eq!(running_stage[0], 2);
@@ -2469,7 +3633,7 @@ mod test {
         for _i in 0..3 {
             if let Some(task) = agg_graph.pop_next_task(&executor1.id)? {
                 let task_status = mock_completed_task(task, &executor1.id);
-                agg_graph.update_task_status(&executor1, vec![task_status], 4, 4)?;
+                agg_graph.update_task_status(&executor1, vec![task_status])?;
             }
         }
         assert_eq!(agg_graph.available_tasks(), 0);
@@ -2492,7 +3656,7 @@ mod test {
                 )),
             },
         );
-        agg_graph.update_task_status(&executor3, vec![task_status_5], 4, 4)?;
+        agg_graph.update_task_status(&executor3, vec![task_status_5])?;
         // Stage 3's new attempt is running
         let running_stage = agg_graph.running_stages();
         assert_eq!(running_stage.len(), 1);
@@ -2532,7 +3696,7 @@ mod test {
         for _i in 0..5 {
             if let Some(task) = agg_graph.pop_next_task(&executor2.id)? {
                 let task_status = mock_completed_task(task, &executor2.id);
-                agg_graph.update_task_status(&executor2, vec![task_status], 4, 4)?;
+                agg_graph.update_task_status(&executor2, vec![task_status])?;
             }
         }
         assert_eq!(agg_graph.available_tasks(), 3);
@@ -2540,7 +3704,7 @@ mod test {
         for _i in 0..3 {
             if let Some(task) = agg_graph.pop_next_task(&executor1.id)? {
                 let task_status = mock_completed_task(task, &executor1.id);
-                agg_graph.update_task_status(&executor1, vec![task_status], 4, 4)?;
+                agg_graph.update_task_status(&executor1, vec![task_status])?;
             }
         }
         assert_eq!(agg_graph.available_tasks(), 0);
@@ -2567,7 +3731,7 @@ mod test {
                 )),
             },
         );
-        agg_graph.update_task_status(&executor3, vec![task_status_1], 4, 4)?;
+        agg_graph.update_task_status(&executor3, vec![task_status_1])?;
 
         // The Running stage is Stage 2 now
         let running_stage = agg_graph.running_stages();
@@ -2603,7 +3767,7 @@ mod test {
 
         // TaskStatus of Stage 2 come together with Stage 3 delayed FetchFailure update.
         // The successful tasks from Stage 2 would try to succeed the Stage2 and the delayed fetch failure try to reset the TaskInfo
-        agg_graph.update_task_status(&executor3, task_status_vec, 4, 4)?;
+        agg_graph.update_task_status(&executor3, task_status_vec)?;
         //The Running stage is still Stage 2, 3 new pending tasks added due to FetchPartitionError(executor1)
         assert_eq!(running_stage.len(), 1);
         assert_eq!(running_stage[0], 2);
@@ -2632,14 +3796,14 @@ mod test {
         for _i in 0..5 {
             if let Some(task) = agg_graph.pop_next_task(&executor2.id)? {
                 let task_status = mock_completed_task(task, &executor2.id);
-                agg_graph.update_task_status(&executor2, vec![task_status], 4, 4)?;
+                agg_graph.update_task_status(&executor2, vec![task_status])?;
             }
         }
         assert_eq!(agg_graph.available_tasks(), 3);
         for _i in 0..3 {
             if let Some(task) = agg_graph.pop_next_task(&executor1.id)? {
                 let task_status = mock_completed_task(task, &executor1.id);
-                agg_graph.update_task_status(&executor1, vec![task_status], 4, 4)?;
+                agg_graph.update_task_status(&executor1, vec![task_status])?;
             }
         }
         assert_eq!(agg_graph.available_tasks(), 0);
@@ -2664,7 +3828,7 @@ mod test {
             );
 
             let _stage_events =
-                agg_graph.update_task_status(&executor3, vec![task_status1], 4, 4)?;
+                agg_graph.update_task_status(&executor3, vec![task_status1])?;
         }
         // The Running stage is Stage 2 now
         let running_stage = agg_graph.running_stages();
@@ -2690,7 +3854,7 @@ mod test {
                 },
             );
             let _stage_events =
-                agg_graph.update_task_status(&executor3, vec![task_status1], 4, 4)?;
+                agg_graph.update_task_status(&executor3, vec![task_status1])?;
         }
         // The Running stage is Stage 1 now
         let running_stage = agg_graph.running_stages();
@@ -2763,8 +3927,6 @@ mod test {
         let stage_events = agg_graph.update_task_status(
             &executor2,
             vec![task_status1, task_status2, task_status3],
-            4,
-            4,
         )?;
 
         assert_eq!(stage_events.len(), 1);
@@ -2792,7 +3954,7 @@ mod test {
         let executor = mock_executor("executor-id1".to_string());
         while let Some(task) = graph.pop_next_task(&executor.id)? {
             let task_status = mock_completed_task(task, &executor.id);
-            graph.update_task_status(&executor, vec![task_status], 1, 1)?;
+            graph.update_task_status(&executor, vec![task_status])?;
         }
 
         Ok(())
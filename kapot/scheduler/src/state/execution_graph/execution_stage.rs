@@ -15,7 +15,7 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::convert::TryInto;
 use std::fmt::{Debug, Formatter};
 use std::iter::FromIterator;
@@ -36,7 +36,7 @@ use kapot_core::execution_plans::ShuffleWriterExec;
 use kapot_core::serde::protobuf::failed_task::FailedReason;
 use kapot_core::serde::protobuf::{
     self, task_info, FailedTask, GraphStageInput, OperatorMetricsSet, ResultLost,
-    SuccessfulTask, TaskStatus,
+    ShuffleWritePartition, SuccessfulTask, TaskStatus,
 };
 use kapot_core::serde::protobuf::{task_status, RunningTask};
 use kapot_core::serde::scheduler::PartitionLocation;
@@ -44,6 +44,7 @@ use kapot_core::serde::KapotCodec;
 use datafusion_proto::physical_plan::AsExecutionPlan;
 
 use crate::display::DisplayablekapotExecutionPlan;
+use crate::planner::SkewJoinConfig;
 
 /// A stage in the ExecutionGraph,
 /// represents a set of tasks (one per each `partition`) which can be executed concurrently.
@@ -97,6 +98,37 @@ impl ExecutionStage {
             ExecutionStage::Failed(stage) => stage.plan.as_ref(),
         }
     }
+
+    /// Get the combined metrics of the finished tasks of this stage
+    pub(crate) fn stage_metrics(&self) -> &[MetricsSet] {
+        match self {
+            ExecutionStage::Running(stage) => stage.stage_metrics.as_deref(),
+            ExecutionStage::Successful(stage) => Some(stage.stage_metrics.as_slice()),
+            ExecutionStage::Failed(stage) => stage.stage_metrics.as_deref(),
+            ExecutionStage::UnResolved(_) | ExecutionStage::Resolved(_) => None,
+        }
+        .unwrap_or_default()
+    }
+
+    /// Get the IDs of the executors which ran or are running the tasks of this stage
+    pub(crate) fn executors(&self) -> BTreeSet<String> {
+        let task_infos: Vec<&TaskInfo> = match self {
+            ExecutionStage::Running(stage) => stage.task_infos.iter().flatten().collect(),
+            ExecutionStage::Successful(stage) => stage.task_infos.iter().collect(),
+            ExecutionStage::Failed(stage) => stage.task_infos.iter().flatten().collect(),
+            ExecutionStage::UnResolved(_) | ExecutionStage::Resolved(_) => vec![],
+        };
+        task_infos
+            .into_iter()
+            .filter_map(|task_info| match &task_info.task_status {
+                task_status::Status::Running(RunningTask { executor_id })
+                | task_status::Status::Successful(SuccessfulTask {
+                    executor_id, ..
+                }) => Some(executor_id.clone()),
+                task_status::Status::Failed(_) => None,
+            })
+            .collect()
+    }
 }
 
 /// For a stage whose input stages are not all completed, we say it's a unresolved stage
@@ -169,6 +201,16 @@ pub(crate) struct RunningStage {
     pub(crate) task_failure_numbers: Vec<usize>,
     /// Combined metrics of the already finished tasks in the stage, If it is None, no task is finished yet.
     pub(crate) stage_metrics: Option<Vec<MetricsSet>>,
+    /// TaskInfo of the speculative copies of the straggler tasks still running.
+    /// The key of the HashMap is the task's partition id
+    pub(crate) speculative_task_infos: HashMap<usize, TaskInfo>,
+    /// Track the number of speculative copies launched for each partition's task, which
+    /// are counted in the attempt numbers along with the failures.
+    /// The index of the Vec is the task's partition id.
+    pub(crate) speculative_task_numbers: Vec<usize>,
+    /// Time in milliseconds after which the failed tasks waiting out their retry backoff
+    /// are rescheduled. The key of the HashMap is the task's partition id
+    pub(crate) retry_backoffs: HashMap<usize, u128>,
 }
 
 /// If a stage finishes successfully, its task statuses and metrics will be finalized
@@ -219,6 +261,68 @@ pub(crate) struct FailedStage {
     pub(crate) error_message: String,
 }
 
+/// Thresholds of the running tasks a speculative copy is launched of, see
+/// [`RunningStage::speculatable_tasks`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct SpeculationConfig {
+    /// A task is speculated when it runs longer than this multiplier times the median
+    /// duration of the successful tasks of its stage
+    pub(crate) multiplier: f64,
+    /// Fraction of the tasks of a stage which must be successful before the stage is
+    /// speculated
+    pub(crate) quantile: f64,
+    /// Minimum duration in milliseconds of a task before it is speculated
+    pub(crate) min_task_runtime_ms: u64,
+}
+
+/// How the failed tasks and stages of a job are retried
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RetryPolicy {
+    /// Number of times a task is attempted before its stage is failed
+    pub(crate) max_task_failures: usize,
+    /// Number of times a stage is attempted after shuffle fetch failures before it is
+    /// failed
+    pub(crate) max_stage_failures: usize,
+    /// Delay in milliseconds before a failed task is retried, doubled with every further
+    /// failure of the task
+    pub(crate) backoff_ms: u64,
+    /// Upper bound in milliseconds of the delay before a failed task is retried
+    pub(crate) max_backoff_ms: u64,
+    /// A task failure whose error message contains one of these patterns is not retried
+    pub(crate) non_retryable_errors: Vec<String>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_task_failures: 4,
+            max_stage_failures: 4,
+            backoff_ms: 0,
+            max_backoff_ms: 30000,
+            non_retryable_errors: vec![],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns true if the error message of a task failure matches one of the non
+    /// retryable patterns
+    pub(crate) fn is_non_retryable(&self, error: &str) -> bool {
+        self.non_retryable_errors
+            .iter()
+            .any(|pattern| error.contains(pattern.as_str()))
+    }
+
+    /// Returns the delay in milliseconds before retrying a task which has failed the
+    /// given number of times
+    pub(crate) fn retry_delay_ms(&self, failures: usize) -> u64 {
+        let exponent = failures.saturating_sub(1).min(32) as u32;
+        self.backoff_ms
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff_ms)
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct TaskInfo {
     /// Task ID
@@ -330,23 +434,45 @@ impl UnresolvedStage {
         }
     }
 
+    /// Number of tasks of the stage once it is resolved
+    pub(super) fn partitions(&self) -> usize {
+        get_stage_partitions(self.plan.clone())
+    }
+
     /// Returns true if all inputs are complete and we can resolve all
     /// UnresolvedShuffleExec operators to ShuffleReadExec
     pub(super) fn resolvable(&self) -> bool {
         self.inputs.iter().all(|(_, input)| input.is_complete())
     }
 
-    /// Change to the resolved state
-    pub(super) fn to_resolved(&self) -> Result<ResolvedStage> {
+    /// Change to the resolved state, switching its join to a broadcast join when the build
+    /// side is smaller than `broadcast_join_threshold` bytes, then coalescing the adjacent
+    /// shuffle partitions it reads up to `coalesce_partitions_target_size` bytes when set,
+    /// then splitting the skewed partitions of its join when `skew_join` is set
+    pub(super) fn to_resolved(
+        &self,
+        coalesce_partitions_target_size: Option<usize>,
+        skew_join: Option<&SkewJoinConfig>,
+        broadcast_join_threshold: Option<usize>,
+    ) -> Result<ResolvedStage> {
         let input_locations = self
             .inputs
             .iter()
             .map(|(stage, input)| (*stage, input.partition_locations.clone()))
             .collect();
-        let plan = crate::planner::remove_unresolved_shuffles(
+        let mut plan = crate::planner::remove_unresolved_shuffles(
             self.plan.clone(),
             &input_locations,
         )?;
+        if let Some(threshold) = broadcast_join_threshold {
+            plan = crate::planner::broadcast_small_join_build_side(plan, threshold)?;
+        }
+        if let Some(target_size) = coalesce_partitions_target_size {
+            plan = crate::planner::coalesce_shuffle_partitions(plan, target_size)?;
+        }
+        if let Some(skew_join) = skew_join {
+            plan = crate::planner::split_skewed_partitions(plan, skew_join)?;
+        }
 
         // TODO reinstate this logic once https://github.com/apache/datafusion/issues/10978
         // is fixed
@@ -565,6 +691,9 @@ impl RunningStage {
             task_infos: vec![None; partitions],
             task_failure_numbers: vec![0; partitions],
             stage_metrics: None,
+            speculative_task_infos: HashMap::new(),
+            speculative_task_numbers: vec![0; partitions],
+            retry_backoffs: HashMap::new(),
         }
     }
 
@@ -675,14 +804,21 @@ impl RunningStage {
         self.task_infos.iter().filter(|s| s.is_some()).count()
     }
 
-    /// Returns a vector of currently running tasks in this stage
+    /// Returns a vector of currently running tasks in this stage, including the
+    /// speculative copies
     pub(super) fn running_tasks(&self) -> Vec<(usize, usize, usize, String)> {
         self.task_infos
             .iter()
             .enumerate()
+            .filter_map(|(partition, info)| info.as_ref().map(|info| (partition, info)))
+            .chain(
+                self.speculative_task_infos
+                    .iter()
+                    .map(|(partition, info)| (*partition, info)),
+            )
             .filter_map(|(partition, info)| match info {
-                Some(TaskInfo {task_id,
-                         task_status: task_status::Status::Running(RunningTask { executor_id }), ..}) => {
+                TaskInfo {task_id,
+                         task_status: task_status::Status::Running(RunningTask { executor_id }), ..} => {
                     Some((*task_id, self.stage_id, partition, executor_id.clone()))
                 }
                 _ => None,
@@ -690,6 +826,117 @@ impl RunningStage {
             .collect()
     }
 
+    /// Returns the partitions of the running tasks which have been running for longer
+    /// than `multiplier` times the median duration of the successful tasks of the stage,
+    /// and at least `min_task_runtime_ms`, along with the executor running them.
+    /// Nothing is returned before `quantile` of the tasks of the stage are successful,
+    /// and tasks which already have a speculative copy are skipped.
+    pub(super) fn speculatable_tasks(
+        &self,
+        config: &SpeculationConfig,
+        now: u128,
+    ) -> Vec<(usize, String)> {
+        let mut durations = self
+            .task_infos
+            .iter()
+            .filter_map(|info| match info {
+                Some(TaskInfo {
+                    scheduled_time,
+                    finish_time,
+                    task_status: task_status::Status::Successful(_),
+                    ..
+                }) => Some(finish_time.saturating_sub(*scheduled_time)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if durations.is_empty()
+            || (durations.len() as f64) < config.quantile * self.partitions as f64
+        {
+            return vec![];
+        }
+        durations.sort_unstable();
+        let median = durations[durations.len() / 2];
+        let threshold = ((median as f64 * config.multiplier) as u128)
+            .max(config.min_task_runtime_ms as u128);
+
+        self.task_infos
+            .iter()
+            .enumerate()
+            .filter_map(|(partition_id, info)| match info {
+                Some(TaskInfo {
+                    scheduled_time,
+                    task_status: task_status::Status::Running(RunningTask { executor_id }),
+                    ..
+                }) if !self.speculative_task_infos.contains_key(&partition_id)
+                    && now.saturating_sub(*scheduled_time) > threshold =>
+                {
+                    Some((partition_id, executor_id.clone()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Record the speculative copy of the running task of the given partition
+    pub(super) fn add_speculative_task(
+        &mut self,
+        partition_id: usize,
+        task_info: TaskInfo,
+    ) {
+        self.speculative_task_infos.insert(partition_id, task_info);
+        self.speculative_task_numbers[partition_id] += 1;
+    }
+
+    /// Reconcile a status update with the speculative copy of the task of the given
+    /// partition, the first attempt to finish successfully being kept. Returns whether
+    /// the status should be applied to the stage, and the task id and executor id of the
+    /// attempt to cancel, if any.
+    pub(super) fn update_speculative_task_info(
+        &mut self,
+        partition_id: usize,
+        status: &TaskStatus,
+    ) -> (bool, Option<(usize, String)>) {
+        let task_id = status.task_id as usize;
+        let successful =
+            matches!(status.status, Some(task_status::Status::Successful(_)));
+        let Some(copy) = self.speculative_task_infos.get(&partition_id) else {
+            // The status of a discarded copy, or of the attempt a copy won against
+            return match &self.task_infos[partition_id] {
+                Some(task_info) if task_id <= task_info.task_id => (true, None),
+                _ => {
+                    debug!(
+                        "Ignore TaskStatus update with TID {} of a discarded attempt of partition {}",
+                        task_id, partition_id
+                    );
+                    (false, None)
+                }
+            };
+        };
+
+        if task_id == copy.task_id {
+            let copy = self.speculative_task_infos.remove(&partition_id).unwrap();
+            if !successful {
+                // The original attempt is still running
+                return (false, None);
+            }
+            let original = self.task_infos[partition_id].replace(copy);
+            (true, original.and_then(|info| running_attempt(&info)))
+        } else if self.task_infos[partition_id]
+            .as_ref()
+            .is_some_and(|info| info.task_id == task_id)
+        {
+            let copy = self.speculative_task_infos.remove(&partition_id).unwrap();
+            if !successful {
+                // Let the copy carry on in place of the failed original attempt
+                self.task_infos[partition_id] = Some(copy);
+                return (false, None);
+            }
+            (true, running_attempt(&copy))
+        } else {
+            (true, None)
+        }
+    }
+
     /// Returns the number of tasks in this stage which are available for scheduling.
     /// If the stage is not yet resolved, then this will return `0`, otherwise it will
     /// return the number of tasks where the task info is not yet set.
@@ -734,6 +981,7 @@ impl RunningStage {
             }
         } else {
             self.task_failure_numbers[partition_id] = 0;
+            self.speculative_task_numbers[partition_id] = 0;
         }
         true
     }
@@ -799,33 +1047,85 @@ impl RunningStage {
         self.task_failure_numbers[partition_id]
     }
 
+    /// Returns the attempt number of the latest attempt of the task of the given
+    /// partition, so that a speculative copy and the attempts following it are not
+    /// numbered like the attempts launched before
+    pub(crate) fn task_attempt_num(&self, partition_id: usize) -> usize {
+        self.task_failure_numbers[partition_id]
+            + self.speculative_task_numbers[partition_id]
+    }
+
     /// Reset the task info for the given task partition. This should be called when a task failed and need to be
     /// re-scheduled.
     pub fn reset_task_info(&mut self, partition_id: usize) {
         self.task_infos[partition_id] = None;
     }
 
+    /// Reset all the running tasks, including the speculative copies, so that they are
+    /// re-scheduled
+    pub(super) fn reset_running_tasks(&mut self) {
+        self.speculative_task_infos.clear();
+        for task in self.task_infos.iter_mut() {
+            if matches!(
+                task,
+                Some(TaskInfo {
+                    task_status: task_status::Status::Running(_),
+                    ..
+                })
+            ) {
+                *task = None;
+            }
+        }
+    }
+
+    /// Keep the failed task of the given partition from being re-scheduled until the
+    /// given time in milliseconds, see [`RunningStage::release_retry_backoffs`]
+    pub(super) fn backoff_task_info(&mut self, partition_id: usize, retry_at: u128) {
+        self.retry_backoffs.insert(partition_id, retry_at);
+    }
+
+    /// Reset the task info of the failed tasks whose retry backoff is over, so that they
+    /// are re-scheduled. Returns the number of tasks reset
+    pub(super) fn release_retry_backoffs(&mut self, now: u128) -> usize {
+        let released = self
+            .retry_backoffs
+            .iter()
+            .filter(|(_, retry_at)| **retry_at <= now)
+            .map(|(partition_id, _)| *partition_id)
+            .collect::<Vec<_>>();
+        for partition_id in released.iter() {
+            self.retry_backoffs.remove(partition_id);
+            self.reset_task_info(*partition_id);
+        }
+        released.len()
+    }
+
     /// Reset the running and completed tasks on a given executor
     /// Returns the number of running tasks that were reset
     pub fn reset_tasks(&mut self, executor: &str) -> usize {
+        self.speculative_task_infos
+            .retain(|_, info| running_attempt(info).is_none_or(|(_, id)| id != executor));
         let mut reset = 0;
-        for task in self.task_infos.iter_mut() {
+        for (partition_id, task) in self.task_infos.iter_mut().enumerate() {
             match task {
                 Some(TaskInfo {
                     task_status: task_status::Status::Running(RunningTask { executor_id }),
                     ..
                 }) if *executor == *executor_id => {
-                    *task = None;
-                    reset += 1;
+                    // The speculative copy of the task, if any, carries on in its place
+                    *task = self.speculative_task_infos.remove(&partition_id);
+                    if task.is_none() {
+                        reset += 1;
+                    }
                 }
                 Some(TaskInfo {
                     task_status:
                         task_status::Status::Successful(SuccessfulTask {
                             executor_id,
-                            partitions: _,
+                            partitions,
                         }),
                     ..
-                }) if *executor == *executor_id => {
+                }) if *executor == *executor_id && !is_stored_remotely(partitions) => {
                     *task = None;
                     reset += 1;
                 }
@@ -912,6 +1212,9 @@ impl SuccessfulStage {
             // It is Ok to forget the previous task failure attempts
             task_failure_numbers: vec![0; self.partitions],
             stage_metrics,
+            speculative_task_infos: HashMap::new(),
+            speculative_task_numbers: vec![0; self.partitions],
+            retry_backoffs: HashMap::new(),
         }
     }
 
@@ -927,10 +1230,11 @@ impl SuccessfulStage {
                     scheduled_time,
                     task_status:
                         task_status::Status::Successful(SuccessfulTask {
-                            executor_id, ..
+                            executor_id,
+                            partitions,
                         }),
                     ..
-                } if *executor == *executor_id => {
+                } if *executor == *executor_id && !is_stored_remotely(partitions) => {
                     *task = TaskInfo {
                         task_id: *task_id,
                         scheduled_time: *scheduled_time,
@@ -1181,6 +1485,22 @@ fn get_stage_partitions(plan: Arc<dyn ExecutionPlan>) -> usize {
         .unwrap_or_else(|| plan.properties().output_partitioning().partition_count())
 }
 
+/// Whether the output of a successful task was written to the remote shuffle storage, in
+/// which case it outlives the executor that produced it
+fn is_stored_remotely(partitions: &[ShuffleWritePartition]) -> bool {
+    !partitions.is_empty() && partitions.iter().all(|p| p.remote_storage)
+}
+
+/// Returns the task id and executor id of a running task
+fn running_attempt(task_info: &TaskInfo) -> Option<(usize, String)> {
+    match &task_info.task_status {
+        task_status::Status::Running(RunningTask { executor_id }) => {
+            Some((task_info.task_id, executor_id.clone()))
+        }
+        _ => None,
+    }
+}
+
 /// This data structure collects the partition locations for an `ExecutionStage`.
 /// Each `ExecutionStage` will hold a `StageOutput`s for each of its child stages.
 /// When all tasks for the child stage are complete, it will mark the `StageOutput`
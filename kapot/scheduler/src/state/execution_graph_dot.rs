@@ -19,7 +19,7 @@
 
 use crate::state::execution_graph::ExecutionGraph;
 use kapot_core::execution_plans::{
-    ShuffleReaderExec, ShuffleWriterExec, UnresolvedShuffleExec,
+    RangeSampleExec, ShuffleReaderExec, ShuffleWriterExec, UnresolvedShuffleExec,
 };
 use datafusion::datasource::listing::PartitionedFile;
 use datafusion::datasource::physical_plan::{
@@ -320,6 +320,8 @@ filter_expr={}",
             "ShuffleWriter [{} partitions]",
             exec.input_partition_count()
         )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<RangeSampleExec>() {
+        format!("RangeSample [sample_size={}]", exec.sample_size())
     } else if plan.as_any().downcast_ref::<MemoryExec>().is_some() {
         "MemoryExec".to_string()
     } else if let Some(exec) = plan.as_any().downcast_ref::<CsvExec>() {
@@ -417,6 +419,7 @@ fn get_file_scan(scan: &FileScanConfig) -> String {
 mod tests {
     use crate::state::execution_graph::ExecutionGraph;
     use crate::state::execution_graph_dot::ExecutionGraphDot;
+    use kapot_core::config::KapotConfig;
     use kapot_core::error::{KapotError, Result};
     use datafusion::arrow::datatypes::{DataType, Field, Schema};
     use datafusion::datasource::MemTable;
@@ -644,7 +647,15 @@ filter_expr="]
             .await?;
         let plan = df.into_optimized_plan()?;
         let plan = ctx.state().create_physical_plan(&plan).await?;
-        ExecutionGraph::new("scheduler_id", "job_id", "job_name", "session_id", plan, 0)
+        ExecutionGraph::new(
+            "scheduler_id",
+            "job_id",
+            "job_name",
+            "session_id",
+            plan,
+            0,
+            &KapotConfig::default(),
+        )
     }
 
     // With the improvement of https://github.com/apache/arrow-datafusion/pull/4122,
@@ -670,6 +681,14 @@ filter_expr="]
             .await?;
         let plan = df.into_optimized_plan()?;
         let plan = ctx.state().create_physical_plan(&plan).await?;
-        ExecutionGraph::new("scheduler_id", "job_id", "job_name", "session_id", plan, 0)
+        ExecutionGraph::new(
+            "scheduler_id",
+            "job_id",
+            "job_name",
+            "session_id",
+            plan,
+            0,
+            &KapotConfig::default(),
+        )
     }
 }
@@ -19,17 +19,20 @@ use std::time::Duration;
 
 use kapot_core::error::KapotError;
 use kapot_core::error::Result;
+use kapot_core::execution_plans::remove_remote_shuffle_data;
+use kapot_core::object_store_registry::KapotObjectStoreRegistry;
 use kapot_core::serde::protobuf;
 
 use crate::cluster::{BoundTask, ClusterState, ExecutorSlot};
 use crate::config::SchedulerConfig;
+use crate::scheduler_server::timestamp_secs;
 
 use crate::state::execution_graph::RunningTaskInfo;
 use crate::state::task_manager::JobInfoCache;
 use kapot_core::serde::protobuf::executor_grpc_client::ExecutorGrpcClient;
 use kapot_core::serde::protobuf::{
-    executor_status, CancelTasksParams, ExecutorHeartbeat, MultiTaskDefinition,
-    RemoveJobDataParams, StopExecutorParams,
+    executor_status, task_status, CancelTasksParams, ExecutorHeartbeat,
+    MultiTaskDefinition, RemoveJobDataParams, StopExecutorParams, TaskStatus,
 };
 use kapot_core::serde::scheduler::{ExecutorData, ExecutorMetadata};
 use kapot_core::utils::{create_grpc_client_connection, get_time_before};
@@ -41,11 +44,21 @@ use tonic::transport::Channel;
 
 type ExecutorClients = Arc<DashMap<String, ExecutorGrpcClient<Channel>>>;
 
+/// Failed tasks of an executor counted towards its exclusion from task scheduling
+#[derive(Default)]
+struct ExecutorFailures {
+    /// Number of failed tasks per job since the executor was last excluded
+    job_failures: HashMap<String, u32>,
+    /// Time in seconds the exclusion of the executor expires at, if it was ever excluded
+    excluded_until: Option<u64>,
+}
+
 #[derive(Clone)]
 pub struct ExecutorManager {
     cluster_state: Arc<dyn ClusterState>,
     config: Arc<SchedulerConfig>,
     clients: ExecutorClients,
+    failures: Arc<DashMap<String, ExecutorFailures>>,
 }
 
 impl ExecutorManager {
@@ -57,6 +70,7 @@ impl ExecutorManager {
             cluster_state,
             config,
             clients: Default::default(),
+            failures: Default::default(),
         }
     }
 
@@ -77,7 +91,9 @@ impl ExecutorManager {
             warn!("There's no active jobs for binding tasks");
             return Ok(vec![]);
         }
-        let alive_executors = self.get_alive_executors();
+        let mut alive_executors = self.get_alive_executors();
+        let excluded_executors = self.get_excluded_executors();
+        alive_executors.retain(|executor| !excluded_executors.contains_key(executor));
         if alive_executors.is_empty() {
             warn!("There's no alive executors for binding tasks");
             return Ok(vec![]);
@@ -137,10 +153,12 @@ impl ExecutorManager {
         Ok(())
     }
 
-    /// Send rpc to Executors to clean up the job data by delayed clean_up_interval seconds
+    /// Send rpc to Executors to clean up the job data by delayed clean_up_interval seconds,
+    /// and remove the shuffle output the job uploaded to `remote_shuffle_storage_url`
     pub(crate) fn clean_up_job_data_delayed(
         &self,
         job_id: String,
+        remote_shuffle_storage_url: Option<String>,
         clean_up_interval: u64,
     ) {
         if clean_up_interval == 0 {
@@ -154,20 +172,33 @@ impl ExecutorManager {
         let executor_manager = self.clone();
         tokio::spawn(async move {
             tokio::time::sleep(Duration::from_secs(clean_up_interval)).await;
-            executor_manager.clean_up_job_data_inner(job_id).await;
+            executor_manager
+                .clean_up_job_data_inner(job_id, remote_shuffle_storage_url)
+                .await;
         });
     }
 
-    /// Send rpc to Executors to clean up the job data in a spawn thread
-    pub fn clean_up_job_data(&self, job_id: String) {
+    /// Send rpc to Executors to clean up the job data in a spawn thread, and remove the
+    /// shuffle output the job uploaded to `remote_shuffle_storage_url`
+    pub fn clean_up_job_data(
+        &self,
+        job_id: String,
+        remote_shuffle_storage_url: Option<String>,
+    ) {
         let executor_manager = self.clone();
         tokio::spawn(async move {
-            executor_manager.clean_up_job_data_inner(job_id).await;
+            executor_manager
+                .clean_up_job_data_inner(job_id, remote_shuffle_storage_url)
+                .await;
         });
     }
 
     /// Send rpc to Executors to clean up the job data
-    async fn clean_up_job_data_inner(&self, job_id: String) {
+    async fn clean_up_job_data_inner(
+        &self,
+        job_id: String,
+        remote_shuffle_storage_url: Option<String>,
+    ) {
         let alive_executors = self.get_alive_executors();
         for executor in alive_executors {
             let job_id_clone = job_id.to_owned();
@@ -189,6 +220,22 @@ impl ExecutorManager {
                 warn!("Failed to get client for Executor {}", executor)
             }
         }
+
+        // the executors which uploaded the shuffle output may be gone by now
+        if let Some(url) = remote_shuffle_storage_url {
+            if let Err(err) = remove_remote_shuffle_data(
+                &job_id,
+                &url,
+                &KapotObjectStoreRegistry::new(),
+            )
+            .await
+            {
+                warn!(
+                    "Failed to remove the shuffle output of job {} from {} due to {:?}",
+                    job_id, url, err
+                )
+            }
+        }
     }
 
     /// Get a list of all executors along with the timestamp of their last recorded heartbeat
@@ -258,6 +305,7 @@ impl ExecutorManager {
         reason: Option<String>,
     ) -> Result<()> {
         info!("Removing executor {}: {:?}", executor_id, reason);
+        self.failures.remove(executor_id);
         self.cluster_state.remove_executor(executor_id).await
     }
 
@@ -360,6 +408,85 @@ impl ExecutorManager {
             .collect()
     }
 
+    /// Count the failed tasks among the task statuses reported by an executor. Once the
+    /// failed tasks of a job, or of all jobs, on the executor cross the configured
+    /// thresholds, the executor is excluded from task scheduling for
+    /// `executor_exclusion_timeout_seconds`.
+    pub(crate) fn record_task_failures(
+        &self,
+        executor_id: &str,
+        tasks_status: &[TaskStatus],
+    ) {
+        let max_failures_per_job = self.config.executor_exclusion_max_failures_per_job;
+        let max_failures = self.config.executor_exclusion_max_failures;
+        if max_failures_per_job == 0 && max_failures == 0 {
+            return;
+        }
+
+        let failed_jobs = tasks_status
+            .iter()
+            .filter(|status| {
+                matches!(&status.status, Some(task_status::Status::Failed(failed_task))
+                    if failed_task.count_to_failures)
+            })
+            .map(|status| &status.job_id)
+            .collect::<Vec<_>>();
+        if failed_jobs.is_empty() || self.is_excluded_executor(executor_id) {
+            return;
+        }
+
+        let mut failures = self.failures.entry(executor_id.to_owned()).or_default();
+        for job_id in failed_jobs {
+            *failures.job_failures.entry(job_id.clone()).or_default() += 1;
+        }
+        let job_failures = failures.job_failures.values().copied().max().unwrap_or(0);
+        let total_failures = failures.job_failures.values().sum::<u32>();
+        if (max_failures_per_job > 0 && job_failures >= max_failures_per_job)
+            || (max_failures > 0 && total_failures >= max_failures)
+        {
+            let excluded_until =
+                timestamp_secs() + self.config.executor_exclusion_timeout_seconds;
+            warn!(
+                "Excluding executor {} from task scheduling until {} after {} failed tasks",
+                executor_id, excluded_until, total_failures
+            );
+            failures.job_failures.clear();
+            failures.excluded_until = Some(excluded_until);
+        }
+    }
+
+    /// Forget the failed tasks of a finished job counted towards the exclusion of the
+    /// executors
+    pub(crate) fn forget_job_failures(&self, job_id: &str) {
+        for mut failures in self.failures.iter_mut() {
+            failures.job_failures.remove(job_id);
+        }
+    }
+
+    /// Returns true if the executor is currently excluded from task scheduling
+    pub(crate) fn is_excluded_executor(&self, executor_id: &str) -> bool {
+        self.failures.get(executor_id).is_some_and(|failures| {
+            failures
+                .excluded_until
+                .is_some_and(|excluded_until| excluded_until > timestamp_secs())
+        })
+    }
+
+    /// Retrieve the executors currently excluded from task scheduling, along with the time
+    /// in seconds their exclusion expires at
+    pub fn get_excluded_executors(&self) -> HashMap<String, u64> {
+        let now = timestamp_secs();
+        self.failures
+            .iter()
+            .filter_map(|failures| {
+                failures
+                    .excluded_until
+                    .filter(|excluded_until| *excluded_until > now)
+                    .map(|excluded_until| (failures.key().clone(), excluded_until))
+            })
+            .collect()
+    }
+
     /// Return a list of expired executors
     pub(crate) fn get_expired_executors(&self) -> Vec<ExecutorHeartbeat> {
         // Threshold for last heartbeat from Active executor before marking dead
@@ -0,0 +1,540 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Archive of the summaries of finished jobs, which outlives the clean up of their
+//! state from the cluster storage
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use datafusion::error::DataFusionError;
+use datafusion::execution::object_store::ObjectStoreRegistry;
+use datafusion::physical_plan::display::DisplayableExecutionPlan;
+use kapot_core::error::{KapotError, Result};
+use kapot_core::object_store_registry::KapotObjectStoreRegistry;
+use kapot_core::serde::protobuf::{job_status, JobStatus};
+use object_store::local::LocalFileSystem;
+use object_store::path::Path;
+use object_store::{ObjectStore, PutPayload};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::state::execution_graph::{ExecutionGraph, ExecutionStage};
+
+/// Number of summaries returned by a listing of the job history without a limit
+pub const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+/// Maximum number of summaries read by a listing of the job history, which returns the
+/// jobs matched so far flagged as truncated once it is reached
+pub const MAX_HISTORY_READS: usize = 1000;
+
+/// Directory of the index objects, one per job named after its ID and holding the name
+/// of the summary of the job
+const INDEX_DIR: &str = "by-id";
+
+/// Summary of a finished job kept in the job history
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JobSummary {
+    pub job_id: String,
+    pub job_name: String,
+    pub session_id: String,
+    pub tenant: String,
+    pub status: String,
+    /// Reason of the failure of the job, if it failed
+    pub error: Option<String>,
+    pub queued_at: u64,
+    pub start_time: u64,
+    pub end_time: u64,
+    /// IDs of the executors which ran the tasks of the job
+    pub executors: BTreeSet<String>,
+    pub stages: Vec<StageSummary>,
+}
+
+/// Summary of a stage of a finished job kept in the job history
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StageSummary {
+    pub stage_id: usize,
+    pub status: String,
+    pub input_rows: usize,
+    pub output_rows: usize,
+    pub elapsed_compute_nanos: usize,
+    /// IDs of the executors which ran the tasks of the stage
+    pub executors: BTreeSet<String>,
+    pub plan: String,
+    /// Reason of the failure of the stage, if it failed
+    pub error: Option<String>,
+}
+
+impl From<&ExecutionGraph> for JobSummary {
+    fn from(graph: &ExecutionGraph) -> Self {
+        let mut stages: Vec<StageSummary> =
+            graph.stages().iter().map(StageSummary::from).collect();
+        stages.sort_by_key(|stage| stage.stage_id);
+        let executors = stages
+            .iter()
+            .flat_map(|stage| stage.executors.iter().cloned())
+            .collect();
+        let (status, error) = status_of(graph.status());
+
+        Self {
+            job_id: graph.job_id().to_owned(),
+            job_name: graph.job_name().to_owned(),
+            session_id: graph.session_id().to_owned(),
+            tenant: graph.tenant().to_owned(),
+            status,
+            error,
+            queued_at: graph.queued_at(),
+            start_time: graph.start_time(),
+            end_time: graph.end_time(),
+            executors,
+            stages,
+        }
+    }
+}
+
+impl From<&JobStatus> for JobSummary {
+    /// Summary of a job which never got an execution graph, e.g. because its planning
+    /// failed
+    fn from(job_status: &JobStatus) -> Self {
+        let (queued_at, start_time, end_time) = match &job_status.status {
+            Some(job_status::Status::Queued(job)) => (job.queued_at, 0, 0),
+            Some(job_status::Status::Running(job)) => (job.queued_at, job.started_at, 0),
+            Some(job_status::Status::Failed(job)) => {
+                (job.queued_at, job.started_at, job.ended_at)
+            }
+            Some(job_status::Status::Successful(job)) => {
+                (job.queued_at, job.started_at, job.ended_at)
+            }
+            None => (0, 0, 0),
+        };
+        let (status, error) = status_of(job_status);
+
+        Self {
+            job_id: job_status.job_id.clone(),
+            job_name: job_status.job_name.clone(),
+            session_id: String::new(),
+            tenant: String::new(),
+            status,
+            error,
+            queued_at,
+            start_time,
+            end_time,
+            executors: BTreeSet::new(),
+            stages: vec![],
+        }
+    }
+}
+
+impl From<(&usize, &ExecutionStage)> for StageSummary {
+    fn from((stage_id, stage): (&usize, &ExecutionStage)) -> Self {
+        let metrics = stage.stage_metrics();
+        let error = match stage {
+            ExecutionStage::Failed(failed_stage) => {
+                Some(failed_stage.error_message.clone())
+            }
+            _ => None,
+        };
+
+        Self {
+            stage_id: *stage_id,
+            status: stage.variant_name().to_owned(),
+            input_rows: metrics
+                .iter()
+                .filter_map(|m| m.sum_by_name("input_rows"))
+                .map(|value| value.as_usize())
+                .sum(),
+            output_rows: metrics.iter().filter_map(|m| m.output_rows()).sum(),
+            elapsed_compute_nanos: metrics
+                .iter()
+                .filter_map(|m| m.elapsed_compute())
+                .sum(),
+            executors: stage.executors(),
+            plan: DisplayableExecutionPlan::new(stage.plan())
+                .indent(false)
+                .to_string(),
+            error,
+        }
+    }
+}
+
+fn status_of(job_status: &JobStatus) -> (String, Option<String>) {
+    match &job_status.status {
+        Some(job_status::Status::Queued(_)) => ("Queued".to_owned(), None),
+        Some(job_status::Status::Running(_)) => ("Running".to_owned(), None),
+        Some(job_status::Status::Failed(job)) => {
+            ("Failed".to_owned(), Some(job.error.clone()))
+        }
+        Some(job_status::Status::Successful(_)) => ("Successful".to_owned(), None),
+        None => ("Unknown".to_owned(), None),
+    }
+}
+
+/// Filter and pagination of a listing of the job history
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct JobHistoryFilter {
+    /// Only the jobs with this status, e.g. `Successful` or `Failed`
+    pub status: Option<String>,
+    /// Only the jobs whose name contains this string
+    pub job_name: Option<String>,
+    /// Only the jobs which ended at or after this time in milliseconds
+    pub since: Option<u64>,
+    /// Only the jobs which ended before this time in milliseconds
+    pub until: Option<u64>,
+    /// Number of matching jobs to skip
+    pub offset: Option<usize>,
+    /// Maximum number of jobs to return, [`DEFAULT_HISTORY_LIMIT`] if not set
+    pub limit: Option<usize>,
+}
+
+impl JobHistoryFilter {
+    fn matches(&self, summary: &JobSummary) -> bool {
+        self.status
+            .as_ref()
+            .is_none_or(|status| status.eq_ignore_ascii_case(&summary.status))
+            && self
+                .job_name
+                .as_ref()
+                .is_none_or(|name| summary.job_name.contains(name.as_str()))
+    }
+
+    fn matches_end_time(&self, end_time: u64) -> bool {
+        self.since.is_none_or(|since| end_time >= since)
+            && self.until.is_none_or(|until| end_time < until)
+    }
+}
+
+/// Page of a listing of the job history
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct JobHistoryPage {
+    pub jobs: Vec<JobSummary>,
+    /// Whether the listing stopped after reading [`MAX_HISTORY_READS`] summaries, before
+    /// finding `limit` matching jobs, so older matching jobs may be missing
+    pub truncated: bool,
+}
+
+/// Store of the summaries of finished jobs, one JSON file per job in a local directory
+/// or an object store path. The files are named after the end time of their job so
+/// listings are ordered by time without reading the summaries, and are found by job ID
+/// through the index objects in the `by-id` directory
+pub struct JobHistory {
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+}
+
+impl JobHistory {
+    /// Open the job history at `path`, either a local directory, created if missing,
+    /// or an object store URL such as `s3://bucket/history`
+    pub fn try_new(path: &str) -> Result<Self> {
+        if path.contains("://") {
+            let url = Url::parse(path).map_err(|e| {
+                KapotError::General(format!("Invalid job history URL {path}: {e}"))
+            })?;
+            let store = KapotObjectStoreRegistry::new().get_store(&url)?;
+            let prefix = Path::from_url_path(url.path())
+                .map_err(|e| DataFusionError::ObjectStore(e.into()))?;
+
+            Ok(Self { store, prefix })
+        } else {
+            std::fs::create_dir_all(path)?;
+            let store = LocalFileSystem::new_with_prefix(path)
+                .map_err(DataFusionError::ObjectStore)?;
+
+            Ok(Self {
+                store: Arc::new(store),
+                prefix: Path::default(),
+            })
+        }
+    }
+
+    /// Save the summary of a finished job
+    pub async fn archive(&self, summary: &JobSummary) -> Result<()> {
+        let name = format!("{:020}-{}.json", summary.end_time, summary.job_id);
+        let location = self.prefix.child(name.as_str());
+        let json = serde_json::to_vec(summary).map_err(|e| {
+            KapotError::Internal(format!(
+                "Failed to serialize the summary of job {}: {e}",
+                summary.job_id
+            ))
+        })?;
+        self.store
+            .put(&location, PutPayload::from(json))
+            .await
+            .map_err(DataFusionError::ObjectStore)?;
+        // the index is written last so that it never points to a missing summary
+        self.store
+            .put(
+                &self.index_location(&summary.job_id),
+                PutPayload::from(name),
+            )
+            .await
+            .map_err(DataFusionError::ObjectStore)?;
+
+        Ok(())
+    }
+
+    /// Get the summary of a job, if it is in the history
+    pub async fn get(&self, job_id: &str) -> Result<Option<JobSummary>> {
+        let name = match self.store.get(&self.index_location(job_id)).await {
+            Ok(index) => index.bytes().await.map_err(DataFusionError::ObjectStore)?,
+            Err(object_store::Error::NotFound { .. }) => return Ok(None),
+            Err(e) => return Err(DataFusionError::ObjectStore(e).into()),
+        };
+        let name = std::str::from_utf8(&name).map_err(|e| {
+            KapotError::Internal(format!("Invalid index of job {job_id}: {e}"))
+        })?;
+
+        Ok(Some(self.read(&self.prefix.child(name)).await?))
+    }
+
+    /// List the summaries of the jobs matching `filter`, the most recently ended first
+    pub async fn list(&self, filter: &JobHistoryFilter) -> Result<JobHistoryPage> {
+        let offset = filter.offset.unwrap_or(0);
+        let limit = filter.limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+
+        let mut locations = self.locations().await?;
+        locations.retain(|(end_time, _)| filter.matches_end_time(*end_time));
+        locations.sort_by(|a, b| b.cmp(a));
+
+        let mut page = JobHistoryPage::default();
+        let mut skipped = 0;
+        for (reads, (_, location)) in locations.into_iter().enumerate() {
+            if page.jobs.len() == limit {
+                break;
+            }
+            if reads == MAX_HISTORY_READS {
+                page.truncated = true;
+                break;
+            }
+            let summary = self.read(&location).await?;
+            if !filter.matches(&summary) {
+                continue;
+            }
+            if skipped < offset {
+                skipped += 1;
+            } else {
+                page.jobs.push(summary);
+            }
+        }
+
+        Ok(page)
+    }
+
+    /// The locations of all the summaries, with the end time of their job
+    async fn locations(&self) -> Result<Vec<(u64, Path)>> {
+        // the index objects are in a child directory, which is not listed
+        let objects = self
+            .store
+            .list_with_delimiter(Some(&self.prefix))
+            .await
+            .map_err(DataFusionError::ObjectStore)?
+            .objects;
+
+        Ok(objects
+            .into_iter()
+            .filter_map(|object| {
+                let end_time = object
+                    .location
+                    .filename()?
+                    .split_once('-')?
+                    .0
+                    .parse()
+                    .ok()?;
+                Some((end_time, object.location))
+            })
+            .collect())
+    }
+
+    fn index_location(&self, job_id: &str) -> Path {
+        self.prefix.child(INDEX_DIR).child(job_id)
+    }
+
+    async fn read(&self, location: &Path) -> Result<JobSummary> {
+        let bytes = self
+            .store
+            .get(location)
+            .await
+            .map_err(DataFusionError::ObjectStore)?
+            .bytes()
+            .await
+            .map_err(DataFusionError::ObjectStore)?;
+
+        serde_json::from_slice(&bytes).map_err(|e| {
+            KapotError::Internal(format!("Failed to read job summary {location}: {e}"))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::{
+        JobHistory, JobHistoryFilter, JobHistoryPage, JobSummary, DEFAULT_HISTORY_LIMIT,
+        MAX_HISTORY_READS,
+    };
+    use crate::test_utils::{
+        mock_executor, revive_graph_and_complete_next_stage,
+        revive_graph_and_complete_next_stage_with_executor, test_aggregation_plan,
+    };
+    use kapot_core::error::Result;
+
+    fn summary(job_id: &str, status: &str, end_time: u64) -> JobSummary {
+        JobSummary {
+            job_id: job_id.to_owned(),
+            job_name: format!("{job_id} name"),
+            session_id: "session".to_owned(),
+            tenant: "tenant".to_owned(),
+            status: status.to_owned(),
+            error: None,
+            queued_at: end_time - 10,
+            start_time: end_time - 5,
+            end_time,
+            executors: BTreeSet::from(["executor-1".to_owned()]),
+            stages: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_job_history() -> Result<()> {
+        let dir =
+            std::env::temp_dir().join(format!("job-history-{}", uuid::Uuid::new_v4()));
+        let history = JobHistory::try_new(dir.to_str().unwrap())?;
+
+        history
+            .archive(&summary("job-1", "Successful", 100))
+            .await?;
+        history.archive(&summary("job-2", "Failed", 200)).await?;
+        history
+            .archive(&summary("job-3", "Successful", 300))
+            .await?;
+
+        assert_eq!(
+            history.get("job-2").await?,
+            Some(summary("job-2", "Failed", 200))
+        );
+        assert_eq!(history.get("job-4").await?, None);
+        // the IDs of the archived jobs end with this one
+        assert_eq!(history.get("2").await?, None);
+
+        let job_ids = |page: JobHistoryPage| -> Vec<String> {
+            assert!(!page.truncated);
+            page.jobs.into_iter().map(|s| s.job_id).collect()
+        };
+        let all = history.list(&JobHistoryFilter::default()).await?;
+        assert_eq!(job_ids(all), vec!["job-3", "job-2", "job-1"]);
+
+        let successful = JobHistoryFilter {
+            status: Some("successful".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            job_ids(history.list(&successful).await?),
+            vec!["job-3", "job-1"]
+        );
+
+        let ended_between = JobHistoryFilter {
+            since: Some(150),
+            until: Some(300),
+            ..Default::default()
+        };
+        assert_eq!(job_ids(history.list(&ended_between).await?), vec!["job-2"]);
+
+        let page = JobHistoryFilter {
+            offset: Some(1),
+            limit: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(job_ids(history.list(&page).await?), vec!["job-2"]);
+
+        std::fs::remove_dir_all(dir)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_reads_bounded() -> Result<()> {
+        let dir =
+            std::env::temp_dir().join(format!("job-history-{}", uuid::Uuid::new_v4()));
+        let history = JobHistory::try_new(dir.to_str().unwrap())?;
+
+        // the failed job ended before all the successful ones
+        history.archive(&summary("failed", "Failed", 100)).await?;
+        for i in 0..MAX_HISTORY_READS as u64 {
+            history
+                .archive(&summary(&format!("job-{i}"), "Successful", 200 + i))
+                .await?;
+        }
+
+        let failed = JobHistoryFilter {
+            status: Some("Failed".to_owned()),
+            ..Default::default()
+        };
+        let page = history.list(&failed).await?;
+        assert!(page.jobs.is_empty());
+        assert!(page.truncated);
+        assert!(history.get("failed").await?.is_some());
+
+        // the listing is not truncated once the time range leaves out enough jobs
+        let failed_until = JobHistoryFilter {
+            until: Some(300),
+            ..failed.clone()
+        };
+        let page = history.list(&failed_until).await?;
+        assert_eq!(page.jobs, vec![summary("failed", "Failed", 100)]);
+        assert!(!page.truncated);
+
+        // nor when enough matching jobs are found before reaching the bound
+        let successful = JobHistoryFilter {
+            status: Some("Successful".to_owned()),
+            ..Default::default()
+        };
+        let page = history.list(&successful).await?;
+        assert_eq!(page.jobs.len(), DEFAULT_HISTORY_LIMIT);
+        assert!(!page.truncated);
+
+        std::fs::remove_dir_all(dir)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_summary_of_execution_graph() -> Result<()> {
+        let mut agg_graph = test_aggregation_plan(4).await;
+        let executor2 = mock_executor("executor-id2".to_owned());
+        revive_graph_and_complete_next_stage(&mut agg_graph)?;
+        revive_graph_and_complete_next_stage_with_executor(&mut agg_graph, &executor2)?;
+
+        let summary = JobSummary::from(&agg_graph);
+        assert_eq!(summary.job_id, "job");
+        assert_eq!(
+            summary.executors,
+            BTreeSet::from(["executor-id1".to_owned(), "executor-id2".to_owned()])
+        );
+        assert_eq!(summary.stages.len(), 2);
+        assert_eq!(summary.stages[0].stage_id, 1);
+        assert_eq!(
+            summary.stages[1].executors,
+            BTreeSet::from(["executor-id2".to_owned()])
+        );
+        assert!(summary
+            .stages
+            .iter()
+            .all(|stage| stage.status == "Successful" && stage.error.is_none()));
+
+        Ok(())
+    }
+}
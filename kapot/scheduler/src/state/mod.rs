@@ -28,12 +28,14 @@ use std::time::Instant;
 use crate::scheduler_server::event::QueryStageSchedulerEvent;
 
 use crate::state::executor_manager::ExecutorManager;
+use crate::state::job_history::JobHistory;
 use crate::state::session_manager::SessionManager;
-use crate::state::task_manager::{TaskLauncher, TaskManager};
+use crate::state::task_manager::{job_tenant, TaskLauncher, TaskManager};
 
 use crate::cluster::{KapotCluster, BoundTask, ExecutorSlot};
 use crate::config::SchedulerConfig;
 use crate::state::execution_graph::TaskDescription;
+use kapot_core::config::KapotConfig;
 use kapot_core::error::{KapotError, Result};
 use kapot_core::event_loop::EventSender;
 use kapot_core::serde::protobuf::TaskStatus;
@@ -50,6 +52,7 @@ use prost::Message;
 pub mod execution_graph;
 pub mod execution_graph_dot;
 pub mod executor_manager;
+pub mod job_history;
 pub mod session_manager;
 pub mod task_manager;
 
@@ -87,6 +90,19 @@ pub fn encode_protobuf<T: Message + Default>(msg: &T) -> Result<Vec<u8>> {
     Ok(value)
 }
 
+/// Open the job history configured for the scheduler, if any. A job history which
+/// cannot be opened is disabled rather than failing the scheduler startup
+fn open_job_history(config: &SchedulerConfig) -> Option<Arc<JobHistory>> {
+    let path = config.job_history_path.as_ref()?;
+    match JobHistory::try_new(path) {
+        Ok(job_history) => Some(Arc::new(job_history)),
+        Err(e) => {
+            error!("Failed to open the job history at {path}, finished jobs will not be archived: {e:?}");
+            None
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct SchedulerState<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> {
     pub executor_manager: ExecutorManager,
@@ -112,7 +128,11 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
                 cluster.job_state(),
                 codec.clone(),
                 scheduler_name,
-            ),
+            )
+            .with_tenant_quotas(config.as_ref().into())
+            .with_scheduling_pool_weights(config.scheduling_pool_weights.clone())
+            .with_job_lease_seconds(config.job_lease_seconds)
+            .with_job_history(open_job_history(&config)),
             session_manager: SessionManager::new(cluster.job_state()),
             codec,
             config,
@@ -146,7 +166,11 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
                 codec.clone(),
                 scheduler_name,
                 dispatcher,
-            ),
+            )
+            .with_tenant_quotas(config.as_ref().into())
+            .with_scheduling_pool_weights(config.scheduling_pool_weights.clone())
+            .with_job_lease_seconds(config.job_lease_seconds)
+            .with_job_history(open_job_history(&config)),
             session_manager: SessionManager::new(cluster.job_state()),
             codec,
             config,
@@ -157,6 +181,104 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
         self.executor_manager.init().await
     }
 
+    /// Queue again the jobs which this scheduler accepted but did not plan before it
+    /// stopped. Returns the events to post to plan them
+    pub(crate) async fn recover_queued_jobs(
+        &self,
+    ) -> Result<Vec<QueryStageSchedulerEvent>> {
+        let mut events = vec![];
+        for job in self.task_manager.get_queued_jobs().await? {
+            let session = job.session.unwrap_or_default();
+            let mut config_builder = KapotConfig::builder();
+            for kv_pair in &session.configs {
+                config_builder = config_builder.set(&kv_pair.key, &kv_pair.value);
+            }
+            let session_ctx = self
+                .session_manager
+                .update_session(&session.session_id, &config_builder.build()?)
+                .await?;
+
+            self.task_manager.requeue_job(
+                &job.job_id,
+                &job.job_name,
+                &job_tenant(&session_ctx),
+                job.queued_at,
+            )?;
+
+            let plan = match T::try_decode(&job.logical_plan).and_then(|plan| {
+                plan.try_into_logical_plan(
+                    &session_ctx,
+                    self.codec.logical_extension_codec(),
+                )
+            }) {
+                Ok(plan) => plan,
+                Err(e) => {
+                    let fail_message = format!(
+                        "Could not restore logical plan of job {}: {e}",
+                        job.job_id
+                    );
+                    error!("{}", fail_message);
+                    self.task_manager
+                        .fail_unscheduled_job(&job.job_id, fail_message, false)
+                        .await?;
+                    self.task_manager.release_job(&job.job_id);
+                    continue;
+                }
+            };
+
+            info!("Job {} restored to the queue", job.job_id);
+            events.push(QueryStageSchedulerEvent::JobQueued {
+                job_id: job.job_id,
+                job_name: job.job_name,
+                session_ctx,
+                plan: Box::new(plan),
+                queued_at: job.queued_at,
+                deadline: (job.deadline > 0).then_some(job.deadline),
+            });
+        }
+
+        Ok(events)
+    }
+
+    /// Renew the leases of this scheduler on the jobs it curates and, if it is the leader
+    /// of the cluster, take over the jobs of the schedulers which stopped renewing their
+    /// leases. Returns the events to post for the jobs taken over
+    pub(crate) async fn renew_leases(&self) -> Result<Vec<QueryStageSchedulerEvent>> {
+        self.task_manager.renew_job_leases().await?;
+        if !self.task_manager.try_acquire_leadership().await? {
+            return Ok(vec![]);
+        }
+
+        let mut events = vec![];
+        let mut taken_over = false;
+        for job_id in self.task_manager.get_expired_jobs().await? {
+            let Some((reset_tasks, job_events)) =
+                self.task_manager.take_over_job(&job_id).await?
+            else {
+                continue;
+            };
+            taken_over = true;
+            events.extend(job_events);
+
+            // the tasks launched by the previous scheduler report their status to it, so
+            // the slots they bound are returned here
+            if self.config.is_push_staged_scheduling() && !reset_tasks.is_empty() {
+                let mut executor_slots: HashMap<String, u32> = HashMap::new();
+                for task in reset_tasks {
+                    *executor_slots.entry(task.executor_id).or_default() += 1;
+                }
+                self.executor_manager
+                    .unbind_tasks(executor_slots.into_iter().collect())
+                    .await?;
+            }
+        }
+        if taken_over && self.config.is_push_staged_scheduling() {
+            events.push(QueryStageSchedulerEvent::ReviveOffers);
+        }
+
+        Ok(events)
+    }
+
     pub(crate) async fn revive_offers(
         &self,
         sender: EventSender<QueryStageSchedulerEvent>,
@@ -173,7 +295,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
         let state = self.clone();
         tokio::spawn(async move {
             let mut if_revive = false;
-            match state.launch_tasks(schedulable_tasks).await {
+            match state.launch_tasks(schedulable_tasks, &sender).await {
                 Ok(unassigned_executor_slots) => {
                     if !unassigned_executor_slots.is_empty() {
                         if let Err(e) = state
@@ -208,10 +330,12 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
     /// 1. The executor related info will be removed from [`ExecutorManager`]
     /// 2. All of affected running execution graph will be rolled backed
     /// 3. All of the running tasks of the affected running stages will be cancelled
+    /// 4. The gang scheduled jobs with tasks on the executor will be failed
     pub(crate) async fn remove_executor(
         &self,
         executor_id: &str,
         reason: Option<String>,
+        sender: &EventSender<QueryStageSchedulerEvent>,
     ) {
         if let Err(e) = self
             .executor_manager
@@ -222,7 +346,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
         }
 
         match self.task_manager.executor_lost(executor_id).await {
-            Ok(tasks) => {
+            Ok((tasks, events)) => {
                 if !tasks.is_empty() {
                     if let Err(e) =
                         self.executor_manager.cancel_running_tasks(tasks).await
@@ -230,6 +354,11 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
                         warn!("Fail to cancel running tasks due to {:?}", e);
                     }
                 }
+                for event in events {
+                    if let Err(e) = sender.post_event(event).await {
+                        error!("Fail to send event due to {:?}", e);
+                    }
+                }
             }
             Err(e) => {
                 error!(
@@ -248,6 +377,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
     async fn launch_tasks(
         &self,
         bound_tasks: Vec<BoundTask>,
+        sender: &EventSender<QueryStageSchedulerEvent>,
     ) -> Result<Vec<ExecutorSlot>> {
         // Put tasks to the same executor together
         // And put tasks belonging to the same stage together for creating MultiTaskDefinition
@@ -280,6 +410,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
             let n_tasks: usize = tasks.iter().map(|stage_tasks| stage_tasks.len()).sum();
 
             let state = self.clone();
+            let sender = sender.clone();
             let join_handle = tokio::spawn(async move {
                 let success = match state
                     .executor_manager
@@ -297,7 +428,9 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
 
                             // It's OK to remove executor aggressively,
                             // since if the executor is in healthy state, it will be registered again.
-                            state.remove_executor(&executor_id, Some(err_msg)).await;
+                            state
+                                .remove_executor(&executor_id, Some(err_msg), &sender)
+                                .await;
 
                             false
                         } else {
@@ -343,6 +476,8 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
             .get_executor_metadata(executor_id)
             .await?;
 
+        self.executor_manager
+            .record_task_failures(executor_id, &tasks_status);
         self.task_manager
             .update_task_statuses(&executor, tasks_status)
             .await
@@ -355,6 +490,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
         session_ctx: Arc<SessionContext>,
         plan: &LogicalPlan,
         queued_at: u64,
+        deadline: Option<u64>,
     ) -> Result<()> {
         let start = Instant::now();
 
@@ -424,6 +560,11 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
             DisplayableExecutionPlan::new(plan.data.as_ref()).indent(false)
         );
 
+        let config = session_ctx
+            .state()
+            .config()
+            .get_extension::<KapotConfig>()
+            .unwrap_or_default();
         self.task_manager
             .submit_job(
                 job_id,
@@ -431,6 +572,8 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
                 &session_ctx.session_id(),
                 plan.data,
                 queued_at,
+                deadline,
+                &config,
             )
             .await?;
 
@@ -442,9 +585,11 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
     }
 
     /// Spawn a delayed future to clean up job data on both Scheduler and Executors
-    pub(crate) fn clean_up_successful_job(&self, job_id: String) {
+    pub(crate) async fn clean_up_successful_job(&self, job_id: String) {
+        self.executor_manager.forget_job_failures(&job_id);
         self.executor_manager.clean_up_job_data_delayed(
             job_id.clone(),
+            self.remote_shuffle_storage_url(&job_id).await,
             self.config.finished_job_data_clean_up_interval_seconds,
         );
         self.task_manager.clean_up_job_delayed(
@@ -454,11 +599,32 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
     }
 
     /// Spawn a delayed future to clean up job data on both Scheduler and Executors
-    pub(crate) fn clean_up_failed_job(&self, job_id: String) {
-        self.executor_manager.clean_up_job_data(job_id.clone());
+    pub(crate) async fn clean_up_failed_job(&self, job_id: String) {
+        self.executor_manager.forget_job_failures(&job_id);
+        self.clean_up_job_data(job_id.clone()).await;
         self.task_manager.clean_up_job_delayed(
             job_id,
             self.config.finished_job_state_clean_up_interval_seconds,
         );
     }
+
+    /// Spawn a future to clean up job data on Executors and in the remote shuffle storage
+    pub(crate) async fn clean_up_job_data(&self, job_id: String) {
+        let remote_shuffle_storage_url = self.remote_shuffle_storage_url(&job_id).await;
+        self.executor_manager
+            .clean_up_job_data(job_id, remote_shuffle_storage_url);
+    }
+
+    /// Object store url the shuffle output of a job is uploaded to, as configured when
+    /// the job was planned
+    async fn remote_shuffle_storage_url(&self, job_id: &str) -> Option<String> {
+        match self.task_manager.get_job_execution_graph(job_id).await {
+            Ok(graph) => graph
+                .and_then(|graph| graph.remote_shuffle_storage_url().map(str::to_owned)),
+            Err(e) => {
+                warn!("Failed to get the execution graph of job {job_id}: {e:?}");
+                None
+            }
+        }
+    }
 }
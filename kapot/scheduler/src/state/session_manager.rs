@@ -79,7 +79,8 @@ pub fn create_datafusion_context(
             "datafusion.optimizer.hash_join_single_partition_threshold",
             kapot_config.hash_join_single_partition_threshold(),
         )
-        .set_bool("datafusion.optimizer.enable_round_robin_repartition", false);
+        .set_bool("datafusion.optimizer.enable_round_robin_repartition", false)
+        .with_extension(Arc::new(kapot_config.clone()));
     let session_state = session_builder(config);
     Arc::new(SessionContext::new_with_state(session_state))
 }
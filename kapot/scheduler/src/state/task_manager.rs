@@ -16,48 +16,47 @@
 // under the License.
 
 use crate::scheduler_server::event::QueryStageSchedulerEvent;
+use crate::scheduler_server::timestamp_millis;
 
 use crate::state::execution_graph::{
     ExecutionGraph, ExecutionStage, RunningTaskInfo, TaskDescription,
 };
 use crate::state::executor_manager::ExecutorManager;
+use crate::state::job_history::{JobHistory, JobSummary};
 
 use kapot_core::error::KapotError;
 use kapot_core::error::Result;
 
 use crate::cluster::JobState;
+use crate::config::SchedulerConfig;
 use kapot_core::serde::protobuf::{
-    job_status, JobStatus, KeyValuePair, MultiTaskDefinition, TaskDefinition, TaskId,
-    TaskStatus,
+    job_status, JobSessionConfig, JobStatus, KeyValuePair, MultiTaskDefinition,
+    QueuedJobInfo, TaskDefinition, TaskId, TaskStatus,
 };
 use kapot_core::serde::scheduler::ExecutorMetadata;
 use kapot_core::serde::KapotCodec;
 use dashmap::DashMap;
 
+use datafusion::logical_expr::LogicalPlan;
 use datafusion::physical_plan::ExecutionPlan;
+use datafusion::prelude::SessionContext;
 use datafusion_proto::logical_plan::AsLogicalPlan;
 use datafusion_proto::physical_plan::AsExecutionPlan;
 use log::{debug, error, info, warn};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::ops::Deref;
 use std::sync::Arc;
 use std::time::Duration;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::RwLock;
 
-use kapot_core::config::KAPOT_DATA_CACHE_ENABLED;
+use kapot_core::config::{KapotConfig, KAPOT_DATA_CACHE_ENABLED};
 use tracing::trace;
 
 type ActiveJobCache = Arc<DashMap<String, JobInfoCache>>;
 
-// TODO move to configuration file
-/// Default max failure attempts for task level retry
-pub const TASK_MAX_FAILURES: usize = 4;
-/// Default max failure attempts for stage level retry
-pub const STAGE_MAX_FAILURES: usize = 4;
-
 #[async_trait::async_trait]
 pub trait TaskLauncher: Send + Sync + 'static {
     async fn launch_tasks(
@@ -118,6 +117,52 @@ pub struct TaskManager<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
     // Cache for active jobs curated by this scheduler
     active_job_cache: ActiveJobCache,
     launcher: Arc<dyn TaskLauncher>,
+    tenant_quotas: TenantQuotas,
+    // Weights of the scheduling pools the task slots are shared between, 1 for the pools
+    // not listed
+    scheduling_pool_weights: Arc<HashMap<String, u64>>,
+    // The queued and running jobs of each tenant, for enforcing the tenant quotas
+    tenant_jobs: Arc<DashMap<String, TenantJobs>>,
+    // Time in seconds the leases of this scheduler on its jobs last, 0 if the jobs are
+    // not leased
+    job_lease_seconds: u64,
+    // Archive of the summaries of the finished jobs, kept when their state is cleaned up
+    job_history: Option<Arc<JobHistory>>,
+}
+
+/// Limits on the jobs and the task slots of each tenant, 0 for no limit
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TenantQuotas {
+    pub max_running_jobs: usize,
+    pub max_queued_jobs: usize,
+    pub max_task_slots: usize,
+}
+
+impl From<&SchedulerConfig> for TenantQuotas {
+    fn from(config: &SchedulerConfig) -> Self {
+        Self {
+            max_running_jobs: config.tenant_max_running_jobs as usize,
+            max_queued_jobs: config.tenant_max_queued_jobs as usize,
+            max_task_slots: config.tenant_max_task_slots as usize,
+        }
+    }
+}
+
+/// The tenant the quotas of the jobs submitted in `session_ctx` are accounted to
+pub(crate) fn job_tenant(session_ctx: &SessionContext) -> String {
+    session_ctx
+        .state()
+        .config()
+        .get_extension::<KapotConfig>()
+        .and_then(|config| config.job_tenant())
+        .unwrap_or_else(|| session_ctx.session_id())
+}
+
+#[derive(Default)]
+struct TenantJobs {
+    // Jobs waiting to be admitted to run, in submission order
+    queued: VecDeque<String>,
+    running: HashSet<String>,
 }
 
 #[derive(Clone)]
@@ -128,6 +173,11 @@ pub struct JobInfoCache {
     pub status: Option<job_status::Status>,
     // Cache for encoded execution stage plan to avoid duplicated encoding for multiple tasks
     encoded_stage_plans: HashMap<usize, Vec<u8>>,
+    // Number of task slots the running jobs of the tenant of the job use at the same
+    // time, 0 for no limit
+    pub tenant_max_task_slots: usize,
+    // Weight of the scheduling pool of the job
+    pub pool_weight: u64,
 }
 
 impl JobInfoCache {
@@ -137,6 +187,8 @@ impl JobInfoCache {
             execution_graph: Arc::new(RwLock::new(graph)),
             status,
             encoded_stage_plans: HashMap::new(),
+            tenant_max_task_slots: 0,
+            pool_weight: 1,
         }
     }
 }
@@ -162,6 +214,11 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
             scheduler_id: scheduler_id.clone(),
             active_job_cache: Arc::new(DashMap::new()),
             launcher: Arc::new(DefaultTaskLauncher::new(scheduler_id)),
+            tenant_quotas: TenantQuotas::default(),
+            scheduling_pool_weights: Arc::new(HashMap::new()),
+            tenant_jobs: Arc::new(DashMap::new()),
+            job_lease_seconds: 0,
+            job_history: None,
         }
     }
 
@@ -178,12 +235,195 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
             scheduler_id,
             active_job_cache: Arc::new(DashMap::new()),
             launcher,
+            tenant_quotas: TenantQuotas::default(),
+            scheduling_pool_weights: Arc::new(HashMap::new()),
+            tenant_jobs: Arc::new(DashMap::new()),
+            job_lease_seconds: 0,
+            job_history: None,
         }
     }
 
-    /// Enqueue a job for scheduling
-    pub fn queue_job(&self, job_id: &str, job_name: &str, queued_at: u64) -> Result<()> {
-        self.state.accept_job(job_id, job_name, queued_at)
+    pub fn with_tenant_quotas(mut self, tenant_quotas: TenantQuotas) -> Self {
+        self.tenant_quotas = tenant_quotas;
+        self
+    }
+
+    pub fn with_scheduling_pool_weights(
+        mut self,
+        pool_weights: HashMap<String, u64>,
+    ) -> Self {
+        self.scheduling_pool_weights = Arc::new(pool_weights);
+        self
+    }
+
+    /// Weight of the scheduling `pool` in the fair sharing of the task slots
+    fn pool_weight(&self, pool: &str) -> u64 {
+        self.scheduling_pool_weights.get(pool).copied().unwrap_or(1)
+    }
+
+    pub fn with_job_lease_seconds(mut self, lease_seconds: u64) -> Self {
+        self.job_lease_seconds = lease_seconds;
+        self
+    }
+
+    pub fn with_job_history(mut self, job_history: Option<Arc<JobHistory>>) -> Self {
+        self.job_history = job_history;
+        self
+    }
+
+    /// The archive of the summaries of the finished jobs, if it is enabled
+    pub fn job_history(&self) -> Option<&Arc<JobHistory>> {
+        self.job_history.as_ref()
+    }
+
+    /// Enqueue a job of `tenant` for scheduling. The job is rejected if the tenant already
+    /// has as many queued jobs as its quota
+    pub fn queue_job(
+        &self,
+        job_id: &str,
+        job_name: &str,
+        tenant: &str,
+        queued_at: u64,
+    ) -> Result<()> {
+        let mut tenant_jobs = self.tenant_jobs.entry(tenant.to_owned()).or_default();
+        let max_queued_jobs = self.tenant_quotas.max_queued_jobs;
+        if max_queued_jobs > 0 && tenant_jobs.queued.len() >= max_queued_jobs {
+            return Err(KapotError::QuotaExceeded(format!(
+                "Job {job_id} rejected, tenant {tenant} already has {max_queued_jobs} queued jobs"
+            )));
+        }
+        self.state.accept_job(job_id, job_name, queued_at)?;
+        tenant_jobs.queued.push_back(job_id.to_owned());
+
+        Ok(())
+    }
+
+    /// Enqueue again a job of `tenant` restored after a restart. Unlike `queue_job`, the
+    /// job is not checked against the quota of the tenant since it was already accepted
+    pub(crate) fn requeue_job(
+        &self,
+        job_id: &str,
+        job_name: &str,
+        tenant: &str,
+        queued_at: u64,
+    ) -> Result<()> {
+        self.state.accept_job(job_id, job_name, queued_at)?;
+        self.tenant_jobs
+            .entry(tenant.to_owned())
+            .or_default()
+            .queued
+            .push_back(job_id.to_owned());
+
+        Ok(())
+    }
+
+    /// Persist a queued job with its logical plan and session settings so that it is
+    /// queued again if the scheduler restarts before planning it
+    pub async fn save_queued_job(
+        &self,
+        job_id: &str,
+        job_name: &str,
+        session_ctx: &SessionContext,
+        plan: &LogicalPlan,
+        queued_at: u64,
+        deadline: Option<u64>,
+    ) -> Result<()> {
+        let mut logical_plan = vec![];
+        T::try_from_logical_plan(plan, self.codec.logical_extension_codec())?
+            .try_encode(&mut logical_plan)?;
+        let configs = session_ctx
+            .state()
+            .config()
+            .get_extension::<KapotConfig>()
+            .map(|config| {
+                config
+                    .settings()
+                    .iter()
+                    .map(|(key, value)| KeyValuePair {
+                        key: key.clone(),
+                        value: value.clone(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        self.state
+            .save_queued_job(QueuedJobInfo {
+                job_id: job_id.to_owned(),
+                job_name: job_name.to_owned(),
+                scheduler_id: self.scheduler_id.clone(),
+                queued_at,
+                deadline: deadline.unwrap_or_default(),
+                session: Some(JobSessionConfig {
+                    session_id: session_ctx.session_id(),
+                    configs,
+                }),
+                logical_plan,
+            })
+            .await
+    }
+
+    /// Get the persisted queued jobs which were accepted by this scheduler
+    pub async fn get_queued_jobs(&self) -> Result<Vec<QueuedJobInfo>> {
+        self.state.get_queued_jobs().await
+    }
+
+    /// Admit a queued job of `tenant` to run, unless the tenant already runs as many jobs
+    /// as its quota. Returns whether the job is admitted
+    pub(crate) fn admit_job(&self, job_id: &str, tenant: &str) -> bool {
+        let mut tenant_jobs = self.tenant_jobs.entry(tenant.to_owned()).or_default();
+        if tenant_jobs.running.contains(job_id) {
+            return true;
+        }
+        let max_running_jobs = self.tenant_quotas.max_running_jobs;
+        if max_running_jobs > 0 && tenant_jobs.running.len() >= max_running_jobs {
+            return false;
+        }
+        tenant_jobs
+            .queued
+            .retain(|queued_job_id| queued_job_id != job_id);
+        tenant_jobs.running.insert(job_id.to_owned());
+
+        true
+    }
+
+    /// Release the quota taken by a finished job and admit the next queued jobs of its
+    /// tenant to run in its place. Returns the admitted jobs
+    pub(crate) fn release_job(&self, job_id: &str) -> Vec<String> {
+        let max_running_jobs = self.tenant_quotas.max_running_jobs;
+        let mut admitted_jobs = vec![];
+        for mut tenant_jobs in self.tenant_jobs.iter_mut() {
+            tenant_jobs
+                .queued
+                .retain(|queued_job_id| queued_job_id != job_id);
+            if tenant_jobs.running.remove(job_id) && max_running_jobs > 0 {
+                while tenant_jobs.running.len() < max_running_jobs {
+                    let Some(next_job_id) = tenant_jobs.queued.pop_front() else {
+                        break;
+                    };
+                    tenant_jobs.running.insert(next_job_id.clone());
+                    admitted_jobs.push(next_job_id);
+                }
+            }
+        }
+        self.tenant_jobs.retain(|_, tenant_jobs| {
+            !tenant_jobs.queued.is_empty() || !tenant_jobs.running.is_empty()
+        });
+
+        admitted_jobs
+    }
+
+    /// Get the number of queued and of running jobs of each tenant
+    pub fn tenant_job_numbers(&self) -> HashMap<String, (usize, usize)> {
+        self.tenant_jobs
+            .iter()
+            .map(|tenant_jobs| {
+                (
+                    tenant_jobs.key().clone(),
+                    (tenant_jobs.queued.len(), tenant_jobs.running.len()),
+                )
+            })
+            .collect()
     }
 
     /// Get the number of queued jobs. If it's big, then it means the scheduler is too busy.
@@ -200,6 +440,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
     /// Generate an ExecutionGraph for the job and save it to the persistent state.
     /// By default, this job will be curated by the scheduler which receives it.
     /// Then we will also save it to the active execution graph
+    #[allow(clippy::too_many_arguments)]
     pub async fn submit_job(
         &self,
         job_id: &str,
@@ -207,6 +448,8 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
         session_id: &str,
         plan: Arc<dyn ExecutionPlan>,
         queued_at: u64,
+        deadline: Option<u64>,
+        config: &KapotConfig,
     ) -> Result<()> {
         let mut graph = ExecutionGraph::new(
             &self.scheduler_id,
@@ -215,14 +458,24 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
             session_id,
             plan,
             queued_at,
-        )?;
+            config,
+        )?
+        .with_deadline(deadline);
         info!("Submitting execution graph: {:?}", graph);
 
         self.state.submit_job(job_id.to_string(), &graph).await?;
+        if self.job_lease_seconds > 0 {
+            self.state
+                .renew_job_leases(&[job_id.to_owned()], self.job_lease_seconds)
+                .await?;
+        }
 
         graph.revive();
-        self.active_job_cache
-            .insert(job_id.to_owned(), JobInfoCache::new(graph));
+        let pool_weight = self.pool_weight(graph.pool());
+        let mut job_info = JobInfoCache::new(graph);
+        job_info.tenant_max_task_slots = self.tenant_quotas.max_task_slots;
+        job_info.pool_weight = pool_weight;
+        self.active_job_cache.insert(job_id.to_owned(), job_info);
 
         Ok(())
     }
@@ -318,12 +571,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
                 self.get_active_execution_graph(&job_id)
             {
                 let mut graph = cached.write().await;
-                graph.update_task_status(
-                    executor,
-                    statuses,
-                    TASK_MAX_FAILURES,
-                    STAGE_MAX_FAILURES,
-                )?
+                graph.update_task_status(executor, statuses)?
             } else {
                 // TODO Deal with curator changed case
                 error!("Fail to find job {} in the active cache and it may not be curated by this scheduler", job_id);
@@ -347,6 +595,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
             let graph = graph.read().await.clone();
             if graph.is_successful() {
                 self.state.save_job(job_id, &graph).await?;
+                self.archive_summary(JobSummary::from(&graph)).await;
             } else {
                 error!("Job {} has not finished and cannot be completed", job_id);
                 return Ok(());
@@ -358,19 +607,13 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
         Ok(())
     }
 
-    /// Cancel the job and return a Vec of running tasks need to cancel
-    pub(crate) async fn cancel_job(
-        &self,
-        job_id: &str,
-    ) -> Result<(Vec<RunningTaskInfo>, usize)> {
-        self.abort_job(job_id, "Cancelled".to_owned()).await
-    }
-
-    /// Abort the job and return a Vec of running tasks need to cancel
+    /// Abort the job and return a Vec of running tasks need to cancel, `timed_out` if it did
+    /// not finish before its deadline
     pub(crate) async fn abort_job(
         &self,
         job_id: &str,
         failure_reason: String,
+        timed_out: bool,
     ) -> Result<(Vec<RunningTaskInfo>, usize)> {
         let (tasks_to_cancel, pending_tasks) = if let Some(graph) =
             self.remove_active_execution_graph(job_id)
@@ -386,9 +629,10 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
                 job_id
             );
 
-            guard.fail_job(failure_reason);
+            guard.fail_job(failure_reason, timed_out);
 
             self.state.save_job(job_id, &guard).await?;
+            self.archive_summary(JobSummary::from(&*guard)).await;
 
             (running_tasks, pending_tasks)
         } else {
@@ -406,10 +650,33 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
         &self,
         job_id: &str,
         failure_reason: String,
+        timed_out: bool,
     ) -> Result<()> {
         self.state
-            .fail_unscheduled_job(job_id, failure_reason)
-            .await
+            .fail_unscheduled_job(job_id, failure_reason, timed_out)
+            .await?;
+
+        if let Some(job_history) = &self.job_history {
+            if let Err(err) = archive_job(self.state.as_ref(), job_history, job_id).await
+            {
+                error!("Failed to archive job {job_id} to the job history: {err:?}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Save the summary of a job which reached its final status to the job history, if
+    /// there is one. A job failing to be archived is not failed for it
+    async fn archive_summary(&self, summary: JobSummary) {
+        if let Some(job_history) = &self.job_history {
+            if let Err(err) = job_history.archive(&summary).await {
+                error!(
+                    "Failed to archive job {} to the job history: {err:?}",
+                    summary.job_id
+                );
+            }
+        }
     }
 
     pub async fn update_job(&self, job_id: &str) -> Result<usize> {
@@ -436,15 +703,36 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
     }
 
     /// return a Vec of running tasks need to cancel
-    pub async fn executor_lost(&self, executor_id: &str) -> Result<Vec<RunningTaskInfo>> {
+    /// Returns the running tasks to cancel along with the events failing the gang scheduled
+    /// jobs which had tasks on the executor
+    pub async fn executor_lost(
+        &self,
+        executor_id: &str,
+    ) -> Result<(Vec<RunningTaskInfo>, Vec<QueryStageSchedulerEvent>)> {
         // Collect all the running task need to cancel when there are running stages rolled back.
         let mut running_tasks_to_cancel: Vec<RunningTaskInfo> = vec![];
+        let mut events = vec![];
         // Collect graphs we update so we can update them in storage
         let updated_graphs: DashMap<String, ExecutionGraph> = DashMap::new();
         {
             for pairs in self.active_job_cache.iter() {
                 let (job_id, job_info) = pairs.pair();
                 let mut graph = job_info.execution_graph.write().await;
+                if graph.gang_scheduling()
+                    && !graph.gang_scheduling_pending()
+                    && graph.has_tasks_on_executor(executor_id)
+                {
+                    // the streamed shuffle output of its tasks is lost with the executor
+                    events.push(QueryStageSchedulerEvent::JobRunningFailed {
+                        job_id: job_id.to_owned(),
+                        fail_message: format!(
+                            "Executor {executor_id} of gang scheduled job {job_id} was lost"
+                        ),
+                        queued_at: graph.queued_at(),
+                        failed_at: timestamp_millis(),
+                    });
+                    continue;
+                }
                 let reset = graph.reset_stages_on_lost_executor(executor_id)?;
                 if !reset.0.is_empty() {
                     updated_graphs.insert(job_id.to_owned(), graph.clone());
@@ -453,7 +741,96 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
             }
         }
 
-        Ok(running_tasks_to_cancel)
+        Ok((running_tasks_to_cancel, events))
+    }
+
+    /// Renew the leases of this scheduler on the jobs it curates. The jobs taken over by
+    /// another scheduler in the meantime are removed from the cache
+    pub(crate) async fn renew_job_leases(&self) -> Result<()> {
+        let job_ids: Vec<String> = self
+            .active_job_cache
+            .iter()
+            .map(|pair| pair.key().clone())
+            .collect();
+        let lost_jobs = self
+            .state
+            .renew_job_leases(&job_ids, self.job_lease_seconds)
+            .await?;
+        for job_id in lost_jobs {
+            warn!("Job {job_id} was taken over by another scheduler, stop curating it");
+            self.remove_active_execution_graph(&job_id);
+        }
+        Ok(())
+    }
+
+    /// Acquire or renew the lease of this scheduler on the leadership of the cluster.
+    /// Returns true if this scheduler is the leader
+    pub(crate) async fn try_acquire_leadership(&self) -> Result<bool> {
+        self.state
+            .try_acquire_leadership(self.job_lease_seconds)
+            .await
+    }
+
+    /// Return the jobs whose lease expired, as the scheduler curating them stopped
+    /// renewing it
+    pub(crate) async fn get_expired_jobs(&self) -> Result<Vec<String>> {
+        self.state.get_expired_jobs().await
+    }
+
+    /// Take over a job whose lease expired and curate it from now on, accounting it to
+    /// the running jobs of its tenant. Returns `None` if the job can't be acquired,
+    /// otherwise the tasks launched by the previous scheduler which are reset along with
+    /// the events tracking the deadline of the job and failing the gang scheduled job
+    /// with running tasks
+    pub(crate) async fn take_over_job(
+        &self,
+        job_id: &str,
+    ) -> Result<Option<(Vec<RunningTaskInfo>, Vec<QueryStageSchedulerEvent>)>> {
+        let Some(mut graph) = self
+            .state
+            .try_acquire_job(job_id, self.job_lease_seconds)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let running_tasks = graph.take_over(&self.scheduler_id);
+        self.state.save_job(job_id, &graph).await?;
+        info!(
+            "Took over job {job_id}, resetting {} tasks launched by the previous scheduler",
+            running_tasks.len()
+        );
+
+        // the job already runs, so it is not checked against the quota of its tenant
+        self.tenant_jobs
+            .entry(graph.tenant().to_owned())
+            .or_default()
+            .running
+            .insert(job_id.to_owned());
+
+        let mut events = vec![QueryStageSchedulerEvent::JobTakenOver {
+            job_id: job_id.to_owned(),
+            deadline: graph.deadline(),
+        }];
+        if graph.gang_scheduling() && !running_tasks.is_empty() {
+            // the shuffle output streamed by the reset tasks can't be read again
+            events.push(QueryStageSchedulerEvent::JobRunningFailed {
+                job_id: job_id.to_owned(),
+                fail_message: format!(
+                    "Scheduler curating gang scheduled job {job_id} was lost"
+                ),
+                queued_at: graph.queued_at(),
+                failed_at: timestamp_millis(),
+            });
+        }
+
+        let pool_weight = self.pool_weight(graph.pool());
+        let mut job_info = JobInfoCache::new(graph);
+        job_info.tenant_max_task_slots = self.tenant_quotas.max_task_slots;
+        job_info.pool_weight = pool_weight;
+        self.active_job_cache.insert(job_id.to_owned(), job_info);
+
+        Ok(Some((running_tasks, events)))
     }
 
     /// Retrieve the number of available tasks for the given job. The value returned
@@ -693,6 +1070,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
             return;
         }
 
+        // the job was archived to the job history when it finished
         let state = self.state.clone();
         tokio::spawn(async move {
             tokio::time::sleep(Duration::from_secs(clean_up_interval)).await;
@@ -703,6 +1081,24 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
     }
 }
 
+/// Save the summary of a finished job to the job history, from its execution graph or,
+/// if it has none, from its status
+async fn archive_job(
+    state: &dyn JobState,
+    job_history: &JobHistory,
+    job_id: &str,
+) -> Result<()> {
+    let summary = if let Some(graph) = state.get_execution_graph(job_id).await? {
+        JobSummary::from(&graph)
+    } else if let Some(status) = state.get_job_status(job_id).await? {
+        JobSummary::from(&status)
+    } else {
+        return Ok(());
+    };
+
+    job_history.archive(&summary).await
+}
+
 pub struct JobOverview {
     pub job_id: String,
     pub job_name: String,
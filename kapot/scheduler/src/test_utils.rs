@@ -32,7 +32,10 @@ use crate::scheduler_server::{timestamp_millis, SchedulerServer};
 use crate::state::executor_manager::ExecutorManager;
 use crate::state::task_manager::TaskLauncher;
 
-use kapot_core::config::{KapotConfig, KAPOT_DEFAULT_SHUFFLE_PARTITIONS};
+use kapot_core::config::{
+    KapotConfig, KAPOT_DEFAULT_SHUFFLE_PARTITIONS,
+    KAPOT_SHUFFLE_RANGE_PARTITIONING_ENABLED,
+};
 use kapot_core::serde::protobuf::job_status::Status;
 use kapot_core::serde::protobuf::{
     task_status, FailedTask, JobStatus, MultiTaskDefinition, ShuffleWritePartition,
@@ -279,6 +282,9 @@ pub fn default_task_runner() -> impl TaskRunner {
                 num_batches: 1,
                 num_rows: 1,
                 num_bytes: 1,
+                range: None,
+                remote_storage: false,
+                checksum: None,
             })
             .collect();
 
@@ -389,6 +395,29 @@ impl SchedulerTest {
     ) -> Result<Self> {
         let cluster = KapotCluster::new_from_config(&config).await?;
 
+        Self::new_with_cluster(
+            "localhost:50050",
+            cluster,
+            config,
+            metrics_collector,
+            num_executors,
+            task_slots_per_executor,
+            runner,
+        )
+        .await
+    }
+
+    /// Create a scheduler named `scheduler_name` on the given cluster state, which may
+    /// be shared with other schedulers
+    pub async fn new_with_cluster(
+        scheduler_name: &str,
+        cluster: KapotCluster,
+        config: SchedulerConfig,
+        metrics_collector: Arc<dyn SchedulerMetricsCollector>,
+        num_executors: usize,
+        task_slots_per_executor: usize,
+        runner: Option<Arc<dyn TaskRunner>>,
+    ) -> Result<Self> {
         let kapot_config = if num_executors > 0 && task_slots_per_executor > 0 {
             KapotConfig::builder()
                 .set(
@@ -423,7 +452,7 @@ impl SchedulerTest {
 
         let mut scheduler: SchedulerServer<LogicalPlanNode, PhysicalPlanNode> =
             SchedulerServer::new_with_task_launcher(
-                "localhost:50050".to_owned(),
+                scheduler_name.to_owned(),
                 cluster,
                 KapotCodec::default(),
                 Arc::new(config),
@@ -463,6 +492,14 @@ impl SchedulerTest {
         })
     }
 
+    /// Set a configuration setting of the session jobs are submitted with
+    pub fn with_session_setting(mut self, key: &str, value: &str) -> Result<Self> {
+        let mut settings = self.kapot_config.settings().clone();
+        settings.insert(key.to_owned(), value.to_owned());
+        self.kapot_config = KapotConfig::with_settings(settings)?;
+        Ok(self)
+    }
+
     pub fn pending_job_number(&self) -> usize {
         self.scheduler.pending_job_number()
     }
@@ -471,6 +508,19 @@ impl SchedulerTest {
         self.scheduler.running_job_number()
     }
 
+    /// The number of queued and of running jobs of each tenant
+    pub fn tenant_job_numbers(&self) -> HashMap<String, (usize, usize)> {
+        self.scheduler.state.task_manager.tenant_job_numbers()
+    }
+
+    /// The executors currently excluded from task scheduling
+    pub fn excluded_executors(&self) -> HashMap<String, u64> {
+        self.scheduler
+            .state
+            .executor_manager
+            .get_excluded_executors()
+    }
+
     pub async fn ctx(&self) -> Result<Arc<SessionContext>> {
         self.scheduler
             .state
@@ -563,8 +613,6 @@ impl SchedulerTest {
                     _ => {
                         if time >= timeout_ms {
                             break Ok(status.unwrap());
-                        } else {
-                            continue;
                         }
                     }
                 }
@@ -595,7 +643,7 @@ impl SchedulerTest {
                     Status::Failed(_) | Status::Successful(_) => {
                         break Ok(status.unwrap())
                     }
-                    _ => continue,
+                    _ => {}
                 }
             }
 
@@ -622,43 +670,24 @@ impl SchedulerTest {
             .submit_job(job_id, job_name, ctx, plan)
             .await?;
 
-        let mut receiver = self.status_receiver.take().unwrap();
-
-        let scheduler_clone = self.scheduler.clone();
-        tokio::spawn(async move {
-            while let Some((executor_id, status)) = receiver.recv().await {
-                scheduler_clone
-                    .update_task_status(&executor_id, status)
-                    .await
-                    .unwrap();
-            }
-        });
-
-        let final_status: Result<JobStatus> = loop {
-            let status = self
-                .scheduler
-                .state
-                .task_manager
-                .get_job_status(job_id)
-                .await?;
+        self.run_submitted(job_id).await
+    }
 
-            if let Some(JobStatus {
-                status: Some(inner),
-                ..
-            }) = status.as_ref()
-            {
-                match inner {
-                    Status::Failed(_) | Status::Successful(_) => {
-                        break Ok(status.unwrap())
-                    }
-                    _ => continue,
+    /// Run the tasks launched to the executors until the already submitted job completes
+    pub async fn run_submitted(&mut self, job_id: &str) -> Result<JobStatus> {
+        if let Some(mut receiver) = self.status_receiver.take() {
+            let scheduler_clone = self.scheduler.clone();
+            tokio::spawn(async move {
+                while let Some((executor_id, status)) = receiver.recv().await {
+                    scheduler_clone
+                        .update_task_status(&executor_id, status)
+                        .await
+                        .unwrap();
                 }
-            }
-
-            tokio::time::sleep(Duration::from_millis(100)).await
-        };
+            });
+        }
 
-        final_status
+        self.await_completion(job_id).await
     }
 }
 
@@ -667,6 +696,7 @@ pub enum MetricEvent {
     Submitted(String, u64, u64),
     Completed(String, u64, u64),
     Cancelled(String),
+    TimedOut(String),
     Failed(String, u64, u64),
 }
 
@@ -676,6 +706,7 @@ impl MetricEvent {
             MetricEvent::Submitted(job, _, _) => job.as_str(),
             MetricEvent::Completed(job, _, _) => job.as_str(),
             MetricEvent::Cancelled(job) => job.as_str(),
+            MetricEvent::TimedOut(job) => job.as_str(),
             MetricEvent::Failed(job, _, _) => job.as_str(),
         }
     }
@@ -732,6 +763,11 @@ impl SchedulerMetricsCollector for TestMetricsCollector {
         guard.push(MetricEvent::Cancelled(job_id.to_owned()));
     }
 
+    fn record_timed_out(&self, job_id: &str) {
+        let mut guard = self.events.lock();
+        guard.push(MetricEvent::TimedOut(job_id.to_owned()));
+    }
+
     fn set_pending_tasks_queue_size(&self, _value: u64) {}
 
     fn gather_metrics(&self) -> Result<Option<(Vec<u8>, String)>> {
@@ -775,6 +811,15 @@ pub fn assert_cancelled_event(job_id: &str, collector: &TestMetricsCollector) {
     assert!(found, "{}", "Expected cancelled event for job {job_id}");
 }
 
+pub fn assert_timed_out_event(job_id: &str, collector: &TestMetricsCollector) {
+    let found = collector
+        .job_events(job_id)
+        .iter()
+        .any(|ev| matches!(ev, MetricEvent::TimedOut(_)));
+
+    assert!(found, "{}", "Expected timed out event for job {job_id}");
+}
+
 pub fn assert_failed_event(job_id: &str, collector: &TestMetricsCollector) {
     let found = collector
         .job_events(job_id)
@@ -817,7 +862,7 @@ pub fn revive_graph_and_complete_next_stage_with_executor(
         for _ in 0..num_available_tasks {
             if let Some(task) = graph.pop_next_task(&executor.id).unwrap() {
                 let task_status = mock_completed_task(task, &executor.id);
-                graph.update_task_status(executor, vec![task_status], 1, 1)?;
+                graph.update_task_status(executor, vec![task_status])?;
             }
         }
     }
@@ -832,6 +877,14 @@ pub async fn test_aggregation_plan(partition: usize) -> ExecutionGraph {
 pub async fn test_aggregation_plan_with_job_id(
     partition: usize,
     job_id: &str,
+) -> ExecutionGraph {
+    test_aggregation_plan_with_config(partition, job_id, &KapotConfig::default()).await
+}
+
+pub async fn test_aggregation_plan_with_config(
+    partition: usize,
+    job_id: &str,
+    kapot_config: &KapotConfig,
 ) -> ExecutionGraph {
     let config = SessionConfig::new().with_target_partitions(partition);
     let ctx = Arc::new(SessionContext::new_with_config(config));
@@ -862,7 +915,16 @@ pub async fn test_aggregation_plan_with_job_id(
         DisplayableExecutionPlan::new(plan.as_ref()).indent(false)
     );
 
-    ExecutionGraph::new("localhost:50050", job_id, "", "session", plan, 0).unwrap()
+    ExecutionGraph::new(
+        "localhost:50050",
+        job_id,
+        "",
+        "session",
+        plan,
+        0,
+        kapot_config,
+    )
+    .unwrap()
 }
 
 pub async fn test_two_aggregations_plan(partition: usize) -> ExecutionGraph {
@@ -898,7 +960,16 @@ pub async fn test_two_aggregations_plan(partition: usize) -> ExecutionGraph {
         DisplayableExecutionPlan::new(plan.as_ref()).indent(false)
     );
 
-    ExecutionGraph::new("localhost:50050", "job", "", "session", plan, 0).unwrap()
+    ExecutionGraph::new(
+        "localhost:50050",
+        "job",
+        "",
+        "session",
+        plan,
+        0,
+        &KapotConfig::default(),
+    )
+    .unwrap()
 }
 
 pub async fn test_coalesce_plan(partition: usize) -> ExecutionGraph {
@@ -926,11 +997,69 @@ pub async fn test_coalesce_plan(partition: usize) -> ExecutionGraph {
         .await
         .unwrap();
 
-    ExecutionGraph::new("localhost:50050", "job", "", "session", plan, 0).unwrap()
+    ExecutionGraph::new(
+        "localhost:50050",
+        "job",
+        "",
+        "session",
+        plan,
+        0,
+        &KapotConfig::default(),
+    )
+    .unwrap()
+}
+
+pub async fn test_range_sort_plan(partition: usize) -> ExecutionGraph {
+    let config = SessionConfig::new().with_target_partitions(partition);
+    let ctx = Arc::new(SessionContext::new_with_config(config));
+    let session_state = ctx.state();
+
+    let schema = Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("gmv", DataType::UInt64, false),
+    ]);
+
+    let logical_plan = scan_empty_with_partitions(None, &schema, Some(vec![0, 1]), 2)
+        .unwrap()
+        .sort(vec![col("gmv").sort(true, false)])
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let optimized_plan = session_state.optimize(&logical_plan).unwrap();
+
+    let plan = session_state
+        .create_physical_plan(&optimized_plan)
+        .await
+        .unwrap();
+
+    let kapot_config = KapotConfig::builder()
+        .set(KAPOT_SHUFFLE_RANGE_PARTITIONING_ENABLED, "true")
+        .set(KAPOT_DEFAULT_SHUFFLE_PARTITIONS, &partition.to_string())
+        .build()
+        .unwrap();
+
+    ExecutionGraph::new(
+        "localhost:50050",
+        "job",
+        "",
+        "session",
+        plan,
+        0,
+        &kapot_config,
+    )
+    .unwrap()
 }
 
 
 pub async fn test_join_plan(partition: usize) -> ExecutionGraph {
+    test_join_plan_with_config(partition, &KapotConfig::default()).await
+}
+
+pub async fn test_join_plan_with_config(
+    partition: usize,
+    kapot_config: &KapotConfig,
+) -> ExecutionGraph {
     let mut config = SessionConfig::new().with_target_partitions(partition);
     config
         .options_mut()
@@ -976,8 +1105,153 @@ pub async fn test_join_plan(partition: usize) -> ExecutionGraph {
         DisplayableExecutionPlan::new(plan.as_ref()).indent(false)
     );
 
-    let graph =
-        ExecutionGraph::new("localhost:50050", "job", "", "session", plan, 0).unwrap();
+    let graph = ExecutionGraph::new(
+        "localhost:50050",
+        "job",
+        "",
+        "session",
+        plan,
+        0,
+        kapot_config,
+    )
+    .unwrap();
+
+    println!("{graph:?}");
+
+    graph
+}
+
+/// A join followed by an aggregation on another column than the join key, so that the
+/// output of the join stage is repartitioned
+pub async fn test_join_group_by_plan(
+    partition: usize,
+    kapot_config: &KapotConfig,
+) -> ExecutionGraph {
+    let mut config = SessionConfig::new().with_target_partitions(partition);
+    config
+        .options_mut()
+        .optimizer
+        .enable_round_robin_repartition = false;
+    let ctx = Arc::new(SessionContext::new_with_config(config));
+    let session_state = ctx.state();
+
+    let schema = Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("gmv", DataType::UInt64, false),
+    ]);
+
+    // we specify the input partitions to be > 1 because of https://github.com/apache/datafusion/issues/12611
+    let left_plan = scan_empty_with_partitions(Some("left"), &schema, None, 2).unwrap();
+
+    let right_plan = scan_empty_with_partitions(Some("right"), &schema, None, 2)
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let logical_plan = left_plan
+        .join(right_plan, JoinType::Inner, (vec!["id"], vec!["id"]), None)
+        .unwrap()
+        .aggregate(vec![col("left.gmv")], vec![count(col("right.gmv"))])
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let optimized_plan = session_state.optimize(&logical_plan).unwrap();
+
+    let plan = session_state
+        .create_physical_plan(&optimized_plan)
+        .await
+        .unwrap();
+
+    println!(
+        "{}",
+        DisplayableExecutionPlan::new(plan.as_ref()).indent(false)
+    );
+
+    let graph = ExecutionGraph::new(
+        "localhost:50050",
+        "job",
+        "",
+        "session",
+        plan,
+        0,
+        kapot_config,
+    )
+    .unwrap();
+
+    println!("{graph:?}");
+
+    graph
+}
+
+/// A join whose right side is an aggregation on another column than the join key, so that
+/// the stage of the right side of the join depends on another stage, followed by an
+/// aggregation
+pub async fn test_join_aggregate_plan(
+    partition: usize,
+    kapot_config: &KapotConfig,
+) -> ExecutionGraph {
+    let mut config = SessionConfig::new().with_target_partitions(partition);
+    config
+        .options_mut()
+        .optimizer
+        .enable_round_robin_repartition = false;
+    let ctx = Arc::new(SessionContext::new_with_config(config));
+    let session_state = ctx.state();
+
+    let schema = Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("gmv", DataType::UInt64, false),
+    ]);
+
+    // we specify the input partitions to be > 1 because of https://github.com/apache/datafusion/issues/12611
+    let left_plan = scan_empty_with_partitions(Some("left"), &schema, None, 2).unwrap();
+
+    let right_plan = scan_empty_with_partitions(Some("right"), &schema, None, 2)
+        .unwrap()
+        .aggregate(
+            vec![col("right.id")],
+            vec![sum(col("right.gmv")).alias("total")],
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let logical_plan = left_plan
+        .join(
+            right_plan,
+            JoinType::Inner,
+            (vec!["left.gmv"], vec!["total"]),
+            None,
+        )
+        .unwrap()
+        .aggregate(vec![col("left.id")], vec![count(col("total"))])
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let optimized_plan = session_state.optimize(&logical_plan).unwrap();
+
+    let plan = session_state
+        .create_physical_plan(&optimized_plan)
+        .await
+        .unwrap();
+
+    println!(
+        "{}",
+        DisplayableExecutionPlan::new(plan.as_ref()).indent(false)
+    );
+
+    let graph = ExecutionGraph::new(
+        "localhost:50050",
+        "job",
+        "",
+        "session",
+        plan,
+        0,
+        kapot_config,
+    )
+    .unwrap();
 
     println!("{graph:?}");
 
@@ -1008,8 +1282,16 @@ pub async fn test_union_all_plan(partition: usize) -> ExecutionGraph {
         DisplayableExecutionPlan::new(plan.as_ref()).indent(false)
     );
 
-    let graph =
-        ExecutionGraph::new("localhost:50050", "job", "", "session", plan, 0).unwrap();
+    let graph = ExecutionGraph::new(
+        "localhost:50050",
+        "job",
+        "",
+        "session",
+        plan,
+        0,
+        &KapotConfig::default(),
+    )
+    .unwrap();
 
     println!("{graph:?}");
 
@@ -1040,8 +1322,16 @@ pub async fn test_union_plan(partition: usize) -> ExecutionGraph {
         DisplayableExecutionPlan::new(plan.as_ref()).indent(false)
     );
 
-    let graph =
-        ExecutionGraph::new("localhost:50050", "job", "", "session", plan, 0).unwrap();
+    let graph = ExecutionGraph::new(
+        "localhost:50050",
+        "job",
+        "",
+        "session",
+        plan,
+        0,
+        &KapotConfig::default(),
+    )
+    .unwrap();
 
     println!("{graph:?}");
 
@@ -1075,6 +1365,9 @@ pub fn mock_completed_task(task: TaskDescription, executor_id: &str) -> TaskStat
             num_batches: 1,
             num_rows: 1,
             num_bytes: 1,
+            range: None,
+            remote_storage: false,
+            checksum: None,
         })
     }
 
@@ -1113,6 +1406,9 @@ pub fn mock_failed_task(task: TaskDescription, failed_task: FailedTask) -> TaskS
             num_batches: 1,
             num_rows: 1,
             num_bytes: 1,
+            range: None,
+            remote_storage: false,
+            checksum: None,
         })
     }
 